@@ -1,7 +1,106 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
 fn main() {
     // Only build NAPI bindings when the nodejs feature is enabled
     #[cfg(feature = "nodejs")]
     {
         napi_build::setup();
     }
+
+    embed_prompts();
+
+    #[cfg(feature = "capi")]
+    generate_c_header();
+
+    #[cfg(feature = "server-grpc")]
+    generate_grpc_service();
+}
+
+/// Generate the server and client of `proto/prompt_composer.proto` for the messages in
+/// `core/grpc.rs`, which are written by hand so that building needs no `protoc`
+#[cfg(feature = "server-grpc")]
+fn generate_grpc_service() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    println!("cargo:rerun-if-changed=proto/prompt_composer.proto");
+    let method = |name: &str, route: &str, message: &str| Method::builder()
+        .name(name)
+        .route_name(route)
+        .input_type(format!("crate::grpc::{}Request", message))
+        .output_type(format!("crate::grpc::{}Response", message))
+        .codec_path("tonic::codec::ProstCodec")
+        .build();
+    let service = Service::builder()
+        .name("PromptComposer")
+        .package("prompt_composer.v1")
+        .method(method("compose", "Compose", "Compose"))
+        .method(method("explain", "Explain", "Explain"))
+        .method(method("refresh_server", "RefreshServer", "RefreshServer"))
+        .method(method("list_modules", "ListModules", "ListModules"))
+        .build();
+    Builder::new().compile(&[service]);
+}
+
+/// Write the declarations of `core/capi.rs` to `$OUT_DIR/prompt_composer.h`, warning when
+/// the committed `include/prompt_composer.h` differs from it
+#[cfg(feature = "capi")]
+fn generate_c_header() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    println!("cargo:rerun-if-changed=core/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=include/prompt_composer.h");
+    let config = cbindgen::Config::from_file(Path::new(crate_dir).join("cbindgen.toml")).expect("cbindgen.toml is valid");
+    let header = Path::new(&std::env::var("OUT_DIR").expect("cargo sets OUT_DIR")).join("prompt_composer.h");
+    cbindgen::generate_with_config(crate_dir, config)
+        .expect("C header can be generated from core/capi.rs")
+        .write_to_file(&header);
+    let committed = fs::read_to_string(Path::new(crate_dir).join("include/prompt_composer.h")).unwrap_or_default();
+    if fs::read_to_string(&header).ok().as_deref() != Some(committed.as_str()) {
+        println!("cargo:warning=include/prompt_composer.h is out of date; run ./generate-header.sh");
+    }
+}
+
+/// Compile the bundled prompts directory into the library as a sorted table of
+/// (relative path, content), read by `core/embedded.rs`
+fn embed_prompts() {
+    let prompts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("prompts");
+    println!("cargo:rerun-if-changed={}", prompts_dir.display());
+
+    let mut files = Vec::new();
+    collect_files(&prompts_dir, &mut files);
+    let mut entries: Vec<(String, PathBuf)> = files.into_iter()
+        .filter_map(|path| {
+            println!("cargo:rerun-if-changed={}", path.display());
+            let relative = path.strip_prefix(&prompts_dir).ok()?;
+            let name = relative.components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Some((name, path))
+        })
+        .collect();
+    entries.sort();
+
+    let table: String = entries.iter()
+        .map(|(name, path)| format!("    ({:?}, include_str!({:?})),\n", name, path.display().to_string()))
+        .collect();
+    let source = format!("pub static FILES: &[(&str, &str)] = &[\n{}];\n", table);
+    let out_dir = std::env::var("OUT_DIR").expect("cargo sets OUT_DIR");
+    fs::write(Path::new(&out_dir).join("embedded_prompts.rs"), source).expect("embedded prompts table is writable");
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            println!("cargo:rerun-if-changed={}", path.display());
+            collect_files(&path, files);
+        } else if matches!(path.extension().and_then(|s| s.to_str()), Some("md" | "toml")) {
+            files.push(path);
+        }
+    }
 }
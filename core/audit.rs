@@ -0,0 +1,153 @@
+//! Audit log of composed prompts. An [`AuditLog`] is a composition hook recording, for
+//! every composition of the composers it is added to, what was asked and which
+//! instructions were composed, so deployments can reconstruct what a model received
+//! without storing the prompts themselves: recomposing the request with the recorded
+//! seed and prompt file versions yields a prompt with the recorded hash.
+
+use crate::composition::fnv1a;
+use crate::hooks::CompositionHook;
+use crate::types::{PromptError, PromptRequest, PromptResponse};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// One composition in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AuditRecord {
+    /// Milliseconds since the Unix epoch when the composition completed
+    pub timestamp_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Hex FNV-1a hash of the request's JSON with its keys sorted
+    pub request_hash: String,
+    /// Bytes of the user prompt
+    pub user_prompt_bytes: usize,
+    pub applied_modules: Vec<String>,
+    /// Versions of the prompt files composed, for files that declare one
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub content_versions: BTreeMap<String, String>,
+    pub selection_seed: u64,
+    /// The response's `content_hash`
+    pub prompt_hash: String,
+    /// Bytes of the system prompt
+    pub prompt_bytes: usize,
+    /// Version of prompt-composer that composed the prompt
+    pub version: String,
+}
+
+impl AuditRecord {
+    pub fn new(request: &PromptRequest, response: &PromptResponse) -> Self {
+        // Going through a value sorts the keys, so equal requests hash equally
+        let request_json = serde_json::to_value(request).map(|value| value.to_string()).unwrap_or_default();
+        let timestamp_ms = crate::SystemTime::now()
+            .duration_since(crate::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        AuditRecord {
+            timestamp_ms,
+            trace_id: request.trace_id.clone(),
+            session_id: request.session_state.as_ref().and_then(|state| state.session_id.clone()),
+            request_hash: format!("{:016x}", fnv1a(request_json.as_bytes())),
+            user_prompt_bytes: request.user_prompt.len(),
+            applied_modules: response.applied_modules.clone(),
+            content_versions: response.content_versions.clone(),
+            selection_seed: response.selection_seed,
+            prompt_hash: response.metadata.content_hash.clone(),
+            prompt_bytes: response.system_prompt.len(),
+            version: response.metadata.version.clone(),
+        }
+    }
+}
+
+type AuditCallback = Box<dyn Fn(&AuditRecord) + Send + Sync>;
+
+enum AuditSink {
+    /// One JSON record per line
+    File(Mutex<std::fs::File>),
+    Callback(AuditCallback),
+}
+
+/// A hook writing an [`AuditRecord`] for every successful composition, added like any
+/// other hook under the name `audit`
+pub struct AuditLog {
+    sink: AuditSink,
+}
+
+impl AuditLog {
+    /// Append records to a JSON Lines file, creating it if needed
+    pub fn to_file(path: impl AsRef<Path>) -> Result<Self, PromptError> {
+        let path = path.as_ref();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| PromptError::config(format!("Failed to open audit log: {}", e)).with_path(path))?;
+        Ok(Self { sink: AuditSink::File(Mutex::new(file)) })
+    }
+
+    /// Pass records to `callback`, for hosts storing them elsewhere
+    pub fn with_callback(callback: impl Fn(&AuditRecord) + Send + Sync + 'static) -> Self {
+        Self { sink: AuditSink::Callback(Box::new(callback)) }
+    }
+
+    fn record(&self, record: &AuditRecord) {
+        match &self.sink {
+            AuditSink::File(file) => {
+                let written = serde_json::to_string(record)
+                    .map_err(std::io::Error::from)
+                    .and_then(|line| writeln!(crate::lock(file), "{}", line));
+                if let Err(e) = written {
+                    tracing::warn!(trace_id = record.trace_id.as_deref(), "Failed to write audit record: {}", e);
+                }
+            }
+            AuditSink::Callback(callback) => callback(record),
+        }
+    }
+}
+
+impl CompositionHook for AuditLog {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    fn on_compose(&self, request: &PromptRequest, response: &PromptResponse) {
+        self.record(&AuditRecord::new(request, response));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_audit_log_records_each_composition() {
+        let path = std::env::temp_dir().join(format!("prompt-composer-audit-{}.jsonl", std::process::id()));
+        let mut composer = PromptComposer::embedded();
+        composer.hooks_mut().add(Arc::new(AuditLog::to_file(&path).unwrap()));
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement the migration")
+            .trace_id("trace-1")
+            .build()
+            .unwrap();
+        let first = composer.compose(&request, &[]).unwrap();
+        composer.compose(&PromptRequest { selection_seed: Some(first.selection_seed), ..request }, &[]).unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records: Vec<AuditRecord> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].trace_id.as_deref(), Some("trace-1"));
+        assert_eq!(records[0].applied_modules, first.applied_modules);
+        assert_eq!(records[0].prompt_hash, first.metadata.content_hash);
+        assert_eq!(records[0].prompt_bytes, first.system_prompt.len());
+        assert_eq!(records[0].prompt_hash, records[1].prompt_hash);
+        assert_ne!(records[0].request_hash, records[1].request_hash);
+    }
+}
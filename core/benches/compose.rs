@@ -0,0 +1,64 @@
+//! Composition on every conversation turn, as hosts run it: a composer kept across turns
+//! with its prompt files cached, and one created per turn.
+//!
+//! Run with `cargo bench --bench compose`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use prompt_composer::composition::PromptComposer;
+use prompt_composer::discovery::ToolDiscovery;
+use prompt_composer::loader::PromptLoader;
+use prompt_composer::types::{McpServer, PromptRequest, SessionState};
+
+fn request() -> PromptRequest {
+    PromptRequest::builder()
+        .user_prompt("Refactor the parser module and run the tests")
+        .mcp_server("filesystem", McpServer {
+            name: "filesystem".to_string(),
+            command: "npx".to_string(),
+            args: vec!["@modelcontextprotocol/server-filesystem".to_string()],
+            env: None,
+        })
+        .session_state(SessionState {
+            tool_call_count: Some(4),
+            original_task: Some("Refactor the parser module".to_string()),
+            ..Default::default()
+        })
+        .selection_seed(7)
+        .build()
+        .unwrap()
+}
+
+fn compose(c: &mut Criterion) {
+    let request = request();
+    let tools = ToolDiscovery::embedded().infer_tools_from_config_immediate(&request.mcp_config);
+
+    let mut composer = PromptComposer::embedded();
+    composer.set_offline(true);
+    c.bench_function("compose/warm", |b| {
+        b.iter(|| composer.compose(black_box(&request), black_box(&tools)).unwrap())
+    });
+
+    c.bench_function("compose/cold", |b| {
+        b.iter(|| {
+            let mut composer = PromptComposer::embedded();
+            composer.set_offline(true);
+            composer.compose(black_box(&request), black_box(&tools)).unwrap()
+        })
+    });
+}
+
+fn load(c: &mut Criterion) {
+    let mut loader = PromptLoader::embedded();
+    loader.load_behavior("planning").unwrap();
+    c.bench_function("loader/cached", |b| {
+        b.iter(|| loader.load_behavior(black_box("planning")).unwrap())
+    });
+
+    let content = loader.load_behavior("planning").unwrap();
+    c.bench_function("loader/extract_guidance", |b| {
+        b.iter(|| loader.extract_guidance(black_box(&content)))
+    });
+}
+
+criterion_group!(benches, compose, load);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+//! Runs the composer as a long-running JSON-RPC daemon on stdio:
+//! `prompt-composer-daemon [--prompts-dir DIR] [--audit-log FILE]`
+
+fn main() {
+    let _logging = prompt_composer::init_logging();
+    let mut prompts_dir = None;
+    let mut audit_log = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--prompts-dir", Some(value)) => prompts_dir = Some(value),
+            ("--audit-log", Some(value)) => audit_log = Some(value),
+            _ => exit("Usage: prompt-composer-daemon [--prompts-dir DIR] [--audit-log FILE]"),
+        }
+    }
+    if let Some(path) = audit_log {
+        let audit = prompt_composer::audit::AuditLog::to_file(&path).unwrap_or_else(|e| exit(&e.to_string()));
+        prompt_composer::register_hook(std::sync::Arc::new(audit));
+    }
+
+    if let Err(e) = prompt_composer::daemon::serve_stdio(prompts_dir) {
+        exit(&e.to_string());
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2)
+}
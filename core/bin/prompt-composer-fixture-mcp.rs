@@ -0,0 +1,17 @@
+//! Runs a fixture MCP server on stdio for integration tests:
+//! `prompt-composer-fixture-mcp [--name NAME] [--tool NAME[=DESCRIPTION]]... [--tools FILE]`
+
+use prompt_composer::mcp_fixture::FixtureMcpServer;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let server = FixtureMcpServer::from_args(&args).unwrap_or_else(|message| exit(&message));
+    if let Err(e) = server.serve_stdio() {
+        exit(&e.to_string());
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2)
+}
@@ -0,0 +1,36 @@
+//! Runs the composer as an HTTP service: `prompt-composer-server [--addr 127.0.0.1:8080]
+//! [--prompts-dir DIR] [--audit-log FILE]`
+
+use std::net::SocketAddr;
+
+#[tokio::main]
+async fn main() {
+    let _logging = prompt_composer::init_logging();
+    let mut addr: SocketAddr = ([127, 0, 0, 1], 8080).into();
+    let mut prompts_dir = None;
+    let mut audit_log = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--addr", Some(value)) => addr = value.parse().unwrap_or_else(|e| exit(&format!("Invalid --addr {}: {}", value, e))),
+            ("--prompts-dir", Some(value)) => prompts_dir = Some(value),
+            ("--audit-log", Some(value)) => audit_log = Some(value),
+            _ => exit("Usage: prompt-composer-server [--addr HOST:PORT] [--prompts-dir DIR] [--audit-log FILE]"),
+        }
+    }
+    if let Some(path) = audit_log {
+        let audit = prompt_composer::audit::AuditLog::to_file(&path).unwrap_or_else(|e| exit(&e.to_string()));
+        prompt_composer::register_hook(std::sync::Arc::new(audit));
+    }
+
+    eprintln!("Serving prompt composition over HTTP on http://{}", addr);
+    if let Err(e) = prompt_composer::http::serve(addr, prompts_dir).await {
+        exit(&e.to_string());
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2)
+}
@@ -0,0 +1,9 @@
+//! Command-line tools for prompt authors: `prompt-composer <command> [options]`
+
+fn main() {
+    let logging = prompt_composer::init_logging();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let status = prompt_composer::cli::run(&args, &mut std::io::stdout().lock());
+    drop(logging);
+    std::process::exit(status)
+}
@@ -0,0 +1,4 @@
+//! Generates the Kotlin bindings of the `kotlin` feature; see `core/kotlin.rs`
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
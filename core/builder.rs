@@ -0,0 +1,440 @@
+use crate::types::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Longest free-text session field accepted, in characters
+pub const MAX_SESSION_TEXT_LENGTH: usize = 10_000;
+
+/// Longest trace ID accepted, in characters
+pub const MAX_TRACE_ID_LENGTH: usize = 256;
+
+impl SessionState {
+    /// Start building a session state with fluent setters
+    pub fn builder() -> SessionStateBuilder {
+        SessionStateBuilder::default()
+    }
+
+    /// Check the state for values no host should send, naming the offending field
+    pub fn validate(&self) -> Result<(), PromptError> {
+        let texts = [
+            ("session_id", &self.session_id),
+            ("original_task", &self.original_task),
+            ("last_action", &self.last_action),
+            ("current_step", &self.current_step),
+        ];
+        for (field, text) in texts {
+            if let Some(text) = text {
+                let length = text.chars().count();
+                if length > MAX_SESSION_TEXT_LENGTH {
+                    return Err(invalid(field, format!(
+                        "{} is {} characters long; the limit is {}", field, length, MAX_SESSION_TEXT_LENGTH
+                    )));
+                }
+            }
+        }
+
+        if self.session_id.as_deref().is_some_and(|id| id.trim().is_empty()) {
+            return Err(invalid("session_id", "session_id must not be empty".to_string()));
+        }
+        if let (Some(failures), Some(calls)) = (self.consecutive_failures, self.tool_call_count) {
+            if failures > calls {
+                return Err(invalid("consecutive_failures", format!(
+                    "consecutive_failures ({}) exceeds tool_call_count ({})", failures, calls
+                )));
+            }
+        }
+        if self.files_modified.iter().flatten().any(|file| file.trim().is_empty()) {
+            return Err(invalid("files_modified", "files_modified contains an empty path".to_string()));
+        }
+        if self.last_tool_results.iter().flatten().any(|result| result.tool.trim().is_empty()) {
+            return Err(invalid("last_tool_results", "last_tool_results contains a result without a tool name".to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(field: &str, message: String) -> PromptError {
+    PromptError::invalid_request(format!("session_state: {}", message)).with_field(format!("session_state.{}", field))
+}
+
+/// Fluent construction of a [`SessionState`], validated on `build`
+#[derive(Debug, Clone, Default)]
+pub struct SessionStateBuilder {
+    state: SessionState,
+}
+
+impl SessionStateBuilder {
+    pub fn session_id(mut self, session_id: impl Into<String>) -> Self {
+        self.state.session_id = Some(session_id.into());
+        self
+    }
+
+    pub fn tool_call_count(mut self, count: u32) -> Self {
+        self.state.tool_call_count = Some(count);
+        self
+    }
+
+    pub fn original_task(mut self, task: impl Into<String>) -> Self {
+        self.state.original_task = Some(task.into());
+        self
+    }
+
+    pub fn has_plan(mut self, has_plan: bool) -> Self {
+        self.state.has_plan = Some(has_plan);
+        self
+    }
+
+    pub fn last_action(mut self, action: impl Into<String>) -> Self {
+        self.state.last_action = Some(action.into());
+        self
+    }
+
+    pub fn current_step(mut self, step: impl Into<String>) -> Self {
+        self.state.current_step = Some(step.into());
+        self
+    }
+
+    /// Add a recent tool error
+    pub fn recent_error(mut self, error: impl Into<ToolError>) -> Self {
+        self.state.recent_errors.get_or_insert_with(Vec::new).push(error.into());
+        self
+    }
+
+    /// Add a file modified earlier in the session
+    pub fn file_modified(mut self, path: impl Into<String>) -> Self {
+        self.state.files_modified.get_or_insert_with(Vec::new).push(path.into());
+        self
+    }
+
+    /// Add the outcome of a recent tool call
+    pub fn tool_result(mut self, result: ToolResultSummary) -> Self {
+        self.state.last_tool_results.get_or_insert_with(Vec::new).push(result);
+        self
+    }
+
+    pub fn consecutive_failures(mut self, failures: u32) -> Self {
+        self.state.consecutive_failures = Some(failures);
+        self
+    }
+
+    pub fn is_long_running(mut self, is_long_running: bool) -> Self {
+        self.state.is_long_running = Some(is_long_running);
+        self
+    }
+
+    /// Add a remembered fact or preference
+    pub fn memory(mut self, memory: impl Into<String>) -> Self {
+        self.state.memories.get_or_insert_with(Vec::new).push(memory.into());
+        self
+    }
+
+    pub fn agent_role(mut self, role: AgentRole) -> Self {
+        self.state.agent_role = Some(role);
+        self
+    }
+
+    pub fn phase(mut self, phase: ConversationPhase) -> Self {
+        self.state.phase = Some(phase);
+        self
+    }
+
+    /// Add a conversation turn; add them oldest first
+    pub fn recent_message(mut self, message: impl Into<String>) -> Self {
+        self.state.recent_messages.get_or_insert_with(Vec::new).push(message.into());
+        self
+    }
+
+    /// Validate and return the session state
+    pub fn build(self) -> Result<SessionState, PromptError> {
+        self.state.validate()?;
+        Ok(self.state)
+    }
+}
+
+impl PromptRequest {
+    /// Start building a request; everything but the user prompt has a default
+    pub fn builder() -> PromptRequestBuilder {
+        PromptRequestBuilder::default()
+    }
+
+    /// Check the request for values no host should send, naming the offending field
+    pub fn validate(&self) -> Result<(), PromptError> {
+        for (field, hints) in [("domain_hints", &self.domain_hints), ("behavior_hints", &self.behavior_hints)] {
+            if hints.iter().flatten().any(|hint| hint.trim().is_empty()) {
+                return Err(PromptError::invalid_request("hints must not be empty strings").with_field(field));
+            }
+        }
+        if let Some((name, weight)) = self.policy.iter()
+            .flat_map(|policy| policy.weights.iter())
+            .find(|(_, weight)| !weight.is_finite() || **weight < 0.0)
+        {
+            return Err(PromptError::invalid_request(format!(
+                "policy weight for {} must be a non-negative number, got {}", name, weight
+            )).with_field(format!("policy.weights.{}", name)));
+        }
+        if self.max_tokens == Some(0) {
+            return Err(PromptError::invalid_request("max_tokens must be positive").with_field("max_tokens"));
+        }
+        if let Some(locale) = self.locale.as_deref()
+            .filter(|locale| locale.is_empty() || !locale.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        {
+            return Err(PromptError::invalid_request(format!(
+                "locale must be a language tag such as de or pt-BR, got {:?}", locale
+            )).with_field("locale"));
+        }
+        // Trace IDs go into log lines and headers, so they are single printable tokens
+        if let Some(trace_id) = self.trace_id.as_deref()
+            .filter(|id| id.is_empty() || id.len() > MAX_TRACE_ID_LENGTH || !id.chars().all(|c| c.is_ascii_graphic()))
+        {
+            return Err(PromptError::invalid_request(format!(
+                "trace_id must be 1 to {} printable ASCII characters without spaces, got {:?}", MAX_TRACE_ID_LENGTH, trace_id
+            )).with_field("trace_id"));
+        }
+        if let Some(state) = &self.session_state {
+            state.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Fluent construction of a [`PromptRequest`], validated on `build`
+#[derive(Debug, Default)]
+pub struct PromptRequestBuilder {
+    user_prompt: Option<String>,
+    mcp_servers: HashMap<String, McpServer>,
+    session_state: Option<SessionState>,
+    domain_hints: Option<Vec<String>>,
+    behavior_hints: Option<Vec<String>>,
+    task_complexity: Option<RequestedComplexity>,
+    response_format: Option<ResponseFormat>,
+    policy: Option<SelectionPolicy>,
+    selection_seed: Option<u64>,
+    module_versions: Option<HashMap<String, String>>,
+    variant_assignments: Option<HashMap<String, String>>,
+    workspace: Option<WorkspaceContext>,
+    conversation: Option<Vec<Message>>,
+    max_tokens: Option<u32>,
+    locale: Option<String>,
+    force_refresh: Option<bool>,
+    trace_id: Option<String>,
+    profile: Option<bool>,
+    /// First error from a setter that can fail, reported by `build`
+    error: Option<PromptError>,
+}
+
+impl PromptRequestBuilder {
+    pub fn user_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.user_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Use the servers of an MCP configuration, replacing any added before
+    pub fn mcp_config(mut self, config: McpConfig) -> Self {
+        self.mcp_servers = config.mcp_servers;
+        self
+    }
+
+    /// Use the servers of an MCP configuration file; a failure is reported by `build`
+    pub fn mcp_config_from_file(mut self, path: impl AsRef<Path>) -> Self {
+        match McpConfig::from_file(path) {
+            Ok(config) => self.mcp_servers = config.mcp_servers,
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Add an MCP server under the given key
+    pub fn mcp_server(mut self, key: impl Into<String>, server: McpServer) -> Self {
+        self.mcp_servers.insert(key.into(), server);
+        self
+    }
+
+    pub fn session_state(mut self, state: SessionState) -> Self {
+        self.session_state = Some(state);
+        self
+    }
+
+    /// Add a domain hint
+    pub fn domain_hint(mut self, domain: impl Into<String>) -> Self {
+        self.domain_hints.get_or_insert_with(Vec::new).push(domain.into());
+        self
+    }
+
+    /// Add a behavior hint
+    pub fn behavior_hint(mut self, behavior: impl Into<String>) -> Self {
+        self.behavior_hints.get_or_insert_with(Vec::new).push(behavior.into());
+        self
+    }
+
+    /// Request a complexity level, or `RequestedComplexity::Auto` to have it assessed
+    pub fn task_complexity(mut self, complexity: impl Into<RequestedComplexity>) -> Self {
+        self.task_complexity = Some(complexity.into());
+        self
+    }
+
+    pub fn response_format(mut self, format: ResponseFormat) -> Self {
+        self.response_format = Some(format);
+        self
+    }
+
+    pub fn policy(mut self, policy: SelectionPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn selection_seed(mut self, seed: u64) -> Self {
+        self.selection_seed = Some(seed);
+        self
+    }
+
+    /// Pin a prompt file (e.g. `planning`) to a version
+    pub fn module_version(mut self, file: impl Into<String>, version: impl Into<String>) -> Self {
+        self.module_versions.get_or_insert_with(HashMap::new).insert(file.into(), version.into());
+        self
+    }
+
+    /// Compose a module's A/B variant
+    pub fn variant(mut self, module: impl Into<String>, variant: impl Into<String>) -> Self {
+        self.variant_assignments.get_or_insert_with(HashMap::new).insert(module.into(), variant.into());
+        self
+    }
+
+    /// Describe the environment the assistant works in
+    pub fn workspace(mut self, workspace: WorkspaceContext) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Add an earlier conversation turn; add them oldest first
+    pub fn message(mut self, role: MessageRole, content: impl Into<String>) -> Self {
+        self.conversation.get_or_insert_with(Vec::new).push(Message { role, content: content.into() });
+        self
+    }
+
+    /// Limit the system prompt to about this many tokens
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Compose the prompt files of a locale where they exist
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    /// Discover tools and read prompt files again rather than use cached ones
+    pub fn force_refresh(mut self, force_refresh: bool) -> Self {
+        self.force_refresh = Some(force_refresh);
+        self
+    }
+
+    /// Record the host's trace or request ID on the composition's log events and in the
+    /// response metadata
+    pub fn trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Time each module and prompt file load, reporting them in the response
+    pub fn profile(mut self, profile: bool) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Validate and return the request
+    pub fn build(self) -> Result<PromptRequest, PromptError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let user_prompt = self.user_prompt
+            .ok_or_else(|| PromptError::invalid_request("user_prompt is required").with_field("user_prompt"))?;
+
+        let request = PromptRequest {
+            user_prompt,
+            mcp_config: McpConfig { mcp_servers: self.mcp_servers },
+            session_state: self.session_state,
+            domain_hints: self.domain_hints,
+            behavior_hints: self.behavior_hints,
+            task_complexity: self.task_complexity,
+            response_format: self.response_format,
+            policy: self.policy,
+            selection_seed: self.selection_seed,
+            module_versions: self.module_versions,
+            variant_assignments: self.variant_assignments,
+            workspace: self.workspace,
+            conversation: self.conversation,
+            max_tokens: self.max_tokens,
+            locale: self.locale,
+            force_refresh: self.force_refresh,
+            trace_id: self.trace_id,
+            profile: self.profile,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_state_builder_validates() {
+        let state = SessionState::builder()
+            .tool_call_count(3)
+            .has_plan(true)
+            .recent_error("ENOENT")
+            .file_modified("src/lib.rs")
+            .build()
+            .unwrap();
+        assert_eq!(state.tool_call_count, Some(3));
+        assert_eq!(state.recent_errors.unwrap()[0].message, "ENOENT");
+
+        let error = SessionState::builder().tool_call_count(1).consecutive_failures(4).build().unwrap_err();
+        assert!(error.to_string().contains("consecutive_failures (4) exceeds tool_call_count (1)"));
+
+        let error = SessionState::builder().original_task("x".repeat(MAX_SESSION_TEXT_LENGTH + 1)).build().unwrap_err();
+        assert!(error.to_string().contains("original_task is 10001 characters long"));
+    }
+
+    #[test]
+    fn test_prompt_request_builder() {
+        let path = std::env::temp_dir().join(format!("prompt-composer-mcp-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"mcpServers": {"fs": {"command": "npx", "args": ["fs-server"]}, "docs": {"url": "https://docs.example.com/sse"}}, "globalShortcut": ""}"#).unwrap();
+
+        let request = PromptRequest::builder()
+            .user_prompt("Fix the failing test")
+            .mcp_config_from_file(&path)
+            .behavior_hint("planning")
+            .selection_seed(7)
+            .build()
+            .unwrap();
+        assert_eq!(request.mcp_config.mcp_servers["fs"].name, "fs");
+        assert!(!request.mcp_config.mcp_servers.contains_key("docs"));
+        assert_eq!(McpConfig::load(path.to_str().unwrap()).unwrap().mcp_servers.len(), 1);
+        assert_eq!(McpClient::from_name("cursor"), Some(McpClient::Cursor));
+        assert_eq!(McpClient::from_name("claude"), Some(McpClient::ClaudeDesktop));
+        assert_eq!(request.behavior_hints, Some(vec!["planning".to_string()]));
+
+        // VS Code keeps its servers under "servers"
+        std::fs::write(&path, r#"{"servers": {"git": {"type": "stdio", "command": "uvx", "args": ["mcp-server-git"]}}}"#).unwrap();
+        assert_eq!(McpConfig::from_file(&path).unwrap().mcp_servers["git"].command, "uvx");
+        std::fs::remove_file(&path).unwrap();
+
+        let error = PromptRequest::builder().build().unwrap_err();
+        assert!(error.to_string().contains("user_prompt is required"));
+        assert_eq!(error.report().field.as_deref(), Some("user_prompt"));
+
+        let error = PromptRequest::builder().user_prompt("x").trace_id("trace 1").build().unwrap_err();
+        assert_eq!(error.report().field.as_deref(), Some("trace_id"));
+
+        let error = PromptRequest::builder().user_prompt("x").mcp_config_from_file(&path).build().unwrap_err();
+        assert_eq!(error.code(), ErrorCode::ConfigError);
+        let report = serde_json::to_value(&error).unwrap();
+        assert_eq!(report["code"], "ConfigError");
+        assert_eq!(report["path"], path.display().to_string());
+    }
+}
@@ -0,0 +1,254 @@
+//! Precompiled prompt bundles: every prompt file, server pattern and selection rule of a
+//! prompts directory in one file, which a composer reads without walking a directory or
+//! opening a file per prompt. Serverless functions and command-line tools that compose
+//! once per process start from a bundle made at build time with `compile`.
+//!
+//! A bundle is `PCBUNDL1`, a little-endian `u32` file count, an index of four `u32`
+//! values per file (path offset, path length, content offset, content length) sorted
+//! by path, then the paths and contents. Native hosts map the file into memory, and
+//! reads borrow from the mapping.
+
+use crate::discovery::ToolDiscovery;
+use crate::provider::PromptProvider;
+use crate::types::PromptError;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"PCBUNDL1";
+
+/// Size of the magic and file count
+const HEADER_LEN: usize = 12;
+
+/// Size of one index entry
+const ENTRY_LEN: usize = 16;
+
+/// Reported as the prompts directory of loaders that read a bundle held in memory
+pub const BUNDLE_PROMPTS_DIR: &str = "<bundle>";
+
+/// Files of a prompts directory that go into a bundle
+const BUNDLED_EXTENSIONS: &[&str] = &["md", "toml"];
+
+/// Encode files, keyed by their path relative to a prompts directory, as a bundle
+pub fn encode(files: &BTreeMap<String, String>) -> Vec<u8> {
+    let data_start = HEADER_LEN + files.len() * ENTRY_LEN;
+    let data_len: usize = files.iter().map(|(path, content)| path.len() + content.len()).sum();
+    let mut index = Vec::with_capacity(data_start);
+    let mut data = Vec::with_capacity(data_len);
+    index.extend_from_slice(MAGIC);
+    index.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    for (path, content) in files {
+        for part in [path.as_bytes(), content.as_bytes()] {
+            index.extend_from_slice(&((data_start + data.len()) as u32).to_le_bytes());
+            index.extend_from_slice(&(part.len() as u32).to_le_bytes());
+            data.extend_from_slice(part);
+        }
+    }
+    index.extend(data);
+    index
+}
+
+/// Compile the prompt files and configuration of a prompts directory into a bundle
+pub fn compile(prompts_dir: &Path) -> Result<Vec<u8>, PromptError> {
+    let mut files = BTreeMap::new();
+    collect(prompts_dir, prompts_dir, &mut files)?;
+    if files.is_empty() {
+        return Err(PromptError::config(format!("No prompt files found in {}", prompts_dir.display())).with_path(prompts_dir));
+    }
+    Ok(encode(&files))
+}
+
+fn collect(root: &Path, dir: &Path, files: &mut BTreeMap<String, String>) -> Result<(), PromptError> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| PromptError::config(format!("Failed to read {}: {}", dir.display(), e)).with_path(dir))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect(root, &path, files)?;
+            continue;
+        }
+        let bundled = path.extension().and_then(|extension| extension.to_str())
+            .is_some_and(|extension| BUNDLED_EXTENSIONS.contains(&extension));
+        let Some(relative) = path.strip_prefix(root).ok().filter(|_| bundled) else {
+            continue;
+        };
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| PromptError::config(format!("Failed to read {}: {}", path.display(), e)).with_path(&path))?;
+        let relative: Vec<_> = relative.components().map(|part| part.as_os_str().to_string_lossy()).collect();
+        files.insert(relative.join("/"), content);
+    }
+    Ok(())
+}
+
+/// Bytes of a bundle, mapped from its file or held in memory
+enum Bytes {
+    Owned(Vec<u8>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Mapped(memmap2::Mmap),
+}
+
+impl std::ops::Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Owned(bytes) => bytes,
+            #[cfg(not(target_arch = "wasm32"))]
+            Bytes::Mapped(map) => map,
+        }
+    }
+}
+
+/// A compiled bundle, providing its files to a `PromptLoader` or `PromptComposer`
+pub struct PromptBundle {
+    name: String,
+    bytes: Bytes,
+    /// Byte ranges of each file's path and content, sorted by path
+    index: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl PromptBundle {
+    /// Read a bundle held in memory
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, PromptError> {
+        Self::parse(BUNDLE_PROMPTS_DIR.to_string(), Bytes::Owned(bytes))
+    }
+
+    /// Map a bundle file into memory. The file must not change while the bundle is open;
+    /// write new bundles to a new path and rename them into place.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PromptError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| PromptError::config(format!("Failed to open prompt bundle: {}", e)).with_path(path))?;
+        // SAFETY: the mapping is read-only, and callers keep the file unchanged while it
+        // is open, as documented above
+        let map = unsafe { memmap2::Mmap::map(&file) }
+            .map_err(|e| PromptError::config(format!("Failed to map prompt bundle: {}", e)).with_path(path))?;
+        Self::parse(path.display().to_string(), Bytes::Mapped(map))
+            .map_err(|e| e.with_path(path))
+    }
+
+    fn parse(name: String, bytes: Bytes) -> Result<Self, PromptError> {
+        let invalid = || PromptError::config("Not a prompt bundle, or a damaged one");
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid());
+        }
+        let word = |at: usize| -> Result<usize, PromptError> {
+            let word: [u8; 4] = bytes.get(at..at.saturating_add(4))
+                .and_then(|word| word.try_into().ok())
+                .ok_or_else(invalid)?;
+            Ok(u32::from_le_bytes(word) as usize)
+        };
+        let range = |at: usize| -> Result<Range<usize>, PromptError> {
+            let (start, len) = (word(at)?, word(at.saturating_add(4))?);
+            let end = start.checked_add(len).filter(|end| *end <= bytes.len()).ok_or_else(invalid)?;
+            Ok(start..end)
+        };
+        let count = word(MAGIC.len())?;
+        let mut index = Vec::with_capacity(count.min(bytes.len() / ENTRY_LEN));
+        for entry in 0..count {
+            let at = entry.checked_mul(ENTRY_LEN).and_then(|at| at.checked_add(HEADER_LEN)).ok_or_else(invalid)?;
+            index.push((range(at)?, range(at.saturating_add(8))?));
+        }
+        if index.windows(2).any(|pair| bytes[pair[0].0.clone()] >= bytes[pair[1].0.clone()]) {
+            return Err(invalid());
+        }
+        Ok(Self { name, bytes, index })
+    }
+
+    /// Number of files in the bundle
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Tool discovery matching servers with the bundle's `server_patterns.toml`, or the
+    /// embedded patterns when the bundle has none
+    pub fn tool_discovery(&self) -> Result<ToolDiscovery, PromptError> {
+        match self.read("server_patterns.toml") {
+            Some(patterns) => ToolDiscovery::with_server_patterns(&patterns),
+            None => Ok(ToolDiscovery::embedded()),
+        }
+    }
+
+    fn text(&self, range: &Range<usize>) -> Option<&str> {
+        std::str::from_utf8(&self.bytes[range.clone()]).ok()
+    }
+}
+
+impl PromptProvider for PromptBundle {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self, path: &str) -> Option<Cow<'_, str>> {
+        let found = self.index.binary_search_by(|(name, _)| self.bytes[name.clone()].cmp(path.as_bytes())).ok()?;
+        self.text(&self.index[found].1).map(Cow::Borrowed)
+    }
+
+    fn paths(&self) -> Vec<String> {
+        self.index.iter().filter_map(|(name, _)| self.text(name).map(str::to_string)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+    use crate::types::PromptRequest;
+    use proptest::prelude::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_bundle_composes_like_its_prompts() {
+        let files: BTreeMap<String, String> = crate::embedded::FILES.iter()
+            .map(|(path, content)| (path.to_string(), content.to_string()))
+            .collect();
+        let path = std::env::temp_dir().join(format!("prompt-composer-{}.bundle", std::process::id()));
+        std::fs::write(&path, encode(&files)).unwrap();
+        let bundle = PromptBundle::open(&path).unwrap();
+        assert_eq!(bundle.len(), files.len());
+        assert_eq!(bundle.read("behaviors/planning.md").as_deref(), files.get("behaviors/planning.md").map(String::as_str));
+        assert!(bundle.read("behaviors/missing.md").is_none());
+
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement the migration, then run the tests")
+            .selection_seed(3)
+            .build()
+            .unwrap();
+        let expected = PromptComposer::embedded().compose(&request, &[]).unwrap();
+        let composed = PromptComposer::with_provider(Arc::new(bundle)).compose(&request, &[]).unwrap();
+        assert_eq!(composed.system_prompt, expected.system_prompt);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(PromptBundle::from_bytes(b"PCBUNDL1\x01\0\0\0".to_vec()).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_damaged_bundles_are_rejected_without_panicking(
+            damage in proptest::collection::vec((any::<usize>(), any::<u8>()), 0..8),
+            cut in any::<usize>(),
+        ) {
+            let files = BTreeMap::from([
+                ("behaviors/planning.md".to_string(), "# Planning".to_string()),
+                ("tools/git.md".to_string(), "# Git".to_string()),
+            ]);
+            let mut bytes = encode(&files);
+            for (at, byte) in damage {
+                let len = bytes.len();
+                bytes[at % len] = byte;
+            }
+            bytes.truncate(cut % (bytes.len() + 1));
+            if let Ok(bundle) = PromptBundle::from_bytes(bytes) {
+                for path in bundle.paths().iter().chain(files.keys()) {
+                    let _ = bundle.read(path);
+                }
+            }
+        }
+    }
+}
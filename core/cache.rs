@@ -0,0 +1,192 @@
+//! Size and age limits of the tool discovery and prompt file caches. Each cache holds its
+//! entries in [`Entries`], which drops entries past the policy's limit as new ones are
+//! stored and reports entries past its time to live as stale.
+
+use crate::types::{CacheCounters, CacheUsage};
+use crate::Instant;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Which entry a full cache drops to make room for a new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Eviction {
+    /// The entry used longest ago
+    #[default]
+    LeastRecentlyUsed,
+    /// The entry stored longest ago, however recently it was used
+    OldestFirst,
+}
+
+/// How long a cache uses its entries and how many it holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Age after which an entry is discovered or read again; `None` keeps entries until
+    /// the cache is cleared
+    pub ttl: Option<Duration>,
+    /// Entries held at most; `None` for no limit
+    pub max_entries: Option<usize>,
+    pub eviction: Eviction,
+}
+
+impl CachePolicy {
+    /// Entries kept until the cache is cleared, without a limit
+    pub const UNBOUNDED: CachePolicy = CachePolicy { ttl: None, max_entries: None, eviction: Eviction::LeastRecentlyUsed };
+
+    /// The policy of the tool discovery cache unless configured: tools are discovered
+    /// again after five minutes
+    pub const TOOLS: CachePolicy = CachePolicy { ttl: Some(Duration::from_secs(300)), ..CachePolicy::UNBOUNDED };
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn with_eviction(mut self, eviction: Eviction) -> Self {
+        self.eviction = eviction;
+        self
+    }
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy::UNBOUNDED
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    stored: Instant,
+    /// Ticks of the entry's storing and of its last use; the last use is atomic so entries
+    /// can be marked used under a read lock
+    inserted: u64,
+    used: AtomicU64,
+}
+
+/// Cache entries under a [`CachePolicy`]
+pub(crate) struct Entries<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    policy: CachePolicy,
+    /// Source of the ticks entries are stored and used at
+    clock: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V> Entries<K, V> {
+    pub(crate) fn new(policy: CachePolicy) -> Self {
+        Self { entries: HashMap::new(), policy, clock: AtomicU64::new(0) }
+    }
+
+    pub(crate) fn policy(&self) -> CachePolicy {
+        self.policy
+    }
+
+    /// Apply a new policy, dropping entries past its limit
+    pub(crate) fn set_policy(&mut self, policy: CachePolicy) {
+        self.policy = policy;
+        self.evict(0);
+    }
+
+    /// The entry if it is younger than the time to live, marked as used
+    pub(crate) fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        let entry = self.entries.get(key)?;
+        if self.policy.ttl.is_some_and(|ttl| entry.stored.elapsed() > ttl) {
+            return None;
+        }
+        entry.used.store(self.tick(), Ordering::Relaxed);
+        Some(&entry.value)
+    }
+
+    /// The entry however old it is, for callers falling back to stale values
+    pub(crate) fn get_stale<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    /// Store an entry, first dropping entries to stay within the limit
+    pub(crate) fn insert(&mut self, key: K, value: V) {
+        if !self.entries.contains_key(&key) {
+            self.evict(1);
+        }
+        let tick = self.tick();
+        let entry = Entry { value, stored: Instant::now(), inserted: tick, used: AtomicU64::new(tick) };
+        self.entries.insert(key, entry);
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|entry| &entry.value)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Entry count and approximate bytes, with `size` estimating each entry
+    pub(crate) fn usage(&self, counters: CacheCounters, size: impl Fn(&K, &V) -> usize) -> CacheUsage {
+        let bytes = self.entries.iter().map(|(key, entry)| size(key, &entry.value)).sum();
+        CacheUsage::new(self.entries.len(), bytes, counters)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Drop entries until `room` more fit within the limit
+    fn evict(&mut self, room: usize) {
+        let Some(max_entries) = self.policy.max_entries else {
+            return;
+        };
+        while !self.entries.is_empty() && self.entries.len() + room > max_entries {
+            let victim = match self.policy.eviction {
+                Eviction::LeastRecentlyUsed => self.entries.iter().min_by_key(|(_, entry)| entry.used.load(Ordering::Relaxed)),
+                Eviction::OldestFirst => self.entries.iter().min_by_key(|(_, entry)| entry.inserted),
+            };
+            let Some(victim) = victim.map(|(key, _)| key.clone()) else {
+                break;
+            };
+            self.entries.remove(&victim);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_cache_drops_by_its_eviction() {
+        let mut recent = Entries::new(CachePolicy::UNBOUNDED.with_max_entries(2));
+        recent.insert("a", 1);
+        recent.insert("b", 2);
+        assert_eq!(recent.get("a"), Some(&1));
+        recent.insert("c", 3);
+        assert_eq!((recent.get("a"), recent.get("b"), recent.len()), (Some(&1), None, 2));
+
+        let mut oldest = Entries::new(CachePolicy::UNBOUNDED.with_max_entries(2).with_eviction(Eviction::OldestFirst));
+        oldest.insert("a", 1);
+        oldest.insert("b", 2);
+        assert_eq!(oldest.get("a"), Some(&1));
+        oldest.insert("c", 3);
+        assert_eq!((oldest.get("a"), oldest.get("b")), (None, Some(&2)));
+
+        let mut expiring = Entries::new(CachePolicy::UNBOUNDED.with_ttl(Duration::ZERO));
+        expiring.insert("a", 1);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!((expiring.get("a"), expiring.get_stale("a")), (None, Some(&1)));
+    }
+}
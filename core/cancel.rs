@@ -0,0 +1,63 @@
+//! Cancellation of a composition in progress. Hosts that compose on every message cancel
+//! the composition of the previous one when a new message arrives.
+//!
+//! Cancellation is cooperative and checked between steps: discovery stops before the next
+//! server, composition before the next module, and the process of a server being listed
+//! or of a running external module is killed. Other steps already started run to their
+//! end. Only discovery that lists servers waits on them, each up to its listing timeout;
+//! discovery that infers tools from the server patterns starts nothing.
+//!
+//! The async composition functions do their work on their first poll and never yield, so
+//! dropping their future cancels nothing. Hosts run them on a blocking thread and cancel
+//! the token from another, e.g. with a [`CancellationToken::cancel_on_drop`] guard held
+//! by the task awaiting the result.
+
+use crate::types::PromptError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared by the host and the compositions it started. Clones share the flag, so
+/// a host keeps one clone and passes another to the composition.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every composition holding a clone of this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `PromptError::Cancelled` once the token is cancelled
+    pub fn check(&self) -> Result<(), PromptError> {
+        match self.is_cancelled() {
+            true => Err(PromptError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// A guard cancelling the token when dropped, so work started for a future stops
+    /// when the future is dropped
+    pub fn cancel_on_drop(self) -> CancelOnDrop {
+        CancelOnDrop(self)
+    }
+}
+
+/// Cancels its token when dropped, see [`CancellationToken::cancel_on_drop`]
+#[derive(Debug)]
+pub struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
@@ -0,0 +1,253 @@
+//! C bindings, built with the `capi` feature, for hosts such as Go, C# and Java that load
+//! the library directly. `include/prompt_composer.h` declares them; cbindgen generates it
+//! from this file, and `./generate-header.sh` copies it there after the C API changes.
+//!
+//! Requests and responses cross the boundary as NUL-terminated UTF-8 JSON, the same JSON
+//! the Python and Node.js bindings accept and return. Strings the library returns are
+//! owned by the caller and released with `pc_string_free`. Functions that fail return
+//! `NULL` and record the error for `pc_last_error` on the calling thread.
+
+use crate::types::{PromptError, PromptRequest, PromptResponse};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use serde::Serialize;
+
+thread_local! {
+    /// Error report of the last failed call on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run a call, returning its JSON as an owned C string, or `NULL` after recording its error
+fn json_result<T: Serialize>(call: impl FnOnce() -> Result<T, PromptError>) -> *mut c_char {
+    let json = call().and_then(|value| serde_json::to_string(&value).map_err(PromptError::from));
+    match json {
+        Ok(json) => {
+            LAST_ERROR.with(|last| last.borrow_mut().take());
+            into_c_string(json)
+        }
+        Err(e) => {
+            let report = serde_json::to_string(&e.report()).unwrap_or_else(|_| e.to_string());
+            LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(report).ok());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// JSON never contains NUL bytes, since they are escaped within strings
+fn into_c_string(text: String) -> *mut c_char {
+    CString::new(text).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Borrow a required string argument
+///
+/// # Safety
+/// `text` is `NULL` or points to a NUL-terminated string that outlives the call.
+unsafe fn required<'a>(text: *const c_char, argument: &str) -> Result<&'a str, PromptError> {
+    optional(text, argument)?.ok_or_else(|| PromptError::invalid_request(format!("{} is NULL", argument)).with_field(argument))
+}
+
+/// Borrow a string argument that may be `NULL`
+///
+/// # Safety
+/// `text` is `NULL` or points to a NUL-terminated string that outlives the call.
+unsafe fn optional<'a>(text: *const c_char, argument: &str) -> Result<Option<&'a str>, PromptError> {
+    if text.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(text).to_str()
+        .map(Some)
+        .map_err(|_| PromptError::invalid_request(format!("{} is not valid UTF-8", argument)).with_field(argument))
+}
+
+/// # Safety
+/// As for [`required`].
+unsafe fn request(request_json: *const c_char) -> Result<PromptRequest, PromptError> {
+    serde_json::from_str(required(request_json, "request_json")?)
+        .map_err(|e| PromptError::invalid_request(e.to_string()))
+}
+
+/// # Safety
+/// As for [`optional`].
+unsafe fn prompts_dir(prompts_dir: *const c_char) -> Result<Option<String>, PromptError> {
+    Ok(optional(prompts_dir, "prompts_dir")?.map(str::to_string))
+}
+
+/// Compose a system prompt with the tools of the request's MCP servers, inferred from the
+/// server patterns of the prompts directory without starting the servers and kept in the
+/// shared tool cache. `prompts_dir` may be `NULL` for the default directory.
+///
+/// # Safety
+/// Arguments are `NULL` or NUL-terminated strings; `request_json` must not be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn pc_compose_system_prompt(request_json: *const c_char, prompts_dir: *const c_char) -> *mut c_char {
+    json_result(|| {
+        let request = request(request_json)?;
+        let prompts_dir = self::prompts_dir(prompts_dir)?;
+        let runtime = crate::runtime()
+            .map_err(|e| PromptError::config(format!("Failed to create async runtime: {}", e)))?;
+        runtime.block_on(crate::compose_system_prompt_with_prompts_dir(request, prompts_dir))
+    })
+}
+
+/// Compose a system prompt from cached tools, or tools inferred from the MCP config,
+/// without waiting for any server
+///
+/// # Safety
+/// As for [`pc_compose_system_prompt`].
+#[no_mangle]
+pub unsafe extern "C" fn pc_compose_system_prompt_cached(request_json: *const c_char, prompts_dir: *const c_char) -> *mut c_char {
+    json_result(|| crate::compose_system_prompt_cached_with_prompts_dir(request(request_json)?, self::prompts_dir(prompts_dir)?))
+}
+
+/// Compose a system prompt without starting a process or contacting the network. With a
+/// `NULL` `prompts_dir` the prompts compiled into the library are used.
+///
+/// # Safety
+/// As for [`pc_compose_system_prompt`].
+#[no_mangle]
+pub unsafe extern "C" fn pc_compose_system_prompt_offline(request_json: *const c_char, prompts_dir: *const c_char) -> *mut c_char {
+    json_result(|| crate::compose_system_prompt_offline(request(request_json)?, self::prompts_dir(prompts_dir)?))
+}
+
+/// The modules a request would get, as a JSON array of decisions, without composing it
+///
+/// # Safety
+/// As for [`pc_compose_system_prompt`].
+#[no_mangle]
+pub unsafe extern "C" fn pc_explain_selection(request_json: *const c_char, prompts_dir: *const c_char) -> *mut c_char {
+    json_result(|| crate::explain_selection(&request(request_json)?, self::prompts_dir(prompts_dir)?))
+}
+
+/// Infer the tools of one server of an MCP config again from the server patterns,
+/// replacing its cached tools, and return them as a JSON array
+///
+/// # Safety
+/// Both arguments are NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn pc_refresh_server_tools(server_name: *const c_char, mcp_config_json: *const c_char) -> *mut c_char {
+    json_result(|| {
+        let server_name = required(server_name, "server_name")?;
+        let mcp_config = serde_json::from_str(required(mcp_config_json, "mcp_config_json")?)
+            .map_err(|e| PromptError::invalid_request(e.to_string()).with_field("mcp_config"))?;
+        let runtime = crate::runtime()
+            .map_err(|e| PromptError::config(format!("Failed to create async runtime: {}", e)))?;
+        runtime.block_on(crate::refresh_server_tools(server_name, &mcp_config))
+    })
+}
+
+/// Registered modules as a JSON array of name, kind, source and enabled state
+#[no_mangle]
+pub extern "C" fn pc_list_modules() -> *mut c_char {
+    json_result(|| Ok(crate::list_modules()))
+}
+
+/// Enable or disable a registered module, returning whether it exists
+///
+/// # Safety
+/// `name` is a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn pc_set_module_enabled(name: *const c_char, enabled: bool) -> bool {
+    optional(name, "name").ok().flatten().is_some_and(|name| crate::set_module_enabled(name, enabled))
+}
+
+/// End a session, calling `on_session_end` on its modules; returns whether it existed
+///
+/// # Safety
+/// `session_id` is a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn pc_end_session(session_id: *const c_char) -> bool {
+    optional(session_id, "session_id").ok().flatten().is_some_and(crate::end_session)
+}
+
+/// JSON schema of composition requests
+#[no_mangle]
+pub extern "C" fn pc_request_schema() -> *mut c_char {
+    into_c_string(PromptRequest::json_schema().to_string())
+}
+
+/// JSON schema of composition responses
+#[no_mangle]
+pub extern "C" fn pc_response_schema() -> *mut c_char {
+    into_c_string(PromptResponse::json_schema().to_string())
+}
+
+/// Version of the library, a static string the caller must not free
+#[no_mangle]
+pub extern "C" fn pc_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Error report of the last call on this thread that returned `NULL`, as JSON with
+/// `code`, `message` and whatever server, module, path or field it names; `NULL` when
+/// the last call succeeded
+#[no_mangle]
+pub extern "C" fn pc_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null_mut(), |report| report.clone().into_raw()))
+}
+
+/// Release a string returned by the library
+///
+/// # Safety
+/// `text` is `NULL` or a string returned by a `pc_` function other than `pc_version`,
+/// and is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn pc_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Take ownership of a returned string
+    fn take(text: *mut c_char) -> Option<String> {
+        if text.is_null() {
+            return None;
+        }
+        let owned = unsafe { CStr::from_ptr(text) }.to_string_lossy().into_owned();
+        unsafe { pc_string_free(text) };
+        Some(owned)
+    }
+
+    #[test]
+    fn test_c_functions_exchange_json() {
+        let request = CString::new(r#"{"user_prompt": "Plan a refactor", "mcp_config": {"mcpServers": {}}}"#).unwrap();
+        let response = take(unsafe { pc_compose_system_prompt_offline(request.as_ptr(), std::ptr::null()) }).unwrap();
+        let response: PromptResponse = serde_json::from_str(&response).unwrap();
+        assert!(!response.system_prompt.is_empty());
+        assert!(take(pc_last_error()).is_none());
+
+        let decisions = take(unsafe { pc_explain_selection(request.as_ptr(), std::ptr::null()) }).unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&decisions).unwrap().is_array());
+
+        let invalid = CString::new(r#"{"mcp_config": {"mcpServers": {}}}"#).unwrap();
+        assert!(take(unsafe { pc_compose_system_prompt_cached(invalid.as_ptr(), std::ptr::null()) }).is_none());
+        let error: serde_json::Value = serde_json::from_str(&take(pc_last_error()).unwrap()).unwrap();
+        assert_eq!(error["code"], "InvalidRequest");
+        assert!(error["message"].as_str().unwrap().contains("user_prompt"));
+
+        assert!(take(unsafe { pc_compose_system_prompt_cached(std::ptr::null(), std::ptr::null()) }).is_none());
+        let error: serde_json::Value = serde_json::from_str(&take(pc_last_error()).unwrap()).unwrap();
+        assert_eq!(error["field"], "request_json");
+
+        let version = unsafe { CStr::from_ptr(pc_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+        assert!(!unsafe { pc_end_session(c"no-such-session".as_ptr()) });
+    }
+
+    #[test]
+    fn test_live_calls_share_one_runtime() {
+        let runtime = crate::runtime().unwrap() as *const _;
+        let request = CString::new(r#"{"user_prompt": "Plan a refactor", "mcp_config": {"mcpServers": {}}}"#).unwrap();
+        let threads: Vec<_> = (0..4).map(|_| {
+            let request = request.clone();
+            std::thread::spawn(move || take(unsafe { pc_compose_system_prompt(request.as_ptr(), std::ptr::null()) }).unwrap())
+        }).collect();
+        for thread in threads {
+            assert!(thread.join().unwrap().contains("system_prompt"));
+        }
+        assert_eq!(crate::runtime().unwrap() as *const _, runtime);
+    }
+}
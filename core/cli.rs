@@ -0,0 +1,823 @@
+//! Command-line interface of the `cli` feature: `prompt-composer <command> [options]`.
+//!
+//! | Command | Does |
+//! |---|---|
+//! | `validate [--prompts-dir DIR]` | checks a prompts directory, exiting 1 on errors |
+//! | `lint [--prompts-dir DIR]` | lints the prompt files of a prompts directory, exiting 1 on errors |
+//! | `coverage --requests PATH [--prompts-dir DIR]` | lists the prompt files and sections no recorded request composed |
+//! | `diff --before REQ --after REQ` | diffs the prompts composed for two requests, exiting 1 when they differ |
+//! | `diff --request REQ --before-dir DIR --after-dir DIR` | diffs the prompts two prompts directories compose |
+//! | `init [DIR] [--force]` | writes the bundled prompts to a new prompts directory |
+//! | `tools --config CONFIG [--live] [--prompts-dir DIR]` | lists each server's tools and the server pattern it matched |
+//! | `bench [--requests PATH] [--iterations N] [--prompts-dir DIR]` | reports composition latency percentiles and cache hit rates |
+//! | `render --request REQ --target TARGET [--prompts-dir DIR]` | prints the prompt and tools as an `openai`, `anthropic` or `ollama` request body |
+//! | `report --request REQ [--format FORMAT] [--prompts-dir DIR]` | prints a `markdown` or `html` report of the composition for prompt reviews |
+//! | `pipe [--live] [--prompts-dir DIR]` | answers each line of requests on stdin with a line of response on stdout |
+//! | `watch --request REQ [--prompts-dir DIR]` | prints the prompt again whenever the request or a prompt file changes |
+//! | `eval --dataset FILE --output FILE [--prompts-dir DIR]` | writes a dataset's composed prompts as evaluator JSONL, exiting 1 when a case fails |
+//! | `compile --output FILE [--prompts-dir DIR]` | compiles a valid prompts directory into a bundle, exiting 1 on errors |
+//!
+//! Without `--prompts-dir`, commands use the default prompts directory: `prompts` or
+//! `../prompts` relative to the working directory. Requests are `PromptRequest` JSON
+//! files whose `mcp_config` may be left out; tools are inferred from the config, so no
+//! MCP server is started.
+
+use crate::cancel::CancellationToken;
+use crate::composition::request_span;
+use crate::coverage::measure_coverage;
+use crate::diff::PromptDiff;
+use crate::eval::{compose_dataset, parse_dataset};
+use crate::discovery::ToolDiscovery;
+use crate::lint::lint_prompts_dir;
+use crate::loader::PromptLoader;
+use crate::render::RenderTarget;
+use crate::report::ReportFormat;
+use crate::types::{DiscoveryMode, McpConfig, PromptError, PromptRequest, PromptResponse, Tool};
+use crate::validate::{validate_prompts_dir, IssueSeverity};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const USAGE: &str = "Usage: prompt-composer <command> [options]
+
+Commands:
+  validate [--prompts-dir DIR]    Check a prompts directory for broken content
+  lint [--prompts-dir DIR]        Check prompt files for dropped headings, contradicting always and never
+                                  directives, long sections, control characters and empty guidance
+  coverage --requests PATH [--prompts-dir DIR]
+                                  List the prompt files and ## sections none of the recorded requests
+                                  composed; PATH is a request file or a directory of them
+  diff [--before REQ --after REQ | --request REQ] [--prompts-dir DIR] [--before-dir DIR] [--after-dir DIR]
+                                  Diff the prompts composed for two requests or prompts directories
+  init [DIR] [--force]            Start a prompts directory (default ./prompts) from the bundled prompts
+  tools --config CONFIG [--live] [--prompts-dir DIR]
+                                  List each MCP server's tools and the server pattern it matched;
+                                  CONFIG is an MCP config file or claude_desktop, cursor or vscode
+  bench [--requests PATH] [--iterations N] [--prompts-dir DIR]
+                                  Compose requests repeatedly and report latency percentiles and cache
+                                  hit rates; PATH is a request file or a directory of them
+  render --request REQ --target openai|anthropic|ollama [--prompts-dir DIR]
+                                  Print the prompt and tools as the provider's request body
+  report --request REQ [--format markdown|html] [--prompts-dir DIR]
+                                  Print a report of the prompt's sections, selection decisions, token
+                                  counts and warnings, for sharing prompt reviews (default markdown)
+  pipe [--live] [--prompts-dir DIR]
+                                  Answer each line of request JSON on stdin with a line of response JSON
+                                  on stdout, or an error report when it fails
+  watch --request REQ [--prompts-dir DIR] [--interval MS]
+                                  Print the prompt again whenever the request or a prompt file changes
+  eval --dataset FILE --output FILE [--prompts-dir DIR]
+                                  Compose each test case of a JSONL dataset and write {\"id\", \"system\",
+                                  \"user\"} JSONL for promptfoo, Inspect and similar evaluators
+  compile --output FILE [--prompts-dir DIR]
+                                  Compile a prompts directory into a bundle composers load at startup";
+
+/// Times `bench` composes each request, unless `--iterations` says otherwise
+const BENCH_ITERATIONS: usize = 100;
+
+/// Requests `bench` composes when given none: a quick question, a multi-step task with
+/// tools, and a long-running session
+const SAMPLE_REQUESTS: &[&str] = &[
+    r#"{"user_prompt": "What does this function return?"}"#,
+    r#"{"user_prompt": "Refactor the parser module and add tests for every public function",
+        "mcp_config": {"mcpServers": {"filesystem": {"command": "npx", "args": ["@modelcontextprotocol/server-filesystem", "."]}}}}"#,
+    r#"{"user_prompt": "Analyze the sales data and summarize the trends",
+        "mcp_config": {"mcpServers": {"data": {"command": "data-server"}, "web": {"command": "web-search"}}},
+        "session_state": {"tool_call_count": 12, "has_plan": true, "consecutive_failures": 2}}"#,
+];
+
+/// How often `watch` looks for changed files, unless `--interval` says otherwise
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exit status of a command that found problems
+pub const EXIT_PROBLEMS: i32 = 1;
+/// Exit status of a command line that could not be understood
+pub const EXIT_USAGE: i32 = 2;
+
+/// Run a command line, without the program name, writing its output to `out`. Returns
+/// the process exit status.
+pub fn run(args: &[String], out: &mut dyn Write) -> i32 {
+    let Some((command, rest)) = args.split_first() else {
+        return usage(USAGE);
+    };
+    let result = match command.as_str() {
+        "validate" => options(rest, &["--prompts-dir"], &[]).and_then(|options| validate(&options, out)),
+        "lint" => options(rest, &["--prompts-dir"], &[]).and_then(|options| lint(&options, out)),
+        "coverage" => options(rest, &["--requests", "--prompts-dir"], &[]).and_then(|options| coverage(&options, out)),
+        "diff" => options(rest, &["--before", "--after", "--request", "--prompts-dir", "--before-dir", "--after-dir"], &[])
+            .and_then(|options| diff(&options, out)),
+        "init" => init(rest, out),
+        "tools" => options(rest, &["--config", "--prompts-dir"], &["--live"]).and_then(|options| tools(&options, out)),
+        "bench" => options(rest, &["--requests", "--iterations", "--prompts-dir"], &[]).and_then(|options| bench(&options, out)),
+        "render" => options(rest, &["--request", "--target", "--prompts-dir"], &[]).and_then(|options| render(&options, out)),
+        "report" => options(rest, &["--request", "--format", "--prompts-dir"], &[]).and_then(|options| report(&options, out)),
+        "pipe" => options(rest, &["--prompts-dir"], &["--live"]).and_then(|options| pipe(&options, std::io::stdin().lock(), out)),
+        "watch" => options(rest, &["--request", "--prompts-dir", "--interval"], &[]).and_then(|options| watch(&options, out)),
+        "eval" => options(rest, &["--dataset", "--output", "--prompts-dir"], &[]).and_then(|options| eval(&options, out)),
+        "compile" => options(rest, &["--output", "--prompts-dir"], &[]).and_then(|options| compile(&options, out)),
+        "help" | "--help" | "-h" => {
+            let _ = writeln!(out, "{}", USAGE);
+            Ok(0)
+        }
+        _ => Err(format!("Unknown command {}\n\n{}", command, USAGE)),
+    };
+    result.unwrap_or_else(|message| usage(&message))
+}
+
+fn usage(message: &str) -> i32 {
+    eprintln!("{}", message);
+    EXIT_USAGE
+}
+
+/// Parse `--name value` options and `--name` switches; switches map to an empty value
+fn options(args: &[String], valued: &[&str], switches: &[&str]) -> Result<HashMap<String, String>, String> {
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if switches.contains(&arg.as_str()) {
+            options.insert(arg.clone(), String::new());
+        } else if valued.contains(&arg.as_str()) {
+            let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+            options.insert(arg.clone(), value.clone());
+        } else {
+            return Err(format!("Unexpected argument {}\n\n{}", arg, USAGE));
+        }
+    }
+    Ok(options)
+}
+
+/// The `--prompts-dir` option, or the directory a composer would read by default
+fn prompts_dir(options: &HashMap<String, String>) -> String {
+    PromptLoader::new(options.get("--prompts-dir").cloned()).prompts_dir().to_string()
+}
+
+fn validate(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let report = validate_prompts_dir(&prompts_dir(options));
+    let write = |out: &mut dyn Write| -> std::io::Result<()> {
+        for issue in &report.issues {
+            let severity = match issue.severity {
+                IssueSeverity::Error => "error",
+                IssueSeverity::Warning => "warning",
+            };
+            writeln!(out, "{}: {}: {}", severity, issue.path, issue.message)?;
+        }
+        let errors = report.issues.iter().filter(|issue| issue.severity == IssueSeverity::Error).count();
+        writeln!(
+            out, "{}: {} files checked, {} errors, {} warnings",
+            report.prompts_dir, report.files_checked, errors, report.issues.len() - errors
+        )
+    };
+    write(out).map_err(|e| e.to_string())?;
+    Ok(if report.is_valid() { 0 } else { EXIT_PROBLEMS })
+}
+
+fn lint(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let prompts_dir = prompts_dir(options);
+    let report = match lint_prompts_dir(&prompts_dir) {
+        Ok(report) => report,
+        Err(e) => {
+            writeln!(out, "error: {}", e).map_err(|e| e.to_string())?;
+            return Ok(EXIT_PROBLEMS);
+        }
+    };
+    let write = |out: &mut dyn Write| -> std::io::Result<()> {
+        for issue in &report.issues {
+            let severity = match issue.severity {
+                IssueSeverity::Error => "error",
+                IssueSeverity::Warning => "warning",
+            };
+            let line = issue.line.map(|line| format!(":{}", line)).unwrap_or_default();
+            writeln!(out, "{}: {}{}: {} [{}]", severity, issue.path, line, issue.message, issue.rule.as_str())?;
+        }
+        let errors = report.issues.iter().filter(|issue| issue.severity == IssueSeverity::Error).count();
+        writeln!(
+            out, "{}: {} files linted, {} errors, {} warnings",
+            prompts_dir, report.files_checked, errors, report.issues.len() - errors
+        )
+    };
+    write(out).map_err(|e| e.to_string())?;
+    Ok(if report.is_valid() { 0 } else { EXIT_PROBLEMS })
+}
+
+fn coverage(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let path = options.get("--requests").ok_or("coverage needs --requests")?;
+    let requests = request_files(path)?.iter()
+        .map(|file| read_request(&file.to_string_lossy()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if requests.is_empty() {
+        return Err("coverage found no requests".to_string());
+    }
+    let prompts_dir = prompts_dir(options);
+    let report = match measure_coverage(&prompts_dir, &requests) {
+        Ok(report) => report,
+        Err(e) => {
+            writeln!(out, "error: {}", e).map_err(|e| e.to_string())?;
+            return Ok(EXIT_PROBLEMS);
+        }
+    };
+    let write = |out: &mut dyn Write| -> std::io::Result<()> {
+        for file in report.unused_files() {
+            writeln!(out, "unused file: {}", file.path)?;
+        }
+        for (file, section) in report.unused_sections() {
+            match section.heading.is_empty() {
+                true => writeln!(out, "unused section: {}:{}: the guidance before the first section", file.path, section.line)?,
+                false => writeln!(out, "unused section: {}:{}: ## {}", file.path, section.line, section.heading)?,
+            }
+        }
+        let sections = report.files.iter().map(|file| file.sections.len()).sum::<usize>();
+        let unused_sections = report.files.iter()
+            .map(|file| file.sections.iter().filter(|section| section.compositions == 0).count())
+            .sum::<usize>();
+        writeln!(
+            out, "{}: {} requests ({} failed), {} of {} files and {} of {} sections never composed",
+            prompts_dir, report.requests, report.failed, report.unused_files().count(), report.files.len(), unused_sections, sections
+        )
+    };
+    write(out).map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+/// Read a request file; `mcp_config` defaults to no servers
+fn read_request(path: &str) -> Result<PromptRequest, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read request {}: {}", path, e))?;
+    parse_request(&content).map_err(|e| format!("Invalid request {}: {}", path, e))
+}
+
+/// Compose with tools inferred from the config and the prompts directory's server
+/// patterns, never starting an MCP server
+fn compose(request: &PromptRequest, prompts_dir: &str) -> Result<PromptResponse, PromptError> {
+    let tools = crate::engine::offline_tools(&request.mcp_config, Some(prompts_dir));
+    crate::global_engine().composer(Some(prompts_dir.to_string()), false).compose(request, &tools)
+}
+
+fn diff(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let before_path = options.get("--before").or(options.get("--request"))
+        .ok_or("diff needs --before and --after requests, or one --request")?;
+    let after_path = options.get("--after").or(options.get("--request"))
+        .ok_or("diff needs --before and --after requests, or one --request")?;
+    let dir = |side: &str| match options.get(side) {
+        Some(dir) => dir.clone(),
+        None => prompts_dir(options),
+    };
+    let (before_dir, after_dir) = (dir("--before-dir"), dir("--after-dir"));
+
+    let before = compose(&read_request(before_path)?, &before_dir).map_err(|e| e.to_string())?;
+    // Bucket variants alike on both sides unless the after request asks otherwise
+    let mut after_request = read_request(after_path)?;
+    after_request.selection_seed.get_or_insert(before.selection_seed);
+    let after = compose(&after_request, &after_dir).map_err(|e| e.to_string())?;
+
+    let label = |path: &str, dir: &str| match before_path == after_path {
+        true => dir.to_string(),
+        false => path.to_string(),
+    };
+    let diff = PromptDiff::between(&before, &after, &label(before_path, &before_dir), &label(after_path, &after_dir));
+    let write = |out: &mut dyn Write| -> std::io::Result<()> {
+        for module in &diff.added_modules {
+            writeln!(out, "+ module {}", module)?;
+        }
+        for module in &diff.removed_modules {
+            writeln!(out, "- module {}", module)?;
+        }
+        if let Some((before, after)) = &diff.complexity {
+            writeln!(out, "~ complexity {:?} -> {:?}", before, after)?;
+        }
+        write!(out, "{}", diff.unified)
+    };
+    write(out).map_err(|e| e.to_string())?;
+    Ok(if diff.is_empty() { 0 } else { EXIT_PROBLEMS })
+}
+
+fn init(args: &[String], out: &mut dyn Write) -> Result<i32, String> {
+    let (switches, dirs): (Vec<&String>, Vec<&String>) = args.iter().partition(|arg| arg.starts_with("--"));
+    if switches.iter().any(|switch| *switch != "--force") || dirs.len() > 1 {
+        return Err(format!("Usage: prompt-composer init [DIR] [--force]\n\n{}", USAGE));
+    }
+    let dir = dirs.first().map_or("prompts", |dir| dir.as_str());
+    let written = crate::embedded::scaffold(Path::new(dir), !switches.is_empty()).map_err(|e| e.to_string())?;
+
+    let write = |out: &mut dyn Write| -> std::io::Result<()> {
+        for name in &written {
+            writeln!(out, "created {}", Path::new(dir).join(name).display())?;
+        }
+        writeln!(out, "{}: {} files written{}", dir, written.len(),
+            if switches.is_empty() { "; existing files are kept unless --force is given" } else { "" })?;
+        writeln!(out, "Edit the markdown under domains/ and behaviors/, add tools/<server>.md for your MCP servers,")?;
+        writeln!(out, "tune selection_rules.toml, and check your changes with: prompt-composer validate --prompts-dir {}", dir)
+    };
+    write(out).map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+fn tools(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let config = McpConfig::load(options.get("--config").ok_or("tools needs a --config")?).map_err(|e| e.to_string())?;
+    let discovery = ToolDiscovery::with_prompts_dir(prompts_dir(options));
+    let discovered = match options.contains_key("--live") {
+        true => Some(discovery.discover_tools_immediate(&config).map_err(|e| e.to_string())?),
+        false => None,
+    };
+
+    let mut written = Ok(());
+    for (name, server) in config.servers_by_name() {
+        let matched = discovery.match_server(name, server).map_err(|e| e.to_string())?;
+        let origin = match (&matched.pattern, &matched.rule) {
+            (Some(pattern), Some(rule)) => format!("{} by {}", pattern, rule),
+            _ => "no server pattern matched; generic fallback".to_string(),
+        };
+        let tools: Vec<&Tool> = match &discovered {
+            Some(discovered) => discovered.iter().filter(|tool| tool.server == *name).collect(),
+            None => matched.tools.iter().collect(),
+        };
+        let command_line = std::iter::once(&server.command).chain(&server.args).cloned().collect::<Vec<_>>().join(" ");
+        written = written
+            .and_then(|_| writeln!(out, "{} ({}): {}", name, command_line, origin))
+            .and_then(|_| tools.iter().try_for_each(|tool| writeln!(out, "  {}  {}", tool.name, tool.description)));
+    }
+    written.map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+/// Parse a request's JSON; `mcp_config` defaults to no servers
+fn parse_request(content: &str) -> Result<PromptRequest, String> {
+    let mut request: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    if let Some(request) = request.as_object_mut() {
+        request.entry("mcp_config").or_insert_with(|| serde_json::json!({"mcpServers": {}}));
+    }
+    serde_json::from_value(request).map_err(|e| e.to_string())
+}
+
+/// The request files at a path: the file itself, or the `.json` files of a directory
+fn request_files(path: &str) -> Result<Vec<PathBuf>, String> {
+    if !Path::new(path).is_dir() {
+        return Ok(vec![PathBuf::from(path)]);
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The value below which `percent` of the sorted samples fall, by nearest rank
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn bench(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let iterations = match options.get("--iterations") {
+        Some(n) => n.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("Invalid --iterations {}", n))?,
+        None => BENCH_ITERATIONS,
+    };
+    let requests = match options.get("--requests") {
+        Some(path) => request_files(path)?.iter()
+            .map(|file| read_request(&file.to_string_lossy()))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => SAMPLE_REQUESTS.iter().map(|json| parse_request(json)).collect::<Result<Vec<_>, _>>()?,
+    };
+    if requests.is_empty() {
+        return Err("bench found no requests".to_string());
+    }
+
+    // One composer and discovery serve every composition, as in a long-running host
+    let dir = prompts_dir(options);
+    let mut composer = crate::global_engine().composer(Some(dir.clone()), false);
+    let discovery = ToolDiscovery::with_prompts_dir(dir);
+    let mut durations = Vec::with_capacity(iterations * requests.len());
+    for _ in 0..iterations {
+        for request in &requests {
+            let start = crate::Instant::now();
+            let tools = discovery.discover_tools_immediate(&request.mcp_config).map_err(|e| e.to_string())?;
+            composer.compose(request, &tools).map_err(|e| e.to_string())?;
+            durations.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+    durations.sort_by(f64::total_cmp);
+
+    let rate = |counters: crate::types::CacheCounters| match counters.hit_rate() {
+        Some(rate) => format!("{:.1}% ({} hits, {} misses)", rate * 100.0, counters.hits, counters.misses),
+        None => "no lookups".to_string(),
+    };
+    let slow = durations.iter().filter(|ms| **ms > 50.0).count();
+    let write = |out: &mut dyn Write| -> std::io::Result<()> {
+        writeln!(out, "compositions:     {} ({} requests x {} iterations)", durations.len(), requests.len(), iterations)?;
+        writeln!(out, "p50:              {:.3} ms", percentile(&durations, 50.0))?;
+        writeln!(out, "p95:              {:.3} ms", percentile(&durations, 95.0))?;
+        writeln!(out, "max:              {:.3} ms", durations[durations.len() - 1])?;
+        writeln!(out, "over 50 ms:       {}", slow)?;
+        writeln!(out, "prompt files:     {}", rate(composer.cache_counters()))?;
+        writeln!(out, "tool discovery:   {}", rate(discovery.cache_counters()))
+    };
+    write(out).map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+fn render(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let request = read_request(options.get("--request").ok_or("render needs a --request")?)?;
+    let target = RenderTarget::from_name(options.get("--target").ok_or("render needs a --target")?).map_err(|e| e.to_string())?;
+    let dir = prompts_dir(options);
+    let tools = crate::engine::offline_tools(&request.mcp_config, Some(&dir));
+    let response = crate::global_engine().composer(Some(dir), false).compose(&request, &tools).map_err(|e| e.to_string())?;
+
+    let body = crate::render::render(&request, &response, &tools, target);
+    let body = serde_json::to_string_pretty(&body).map_err(|e| e.to_string())?;
+    writeln!(out, "{}", body).map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+fn report(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let request = read_request(options.get("--request").ok_or("report needs a --request")?)?;
+    let format = ReportFormat::from_name(options.get("--format").map_or("markdown", String::as_str)).map_err(|e| e.to_string())?;
+    let dir = prompts_dir(options);
+    let tools = crate::engine::offline_tools(&request.mcp_config, Some(&dir));
+    let mut sections = Vec::new();
+    let response = crate::global_engine().composer(Some(dir), false)
+        .compose_streaming(&request, &tools, |section| sections.push(section))
+        .map_err(|e| e.to_string())?;
+    write!(out, "{}", response.to_report_with_sections(&sections, format)).map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+/// Answer request lines until the input ends, with one composer and discovery cache for
+/// all of them. Blank lines are skipped; every other line gets exactly one line back.
+fn pipe(options: &HashMap<String, String>, input: impl BufRead, out: &mut dyn Write) -> Result<i32, String> {
+    let live = options.contains_key("--live");
+    let dir = prompts_dir(options);
+    let mut composer = crate::global_engine().composer(Some(dir.clone()), false);
+    let discovery = ToolDiscovery::with_prompts_dir(dir);
+
+    for line in input.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = parse_request(&line)
+            .map_err(|e| PromptError::invalid_request(e).with_field("request"))
+            .and_then(|request| {
+                let tools = request_span(&request).in_scope(|| match (live, request.force_refresh.unwrap_or(false)) {
+                    (_, true) => discovery.rediscover_tools(&request.mcp_config, &CancellationToken::new()),
+                    (true, false) => discovery.discover_tools_immediate(&request.mcp_config),
+                    (false, false) => Ok(discovery.cached_or_inferred_tools(&request.mcp_config)),
+                })?;
+                let mut response = composer.compose(&request, &tools)?;
+                response.metadata.discovery = if live { DiscoveryMode::Live } else { DiscoveryMode::Cached };
+                Ok(response)
+            });
+        let reply = match response {
+            Ok(response) => serde_json::to_string(&response),
+            Err(e) => serde_json::to_string(&serde_json::json!({"error": e.report()})),
+        };
+        let reply = reply.map_err(|e| e.to_string())?;
+        writeln!(out, "{}", reply).and_then(|_| out.flush()).map_err(|e| e.to_string())?;
+    }
+    Ok(0)
+}
+
+/// Modification time of every file under the given paths, to notice edits by polling
+fn modification_times(paths: &[&Path]) -> BTreeMap<PathBuf, SystemTime> {
+    let mut times = BTreeMap::new();
+    let mut pending: Vec<PathBuf> = paths.iter().map(|path| path.to_path_buf()).collect();
+    while let Some(path) = pending.pop() {
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            pending.extend(std::fs::read_dir(&path).into_iter().flatten().filter_map(|entry| entry.ok().map(|entry| entry.path())));
+        } else if let Ok(modified) = metadata.modified() {
+            times.insert(path, modified);
+        }
+    }
+    times
+}
+
+fn watch(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let request_path = options.get("--request").ok_or("watch needs a --request")?;
+    let dir = prompts_dir(options);
+    let interval = match options.get("--interval") {
+        Some(ms) => Duration::from_millis(ms.parse().map_err(|e| format!("Invalid --interval {}: {}", ms, e))?),
+        None => WATCH_INTERVAL,
+    };
+
+    let watched = [Path::new(request_path), Path::new(&dir)];
+    let mut seen = None;
+    loop {
+        let times = modification_times(&watched);
+        if seen.as_ref() != Some(&times) {
+            seen = Some(times);
+            // Files are read afresh each time, so a composer is not kept between changes
+            let composed = read_request(request_path)
+                .and_then(|request| compose(&request, &dir).map_err(|e| e.to_string()));
+            let written = match composed {
+                Ok(response) => writeln!(
+                    out, "==> {} modules: {}\n{}\n",
+                    request_path, response.applied_modules.join(", "), response.system_prompt
+                ),
+                Err(message) => writeln!(out, "==> {} failed: {}\n", request_path, message),
+            };
+            written.and_then(|_| out.flush()).map_err(|e| e.to_string())?;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn eval(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let dataset = options.get("--dataset").ok_or("eval needs a --dataset")?;
+    let output = options.get("--output").ok_or("eval needs an --output")?;
+    let content = std::fs::read_to_string(dataset).map_err(|e| format!("Failed to read dataset {}: {}", dataset, e))?;
+    let cases = parse_dataset(&content).map_err(|e| format!("{}: {}", dataset, e))?;
+    let dir = prompts_dir(options);
+
+    // Failed cases are left out of the output, which evaluators read line by line
+    let mut lines = String::new();
+    let mut failed = 0;
+    for (case, outcome) in cases.iter().zip(compose_dataset(&dir, &cases)) {
+        match outcome {
+            Ok(record) => {
+                lines.push_str(&serde_json::to_string(&record).map_err(|e| e.to_string())?);
+                lines.push('\n');
+            }
+            Err(e) => {
+                failed += 1;
+                writeln!(out, "error: {}: {}", case.id, e).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    std::fs::write(output, lines).map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    writeln!(out, "{}: {} cases composed into {}, {} failed", dir, cases.len() - failed, output, failed).map_err(|e| e.to_string())?;
+    Ok(if failed == 0 { 0 } else { EXIT_PROBLEMS })
+}
+
+fn compile(options: &HashMap<String, String>, out: &mut dyn Write) -> Result<i32, String> {
+    let output = options.get("--output").ok_or("compile needs an --output")?;
+    let dir = prompts_dir(options);
+
+    // A bundle is only as good as the directory it was made from
+    let report = validate_prompts_dir(&dir);
+    let errors: Vec<_> = report.issues.iter().filter(|issue| issue.severity == IssueSeverity::Error).collect();
+    if !errors.is_empty() {
+        for issue in errors {
+            writeln!(out, "error: {}: {}", issue.path, issue.message).map_err(|e| e.to_string())?;
+        }
+        return Ok(EXIT_PROBLEMS);
+    }
+
+    let bundle = crate::bundle::compile(Path::new(&dir)).map_err(|e| e.to_string())?;
+    // Written beside the output and renamed over it, so composers with the old bundle
+    // mapped never see it change
+    let partial = format!("{}.partial", output);
+    std::fs::write(&partial, &bundle)
+        .and_then(|_| std::fs::rename(&partial, output))
+        .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    let files = crate::bundle::PromptBundle::from_bytes(bundle).map_err(|e| e.to_string())?.len();
+    writeln!(out, "{}: {} files compiled into {}", dir, files, output).map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_command(args: &[&str]) -> (i32, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut out = Vec::new();
+        let status = run(&args, &mut out);
+        (status, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_validate_reports_and_sets_exit_status() {
+        let (status, output) = run_command(&["validate", "--prompts-dir", concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")]);
+        assert_eq!(status, 0, "{}", output);
+        assert!(output.contains("0 errors"));
+
+        let (status, output) = run_command(&["validate", "--prompts-dir", "/nonexistent/prompts"]);
+        assert_eq!(status, EXIT_PROBLEMS);
+        assert!(output.starts_with("error: /nonexistent/prompts"));
+
+        assert_eq!(run_command(&["validate", "--prompts-dir"]).0, EXIT_USAGE);
+        assert_eq!(run_command(&["frobnicate"]).0, EXIT_USAGE);
+        assert_eq!(run_command(&[]).0, EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_lint_reports_issues_with_their_lines() {
+        let (status, output) = run_command(&["lint", "--prompts-dir", concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")]);
+        assert_eq!(status, 0, "{}", output);
+        assert!(output.contains("0 errors, 0 warnings"));
+
+        let dir = std::env::temp_dir().join(format!("prompt-composer-cli-lint-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("behaviors")).unwrap();
+        std::fs::write(dir.join("behaviors/planning.md"), "# Planning\n## Steps\n- Plan first\n# Notes\n").unwrap();
+        let (status, output) = run_command(&["lint", "--prompts-dir", &dir.to_string_lossy()]);
+        assert_eq!(status, 0, "{}", output);
+        assert!(output.starts_with("warning: "));
+        assert!(output.contains("planning.md:4: \"# Notes\" is dropped; only the title may be a # heading [dropped-heading]"));
+
+        std::fs::write(dir.join("behaviors/empty.md"), "# Empty\n").unwrap();
+        assert_eq!(run_command(&["lint", "--prompts-dir", &dir.to_string_lossy()]).0, EXIT_PROBLEMS);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(run_command(&["lint", "--prompts-dir", "/nonexistent/prompts"]).0, EXIT_PROBLEMS);
+    }
+
+    #[test]
+    fn test_coverage_lists_content_no_request_composed() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-cli-coverage-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("requests")).unwrap();
+        std::fs::write(dir.join("requests/question.json"), r#"{"user_prompt": "What does this function return?"}"#).unwrap();
+        let requests = dir.join("requests").to_string_lossy().to_string();
+        let (status, output) = run_command(&["coverage", "--requests", &requests, "--prompts-dir", concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")]);
+        assert_eq!(status, 0, "{}", output);
+        assert!(output.contains("unused file: behaviors/planning.md\n"), "{}", output);
+        assert!(output.contains("1 requests (0 failed)"), "{}", output);
+
+        assert_eq!(run_command(&["coverage", "--requests", &requests, "--prompts-dir", "/nonexistent/prompts"]).0, EXIT_PROBLEMS);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(run_command(&["coverage"]).0, EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_diff_shows_module_and_prompt_changes() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-cli-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let simple = dir.join("simple.json");
+        let planned = dir.join("planned.json");
+        std::fs::write(&simple, r#"{"user_prompt": "Hi", "task_complexity": "Simple"}"#).unwrap();
+        std::fs::write(&planned, r#"{"user_prompt": "Hi", "task_complexity": "Simple", "behavior_hints": ["planning"]}"#).unwrap();
+        let prompts = concat!(env!("CARGO_MANIFEST_DIR"), "/prompts");
+
+        let (status, output) = run_command(&["diff", "--request", &simple.to_string_lossy(), "--prompts-dir", prompts]);
+        assert_eq!((status, output.as_str()), (0, ""));
+
+        let (status, output) = run_command(&[
+            "diff", "--before", &simple.to_string_lossy(), "--after", &planned.to_string_lossy(), "--prompts-dir", prompts,
+        ]);
+        assert_eq!(status, EXIT_PROBLEMS);
+        assert!(output.starts_with("+ module task_planning\n"), "{}", output);
+        assert!(output.contains(&format!("+++ {}", planned.display())));
+        assert!(output.lines().any(|line| line.starts_with('+') && line.contains("PLANNING")));
+
+        assert_eq!(run_command(&["diff", "--before", &simple.to_string_lossy()]).0, EXIT_USAGE);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_init_scaffolds_then_keeps_existing_files() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-cli-init-{}", std::process::id()));
+        let dir_arg = dir.to_string_lossy().to_string();
+        let (status, output) = run_command(&["init", &dir_arg]);
+        assert_eq!(status, 0);
+        assert!(output.contains("selection_rules.toml"));
+        assert_eq!(run_command(&["validate", "--prompts-dir", &dir_arg]).0, 0);
+
+        let (_, output) = run_command(&["init", &dir_arg]);
+        assert!(output.starts_with(&format!("{}: 0 files written", dir_arg)), "{}", output);
+        assert_eq!(run_command(&["init", &dir_arg, "--overwrite"]).0, EXIT_USAGE);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tools_shows_matched_patterns() {
+        let config = std::env::temp_dir().join(format!("prompt-composer-cli-tools-{}.json", std::process::id()));
+        std::fs::write(&config, r#"{"mcpServers": {
+            "fs": {"command": "npx", "args": ["@modelcontextprotocol/server-filesystem"]},
+            "acme": {"command": "acme-mcp"}
+        }}"#).unwrap();
+        let prompts = concat!(env!("CARGO_MANIFEST_DIR"), "/prompts");
+
+        let (status, output) = run_command(&["tools", "--config", &config.to_string_lossy(), "--prompts-dir", prompts]);
+        assert_eq!(status, 0);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "acme (acme-mcp): no server pattern matched; generic fallback");
+        assert!(lines[1].starts_with("  acme.execute"));
+        assert!(lines[2].starts_with("fs (npx @modelcontextprotocol/server-filesystem): filesystem_servers by name pattern"), "{}", lines[2]);
+
+        let (status, live) = run_command(&["tools", "--config", &config.to_string_lossy(), "--prompts-dir", prompts, "--live"]);
+        assert_eq!((status, live), (0, output));
+        assert_eq!(run_command(&["tools"]).0, EXIT_USAGE);
+        std::fs::remove_file(&config).unwrap();
+    }
+
+    #[test]
+    fn test_bench_reports_latency_and_cache_hits() {
+        let (status, output) = run_command(&["bench", "--iterations", "5", "--prompts-dir", concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")]);
+        assert_eq!(status, 0, "{}", output);
+        assert!(output.starts_with("compositions:     15 (3 requests x 5 iterations)\n"), "{}", output);
+        assert!(output.contains("p95:"));
+        // Each file and server is read once, then cached
+        let files = output.lines().find(|line| line.starts_with("prompt files:")).unwrap();
+        assert!(files.contains('%') && !files.contains(" 0 hits"), "{}", files);
+        assert!(output.contains("tool discovery:   80.0% (12 hits, 3 misses)"), "{}", output);
+
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], 50.0), 2.0);
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], 95.0), 4.0);
+        assert_eq!(run_command(&["bench", "--iterations", "0"]).0, EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_render_prints_a_provider_request_body() {
+        let request = std::env::temp_dir().join(format!("prompt-composer-cli-render-{}.json", std::process::id()));
+        std::fs::write(&request, r#"{"user_prompt": "List the files", "mcp_config": {"mcpServers": {"fs": {"command": "filesystem"}}}}"#).unwrap();
+        let request_arg = request.to_string_lossy().to_string();
+        let prompts = concat!(env!("CARGO_MANIFEST_DIR"), "/prompts");
+
+        let (status, output) = run_command(&["render", "--request", &request_arg, "--target", "anthropic", "--prompts-dir", prompts]);
+        assert_eq!(status, 0);
+        let body: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert!(!body["system"].as_str().unwrap().is_empty());
+        assert!(body["tools"].as_array().unwrap().iter().any(|tool| tool["name"] == "fs_read_file"));
+
+        assert_eq!(run_command(&["render", "--request", &request_arg, "--target", "gemini"]).0, EXIT_USAGE);
+        std::fs::remove_file(&request).unwrap();
+    }
+
+    #[test]
+    fn test_report_prints_the_composition_in_the_chosen_format() {
+        let request = std::env::temp_dir().join(format!("prompt-composer-cli-report-{}.json", std::process::id()));
+        std::fs::write(&request, r#"{"user_prompt": "Plan and implement the migration"}"#).unwrap();
+        let request_arg = request.to_string_lossy().to_string();
+        let prompts = concat!(env!("CARGO_MANIFEST_DIR"), "/prompts");
+
+        let (status, output) = run_command(&["report", "--request", &request_arg, "--prompts-dir", prompts]);
+        assert_eq!(status, 0);
+        assert!(output.starts_with("# Prompt composition report") && output.contains("| Section | Tokens | Characters |"));
+        let (status, output) = run_command(&["report", "--request", &request_arg, "--format", "html", "--prompts-dir", prompts]);
+        assert_eq!(status, 0);
+        assert!(output.starts_with("<!DOCTYPE html>"));
+
+        assert_eq!(run_command(&["report", "--request", &request_arg, "--format", "pdf"]).0, EXIT_USAGE);
+        std::fs::remove_file(&request).unwrap();
+    }
+
+    #[test]
+    fn test_pipe_answers_each_request_line() {
+        let options = HashMap::from([("--prompts-dir".to_string(), concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string())]);
+        let input = "{\"user_prompt\": \"Plan a refactor\"}\n\nnot json\n{\"user_prompt\": \"Hi\", \"max_tokens\": 0}\n";
+        let mut out = Vec::new();
+        assert_eq!(pipe(&options, input.as_bytes(), &mut out).unwrap(), 0);
+
+        let replies: Vec<serde_json::Value> = String::from_utf8(out).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(replies.len(), 3);
+        assert!(!replies[0]["system_prompt"].as_str().unwrap().is_empty());
+        assert_eq!(replies[0]["metadata"]["discovery"], "Cached");
+        assert_eq!(replies[1]["error"]["code"], "InvalidRequest");
+        assert_eq!(replies[2]["error"]["field"], "max_tokens");
+    }
+
+    #[test]
+    fn test_watch_notices_edits_to_prompt_files() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-cli-watch-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("behaviors")).unwrap();
+        let planning = dir.join("behaviors").join("planning.md");
+        std::fs::write(&planning, "## Plan").unwrap();
+
+        let before = modification_times(&[&dir]);
+        assert_eq!(before.keys().collect::<Vec<_>>(), [&planning]);
+        std::fs::File::options().append(true).open(&planning).unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(1)).unwrap();
+        assert_ne!(modification_times(&[&dir]), before);
+        std::fs::write(dir.join("behaviors").join("safety.md"), "## Safety").unwrap();
+        assert_eq!(modification_times(&[&dir]).len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_eval_writes_a_line_per_case() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-cli-eval-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dataset = dir.join("dataset.jsonl").to_string_lossy().to_string();
+        let output = dir.join("prompts.jsonl").to_string_lossy().to_string();
+        std::fs::write(&dataset, "{\"id\": \"plan\", \"user_prompt\": \"Plan the migration\"}\n{\"id\": \"question\", \"user_prompt\": \"What does this return?\"}\n").unwrap();
+        let (status, message) = run_command(&["eval", "--dataset", &dataset, "--output", &output, "--prompts-dir", concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")]);
+        assert_eq!(status, 0, "{}", message);
+        assert!(message.contains("2 cases composed"), "{}", message);
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[1]["id"].as_str(), lines[1]["user"].as_str()), (Some("question"), Some("What does this return?")));
+        assert!(lines[0]["system"].as_str().is_some_and(|system| !system.is_empty()));
+
+        std::fs::write(&dataset, "{\"id\": \"plan\"}\n").unwrap();
+        assert_eq!(run_command(&["eval", "--dataset", &dataset, "--output", &output]).0, EXIT_USAGE);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(run_command(&["eval", "--dataset", &dataset]).0, EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_compile_writes_a_bundle_of_a_valid_directory() {
+        let output = std::env::temp_dir().join(format!("prompt-composer-cli-{}.bundle", std::process::id()));
+        let output = output.to_string_lossy();
+        let (status, message) = run_command(&["compile", "--output", &output, "--prompts-dir", concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")]);
+        assert_eq!(status, 0, "{}", message);
+        let bundle = crate::bundle::PromptBundle::open(output.as_ref()).unwrap();
+        assert!(message.contains(&format!("{} files compiled", bundle.len())));
+        std::fs::remove_file(output.as_ref()).unwrap();
+
+        let (status, message) = run_command(&["compile", "--output", &output, "--prompts-dir", "/nonexistent/prompts"]);
+        assert_eq!(status, EXIT_PROBLEMS);
+        assert!(message.starts_with("error: /nonexistent/prompts"));
+        assert_eq!(run_command(&["compile"]).0, EXIT_USAGE);
+    }
+}
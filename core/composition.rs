@@ -1,144 +1,838 @@
 use crate::types::*;
-use crate::modules::{ModuleSelector, PromptModule};
-use crate::loader::PromptLoader;
+use crate::cache::CachePolicy;
+use crate::cancel::CancellationToken;
+use crate::modules::{DeclarativeModule, ModuleSelector, OutputFormatModule, PromptModule, SelectionHints, WorkspaceModule};
+use crate::registry::ModuleRegistry;
+use crate::session::{ModuleState, SessionStore};
+use crate::loader::{ContentCache, PromptLoader};
+use crate::provider::PromptProvider;
+use crate::external_module::ExternalModule;
+use crate::hooks::HookSet;
+use crate::metrics::CacheKind;
+use crate::rules::{ComplexityMatcher, PluginFile, SelectionRules};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Main prompt composition service
 pub struct PromptComposer {
     loader: PromptLoader,
+    rules: SelectionRules,
+    complexity_matcher: ComplexityMatcher,
+    registry: ModuleRegistry,
+    sessions: SessionStore,
+    hooks: HookSet,
+    /// Whether external modules, which start processes, are left out
+    offline: bool,
+    /// Cancellation of the composition in progress
+    cancel: CancellationToken,
+    /// Time each part of the composition in progress took
+    timings: Vec<ModuleTiming>,
+}
+
+/// Serialized form of a composer's configuration, see `PromptComposer::export_config`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ComposerConfig {
+    /// Version of this layout
+    format: u32,
+    prompts_dir: String,
+    /// Prompt files of a composer that reads them from memory, by path
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    files: BTreeMap<String, String>,
+    offline: bool,
+    rules: SelectionRules,
+    modules: Vec<ModuleInfo>,
+}
+
+impl ComposerConfig {
+    const FORMAT: u32 = 1;
+}
+
+/// Outcome of the selection steps of composition
+struct Selection<'a> {
+    modules: Vec<Box<dyn PromptModule>>,
+    trace: Vec<ModuleDecision>,
+    warnings: Vec<Warning>,
+    complexity: TaskComplexity,
+    complexity_evidence: Option<ComplexityEvidence>,
+    session_state: Cow<'a, SessionState>,
+    seed: u64,
+}
+
+/// Which text of the hooks is written
+#[derive(Clone, Copy)]
+enum HookSection {
+    Prologue,
+    Epilogue,
+}
+
+impl HookSection {
+    fn label(self) -> &'static str {
+        match self {
+            HookSection::Prologue => "prologue",
+            HookSection::Epilogue => "epilogue",
+        }
+    }
+}
+
+/// Span of everything done for a request, carrying its trace ID to every log event
+/// inside. Info level, so warnings logged inside keep the trace ID.
+pub(crate) fn request_span(request: &PromptRequest) -> tracing::Span {
+    tracing::info_span!("prompt_request", trace_id = request.trace_id.as_deref())
+}
+
+/// Warning of a slow composition naming the parts that took longest, out of selection
+/// and the timed modules
+fn slow_composition_warning(elapsed: Duration, selection_ms: f64, timings: &[ModuleTiming]) -> Warning {
+    let mut parts: Vec<(&str, f64)> = timings.iter().map(|timing| (timing.module.as_str(), timing.duration_ms)).collect();
+    parts.push(("selection", selection_ms));
+    parts.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let slowest: Vec<String> = parts.iter().take(3).map(|(part, ms)| format!("{} {:.1}ms", part, ms)).collect();
+    Warning::new(
+        WarningSeverity::Info,
+        "composer",
+        format!(
+            "Prompt composition took {}ms, most in {}; set profile on the request to time every module and file load",
+            elapsed.as_millis(),
+            slowest.join(", ")
+        )
+    )
+}
+
+/// Characters per token assumed by `PromptRequest::max_tokens`
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens of a text as `PromptRequest::max_tokens` counts them
+pub(crate) fn estimated_tokens(text: &str) -> usize {
+    text.len().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Builds the system prompt section by section, passing each section to the sink of a
+/// streaming composition as it is written. Consecutive module sections are separated by
+/// a newline, all others by a blank line.
+struct PromptWriter<'a> {
+    content: String,
+    after_module: bool,
+    /// Most characters the prompt may have
+    budget: Option<usize>,
+    /// Sections left out to stay within the budget
+    omitted: Vec<String>,
+    sink: Option<&'a mut dyn FnMut(PromptSection)>,
+}
+
+impl<'a> PromptWriter<'a> {
+    fn new(max_tokens: Option<u32>, sink: Option<&'a mut dyn FnMut(PromptSection)>) -> Self {
+        let budget = max_tokens.map(|tokens| tokens as usize * CHARS_PER_TOKEN);
+        Self { content: String::new(), after_module: false, budget, omitted: Vec::new(), sink }
+    }
+
+    /// Append a section, returning false when it was left out to stay within the budget
+    fn write(&mut self, source: &str, text: &str, is_module: bool) -> bool {
+        if text.is_empty() {
+            return true;
+        }
+        let separator = match self.content.is_empty() {
+            true => "",
+            false if self.after_module && is_module => "\n",
+            false => "\n\n",
+        };
+        if self.budget.is_some_and(|budget| self.content.len() + separator.len() + text.len() > budget) {
+            self.omitted.push(source.to_string());
+            return false;
+        }
+        let start = self.content.len();
+        self.content.push_str(separator);
+        self.content.push_str(text);
+        self.after_module = is_module;
+        if let Some(sink) = self.sink.as_mut() {
+            sink(PromptSection { module: source.to_string(), text: self.content[start..].to_string() });
+        }
+        true
+    }
+
+    /// The prompt, and a warning naming the sections left out to stay within the budget
+    fn finish(self, max_tokens: Option<u32>) -> (String, Option<Warning>) {
+        let warning = (!self.omitted.is_empty()).then(|| Warning::new(
+            WarningSeverity::Warning,
+            "composer",
+            format!("Left out {} to stay within max_tokens {}", self.omitted.join(", "), max_tokens.unwrap_or_default())
+        ));
+        (self.content, warning)
+    }
 }
 
 impl PromptComposer {
     /// Create a new composer with default prompts directory
     pub fn new() -> Self {
-        Self {
-            loader: PromptLoader::new(None),
-        }
+        Self::from_loader(PromptLoader::new(None))
     }
 
     /// Create a new composer with custom prompts directory
     pub fn with_prompts_dir(prompts_dir: String) -> Self {
+        Self::from_loader(PromptLoader::new(Some(prompts_dir)))
+    }
+
+    /// Create a composer for the prompts compiled into the library, which reads nothing
+    /// from disk
+    pub fn embedded() -> Self {
+        Self::from_loader(PromptLoader::embedded())
+    }
+
+    /// Create a composer for prompt files held in memory, keyed by their path relative to
+    /// a prompts directory (`behaviors/planning.md`, `selection_rules.toml`)
+    pub fn from_files(files: HashMap<String, String>) -> Self {
+        Self::from_loader(PromptLoader::from_files(files))
+    }
+
+    /// Create a composer that reads prompt files and selection rules from a provider
+    /// instead of a prompts directory
+    pub fn with_provider(provider: Arc<dyn PromptProvider>) -> Self {
+        Self::from_loader(PromptLoader::with_provider(provider))
+    }
+
+    /// Create a composer around a loader, reading selection rules from its prompts directory
+    fn from_loader(loader: PromptLoader) -> Self {
+        let rules = match loader.reads_disk() {
+            true => SelectionRules::load(loader.prompts_dir()),
+            false => loader.provided_file("selection_rules.toml").map_or(Ok(SelectionRules::default()), |rules| SelectionRules::from_toml(&rules)),
+        };
+        let defaults = SelectionRules::default();
+        let complexity_matcher = defaults.complexity.compile()
+            .expect("default complexity rules compile");
+        let mut composer = Self {
+            loader,
+            rules: defaults,
+            complexity_matcher,
+            registry: ModuleRegistry::new(),
+            sessions: SessionStore::new(),
+            hooks: HookSet::new(),
+            offline: false,
+            cancel: CancellationToken::new(),
+            timings: Vec::new(),
+        };
+        if let Err(e) = rules.and_then(|rules| composer.apply_rules(rules)) {
+            tracing::warn!("{}; using default selection rules", e);
+        }
+        composer.reload_plugins();
+        composer
+    }
+
+    /// A composer with this one's prompts, rules, modules, hooks and offline setting that
+    /// shares its session store and shared prompt cache, so a composition can run without
+    /// holding this one
+    #[cfg(feature = "nodejs")]
+    pub(crate) fn fork(&self) -> Self {
         Self {
-            loader: PromptLoader::new(Some(prompts_dir)),
+            loader: self.loader.fork(),
+            rules: self.rules.clone(),
+            complexity_matcher: self.complexity_matcher.clone(),
+            registry: self.registry.clone(),
+            sessions: self.sessions.clone(),
+            hooks: self.hooks.clone(),
+            offline: self.offline,
+            cancel: CancellationToken::new(),
+            timings: Vec::new(),
         }
     }
 
+    /// Select with the given rules, replacing the modules declared by the previous ones.
+    /// Rules whose complexity patterns do not compile are rejected.
+    fn apply_rules(&mut self, rules: SelectionRules) -> Result<(), PromptError> {
+        self.complexity_matcher = rules.complexity.compile()?;
+        self.registry.unregister_source(ModuleSource::Config);
+        for declared in &rules.modules {
+            let module = Arc::new(DeclarativeModule::new(declared.clone()));
+            if let Err(e) = self.registry.register_as(module, declared.kind, ModuleSource::Config) {
+                tracing::warn!("{}", e);
+            }
+        }
+        self.rules = rules;
+        Ok(())
+    }
+
+    /// Snapshot of everything that decides this composer's output, as JSON: where its
+    /// prompts come from (with the files themselves when they are held in memory), its
+    /// selection rules with their thresholds and patterns, whether it is offline, and the
+    /// enabled state of its modules. The same composer always exports the same JSON.
+    ///
+    /// Modules registered in code and hooks cannot be serialized; a process importing
+    /// the snapshot registers them itself.
+    pub fn export_config(&self) -> Result<String, PromptError> {
+        let config = ComposerConfig {
+            format: ComposerConfig::FORMAT,
+            prompts_dir: self.loader.prompts_dir().to_string(),
+            files: self.loader.provided_files().unwrap_or_default(),
+            offline: self.offline,
+            rules: self.rules.clone(),
+            modules: self.registry.list(),
+        };
+        Ok(serde_json::to_string_pretty(&config)?)
+    }
+
+    /// Create a composer from a snapshot made by `export_config`. The snapshot's rules
+    /// replace the selection rules of its prompts directory, and modules it lists as
+    /// disabled are disabled; modules it lists that are not known here are ignored.
+    pub fn import_config(json: &str) -> Result<Self, PromptError> {
+        let config: ComposerConfig = serde_json::from_str(json)
+            .map_err(|e| PromptError::config(format!("Invalid composer configuration: {}", e)))?;
+        if config.format != ComposerConfig::FORMAT {
+            return Err(PromptError::config(format!(
+                "Unsupported composer configuration format {}; expected {}", config.format, ComposerConfig::FORMAT
+            )));
+        }
+
+        let loader = match config.prompts_dir.as_str() {
+            _ if !config.files.is_empty() => PromptLoader::from_files(config.files.into_iter().collect()),
+            crate::embedded::PROMPTS_DIR => PromptLoader::embedded(),
+            _ => PromptLoader::new(Some(config.prompts_dir)),
+        };
+        let mut composer = Self::from_loader(loader);
+        composer.apply_rules(config.rules)?;
+        // Plugins follow the declared modules, as they do in a new composer
+        composer.reload_plugins();
+        composer.offline = config.offline;
+        for module in &config.modules {
+            composer.registry.set_enabled(&module.name, module.enabled);
+        }
+        Ok(composer)
+    }
+
+    /// Load the modules in the `plugins/` directory of the prompts directory, replacing
+    /// those loaded before: declarative modules from `.toml` files and, with the
+    /// `wasm-plugins` feature, compiled plugins from `.wasm` files. Files that fail to
+    /// load are skipped with a warning. Returns the number of modules loaded.
+    pub fn reload_plugins(&mut self) -> usize {
+        self.registry.unregister_source(ModuleSource::Plugin);
+        if !self.loader.reads_disk() {
+            return 0;
+        }
+
+        let plugins_dir = Path::new(self.loader.prompts_dir()).join("plugins");
+        let Ok(entries) = fs::read_dir(&plugins_dir) else {
+            return 0;
+        };
+        let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        paths.sort();
+
+        let mut loaded = 0;
+        for path in paths {
+            let modules: Vec<(Arc<dyn PromptModule>, ModuleKind)> = match path.extension().and_then(|s| s.to_str()) {
+                Some("toml") => match PluginFile::load(&path) {
+                    Ok(plugin) => plugin.modules.into_iter()
+                        .map(|declared| {
+                            let kind = declared.kind;
+                            (Arc::new(DeclarativeModule::new(declared)) as Arc<dyn PromptModule>, kind)
+                        })
+                        .collect(),
+                    Err(e) => {
+                        tracing::warn!("{}", e);
+                        continue;
+                    }
+                },
+                #[cfg(feature = "wasm-plugins")]
+                Some("wasm") => match crate::wasm_plugin::WasmModule::load(&path.to_string_lossy()) {
+                    Ok(module) => vec![(Arc::new(module) as Arc<dyn PromptModule>, ModuleKind::Behavior)],
+                    Err(e) => {
+                        tracing::warn!("{}", e);
+                        continue;
+                    }
+                },
+                #[cfg(not(feature = "wasm-plugins"))]
+                Some("wasm") => {
+                    tracing::warn!(plugin = ?path, "skipping plugin; built without the wasm-plugins feature");
+                    continue;
+                }
+                _ => continue,
+            };
+
+            for (module, kind) in modules {
+                match self.registry.register_as(module, kind, ModuleSource::Plugin) {
+                    Ok(()) => loaded += 1,
+                    Err(e) => tracing::warn!("{}", e),
+                }
+            }
+        }
+        loaded
+    }
+
+    /// Register a module implemented outside the crate. It can be requested by name in
+    /// hints and policies, and is otherwise ranked with the auto-detected behaviors.
+    /// A module registered under an existing name replaces it; built-in modules cannot
+    /// be replaced.
+    pub fn register_module(&mut self, module: Arc<dyn PromptModule>) {
+        if let Err(e) = self.registry.register(module, ModuleSource::Registered) {
+            tracing::warn!("{}", e);
+        }
+    }
+
+    /// Remove a registered module, returning whether it was registered
+    pub fn unregister_module(&mut self, name: &str) -> bool {
+        self.registry.unregister(name)
+    }
+
+    /// Enable or disable a module, returning whether the module exists. Disabled modules
+    /// are never selected, even when hinted or enabled by policy.
+    pub fn set_module_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        self.registry.set_enabled(name, enabled)
+    }
+
+    /// Name, kind, source and enabled state of every module this composer knows
+    pub fn list_modules(&self) -> Vec<ModuleInfo> {
+        self.registry.list()
+    }
+
+    /// Modules available to this composer
+    pub fn registry(&self) -> &ModuleRegistry {
+        &self.registry
+    }
+
+    /// Mutable access to the modules available to this composer
+    pub fn registry_mut(&mut self) -> &mut ModuleRegistry {
+        &mut self.registry
+    }
+
+    /// Prologue, epilogue and observer hooks run by this composer
+    pub fn hooks(&self) -> &HookSet {
+        &self.hooks
+    }
+
+    /// Mutable access to the hooks run by this composer
+    pub fn hooks_mut(&mut self) -> &mut HookSet {
+        &mut self.hooks
+    }
+
+    /// Per-session module state kept by this composer
+    pub fn session_store(&self) -> &SessionStore {
+        &self.sessions
+    }
+
+    /// Keep module state in the given store, e.g. one shared with other composers
+    pub fn set_session_store(&mut self, sessions: SessionStore) {
+        self.sessions = sessions;
+    }
+
+    /// Reuse prompt files read from disk by other composers sharing the cache
+    pub fn set_content_cache(&mut self, cache: ContentCache) {
+        self.loader.set_content_cache(cache);
+    }
+
+    /// Limit how long the composer keeps prompt files it read and how many
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.loader.set_cache_policy(policy);
+    }
+
+    /// End a session, calling `on_session_end` on each module composed in it and
+    /// dropping its state. Returns whether the session existed.
+    pub fn end_session(&mut self, session_id: &str) -> bool {
+        let Some(modules) = self.sessions.end(session_id) else {
+            return false;
+        };
+        for (name, mut state) in modules {
+            if let Some(module) = self.registry.create(&name, &self.rules, &TaskComplexity::default()) {
+                module.on_session_end(&mut state);
+            }
+        }
+        true
+    }
+
+    /// Load a compiled WASM plugin and register it as a module
+    #[cfg(feature = "wasm-plugins")]
+    pub fn register_wasm_plugin(&mut self, path: &str) -> Result<(), PromptError> {
+        let module = crate::wasm_plugin::WasmModule::load(path)?;
+        self.register_module(Arc::new(module));
+        Ok(())
+    }
+
+    /// Leave out external modules, so composition never starts a process. Tools are
+    /// whatever the caller passes, so offline callers pass inferred tools.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Whether external modules are left out
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// The registry plus the configured external modules, bound to this request
+    fn request_registry(&self, request: &PromptRequest, rules: &SelectionRules) -> Cow<'_, ModuleRegistry> {
+        if self.offline || rules.external_modules.is_empty() {
+            return Cow::Borrowed(&self.registry);
+        }
+        let mut registry = self.registry.clone();
+        for external in &rules.external_modules {
+            let module = ExternalModule::new(external.clone(), &request.user_prompt)
+                .with_cancellation(self.cancel.clone());
+            let module = Arc::new(module);
+            if let Err(e) = registry.register(module, ModuleSource::External) {
+                tracing::warn!("{}", e);
+            }
+        }
+        Cow::Owned(registry)
+    }
+
+    /// Selection rules used to decide which modules apply
+    pub fn selection_rules(&self) -> &SelectionRules {
+        &self.rules
+    }
+
     /// Compose a system prompt based on request
     pub fn compose(&mut self, request: &PromptRequest, tools: &[Tool]) -> Result<PromptResponse, PromptError> {
-        let start_time = std::time::Instant::now();
+        self.compose_with_sink(request, tools, None)
+    }
+
+    /// Compose a system prompt, passing each section to `on_section` as soon as it is
+    /// generated, so hosts can start work on the prompt before it is complete
+    pub fn compose_streaming(
+        &mut self,
+        request: &PromptRequest,
+        tools: &[Tool],
+        mut on_section: impl FnMut(PromptSection)
+    ) -> Result<PromptResponse, PromptError> {
+        self.compose_with_sink(request, tools, Some(&mut on_section))
+    }
+
+    /// Compose a system prompt, failing with `PromptError::Cancelled` before the next
+    /// module once `cancel` is cancelled and killing a running external module
+    pub fn compose_cancellable(&mut self, request: &PromptRequest, tools: &[Tool], cancel: &CancellationToken) -> Result<PromptResponse, PromptError> {
+        let previous = std::mem::replace(&mut self.cancel, cancel.clone());
+        let result = self.compose_with_sink(request, tools, None);
+        self.cancel = previous;
+        result
+    }
+
+    /// Compose, reporting the composition and its prompt file lookups to the metrics
+    /// recorder
+    fn compose_with_sink(
+        &mut self,
+        request: &PromptRequest,
+        tools: &[Tool],
+        sink: Option<&mut dyn FnMut(PromptSection)>
+    ) -> Result<PromptResponse, PromptError> {
+        let _span = request_span(request).entered();
+        let before = self.loader.cache_counters();
+        let result = self.compose_sections(request, tools, sink);
+        let lookups = self.loader.cache_counters().since(before);
+        crate::metrics::record(|metrics| {
+            metrics.cache_lookups(CacheKind::Prompts, lookups);
+            match &result {
+                Ok(response) => {
+                    let duration = Duration::from_secs_f64(response.metadata.duration_ms / 1000.0);
+                    metrics.composition(duration, estimated_tokens(&response.system_prompt));
+                    for module in &response.applied_modules {
+                        metrics.module_applied(module);
+                    }
+                }
+                Err(e) => metrics.composition_failed(e.code()),
+            }
+        });
+        result
+    }
+
+    fn compose_sections(
+        &mut self,
+        request: &PromptRequest,
+        tools: &[Tool],
+        sink: Option<&mut dyn FnMut(PromptSection)>
+    ) -> Result<PromptResponse, PromptError> {
+        let start_time = crate::Instant::now();
+        let _span = tracing::debug_span!("compose", session_id = request.session_state.as_ref().and_then(|state| state.session_id.as_deref())).entered();
+        self.cancel.check()?;
+        let Selection {
+            modules,
+            trace: selection_trace,
+            mut warnings,
+            complexity,
+            complexity_evidence,
+            session_state,
+            seed: selection_seed,
+        } = self.select(request, tools)?;
+        let selection_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+        
+        // Generate prompt content (this will include tool instructions) from the pinned file
+        // versions and assigned variants; applied modules include both
+        self.loader.set_version_pins(request.module_versions.clone().unwrap_or_default());
+        self.loader.set_locale(request.locale.clone());
+        self.loader.set_force_refresh(request.force_refresh.unwrap_or(false));
+        let profiling = request.profile.unwrap_or(false);
+        self.loader.set_profiling(profiling);
+        self.timings.clear();
+        let variants = self.assign_variants(request, &modules, selection_seed);
+        let mut writer = PromptWriter::new(request.max_tokens, sink);
+        let mut applied_modules = self.write_hook_sections(request, HookSection::Prologue, &mut writer, &mut warnings);
+        let generated = self.generate_prompt_content(request, tools, &modules, &variants, &session_state, &mut writer, &mut warnings);
+        let content_versions = self.loader.loaded_versions().clone();
+        self.clear_request_options();
+        applied_modules.extend(generated?);
+        for name in self.write_hook_sections(request, HookSection::Epilogue, &mut writer, &mut warnings) {
+            if !applied_modules.contains(&name) {
+                applied_modules.push(name);
+            }
+        }
+        let (system_prompt, budget_warning) = writer.finish(request.max_tokens);
+        warnings.extend(budget_warning);
+        let timings = std::mem::take(&mut self.timings);
         
+        // Track performance
+        let elapsed = start_time.elapsed();
+        tracing::debug!(modules = applied_modules.len(), elapsed_ms = elapsed.as_millis() as u64, "composed prompt");
+        if elapsed.as_millis() > 50 {
+            warnings.push(slow_composition_warning(elapsed, selection_ms, &timings));
+        }
+
+        let content_hash = format!("{:016x}", fnv1a(system_prompt.as_bytes()));
+        let response = PromptResponse {
+            system_prompt,
+            applied_modules,
+            recognized_tools: tools.iter().map(|t| t.name.clone()).collect(),
+            complexity_assessment: complexity,
+            complexity_evidence,
+            selection_seed,
+            selection_trace,
+            content_versions,
+            warnings,
+            metadata: ResponseMetadata {
+                duration_ms: elapsed.as_secs_f64() * 1000.0,
+                discovery: DiscoveryMode::Provided,
+                content_hash,
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                trace_id: request.trace_id.clone(),
+            },
+            profile: profiling.then_some(CompositionProfile { selection_ms, modules: timings }),
+        };
+        self.hooks.notify(request, &response);
+        Ok(response)
+    }
+
+    /// Decide which modules a request gets without generating content or touching
+    /// session state, returning the decisions `compose` would report in
+    /// `PromptResponse::selection_trace`.
+    pub fn explain(&self, request: &PromptRequest, tools: &[Tool]) -> Result<Vec<ModuleDecision>, PromptError> {
+        Ok(self.select(request, tools)?.trace)
+    }
+
+    /// The selection steps of composition, shared by `compose` and `explain`
+    fn select<'a>(&self, request: &'a PromptRequest, tools: &[Tool]) -> Result<Selection<'a>, PromptError> {
         // Validate prompts directory exists
         self.loader.validate_prompts_dir()?;
         
         // Assess task complexity
-        let complexity = assess_task_complexity(request);
+        let (complexity, complexity_evidence) = assess_task_complexity(request, &self.complexity_matcher);
+        
+        // Reject values no host should send
+        request.validate()?;
         
-        // Get session state with defaults
-        let session_state = request.session_state.as_ref().cloned().unwrap_or_default();
+        // Get session state with defaults, filling gaps from the conversation
+        let session_state = session_state_with_conversation(request);
         
-        // Select appropriate modules
-        let modules = ModuleSelector::select_modules(
+        // Reuse the caller's seed so randomized steps reproduce, or pick a fresh one to report
+        let seed = request.selection_seed.unwrap_or_else(generate_seed);
+        
+        // Select appropriate modules under the configured and per-request policy
+        let rules = self.rules.with_policy(request.policy.as_ref());
+        let (mut modules, mut trace, warnings) = ModuleSelector::select_modules_traced(
             tools, 
             &request.user_prompt, 
             &session_state,
-            request.domain_hints.as_deref(),
-            request.behavior_hints.as_deref()
+            SelectionHints {
+                domains: request.domain_hints.as_deref(),
+                behaviors: request.behavior_hints.as_deref(),
+            },
+            &complexity,
+            &rules,
+            &self.request_registry(request, &rules)
         );
         
-        // Generate prompt content (this will include tool instructions)
-        let (system_prompt, tool_instructions_used) = self.generate_prompt_content(request, tools, &modules, &session_state)?;
+        // The workspace is stated first so later guidance can rely on it
+        if let Some(workspace) = request.workspace.as_ref().filter(|w| !w.is_empty() && self.registry.is_enabled("workspace")) {
+            modules.insert(0, Box::new(WorkspaceModule::new(workspace.clone())));
+            trace.push(ModuleDecision::selected("workspace", DecisionSource::Request, None, "workspace is set"));
+        }
         
-        // Build applied modules list (includes both behavior/domain modules and tool instructions)
-        let mut applied_modules: Vec<String> = modules.iter().map(|m| m.name().to_string()).collect();
-        applied_modules.extend(tool_instructions_used.into_iter().map(|t| format!("tool:{}", t)));
+        // Output format is requested explicitly, so it bypasses auto-detection
+        if let Some(format) = request.response_format.as_ref().filter(|_| self.registry.is_enabled("output_format")) {
+            modules.push(Box::new(OutputFormatModule::new(format.clone())));
+            trace.push(ModuleDecision::selected("output_format", DecisionSource::Request, None, "response_format is set"));
+        }
         
-        // Track performance
-        let elapsed = start_time.elapsed();
-        if elapsed.as_millis() > 50 {
-            eprintln!("Warning: Prompt composition took {}ms", elapsed.as_millis());
+        Ok(Selection { modules, trace, warnings, complexity, complexity_evidence, session_state, seed })
+    }
+
+    /// Write the prologues or epilogues of the hooks, returning `hook:<name>` for each
+    /// hook that contributed. A hook that fails contributes nothing and is reported as a
+    /// warning.
+    fn write_hook_sections(
+        &mut self,
+        request: &PromptRequest,
+        section: HookSection,
+        writer: &mut PromptWriter,
+        warnings: &mut Vec<Warning>
+    ) -> Vec<String> {
+        let mut names = Vec::new();
+        for hook in self.hooks.iter() {
+            let name = format!("hook:{}", hook.name());
+            let start_time = crate::Instant::now();
+            let text = match section {
+                HookSection::Prologue => hook.prologue(request),
+                HookSection::Epilogue => hook.epilogue(request),
+            };
+            self.timings.push(ModuleTiming {
+                module: name.clone(),
+                duration_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+                file_loads: Vec::new(),
+            });
+            match text {
+                Ok(Some(text)) if !text.trim().is_empty() => {
+                    if writer.write(&name, &text, false) {
+                        names.push(name);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warnings.push(Warning::new(
+                    WarningSeverity::Warning,
+                    name,
+                    format!("{} failed: {}", section.label(), e)
+                )),
+            }
         }
+        names
+    }
 
-        Ok(PromptResponse {
-            system_prompt,
-            applied_modules,
-            recognized_tools: tools.iter().map(|t| t.name.clone()).collect(),
-            complexity_assessment: complexity,
-        })
+    /// Variant to compose per module: assigned by the request, or bucketed by seed among
+    /// the configured variants
+    fn assign_variants(&self, request: &PromptRequest, modules: &[Box<dyn PromptModule>], seed: u64) -> HashMap<String, String> {
+        let assigned = request.variant_assignments.as_ref();
+        modules.iter()
+            .filter_map(|module| {
+                let name = module.name();
+                let variant = assigned.and_then(|assigned| assigned.get(name).cloned())
+                    .or_else(|| {
+                        let variants = self.rules.variants.get(name)?;
+                        assign_variant(seed, name, variants).map(str::to_string)
+                    })?;
+                Some((name.to_string(), variant))
+            })
+            .collect()
     }
 
-    /// Generate the final prompt content by combining all module outputs
+    /// Write the content of all modules, then the tool instructions. Returns the applied
+    /// modules: module names (`name.variant` when a variant file was used) followed by
+    /// `tool:<server>` for each tool instruction file.
+    #[allow(clippy::too_many_arguments)]
     fn generate_prompt_content(
         &mut self,
         request: &PromptRequest,
         tools: &[Tool], 
         modules: &[Box<dyn PromptModule>], 
-        session_state: &SessionState
-    ) -> Result<(String, Vec<String>), PromptError> {
-        let mut content = String::new();
+        variants: &HashMap<String, String>,
+        session_state: &SessionState,
+        writer: &mut PromptWriter,
+        warnings: &mut Vec<Warning>
+    ) -> Result<Vec<String>, PromptError> {
+        let mut applied_modules = Vec::new();
+        let mut generated = false;
         
         // Add content from behavior/domain modules
         for module in modules {
-            let module_content = module.generate_content(tools, session_state, &mut self.loader)?;
-            if !module_content.is_empty() {
-                if !content.is_empty() {
-                    content.push('\n');
-                }
-                content.push_str(&module_content);
+            self.cancel.check()?;
+            let _span = tracing::debug_span!("generate_module", module = module.name()).entered();
+            let start_time = crate::Instant::now();
+            let variant = variants.get(module.name());
+            self.loader.set_variant(variant.cloned());
+            let module_content = match &session_state.session_id {
+                Some(session_id) => self.sessions.with_state(session_id, module.as_ref(), |state| {
+                    module.generate_stateful(tools, session_state, &mut self.loader, state)
+                }),
+                None => module.generate_stateful(tools, session_state, &mut self.loader, &mut ModuleState::new()),
+            };
+            let applied = match variant {
+                Some(variant) if self.loader.variant_loaded() => format!("{}.{}", module.name(), variant),
+                _ => module.name().to_string(),
+            };
+            self.loader.set_variant(None);
+            let module_content = module_content.map_err(|e| e.with_module(module.name()))?;
+            self.record_timing(&applied, start_time);
+            if already_instructed(request, &module_content) {
+                continue;
+            }
+            if writer.write(&applied, &module_content, true) {
+                generated |= !module_content.is_empty();
+                applied_modules.push(applied);
             }
         }
         
         // Add tool-specific instructions for each MCP server
-        let (tool_instructions, included_tools) = self.generate_tool_instructions(request)?;
-        if !tool_instructions.is_empty() {
-            if !content.is_empty() {
-                content.push_str("\n\n");
-            }
-            content.push_str(&tool_instructions);
-        }
+        let included_tools = self.generate_tool_instructions(request, writer, warnings);
+        generated |= !included_tools.is_empty();
+        applied_modules.extend(included_tools.into_iter().map(|tool| format!("tool:{}", tool)));
         
         // Add general guidance if we have tools but no specific modules generated content
-        if content.is_empty() && !tools.is_empty() {
-            content = format!(
+        if !generated && !tools.is_empty() {
+            writer.write("composer", &format!(
                 "You have access to {} tools. Use them appropriately to complete the user's request.",
                 tools.len()
-            );
+            ), false);
         }
         
-        Ok((content, included_tools))
+        Ok(applied_modules)
+    }
+
+    /// Drop the loader options a request set, whether or not its composition succeeded, so
+    /// they don't carry over to the next one
+    fn clear_request_options(&mut self) {
+        self.loader.set_version_pins(HashMap::new());
+        self.loader.set_locale(None);
+        self.loader.set_force_refresh(false);
+        self.loader.set_profiling(false);
     }
 
-    /// Generate tool-specific instructions based on available MCP servers
-    fn generate_tool_instructions(&mut self, request: &PromptRequest) -> Result<(String, Vec<String>), PromptError> {
-        let mut tool_content = String::new();
+    /// Write tool-specific instructions based on available MCP servers, returning the
+    /// servers instructed. Instruction files that fail to load are skipped with a warning.
+    fn generate_tool_instructions(&mut self, request: &PromptRequest, writer: &mut PromptWriter, warnings: &mut Vec<Warning>) -> Vec<String> {
         let mut included_tools = Vec::new();
         
         // Check each MCP server for corresponding tool instructions
-        for (server_name, _server_config) in &request.mcp_config.mcp_servers {
+        for (server_name, _) in request.mcp_config.servers_by_name() {
             if self.loader.has_tool_prompt(server_name) {
-                match self.loader.load_tool(server_name) {
+                let start_time = crate::Instant::now();
+                let loaded = self.loader.load_tool(server_name);
+                self.record_timing(&format!("tool:{}", server_name), start_time);
+                match loaded {
                     Ok(tool_instructions) => {
-                        if !tool_content.is_empty() {
-                            tool_content.push_str("\n\n");
-                        }
-                        
                         // Extract the guidance content and format it appropriately
                         let guidance = self.loader.extract_guidance(&tool_instructions);
-                        if !guidance.is_empty() {
-                            tool_content.push_str(&guidance);
+                        if !guidance.is_empty() && writer.write(&format!("tool:{}", server_name), &guidance, false) {
+                            tracing::debug!(server = %server_name, "included tool instructions");
                             included_tools.push(server_name.clone());
                         }
                     }
                     Err(e) => {
-                        eprintln!("Warning: Failed to load tool instructions for {}: {}", server_name, e);
+                        warnings.push(Warning::new(
+                            WarningSeverity::Warning,
+                            format!("tool:{}", server_name),
+                            format!("Failed to load tool instructions for {}: {}", server_name, e)
+                        ));
                     }
                 }
             }
         }
         
-        if !included_tools.is_empty() {
-            eprintln!("Included tool-specific instructions for: {}", included_tools.join(", "));
-        }
-        
-        Ok((tool_content, included_tools))
+        included_tools
+    }
+
+    /// Record the time a part of the composition took since `start_time`, with the
+    /// prompt files it loaded
+    fn record_timing(&mut self, module: &str, start_time: crate::Instant) {
+        let file_loads = self.loader.take_file_loads();
+        self.timings.push(ModuleTiming {
+            module: module.to_string(),
+            duration_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+            file_loads,
+        });
     }
 
     /// Get list of available domain modules
@@ -155,6 +849,37 @@ impl PromptComposer {
     pub fn list_tools(&self) -> Result<Vec<String>, PromptError> {
         self.loader.list_tools()
     }
+
+    /// Whether the prompts directory has instructions for an MCP server's tools
+    pub fn has_tool_prompt(&self, server: &str) -> bool {
+        self.loader.has_tool_prompt(server)
+    }
+
+    /// Content of a prompt file in "domains", "behaviors" or "tools", as composition
+    /// reads it
+    pub fn prompt_content(&mut self, category: &str, name: &str) -> Result<String, PromptError> {
+        self.loader.read_prompt(category, name)
+    }
+
+    /// Path of the prompts directory this composer reads from
+    pub fn prompts_dir(&self) -> &str {
+        self.loader.prompts_dir()
+    }
+
+    /// Prompt file loads answered from the cache and loads that read the file
+    pub fn cache_counters(&self) -> CacheCounters {
+        self.loader.cache_counters()
+    }
+
+    /// Prompt files the composer keeps and their approximate bytes, with its cache counters
+    pub fn cache_usage(&self) -> CacheUsage {
+        self.loader.cache_usage()
+    }
+
+    /// Forget cached prompt files so edits on disk take effect
+    pub fn clear_cache(&mut self) {
+        self.loader.clear_cache();
+    }
 }
 
 impl Default for PromptComposer {
@@ -163,31 +888,86 @@ impl Default for PromptComposer {
     }
 }
 
-/// Assess task complexity based on user prompt and configuration
-fn assess_task_complexity(request: &PromptRequest) -> TaskComplexity {
-    match request.task_complexity {
-        Some(ref complexity) => complexity.clone(),
+/// Seed derived from the clock, for requests that do not supply one
+fn generate_seed() -> u64 {
+    crate::SystemTime::now()
+        .duration_since(crate::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// The request's session state, with fields the host left unset derived from the
+/// conversation: the first user turn as the original task, user and assistant turns
+/// as recent messages, and tool turns as the tool call count. The request's state is
+/// borrowed when there is no conversation to fill it from.
+fn session_state_with_conversation(request: &PromptRequest) -> Cow<'_, SessionState> {
+    let conversation = request.conversation.as_deref().unwrap_or_default();
+    match &request.session_state {
+        Some(state) if conversation.is_empty() => return Cow::Borrowed(state),
+        None if conversation.is_empty() => return Cow::Owned(SessionState::default()),
+        _ => {}
+    }
+    let mut state = request.session_state.clone().unwrap_or_default();
+
+    if state.original_task.is_none() {
+        state.original_task = conversation.iter()
+            .find(|message| message.role == MessageRole::User)
+            .map(|message| message.content.clone());
+    }
+    if state.recent_messages.is_none() {
+        let messages: Vec<String> = conversation.iter()
+            .filter(|message| matches!(message.role, MessageRole::User | MessageRole::Assistant))
+            .map(|message| message.content.clone())
+            .collect();
+        state.recent_messages = (!messages.is_empty()).then_some(messages);
+    }
+    if state.tool_call_count.is_none() {
+        let tool_calls = conversation.iter().filter(|message| message.role == MessageRole::Tool).count();
+        state.tool_call_count = (tool_calls > 0).then_some(tool_calls as u32);
+    }
+    Cow::Owned(state)
+}
+
+/// Whether a system turn of the conversation already carries this module content
+fn already_instructed(request: &PromptRequest, module_content: &str) -> bool {
+    let module_content = module_content.trim();
+    !module_content.is_empty() && request.conversation.iter().flatten()
+        .any(|message| message.role == MessageRole::System && message.content.contains(module_content))
+}
+
+/// Bucket a module into one of its variants. The choice is stable for a given seed and
+/// module, so hosts that derive the seed from a user or session id keep that user in
+/// the same bucket across requests.
+pub fn assign_variant<'a>(seed: u64, module: &str, variants: &'a [String]) -> Option<&'a str> {
+    if variants.is_empty() {
+        return None;
+    }
+    let hash = fnv1a(seed.to_le_bytes().iter().chain(module.as_bytes()));
+    Some(variants[(hash % variants.len() as u64) as usize].as_str())
+}
+
+/// FNV-1a, used where hashes must not change between Rust releases
+pub(crate) fn fnv1a<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Resolve the task complexity: the level the request asks for, or one assessed from
+/// the user prompt and configuration along with the evidence behind it
+fn assess_task_complexity(
+    request: &PromptRequest,
+    matcher: &ComplexityMatcher
+) -> (TaskComplexity, Option<ComplexityEvidence>) {
+    match request.task_complexity.as_ref().and_then(RequestedComplexity::level) {
+        Some(complexity) => (complexity, None),
         None => {
             // Auto-detect complexity
-            let prompt = &request.user_prompt;
-            let complex_indicators = [
-                "refactor", "implement", "create", "build", "develop",
-                "comprehensive", "analysis", "strategy", "plan", "design",
-                "multiple", "all", "entire", "complete", "full", "system"
-            ];
-            
-            let prompt_lower = prompt.to_lowercase();
-            let has_complex_keywords = complex_indicators.iter()
-                .any(|indicator| prompt_lower.contains(indicator));
-            
-            let is_long = prompt.len() > 100;
-            let has_multiple_servers = request.mcp_config.mcp_servers.len() > 2;
-            
-            if has_complex_keywords || is_long || has_multiple_servers {
-                TaskComplexity::Complex
-            } else {
-                TaskComplexity::Simple
-            }
+            let (complexity, evidence) = matcher.assess(&request.user_prompt, request.mcp_config.mcp_servers.len());
+            (complexity, Some(evidence))
         }
     }
 }
@@ -195,6 +975,11 @@ fn assess_task_complexity(request: &PromptRequest) -> TaskComplexity {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rules::ComplexityRules;
+    use proptest::collection::{btree_map, vec};
+    use proptest::option::of;
+    use proptest::prelude::*;
+    use proptest::sample::select;
     use std::collections::HashMap;
 
     #[test]
@@ -214,6 +999,18 @@ mod tests {
             domain_hints: None,
             behavior_hints: None,
             task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
         };
 
         let complex_request = PromptRequest {
@@ -223,12 +1020,735 @@ mod tests {
             domain_hints: None,
             behavior_hints: None,
             task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
         };
 
-        let simple_complexity = assess_task_complexity(&simple_request);
-        let complex_complexity = assess_task_complexity(&complex_request);
+        let matcher = ComplexityRules::default().compile().unwrap();
+        let (simple_complexity, _) = assess_task_complexity(&simple_request, &matcher);
+        let (complex_complexity, _) = assess_task_complexity(&complex_request, &matcher);
 
         assert!(matches!(simple_complexity, TaskComplexity::Simple));
         assert!(matches!(complex_complexity, TaskComplexity::Complex));
+
+        // Auto is resolved like an unset complexity
+        let auto_request = PromptRequest { task_complexity: Some(RequestedComplexity::Auto), ..complex_request };
+        let (auto_complexity, evidence) = assess_task_complexity(&auto_request, &matcher);
+        assert_eq!(auto_complexity, TaskComplexity::Complex);
+        assert!(evidence.is_some());
+    }
+
+    #[test]
+    fn test_response_format_echoes_schema() {
+        let request = PromptRequest {
+            user_prompt: "List the open issues".to_string(),
+            mcp_config: McpConfig { mcp_servers: HashMap::new() },
+            session_state: None,
+            domain_hints: None,
+            behavior_hints: None,
+            task_complexity: None,
+            response_format: Some(ResponseFormat::Json {
+                schema: Some(serde_json::json!({"type": "array", "items": {"type": "string"}})),
+            }),
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
+        };
+
+        let response = PromptComposer::new().compose(&request, &[]).unwrap();
+        assert!(response.applied_modules.contains(&"output_format".to_string()));
+        assert!(response.system_prompt.contains("\"type\": \"array\""));
+    }
+
+    #[test]
+    fn test_trace_id_is_echoed_in_metadata() {
+        let request = PromptRequest::builder()
+            .user_prompt("List the open issues")
+            .trace_id("4bf92f3577b34da6a3ce929d0e0e4736")
+            .build()
+            .unwrap();
+        let response = PromptComposer::new().compose(&request, &[]).unwrap();
+        assert_eq!(response.metadata.trace_id.as_deref(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+
+        let untraced = PromptRequest { trace_id: None, ..request };
+        assert!(PromptComposer::new().compose(&untraced, &[]).unwrap().metadata.trace_id.is_none());
+    }
+
+    #[test]
+    fn test_profile_times_modules_and_file_loads() {
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement the migration, then run the tests")
+            .profile(true)
+            .build()
+            .unwrap();
+        let mut composer = PromptComposer::with_prompts_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string());
+        let first = composer.compose(&request, &[]).unwrap();
+        let profile = first.profile.unwrap();
+        let timed: Vec<&str> = profile.modules.iter().map(|timing| timing.module.as_str()).collect();
+        assert!(first.applied_modules.iter().all(|module| timed.contains(&module.as_str())));
+        let planning = profile.modules.iter().find(|timing| timing.module == "task_planning").unwrap();
+        assert_eq!(planning.file_loads[0].file, "behaviors/planning.md");
+        assert!(!planning.file_loads[0].cached);
+
+        let second = composer.compose(&request, &[]).unwrap().profile.unwrap();
+        assert!(second.modules.iter().flat_map(|timing| &timing.file_loads).all(|load| load.cached));
+        assert!(composer.compose(&PromptRequest { profile: None, ..request.clone() }, &[]).unwrap().profile.is_none());
+
+        let failing = PromptRequest {
+            module_versions: Some(HashMap::from([("planning".to_string(), "v99".to_string())])),
+            ..request.clone()
+        };
+        assert!(composer.compose(&failing, &[]).is_err());
+        composer.loader.load_behavior("planning").unwrap();
+        assert!(composer.loader.take_file_loads().is_empty());
+
+        let timings = [ModuleTiming { module: "task_planning".to_string(), duration_ms: 61.0, file_loads: Vec::new() }];
+        let warning = slow_composition_warning(Duration::from_millis(64), 2.5, &timings);
+        assert!(warning.message.starts_with("Prompt composition took 64ms, most in task_planning 61.0ms, selection 2.5ms;"));
+    }
+
+    #[test]
+    fn test_conversation_informs_composition() {
+        let tools = vec![Tool {
+            name: "fs.read_file".to_string(),
+            description: "Read a file".to_string(),
+            server: "fs".to_string(),
+            schema: None,
+        }];
+        let request = PromptRequest::builder()
+            .user_prompt("now do it")
+            .task_complexity(TaskComplexity::Simple)
+            .message(MessageRole::User, "The python function in parser.rs panics on empty input")
+            .message(MessageRole::Tool, "thread 'main' panicked at src/parser.rs:12")
+            .build()
+            .unwrap();
+        let state = session_state_with_conversation(&request);
+        assert_eq!(state.tool_call_count, Some(1));
+        assert_eq!(state.recent_messages.as_ref().map(Vec::len), Some(1));
+
+        let mut composer = PromptComposer::new();
+        let first = composer.compose(&request, &tools).unwrap();
+        assert!(first.applied_modules.contains(&"programming".to_string()));
+
+        // Guidance already given in a system turn is not repeated
+        let mut repeated = request.clone();
+        repeated.conversation.as_mut().unwrap().insert(0, Message {
+            role: MessageRole::System,
+            content: first.system_prompt.clone(),
+        });
+        let second = composer.compose(&repeated, &tools).unwrap();
+        assert!(!second.applied_modules.contains(&"programming".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_policy_module_is_a_warning() {
+        let request = PromptRequest::builder()
+            .user_prompt("Tidy up the notes")
+            .policy(SelectionPolicy { enable: vec!["no_such_module".to_string()], ..Default::default() })
+            .build()
+            .unwrap();
+
+        let response = PromptComposer::new().compose(&request, &[]).unwrap();
+        let warning = response.warnings.iter().find(|w| w.source == "selection").unwrap();
+        assert_eq!(warning.severity, WarningSeverity::Warning);
+        assert!(warning.message.contains("'no_such_module'"));
+    }
+
+    #[test]
+    fn test_workspace_is_stated_first() {
+        let request = PromptRequest::builder()
+            .user_prompt("Run the tests")
+            .workspace(WorkspaceContext {
+                cwd: Some("/home/dev/app".to_string()),
+                os: Some("linux".to_string()),
+                git_branch: Some("main".to_string()),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let mut composer = PromptComposer::new();
+        let response = composer.compose(&request, &[]).unwrap();
+        assert_eq!(response.applied_modules.first().map(String::as_str), Some("workspace"));
+        assert!(response.system_prompt.contains("- Working directory: /home/dev/app"));
+        assert!(!response.system_prompt.contains("Shell:"));
+
+        composer.set_module_enabled("workspace", false);
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(!response.system_prompt.contains("WORKSPACE:"));
+    }
+
+    #[test]
+    fn test_hooks_surround_composition() {
+        use crate::hooks::CompositionHook;
+        use std::sync::Mutex;
+
+        struct Framing {
+            composed: Mutex<Vec<String>>,
+        }
+
+        impl CompositionHook for Framing {
+            fn name(&self) -> &str {
+                "framing"
+            }
+
+            fn prologue(&self, request: &PromptRequest) -> Result<Option<String>, PromptError> {
+                Ok(Some(format!("TENANT NOTE for: {}", request.user_prompt)))
+            }
+
+            fn epilogue(&self, _request: &PromptRequest) -> Result<Option<String>, PromptError> {
+                Err(PromptError::config("epilogue store unavailable"))
+            }
+
+            fn on_compose(&self, _request: &PromptRequest, response: &PromptResponse) {
+                self.composed.lock().unwrap().push(response.metadata.content_hash.clone());
+            }
+        }
+
+        let hook = Arc::new(Framing { composed: Mutex::new(Vec::new()) });
+        let mut composer = PromptComposer::new();
+        composer.hooks_mut().add(hook.clone());
+        let request = PromptRequest::builder().user_prompt("Plan the release").build().unwrap();
+
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(response.system_prompt.starts_with("TENANT NOTE for: Plan the release"));
+        assert_eq!(response.applied_modules.first().map(String::as_str), Some("hook:framing"));
+        assert!(response.warnings.iter().any(|w| w.source == "hook:framing" && w.message.contains("epilogue store unavailable")));
+        assert_eq!(*hook.composed.lock().unwrap(), vec![response.metadata.content_hash.clone()]);
+
+        assert!(composer.hooks_mut().remove("framing"));
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(!response.system_prompt.contains("TENANT NOTE"));
+        assert_eq!(hook.composed.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_streamed_sections_make_up_the_prompt() {
+        let mut composer = PromptComposer::new();
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement the parser refactor")
+            .domain_hint("programming")
+            .behavior_hint("planning")
+            .build()
+            .unwrap();
+
+        let mut sections = Vec::new();
+        let response = composer.compose_streaming(&request, &[], |section| sections.push(section)).unwrap();
+        assert!(sections.len() >= 2);
+        assert_eq!(sections.iter().map(|section| section.text.as_str()).collect::<String>(), response.system_prompt);
+        assert!(sections.iter().all(|section| response.applied_modules.contains(&section.module)));
+        assert_eq!(composer.compose(&request, &[]).unwrap().system_prompt, response.system_prompt);
+
+        // A budget smaller than the prompt leaves out the sections past it
+        let budget = (response.system_prompt.len() / CHARS_PER_TOKEN / 2) as u32;
+        let limited = PromptRequest { max_tokens: Some(budget), ..request };
+        let response = composer.compose(&limited, &[]).unwrap();
+        assert!(response.system_prompt.len() <= budget as usize * CHARS_PER_TOKEN);
+        assert!(response.warnings.iter().any(|w| w.message.contains("to stay within max_tokens")));
+        assert!(sections.iter().any(|section| !response.applied_modules.contains(&section.module)));
+    }
+
+    struct ReleaseNotesModule;
+
+    impl PromptModule for ReleaseNotesModule {
+        fn name(&self) -> &str {
+            "release_notes"
+        }
+
+        fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, _loader: &mut PromptLoader) -> Result<String, PromptError> {
+            Ok("\nRELEASE NOTES:\nMention user-facing changes.".to_string())
+        }
+
+        fn applies_to(&self, _tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> bool {
+            user_prompt.contains("release")
+        }
+    }
+
+    #[test]
+    fn test_registered_module_joins_composition() {
+        let mut composer = PromptComposer::new();
+        composer.register_module(Arc::new(ReleaseNotesModule));
+
+        let mut request = PromptRequest {
+            user_prompt: "Draft the release announcement".to_string(),
+            mcp_config: McpConfig { mcp_servers: HashMap::new() },
+            session_state: None,
+            domain_hints: None,
+            behavior_hints: None,
+            task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
+        };
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(response.applied_modules.contains(&"release_notes".to_string()));
+        assert!(response.system_prompt.contains("RELEASE NOTES:"));
+
+        request.user_prompt = "Say hello".to_string();
+        request.behavior_hints = Some(vec!["release_notes".to_string()]);
+        let response = composer.compose(&request, &[]).unwrap();
+        assert_eq!(response.applied_modules, vec!["release_notes"]);
+        let info = composer.list_modules().into_iter().find(|info| info.name == "release_notes").unwrap();
+        assert_eq!((info.kind, info.source), (ModuleKind::Behavior, ModuleSource::Registered));
+        assert!(composer.set_module_enabled("release_notes", false));
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(response.applied_modules.is_empty());
+        assert!(response.selection_trace.iter().any(|decision| decision.reason == "disabled in the registry"));
+    }
+
+    #[test]
+    fn test_selection_seed_and_trace_are_reported() {
+        let request = PromptRequest {
+            user_prompt: "Analyze the csv data and report trends".to_string(),
+            mcp_config: McpConfig { mcp_servers: HashMap::new() },
+            session_state: None,
+            domain_hints: None,
+            behavior_hints: None,
+            task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: Some(42),
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
+        };
+
+        let response = PromptComposer::new().compose(&request, &[]).unwrap();
+        assert_eq!(response.selection_seed, 42);
+
+        let analysis = response.selection_trace.iter().find(|d| d.module == "analysis").unwrap();
+        assert!(analysis.selected);
+        assert_eq!(analysis.source, DecisionSource::Detected);
+        let system = response.selection_trace.iter().find(|d| d.module == "system").unwrap();
+        assert!(!system.selected);
+        assert_eq!(system.reason, "not relevant");
+
+        let explained = PromptComposer::new().explain(&request, &[]).unwrap();
+        assert_eq!(serde_json::to_value(&explained).unwrap(), serde_json::to_value(&response.selection_trace).unwrap());
+    }
+
+    /// Counts how often its guidance has been shown in each session
+    struct ReminderModule {
+        ended: Arc<std::sync::atomic::AtomicU64>,
+    }
+
+    impl PromptModule for ReminderModule {
+        fn name(&self) -> &str {
+            "reminder"
+        }
+
+        fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, _loader: &mut PromptLoader) -> Result<String, PromptError> {
+            Ok(String::new())
+        }
+
+        fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, _session_state: &SessionState) -> bool {
+            true
+        }
+
+        fn on_session_start(&self, state: &mut ModuleState) {
+            state.insert("shown".to_string(), 0.into());
+        }
+
+        fn on_session_end(&self, state: &mut ModuleState) {
+            let shown = state["shown"].as_u64().unwrap_or_default();
+            self.ended.store(shown, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        fn generate_stateful(
+            &self,
+            _tools: &[Tool],
+            _session_state: &SessionState,
+            _loader: &mut PromptLoader,
+            state: &mut ModuleState
+        ) -> Result<String, PromptError> {
+            let shown = state["shown"].as_u64().unwrap_or_default() + 1;
+            state.insert("shown".to_string(), shown.into());
+            Ok(format!("\nREMINDER #{}", shown))
+        }
+    }
+
+    #[test]
+    fn test_module_state_persists_across_session_turns() {
+        let ended = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let mut composer = PromptComposer::new();
+        composer.register_module(Arc::new(ReminderModule { ended: ended.clone() }));
+
+        let request = PromptRequest {
+            user_prompt: "Say hello".to_string(),
+            mcp_config: McpConfig { mcp_servers: HashMap::new() },
+            session_state: Some(SessionState { session_id: Some("s1".to_string()), ..Default::default() }),
+            domain_hints: None,
+            behavior_hints: Some(vec!["reminder".to_string()]),
+            task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
+        };
+        assert!(composer.compose(&request, &[]).unwrap().system_prompt.contains("REMINDER #1"));
+        assert!(composer.compose(&request, &[]).unwrap().system_prompt.contains("REMINDER #2"));
+
+        assert!(composer.end_session("s1"));
+        assert_eq!(ended.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert!(!composer.end_session("s1"));
+    }
+
+    #[test]
+    fn test_plugins_directory_adds_modules() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-plugins-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("domains")).unwrap();
+        std::fs::create_dir_all(dir.join("behaviors")).unwrap();
+        std::fs::create_dir_all(dir.join("plugins")).unwrap();
+        std::fs::write(dir.join("plugins/style.toml"), r###"
+            [[modules]]
+            name = "house_style"
+            keywords = ["announcement"]
+            text = "## Voice\n- Write in plain English"
+        "###).unwrap();
+
+        let mut composer = PromptComposer::with_prompts_dir(dir.to_string_lossy().to_string());
+        let info = composer.list_modules().into_iter().find(|info| info.name == "house_style").unwrap();
+        assert_eq!(info.source, ModuleSource::Plugin);
+
+        let request = PromptRequest {
+            user_prompt: "Draft the announcement".to_string(),
+            mcp_config: McpConfig { mcp_servers: HashMap::new() },
+            session_state: None,
+            domain_hints: None,
+            behavior_hints: None,
+            task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
+        };
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(response.system_prompt.contains("HOUSE_STYLE:\nVoice:\n- Write in plain English"));
+
+        std::fs::write(dir.join("plugins/broken.toml"), "[[modules]]").unwrap();
+        std::fs::remove_file(dir.join("plugins/style.toml")).unwrap();
+        assert_eq!(composer.reload_plugins(), 0);
+        assert!(composer.list_modules().iter().all(|info| info.name != "house_style"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_variants_are_composed_and_reported() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-variants-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("domains")).unwrap();
+        std::fs::create_dir_all(dir.join("behaviors")).unwrap();
+        std::fs::write(dir.join("behaviors/concise.md"), "## Style\n- Be brief").unwrap();
+        std::fs::write(dir.join("behaviors/concise.terse.md"), "## Style\n- One line only").unwrap();
+
+        let mut request = PromptRequest {
+            user_prompt: "Say hello".to_string(),
+            mcp_config: McpConfig { mcp_servers: HashMap::new() },
+            session_state: None,
+            domain_hints: None,
+            behavior_hints: Some(vec!["concise".to_string()]),
+            task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: Some(HashMap::from([("concise".to_string(), "terse".to_string())])),
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
+        };
+        let mut composer = PromptComposer::with_prompts_dir(dir.to_string_lossy().to_string());
+        let response = composer.compose(&request, &[]).unwrap();
+        assert_eq!(response.applied_modules, vec!["concise.terse"]);
+        assert!(response.system_prompt.contains("One line only"));
+
+        request.variant_assignments = Some(HashMap::from([("concise".to_string(), "missing".to_string())]));
+        let response = composer.compose(&request, &[]).unwrap();
+        assert_eq!(response.applied_modules, vec!["concise"]);
+
+        let variants = vec!["control".to_string(), "experiment".to_string()];
+        assert_eq!(assign_variant(7, "concise", &variants), assign_variant(7, "concise", &variants));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Text likely to trip up composition: any characters, and names and markup that
+    /// modules and prompt files use
+    fn text() -> impl Strategy<Value = String> {
+        prop_oneof![
+            any::<String>(),
+            "[a-z_.@]{0,12}",
+            "(plan|implement|test|read file|\\.\\./|@|#|- |\n|```|é|🚀| ){0,12}",
+        ]
+    }
+
+    prop_compose! {
+        fn session_state()(
+            session_id in of(text()),
+            tool_call_count in of(any::<u32>()),
+            consecutive_failures in of(any::<u32>()),
+            has_plan in of(any::<bool>()),
+            recent_errors in of(vec(text(), 0..3)),
+            files_modified in of(vec(text(), 0..3)),
+            results in of(vec((text(), any::<bool>()), 0..3)),
+            memories in of(vec(text(), 0..3)),
+            phase in of(select(vec!["Exploration", "Planning", "Execution", "Review"])),
+            agent_role in of(select(vec!["Orchestrator", "Worker"])),
+            recent_messages in of(vec(text(), 0..3)),
+        ) -> serde_json::Value {
+            let results = results.map(|results| results.into_iter()
+                .map(|(tool, success)| serde_json::json!({"tool": tool, "success": success}))
+                .collect::<Vec<_>>());
+            serde_json::json!({
+                "session_id": session_id, "tool_call_count": tool_call_count, "consecutive_failures": consecutive_failures,
+                "has_plan": has_plan, "recent_errors": recent_errors, "files_modified": files_modified,
+                "last_tool_results": results, "memories": memories, "phase": phase, "agent_role": agent_role,
+                "recent_messages": recent_messages,
+            })
+        }
+    }
+
+    prop_compose! {
+        fn request_json()(
+            user_prompt in text(),
+            servers in btree_map(text(), text(), 0..3),
+            session_state in of(session_state()),
+            hints in (of(vec(text(), 0..3)), of(vec(text(), 0..3))),
+            task_complexity in of(select(vec!["Auto", "Trivial", "Simple", "Moderate", "Complex", "Epic"])),
+            response_format in of(prop_oneof![
+                Just(serde_json::json!({"type": "Json", "schema": {"type": "object"}})),
+                vec(text(), 0..3).prop_map(|columns| serde_json::json!({"type": "MarkdownTable", "columns": columns})),
+                text().prop_map(|language| serde_json::json!({"type": "CodeOnly", "language": language})),
+            ]),
+            policy in of((vec(text(), 0..2), vec(text(), 0..2), btree_map(text(), -4.0f32..4.0, 0..2))),
+            numbers in (of(any::<u64>()), of(any::<u32>()), of(any::<bool>())),
+            files in (of(btree_map(text(), text(), 0..2)), of(btree_map(text(), text(), 0..2)), of(text())),
+            workspace in of(vec(text(), 5)),
+            conversation in of(vec((select(vec!["system", "user", "assistant", "tool"]), text()), 0..4)),
+            trace_id in of(text()),
+        ) -> serde_json::Value {
+            let servers: serde_json::Map<String, serde_json::Value> = servers.into_iter()
+                .map(|(name, command)| (name, serde_json::json!({"command": command})))
+                .collect();
+            let policy = policy.map(|(enable, disable, weights)| serde_json::json!({"enable": enable, "disable": disable, "weights": weights}));
+            let workspace = workspace.map(|facts| serde_json::json!({
+                "cwd": facts[0], "os": facts[1], "shell": facts[2], "git_branch": facts[3], "project_type": facts[4],
+            }));
+            let conversation = conversation.map(|turns| turns.into_iter()
+                .map(|(role, content)| serde_json::json!({"role": role, "content": content}))
+                .collect::<Vec<_>>());
+            serde_json::json!({
+                "user_prompt": user_prompt, "mcp_config": {"mcpServers": servers}, "session_state": session_state,
+                "domain_hints": hints.0, "behavior_hints": hints.1, "task_complexity": task_complexity,
+                "response_format": response_format, "policy": policy, "selection_seed": numbers.0,
+                "max_tokens": numbers.1, "profile": numbers.2, "module_versions": files.0,
+                "variant_assignments": files.1, "locale": files.2, "workspace": workspace,
+                "conversation": conversation, "trace_id": trace_id,
+            })
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(128))]
+
+        /// Whatever a host sends, composition returns a response or an error
+        #[test]
+        fn test_no_request_panics_the_composer(request in request_json()) {
+            let request: PromptRequest = serde_json::from_value(request).unwrap();
+            let tools: Vec<Tool> = request.mcp_config.mcp_servers.keys()
+                .map(|server| Tool { name: format!("{}.run", server), description: request.user_prompt.clone(), server: server.clone(), schema: None })
+                .collect();
+            let mut composer = PromptComposer::embedded();
+            let _ = composer.explain(&request, &tools);
+            if let Ok(response) = composer.compose(&request, &tools) {
+                response.to_report(crate::report::ReportFormat::Html);
+            }
+            let _ = PromptComposer::with_prompts_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string()).compose(&request, &tools);
+        }
+    }
+
+    #[test]
+    fn test_missing_prompt_file_error_names_module_file_and_cause() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-missing-file-{}", std::process::id()));
+        crate::embedded::scaffold(&dir, true).unwrap();
+        std::fs::remove_file(dir.join("behaviors/tools.md")).unwrap();
+        let prompts_dir = dir.to_string_lossy().to_string();
+        let tools = vec![Tool { name: "lookup".to_string(), description: "Look up a record".to_string(), schema: None, server: "records".to_string() }];
+        let request = PromptRequest::builder().user_prompt("Find the record").build().unwrap();
+        let error = PromptComposer::with_prompts_dir(prompts_dir.clone()).compose(&request, &tools).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let report = error.report();
+        assert_eq!(report.code, ErrorCode::ModuleLoadingFailed);
+        assert_eq!(report.module.as_deref(), Some("tool_usage"));
+        assert_eq!(report.category.as_deref(), Some("behaviors"));
+        assert!(report.path.unwrap().ends_with("tools.md"));
+        assert_eq!(report.prompts_dir, Some(prompts_dir));
+        let cause = std::error::Error::source(&error).unwrap().downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(cause.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(report.causes, vec![cause.to_string()]);
+        assert!(report.message.ends_with(&format!(": {}", cause)));
+    }
+
+    #[test]
+    fn test_identical_requests_compose_identical_prompts() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-deterministic-{}", std::process::id()));
+        crate::embedded::scaffold(&dir, true).unwrap();
+        let servers: Vec<String> = (0..8).map(|i| format!("server{}", i)).collect();
+        for server in &servers {
+            std::fs::write(dir.join("tools").join(format!("{}.md", server)), format!("## Using {}\n- Call it with care", server)).unwrap();
+        }
+        let prompts_dir = dir.to_string_lossy().to_string();
+        let config: serde_json::Map<String, serde_json::Value> = servers.iter()
+            .map(|server| (server.clone(), serde_json::json!({"command": server})))
+            .collect();
+        let request = serde_json::json!({
+            "user_prompt": "Plan and implement the migration, then run the tests",
+            "mcp_config": {"mcpServers": config},
+            "selection_seed": 7,
+        }).to_string();
+
+        // Each request and discovery has maps of its own, iterated in orders of their own
+        let compose = || {
+            let request: PromptRequest = serde_json::from_str(&request).unwrap();
+            let tools = crate::discovery::ToolDiscovery::with_prompts_dir(prompts_dir.clone()).discover_tools_immediate(&request.mcp_config).unwrap();
+            let mut response = serde_json::to_value(PromptComposer::with_prompts_dir(prompts_dir.clone()).compose(&request, &tools).unwrap()).unwrap();
+            response["metadata"]["duration_ms"] = serde_json::json!(0);
+            response.to_string()
+        };
+        let first = compose();
+        for _ in 0..10 {
+            assert_eq!(compose(), first);
+        }
+        let prompt = serde_json::from_str::<serde_json::Value>(&first).unwrap()["system_prompt"].as_str().unwrap().to_string();
+        let positions: Vec<usize> = servers.iter().map(|server| prompt.find(&format!("Using {}:", server)).unwrap()).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "tool instructions are in order of server name");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_offline_composition_starts_no_process() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-offline-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("domains")).unwrap();
+        std::fs::create_dir_all(dir.join("behaviors")).unwrap();
+        std::fs::write(dir.join("selection_rules.toml"), r#"
+            [[external_modules]]
+            name = "company_style"
+            command = "sh"
+            args = ["-c", "printf 'COMPANY STYLE'"]
+        "#).unwrap();
+
+        let request = PromptRequest::builder().user_prompt("Write the changelog").build().unwrap();
+        let mut composer = PromptComposer::with_prompts_dir(dir.to_string_lossy().to_string());
+        assert!(composer.compose(&request, &[]).unwrap().system_prompt.contains("COMPANY STYLE"));
+        composer.set_offline(true);
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(!response.system_prompt.contains("COMPANY STYLE"));
+        assert!(response.selection_trace.iter().all(|decision| decision.module != "company_style"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_exported_config_recreates_the_composer() {
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement a refactor of the parser")
+            .selection_seed(3)
+            .build()
+            .unwrap();
+        let mut original = PromptComposer::with_prompts_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string());
+        original.rules.planning.long_prompt_length = 10;
+        original.set_module_enabled("safety", false);
+        original.set_offline(true);
+
+        let exported = original.export_config().unwrap();
+        assert_eq!(exported, original.export_config().unwrap());
+        let mut imported = PromptComposer::import_config(&exported).unwrap();
+        assert_eq!(imported.export_config().unwrap(), exported);
+        assert!(imported.is_offline());
+        assert_eq!(imported.rules.planning.long_prompt_length, 10);
+        assert!(!imported.registry().is_enabled("safety"));
+        assert_eq!(
+            imported.compose(&request, &[]).unwrap().system_prompt,
+            original.compose(&request, &[]).unwrap().system_prompt
+        );
+
+        let files = HashMap::from([("behaviors/planning.md".to_string(), "## Plan\n- in memory".to_string())]);
+        let in_memory = PromptComposer::from_files(files).export_config().unwrap();
+        let imported = PromptComposer::import_config(&in_memory).unwrap();
+        assert_eq!(imported.list_behaviors().unwrap(), vec!["planning"]);
+
+        let future = exported.replacen("\"format\": 1", "\"format\": 99", 1);
+        assert!(matches!(PromptComposer::import_config(&future), Err(PromptError::ConfigError { .. })));
+    }
+
+    #[test]
+    fn test_embedded_prompts_compose_like_the_bundled_directory() {
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement a refactor of the parser")
+            .behavior_hint("planning")
+            .selection_seed(7)
+            .build()
+            .unwrap();
+        let mut bundled = PromptComposer::with_prompts_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string());
+        let mut embedded = PromptComposer::embedded();
+
+        assert_eq!(embedded.prompts_dir(), crate::embedded::PROMPTS_DIR);
+        assert_eq!(embedded.list_behaviors().unwrap(), bundled.list_behaviors().unwrap());
+        assert_eq!(
+            embedded.compose(&request, &[]).unwrap().system_prompt,
+            bundled.compose(&request, &[]).unwrap().system_prompt
+        );
     }
 }
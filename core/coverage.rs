@@ -0,0 +1,158 @@
+//! Coverage of a prompts directory by a corpus of recorded requests: which prompt files,
+//! and which `##` sections of them, made it into the prompts composed for the requests,
+//! so teams can find content no request uses and prune it from large prompts
+//! directories.
+
+use crate::composition::PromptComposer;
+use crate::engine::offline_tools;
+use crate::loader::{extract_guidance, markdown_sections, split_frontmatter};
+use crate::types::{PromptError, PromptRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// How often a `##` section of a prompt file was composed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SectionCoverage {
+    /// The section's heading; empty for the guidance before the first section
+    pub heading: String,
+    /// Line of the file the section starts on, counting from 1
+    pub line: usize,
+    /// Requests whose prompt has the section's guidance
+    pub compositions: usize,
+}
+
+/// How often a prompt file was composed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileCoverage {
+    /// Path in the prompts directory, such as `behaviors/planning.md`
+    pub path: String,
+    /// Requests whose composition loaded the file
+    pub compositions: usize,
+    pub sections: Vec<SectionCoverage>,
+}
+
+/// Coverage of a prompts directory by a corpus of requests
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub prompts_dir: String,
+    /// Requests composed
+    pub requests: usize,
+    /// Requests whose composition failed, which cover nothing
+    pub failed: usize,
+    /// Every prompt file of `domains`, `behaviors` and `tools`, by path
+    pub files: Vec<FileCoverage>,
+}
+
+impl CoverageReport {
+    /// Files no request's composition loaded
+    pub fn unused_files(&self) -> impl Iterator<Item = &FileCoverage> {
+        self.files.iter().filter(|file| file.compositions == 0)
+    }
+
+    /// Sections of files some request loaded that no prompt had, such as sections
+    /// dropped to stay within `max_tokens`
+    pub fn unused_sections(&self) -> impl Iterator<Item = (&FileCoverage, &SectionCoverage)> {
+        self.files.iter()
+            .filter(|file| file.compositions > 0)
+            .flat_map(|file| file.sections.iter().filter(|section| section.compositions == 0).map(move |section| (file, section)))
+    }
+}
+
+/// Compose each request against `prompts_dir`, with tools inferred from its config, and
+/// count the requests that composed each prompt file and each of its sections
+pub fn measure_coverage(prompts_dir: &str, requests: &[PromptRequest]) -> Result<CoverageReport, PromptError> {
+    let root = Path::new(prompts_dir);
+    if !root.is_dir() {
+        return Err(PromptError::config("Prompts directory does not exist").with_path(root));
+    }
+    // Each file with the guidance each of its sections puts in a prompt
+    let mut files: Vec<(FileCoverage, Vec<String>)> = Vec::new();
+    for category in ["domains", "behaviors", "tools"] {
+        let mut paths: Vec<_> = fs::read_dir(root.join(category)).into_iter().flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("md"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| PromptError::config(format!("Failed to read prompt file: {}", e)).with_path(&path).with_source(e))?;
+            let (body, _) = split_frontmatter(&content);
+            let offset = content[..content.len() - body.len()].matches('\n').count();
+            let (sections, guidance) = markdown_sections(body).into_iter()
+                .map(|(heading, range)| {
+                    let line = offset + body[..range.start].matches('\n').count() + 1;
+                    (SectionCoverage { heading, line, compositions: 0 }, extract_guidance(&body[range]))
+                })
+                .unzip();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            files.push((FileCoverage { path: format!("{}/{}", category, name), compositions: 0, sections }, guidance));
+        }
+    }
+
+    let mut composer = PromptComposer::with_prompts_dir(prompts_dir.to_string());
+    let mut report = CoverageReport { prompts_dir: prompts_dir.to_string(), requests: requests.len(), ..Default::default() };
+    for request in requests {
+        let request = PromptRequest { profile: Some(true), ..request.clone() };
+        let tools = offline_tools(&request.mcp_config, Some(prompts_dir));
+        let response = match composer.compose(&request, &tools) {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::debug!(trace_id = request.trace_id.as_deref(), "Composition for coverage failed: {}", e);
+                report.failed += 1;
+                continue;
+            }
+        };
+        let loaded: BTreeSet<&str> = response.profile.iter()
+            .flat_map(|profile| &profile.modules)
+            .flat_map(|module| &module.file_loads)
+            .map(|load| load.file.as_str())
+            .collect();
+        for (file, guidance) in &mut files {
+            if !loaded.contains(file.path.as_str()) {
+                continue;
+            }
+            file.compositions += 1;
+            for (section, guidance) in file.sections.iter_mut().zip(guidance.iter()) {
+                if response.system_prompt.contains(guidance.as_str()) {
+                    section.compositions += 1;
+                }
+            }
+        }
+    }
+
+    report.files = files.into_iter().map(|(file, _)| file).collect();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage_finds_unused_files_and_sections() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-coverage-{}", std::process::id()));
+        crate::embedded::scaffold(&dir, true).unwrap();
+        fs::write(dir.join("behaviors/planning.md"), "# Planning\n\nPlan first.\n\n## Steps\n- List the steps\n").unwrap();
+        fs::write(dir.join("domains/legacy.md"), "## Legacy\n- Nothing uses this\n").unwrap();
+        let planning = PromptRequest::builder().user_prompt("Plan and implement the migration, then run the tests").build().unwrap();
+        // Loads the planning file but leaves its guidance out to stay within max_tokens
+        let limited = PromptRequest { max_tokens: Some(5), ..planning.clone() };
+        let question = PromptRequest::builder().user_prompt("What does this function return?").build().unwrap();
+        let report = measure_coverage(&dir.to_string_lossy(), &[planning, limited, question]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((report.requests, report.failed), (3, 0));
+        let unused: Vec<&str> = report.unused_files().map(|file| file.path.as_str()).collect();
+        assert!(unused.contains(&"domains/legacy.md"));
+        assert!(!unused.contains(&"behaviors/planning.md"));
+        let planning = report.files.iter().find(|file| file.path == "behaviors/planning.md").unwrap();
+        assert_eq!(planning.compositions, 2);
+        let sections: Vec<(&str, usize, usize)> = planning.sections.iter()
+            .map(|section| (section.heading.as_str(), section.line, section.compositions))
+            .collect();
+        assert_eq!(sections, [("", 1, 1), ("Steps", 5, 1)]);
+        assert!(measure_coverage("/nonexistent/prompts", &[]).is_err());
+    }
+}
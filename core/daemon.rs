@@ -0,0 +1,167 @@
+//! Long-running daemon of the `daemon` feature, for editors and command-line tools that
+//! compose on every keystroke or turn. It answers JSON-RPC 2.0 on stdio, one message per
+//! line, with one composer whose prompt files and discovered tools stay cached between
+//! calls, so only the first call pays for reading the prompts directory.
+//!
+//! | Method | Params | Result |
+//! |---|---|---|
+//! | `initialize` | | server name, version and methods |
+//! | `compose` | `{"request": PromptRequest, "live"?: bool}` | `PromptResponse` |
+//! | `explain` | `{"request": PromptRequest}` | `ModuleDecision` array |
+//! | `refreshServer` | `{"server": string, "mcp_config": McpConfig}` | `Tool` array |
+//! | `listModules` | | `ModuleInfo` array |
+//! | `listDomains`, `listBehaviors` | | string array |
+//! | `cacheStats` | | `CacheStats` |
+//! | `clearCache` | | `null` |
+//! | `health` | | `{"status": "ok"}` while the daemon answers |
+//! | `readiness` | | `Readiness` of the prompts directory |
+//!
+//! Failures are JSON-RPC errors whose `data` is the error report. The daemon exits when
+//! its input is closed.
+
+use crate::jsonrpc::{self, RpcError};
+use crate::service::ComposerService;
+use crate::types::{ErrorCode, McpConfig, PromptError, PromptRequest};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// JSON-RPC error code of a failed composition, in the range reserved for servers
+pub const COMPOSITION_FAILED: i64 = -32000;
+
+const METHODS: &[&str] = &[
+    "initialize", "compose", "explain", "refreshServer", "listModules", "listDomains", "listBehaviors", "cacheStats", "clearCache",
+    "health", "readiness",
+];
+
+#[derive(Deserialize)]
+struct ComposeParams {
+    request: PromptRequest,
+    /// Discover tools by starting the request's MCP servers, instead of using cached or
+    /// inferred tools
+    #[serde(default)]
+    live: bool,
+}
+
+#[derive(Deserialize)]
+struct RefreshParams {
+    server: String,
+    mcp_config: McpConfig,
+}
+
+pub struct Daemon {
+    service: ComposerService,
+}
+
+impl Daemon {
+    /// A daemon reading prompts from `prompts_dir`, or the default prompts directory
+    pub fn new(prompts_dir: Option<String>) -> Self {
+        Self { service: ComposerService::new(prompts_dir) }
+    }
+
+    /// Answer one JSON-RPC method call
+    pub fn handle(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(json!({
+                "serverInfo": {"name": "prompt-composer", "version": env!("CARGO_PKG_VERSION")},
+                "methods": METHODS,
+            })),
+            "compose" => {
+                let params: ComposeParams = parse(params)?;
+                reply(self.service.compose(&params.request, params.live))
+            }
+            "explain" => {
+                let params: ComposeParams = parse(params)?;
+                reply(self.service.explain(&params.request))
+            }
+            "refreshServer" => {
+                let params: RefreshParams = parse(params)?;
+                reply(self.service.refresh_server(&params.server, &params.mcp_config))
+            }
+            "listModules" => Ok(json!(self.service.list_modules())),
+            "listDomains" => reply(self.service.list_domains()),
+            "listBehaviors" => reply(self.service.list_behaviors()),
+            "cacheStats" => Ok(json!(self.service.cache_stats())),
+            "clearCache" => {
+                self.service.clear_cache();
+                Ok(Value::Null)
+            }
+            "health" => Ok(json!({"status": "ok"})),
+            "readiness" => Ok(json!(self.service.readiness())),
+            _ => Err(RpcError::new(jsonrpc::METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| {
+        let report = PromptError::invalid_request(e.to_string()).with_field("params").report();
+        RpcError::new(jsonrpc::INVALID_PARAMS, report.message.clone()).with_data(json!(report))
+    })
+}
+
+/// A call's result, or its error as a JSON-RPC error carrying the report
+fn reply<T: serde::Serialize>(result: Result<T, PromptError>) -> Result<Value, RpcError> {
+    match result {
+        Ok(value) => Ok(json!(value)),
+        Err(e) => {
+            let report = e.report();
+            let code = match report.code {
+                ErrorCode::InvalidRequest => jsonrpc::INVALID_PARAMS,
+                _ => COMPOSITION_FAILED,
+            };
+            Err(RpcError::new(code, report.message.clone()).with_data(json!(report)))
+        }
+    }
+}
+
+/// Serve JSON-RPC on stdin and stdout until stdin is closed
+pub fn serve_stdio(prompts_dir: Option<String>) -> std::io::Result<()> {
+    let daemon = Daemon::new(prompts_dir);
+    jsonrpc::serve(std::io::stdin().lock(), std::io::stdout().lock(), |method, params| daemon.handle(method, params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daemon_composes_from_warm_caches() {
+        let daemon = Daemon::new(Some(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string()));
+        let request = json!({"user_prompt": "Plan and implement a refactor", "mcp_config": {"mcpServers": {}}});
+        let mut input = String::new();
+        for message in [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "compose", "params": {"request": request}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "compose", "params": {"request": request}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "explain", "params": {"request": request}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "compose", "params": {"request": {}}}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "refreshServer", "params": {"server": "nowhere", "mcp_config": {"mcpServers": {}}}}),
+            json!({"jsonrpc": "2.0", "id": 7, "method": "clearCache"}),
+            json!({"jsonrpc": "2.0", "id": 8, "method": "listBehaviors"}),
+            json!({"jsonrpc": "2.0", "id": 9, "method": "readiness"}),
+        ] {
+            input.push_str(&format!("{}\n", message));
+        }
+
+        let mut output = Vec::new();
+        jsonrpc::serve(input.as_bytes(), &mut output, |method, params| daemon.handle(method, params)).unwrap();
+        let responses: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(responses.len(), 9);
+
+        assert_eq!(responses[0]["result"]["methods"].as_array().unwrap().len(), METHODS.len());
+        let first = &responses[1]["result"];
+        assert!(!first["system_prompt"].as_str().unwrap().is_empty());
+        assert_eq!(first["metadata"]["discovery"], "Cached");
+        assert_eq!(responses[2]["result"]["system_prompt"], first["system_prompt"]);
+        let selected = responses[3]["result"].as_array().unwrap().iter().filter(|decision| decision["selected"] == true).count();
+        assert_eq!(selected, first["applied_modules"].as_array().unwrap().len());
+
+        assert_eq!(responses[4]["error"]["code"], jsonrpc::INVALID_PARAMS);
+        assert_eq!(responses[4]["error"]["data"]["code"], "InvalidRequest");
+        assert_eq!(responses[5]["error"]["code"], COMPOSITION_FAILED);
+        assert_eq!(responses[5]["error"]["data"]["server"], "nowhere");
+        assert!(responses[6]["result"].is_null());
+        assert!(responses[7]["result"].as_array().unwrap().iter().any(|behavior| behavior == "planning"));
+        assert_eq!(responses[8]["result"]["ready"], true);
+    }
+}
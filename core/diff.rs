@@ -0,0 +1,175 @@
+//! Differences between two composed prompts, for reviewing how a change to a request or
+//! to the prompt files alters what a model is told.
+
+use crate::types::{PromptResponse, TaskComplexity};
+use serde::{Deserialize, Serialize};
+
+/// Lines of unchanged context shown around each change
+pub const CONTEXT_LINES: usize = 3;
+
+/// How one composed prompt differs from another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptDiff {
+    /// Applied modules of the second response that the first lacks
+    pub added_modules: Vec<String>,
+    /// Applied modules of the first response that the second lacks
+    pub removed_modules: Vec<String>,
+    /// Complexity of both responses, when it changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub complexity: Option<(TaskComplexity, TaskComplexity)>,
+    /// Unified diff of the system prompts; empty when they are equal
+    pub unified: String,
+}
+
+impl PromptDiff {
+    /// Compare two responses, labeling the sides of the unified diff
+    pub fn between(before: &PromptResponse, after: &PromptResponse, before_label: &str, after_label: &str) -> Self {
+        let missing_from = |modules: &[String], other: &[String]| -> Vec<String> {
+            modules.iter().filter(|module| !other.contains(module)).cloned().collect()
+        };
+        Self {
+            added_modules: missing_from(&after.applied_modules, &before.applied_modules),
+            removed_modules: missing_from(&before.applied_modules, &after.applied_modules),
+            complexity: (before.complexity_assessment != after.complexity_assessment)
+                .then(|| (before.complexity_assessment.clone(), after.complexity_assessment.clone())),
+            unified: unified_diff(&before.system_prompt, &after.system_prompt, before_label, after_label),
+        }
+    }
+
+    /// Whether the prompts and their modules are the same
+    pub fn is_empty(&self) -> bool {
+        self.unified.is_empty() && self.added_modules.is_empty() && self.removed_modules.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Line edits turning `before` into `after`, from their longest common subsequence
+fn edits(before: &[&str], after: &[&str]) -> Vec<Edit> {
+    let (n, m) = (before.len(), after.len());
+    // common[i][j]: length of the longest common subsequence of before[i..] and after[j..]
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = match before[i] == after[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut edits = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && before[i] == after[j] {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || common[i + 1][j] >= common[i][j + 1]) {
+            // Deletions come first, as diff tools show them
+            edits.push(Edit::Delete);
+            i += 1;
+        } else {
+            edits.push(Edit::Insert);
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// Unified diff of two texts by line, with `CONTEXT_LINES` of context; empty when the
+/// texts are equal
+pub fn unified_diff(before: &str, after: &str, before_label: &str, after_label: &str) -> String {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+    let edits = edits(&before, &after);
+    if edits.iter().all(|edit| *edit == Edit::Keep) {
+        return String::new();
+    }
+
+    // Positions in both texts before each edit
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for edit in &edits {
+        positions.push((i, j));
+        match edit {
+            Edit::Keep => (i, j) = (i + 1, j + 1),
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    // Hunks are runs of edits whose changes are at most two contexts apart
+    let changed: Vec<usize> = (0..edits.len()).filter(|&k| edits[k] != Edit::Keep).collect();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &k in &changed {
+        let start = k.saturating_sub(CONTEXT_LINES);
+        let end = (k + 1 + CONTEXT_LINES).min(edits.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", before_label, after_label);
+    for (start, end) in hunks {
+        let (before_start, after_start) = positions[start];
+        let (before_end, after_end) = positions[end];
+        let range = |start: usize, len: usize| match len {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", start + 1, len),
+        };
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(before_start, before_end - before_start),
+            range(after_start, after_end - after_start)
+        ));
+        for k in start..end {
+            let (i, j) = positions[k];
+            match edits[k] {
+                Edit::Keep => diff.push_str(&format!(" {}\n", before[i])),
+                Edit::Delete => diff.push_str(&format!("-{}\n", before[i])),
+                Edit::Insert => diff.push_str(&format!("+{}\n", after[j])),
+            }
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_groups_changes_into_hunks() {
+        assert_eq!(unified_diff("a\nb", "a\nb", "before", "after"), "");
+
+        let before = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12";
+        let after = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13";
+        assert_eq!(unified_diff(before, after, "before", "after"), "\
+--- before
++++ after
+@@ -1,6 +1,6 @@
+ 1
+ 2
+-3
++three
+ 4
+ 5
+ 6
+@@ -10,3 +10,4 @@
+ 10
+ 11
+ 12
++13
+");
+        assert_eq!(unified_diff("", "new", "a", "b"), "--- a\n+++ b\n@@ -0,0 +1 @@\n+new\n");
+    }
+}
@@ -1,9 +1,13 @@
+use crate::cache::{CachePolicy, Entries};
+use crate::cancel::CancellationToken;
+use crate::metrics::CacheKind;
 use crate::types::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use std::time::{SystemTime, Duration};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
 
 /// Configuration for server pattern matching
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,51 +35,126 @@ struct ServerPatternsConfig {
     web_servers: Option<ServerPattern>,
 }
 
-/// Tool discovery service that dynamically categorizes tools based on external patterns
+fn parse_server_patterns(content: &str) -> Result<ServerPatternsConfig, PromptError> {
+    toml::from_str(content)
+        .map_err(|e| PromptError::config(format!("Failed to parse server patterns config: {}", e)))
+}
+
+/// The server pattern a server matched and the tools inferred from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerMatch {
+    pub server: String,
+    /// Section of `server_patterns.toml` that matched, e.g. `filesystem_servers`; `None`
+    /// when the server fell back to the generic `<server>.execute` tool
+    pub pattern: Option<String>,
+    /// The pattern within the section that matched, e.g. `name pattern "fs"`
+    pub rule: Option<String>,
+    pub tools: Vec<Tool>,
+}
+
+/// Tool discovery service that dynamically categorizes tools based on external patterns.
+/// Discovery infers a server's tools from the server patterns without starting it,
+/// unless it is set to list servers with `with_server_listing`.
+///
+/// The cache sits behind a read-write lock and is taken for writing only to store a
+/// server's tools, which are inferred with no lock held, so threads composing with
+/// cached tools proceed in parallel and share one discovery without wrapping it in a
+/// mutex. Tools are discovered again once older than the cache policy's time to live,
+/// five minutes unless configured.
 pub struct ToolDiscovery {
-    tools_by_server: HashMap<String, Vec<Tool>>,
-    last_refresh: HashMap<String, SystemTime>,
-    cache_duration: Duration,
-    server_patterns: Option<ServerPatternsConfig>,
+    tools_by_server: RwLock<Entries<String, Vec<Tool>>>,
+    /// Servers whose tools discovery took from the cache, and servers it discovered
+    hits: AtomicU64,
+    misses: AtomicU64,
+    server_patterns: RwLock<Option<Arc<ServerPatternsConfig>>>,
     prompts_dir: Option<String>,
+    /// Whether server patterns come from the embedded prompts rather than a file
+    embedded: bool,
+    /// How long a server may take to list its tools, when servers are started and asked
+    server_listing: Option<Duration>,
 }
 
 impl ToolDiscovery {
     pub fn new() -> Self {
         Self {
-            tools_by_server: HashMap::new(),
-            last_refresh: HashMap::new(),
-            cache_duration: Duration::from_secs(300), // 5 minutes cache
-            server_patterns: None,
+            tools_by_server: RwLock::new(Entries::new(CachePolicy::TOOLS)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            server_patterns: RwLock::new(None),
             prompts_dir: None,
+            embedded: false,
+            server_listing: None,
         }
     }
 
     /// Create a new ToolDiscovery with custom prompts directory
     pub fn with_prompts_dir(prompts_dir: String) -> Self {
-        Self {
-            tools_by_server: HashMap::new(),
-            last_refresh: HashMap::new(),
-            cache_duration: Duration::from_secs(300), // 5 minutes cache
-            server_patterns: None,
-            prompts_dir: Some(prompts_dir),
-        }
+        Self { prompts_dir: Some(prompts_dir), ..Self::new() }
+    }
+
+    /// Create a ToolDiscovery that matches servers with the server patterns compiled into
+    /// the library, reading nothing from disk
+    pub fn embedded() -> Self {
+        Self { embedded: true, ..Self::new() }
+    }
+
+    /// Create a ToolDiscovery that matches servers with the content of a
+    /// `server_patterns.toml` that is not on disk
+    pub fn with_server_patterns(content: &str) -> Result<Self, PromptError> {
+        let patterns = parse_server_patterns(content)?;
+        Ok(Self { server_patterns: RwLock::new(Some(Arc::new(patterns))), ..Self::new() })
+    }
+
+    /// Start each server being discovered and list its tools over stdio, waiting up to
+    /// `timeout` for each, rather than inferring them from the server patterns. A server
+    /// that cannot be started or does not list its tools in time gets inferred tools.
+    /// Cached and offline composition never start a server.
+    pub fn with_server_listing(mut self, timeout: Duration) -> Self {
+        self.server_listing = Some(timeout);
+        self
+    }
+
+    /// The tool cache for reading, recovered from a thread that panicked while writing it
+    pub(crate) fn cache(&self) -> RwLockReadGuard<'_, Entries<String, Vec<Tool>>> {
+        self.tools_by_server.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The tool cache for writing, held only to store or remove entries
+    pub(crate) fn cache_mut(&self) -> RwLockWriteGuard<'_, Entries<String, Vec<Tool>>> {
+        self.tools_by_server.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Limit how long tools are cached and for how many servers, dropping servers past
+    /// the new limit
+    pub fn set_cache_policy(&self, policy: CachePolicy) {
+        self.cache_mut().set_policy(policy);
+    }
+
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.cache().policy()
     }
 
     /// Load server patterns from external configuration file
-    fn load_server_patterns(&mut self) -> Result<&ServerPatternsConfig, PromptError> {
-        if self.server_patterns.is_none() {
+    fn load_server_patterns(&self) -> Result<Arc<ServerPatternsConfig>, PromptError> {
+        if let Some(patterns) = self.server_patterns.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+            return Ok(patterns.clone());
+        }
+        let patterns = if self.embedded {
+            let config_content = crate::embedded::file("server_patterns.toml")
+                .ok_or_else(|| PromptError::config("Server patterns configuration is not embedded"))?;
+            parse_server_patterns(config_content)?
+        } else {
             let config_path = self.find_config_file()?;
-            let config_content = fs::read_to_string(config_path)
-                .map_err(|e| PromptError::ConfigError(format!("Failed to read server patterns config: {}", e)))?;
+            let config_content = fs::read_to_string(&config_path)
+                .map_err(|e| PromptError::config(format!("Failed to read server patterns config: {}", e)).with_path(&config_path))?;
             
-            let patterns: ServerPatternsConfig = toml::from_str(&config_content)
-                .map_err(|e| PromptError::ConfigError(format!("Failed to parse server patterns config: {}", e)))?;
-            
-            self.server_patterns = Some(patterns);
-        }
+            toml::from_str(&config_content)
+                .map_err(|e| PromptError::config(format!("Failed to parse server patterns config: {}", e)).with_path(&config_path))?
+        };
         
-        Ok(self.server_patterns.as_ref().unwrap())
+        let patterns = Arc::new(patterns);
+        *self.server_patterns.write().unwrap_or_else(PoisonError::into_inner) = Some(patterns.clone());
+        Ok(patterns)
     }
 
     /// Find the server patterns configuration file
@@ -86,9 +165,9 @@ impl ToolDiscovery {
             if config_path.exists() {
                 return Ok(config_path.to_string_lossy().to_string());
             } else {
-                return Err(PromptError::ConfigError(
+                return Err(PromptError::config(
                     format!("Server patterns configuration file not found at: {}", config_path.display())
-                ));
+                ).with_path(&config_path));
             }
         }
 
@@ -105,114 +184,170 @@ impl ToolDiscovery {
             }
         }
 
-        Err(PromptError::ConfigError(
-            "Server patterns configuration file not found. Expected: prompts/server_patterns.toml".to_string()
+        Err(PromptError::config(
+            "Server patterns configuration file not found. Expected: prompts/server_patterns.toml"
         ))
     }
 
     /// Discover tools from all configured MCP servers
-    pub async fn discover_tools(&mut self, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
+    pub async fn discover_tools(&self, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
+        self.discover_tools_immediate(mcp_config)
+    }
+
+    /// `discover_tools` without a runtime, for callers that hold the discovery behind a
+    /// lock and must not keep it across an await
+    pub fn discover_tools_immediate(&self, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
+        self.discover_tools_cancellable(mcp_config, &CancellationToken::new())
+    }
+
+    /// `discover_tools_immediate` that stops before the next server once `cancel` is
+    /// cancelled, keeping the tools of the servers already discovered in the cache
+    pub fn discover_tools_cancellable(&self, mcp_config: &McpConfig, cancel: &CancellationToken) -> Result<Vec<Tool>, PromptError> {
+        self.discover(mcp_config, false, cancel)
+    }
+
+    /// `discover_tools_cancellable` that discovers every server's tools again, replacing
+    /// cached tools however fresh they are
+    pub fn rediscover_tools(&self, mcp_config: &McpConfig, cancel: &CancellationToken) -> Result<Vec<Tool>, PromptError> {
+        self.discover(mcp_config, true, cancel)
+    }
+
+    fn discover(&self, mcp_config: &McpConfig, refresh: bool, cancel: &CancellationToken) -> Result<Vec<Tool>, PromptError> {
+        let _span = tracing::debug_span!("discover_tools", servers = mcp_config.mcp_servers.len(), refresh).entered();
         let mut all_tools = Vec::new();
+        let mut lookups = CacheCounters::default();
         
-        for (server_name, server_config) in &mcp_config.mcp_servers {
-            if self.needs_refresh(server_name) {
-                match self.infer_server_tools(server_name, server_config) {
-                    Ok(tools) => {
-                        self.tools_by_server.insert(server_name.clone(), tools.clone());
-                        self.last_refresh.insert(server_name.clone(), SystemTime::now());
-                        all_tools.extend(tools);
-                    }
-                    Err(e) => {
-                        // Log error but continue with other servers
-                        eprintln!("Failed to infer tools from server {}: {}", server_name, e);
-                        // Use cached tools if available
-                        if let Some(cached_tools) = self.tools_by_server.get(server_name) {
-                            all_tools.extend(cached_tools.clone());
-                        }
-                    }
+        for (server_name, server_config) in mcp_config.servers_by_name() {
+            cancel.check()?;
+            // Use cached tools while they are fresh
+            if let Some(cached) = self.cache().get(server_name.as_str()).filter(|_| !refresh) {
+                tracing::trace!(server = %server_name, tools = cached.len(), "using cached tools");
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                lookups.record(true);
+                all_tools.extend(cached.iter().cloned());
+                continue;
+            }
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            lookups.record(false);
+            match self.discover_server(server_name, server_config, cancel) {
+                Ok(tools) => {
+                    tracing::debug!(server = %server_name, tools = tools.len(), "discovered tools");
+                    self.store(server_name, tools.clone());
+                    all_tools.extend(tools);
                 }
-            } else {
-                // Use cached tools
-                if let Some(cached_tools) = self.tools_by_server.get(server_name) {
-                    all_tools.extend(cached_tools.clone());
+                Err(PromptError::Cancelled) => return Err(PromptError::Cancelled),
+                Err(e) => {
+                    // Log error but continue with other servers
+                    tracing::warn!(server = %server_name, "Failed to infer tools: {}", e);
+                    crate::metrics::record(|metrics| metrics.discovery_failed(server_name));
+                    // Use cached tools if available
+                    if let Some(cached) = self.cache().get_stale(server_name.as_str()) {
+                        all_tools.extend(cached.iter().cloned());
+                    }
                 }
             }
         }
         
+        crate::metrics::record(|metrics| metrics.cache_lookups(CacheKind::Tools, lookups));
         Ok(all_tools)
     }
 
-    /// Check if we need to refresh tools for a server
-    fn needs_refresh(&self, server_name: &str) -> bool {
-        match self.last_refresh.get(server_name) {
-            Some(last_time) => {
-                SystemTime::now().duration_since(*last_time)
-                    .unwrap_or(Duration::from_secs(u64::MAX)) > self.cache_duration
+    fn store(&self, server_name: &str, tools: Vec<Tool>) {
+        self.cache_mut().insert(server_name.to_string(), tools);
+    }
+
+    /// A server's tools as listed by the server when discovery lists servers, and inferred
+    /// from the server patterns otherwise or when listing fails
+    fn discover_server(&self, server_name: &str, server_config: &McpServer, cancel: &CancellationToken) -> Result<Vec<Tool>, PromptError> {
+        if let Some(timeout) = self.server_listing {
+            match crate::mcp_client::list_server_tools(server_name, server_config, timeout, cancel) {
+                Ok(tools) => return Ok(tools),
+                Err(PromptError::Cancelled) => return Err(PromptError::Cancelled),
+                Err(e) => {
+                    tracing::warn!(server = %server_name, "{}; inferring its tools", e);
+                    crate::metrics::record(|metrics| metrics.discovery_failed(server_name));
+                }
             }
-            None => true,
         }
+        self.infer_server_tools(server_name, server_config)
     }
 
     /// Infer tools from server configuration using external patterns
-    fn infer_server_tools(&mut self, server_name: &str, server_config: &McpServer) -> Result<Vec<Tool>, PromptError> {
-        let patterns = self.load_server_patterns()?.clone();
+    fn infer_server_tools(&self, server_name: &str, server_config: &McpServer) -> Result<Vec<Tool>, PromptError> {
+        Ok(self.match_server(server_name, server_config)?.tools)
+    }
+
+    /// Which server pattern a server matches and the tools inferred from it, for
+    /// finding out why a server gets only the generic `<server>.execute` tool
+    pub fn match_server(&self, server_name: &str, server_config: &McpServer) -> Result<ServerMatch, PromptError> {
+        let patterns = self.load_server_patterns()?;
         
         // Try to match against each pattern type
         let all_patterns = [
-            ("filesystem", patterns.filesystem_servers.as_ref()),
-            ("weather", patterns.weather_servers.as_ref()),
-            ("desktop", patterns.desktop_servers.as_ref()),
-            ("dynamic", patterns.dynamic_servers.as_ref()),
-            ("data", patterns.data_servers.as_ref()),
-            ("web", patterns.web_servers.as_ref()),
+            ("filesystem_servers", patterns.filesystem_servers.as_ref()),
+            ("weather_servers", patterns.weather_servers.as_ref()),
+            ("desktop_servers", patterns.desktop_servers.as_ref()),
+            ("dynamic_servers", patterns.dynamic_servers.as_ref()),
+            ("data_servers", patterns.data_servers.as_ref()),
+            ("web_servers", patterns.web_servers.as_ref()),
         ];
 
-        for (_pattern_type, pattern_opt) in &all_patterns {
+        for (pattern_type, pattern_opt) in &all_patterns {
             if let Some(pattern) = pattern_opt {
-                if Self::matches_pattern(server_name, server_config, pattern) {
-                    return Ok(Self::create_tools_from_pattern(server_name, pattern));
+                if let Some(rule) = Self::matches_pattern(server_name, server_config, pattern) {
+                    return Ok(ServerMatch {
+                        server: server_name.to_string(),
+                        pattern: Some(pattern_type.to_string()),
+                        rule: Some(rule),
+                        tools: Self::create_tools_from_pattern(server_name, pattern),
+                    });
                 }
             }
         }
 
         // Fallback: create a generic tool
-        Ok(vec![
-            Tool {
-                name: format!("{}.execute", server_name),
-                description: format!("Execute {} functionality", server_config.command),
-                server: server_name.to_string(),
-                schema: None,
-            },
-        ])
+        Ok(ServerMatch {
+            server: server_name.to_string(),
+            pattern: None,
+            rule: None,
+            tools: vec![
+                Tool {
+                    name: format!("{}.execute", server_name),
+                    description: format!("Execute {} functionality", server_config.command),
+                    server: server_name.to_string(),
+                    schema: None,
+                },
+            ],
+        })
     }
 
-    /// Check if a server matches a given pattern
-    fn matches_pattern(server_name: &str, server_config: &McpServer, pattern: &ServerPattern) -> bool {
+    /// Check if a server matches a given pattern, describing the rule that matched
+    fn matches_pattern(server_name: &str, server_config: &McpServer, pattern: &ServerPattern) -> Option<String> {
         let server_name_lower = server_name.to_lowercase();
         let command_lower = server_config.command.to_lowercase();
         
         // Check name patterns
         for name_pattern in &pattern.name_patterns {
             if server_name_lower.contains(&name_pattern.to_lowercase()) {
-                return true;
+                return Some(format!("name pattern {:?}", name_pattern));
             }
         }
         
         // Check command patterns
         for command_pattern in &pattern.command_patterns {
             if command_lower.contains(&command_pattern.to_lowercase()) {
-                return true;
+                return Some(format!("command pattern {:?}", command_pattern));
             }
         }
         
         // Check argument patterns
         for arg_pattern in &pattern.arg_patterns {
             if server_config.args.iter().any(|arg| arg.to_lowercase().contains(&arg_pattern.to_lowercase())) {
-                return true;
+                return Some(format!("argument pattern {:?}", arg_pattern));
             }
         }
         
-        false
+        None
     }
 
     /// Create tools from a matched pattern
@@ -228,27 +363,62 @@ impl ToolDiscovery {
     }
 
     /// Force refresh tools for a specific server
-    pub async fn refresh_server(&mut self, server_name: &str, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
+    pub async fn refresh_server(&self, server_name: &str, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
+        self.refresh_server_immediate(server_name, mcp_config)
+    }
+
+    /// `refresh_server` without a runtime
+    pub fn refresh_server_immediate(&self, server_name: &str, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
         if let Some(server_config) = mcp_config.mcp_servers.get(server_name) {
-            let tools = self.infer_server_tools(server_name, server_config)?;
-            self.tools_by_server.insert(server_name.to_string(), tools.clone());
-            self.last_refresh.insert(server_name.to_string(), SystemTime::now());
+            let tools = self.discover_server(server_name, server_config, &CancellationToken::new())?;
+            self.store(server_name, tools.clone());
             Ok(tools)
         } else {
-            Err(PromptError::ConfigError(format!("Server {} not found in configuration", server_name)))
+            Err(PromptError::config(format!("Server {} not found in configuration", server_name)).with_server(server_name))
         }
     }
 
     /// Get cached tools for all servers
     pub fn get_cached_tools(&self) -> Vec<Tool> {
-        self.tools_by_server.values().flatten().cloned().collect()
+        self.cache().values().flat_map(|cached| cached.iter().cloned()).collect()
+    }
+
+    /// Discovered tools, or tools inferred from the config while nothing is discovered
+    pub fn cached_or_inferred_tools(&self, mcp_config: &McpConfig) -> Vec<Tool> {
+        let cached = self.get_cached_tools();
+        if cached.is_empty() {
+            self.infer_tools_from_config_immediate(mcp_config)
+        } else {
+            cached
+        }
+    }
+
+    /// Servers whose tools `discover_tools` took from the cache, and servers it discovered
+    pub fn cache_counters(&self) -> CacheCounters {
+        CacheCounters {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Servers and approximate bytes of the cached tools, with the cache counters
+    pub fn cache_usage(&self) -> CacheUsage {
+        self.cache().usage(self.cache_counters(), |server, tools| {
+            server.len() + tools.iter().map(Tool::approximate_size).sum::<usize>()
+        })
+    }
+
+    /// Forget discovered tools and the server patterns, so both are read again
+    pub fn clear_cache(&self) {
+        self.cache_mut().clear();
+        *self.server_patterns.write().unwrap_or_else(PoisonError::into_inner) = None;
     }
 
     /// Infer tools from MCP configuration without caching (for immediate use)
-    pub fn infer_tools_from_config_immediate(&mut self, mcp_config: &McpConfig) -> Vec<Tool> {
+    pub fn infer_tools_from_config_immediate(&self, mcp_config: &McpConfig) -> Vec<Tool> {
         let mut tools = Vec::new();
         
-        for (server_name, server_config) in &mcp_config.mcp_servers {
+        for (server_name, server_config) in mcp_config.servers_by_name() {
             match self.infer_server_tools(server_name, server_config) {
                 Ok(server_tools) => tools.extend(server_tools),
                 Err(_) => {
@@ -279,10 +449,123 @@ mod tests {
 
     #[test]
     fn test_pattern_matching() {
-        let mut discovery = ToolDiscovery::new();
+        let discovery = ToolDiscovery::new();
         
         // This would need the config file to exist for a real test
         // For now, just test the structure
-        assert!(discovery.server_patterns.is_none());
+        assert!(discovery.server_patterns.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_match_server_names_the_matching_rule() {
+        let discovery = ToolDiscovery::embedded();
+        let server = |command: &str, args: &[&str]| McpServer {
+            name: String::new(),
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: None,
+        };
+
+        let matched = discovery.match_server("docs", &server("npx", &["@modelcontextprotocol/server-filesystem"])).unwrap();
+        assert_eq!(matched.pattern.as_deref(), Some("filesystem_servers"));
+        assert_eq!(matched.rule.as_deref(), Some("argument pattern \"@modelcontextprotocol/server-filesystem\""));
+        assert!(matched.tools.iter().any(|tool| tool.name == "docs.read_file"));
+
+        let fallback = discovery.match_server("acme", &server("acme-mcp", &[])).unwrap();
+        assert!(fallback.pattern.is_none() && fallback.rule.is_none());
+        assert_eq!(fallback.tools[0].name, "acme.execute");
+    }
+
+    #[test]
+    fn test_concurrent_discovery_shares_the_cache() {
+        let discovery = ToolDiscovery::embedded();
+        let mcp_servers = (0..4).map(|i| {
+            let server = McpServer {
+                name: format!("fs-{}", i),
+                command: "npx".to_string(),
+                args: vec!["@modelcontextprotocol/server-filesystem".to_string()],
+                env: None,
+            };
+            (server.name.clone(), server)
+        }).collect();
+        let mcp_config = McpConfig { mcp_servers };
+
+        // A thread that panics while writing the cache must not break the others
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let _guard = discovery.cache_mut();
+                panic!("poisoning the tool cache");
+            }).join().unwrap_err();
+        });
+
+        std::thread::scope(|scope| {
+            for i in 0..16 {
+                let (discovery, mcp_config) = (&discovery, &mcp_config);
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        let tools = match i % 4 {
+                            0 => discovery.refresh_server_immediate("fs-0", mcp_config).unwrap(),
+                            1 => discovery.cached_or_inferred_tools(mcp_config),
+                            _ => discovery.discover_tools_immediate(mcp_config).unwrap(),
+                        };
+                        assert!(tools.iter().any(|tool| tool.name.ends_with(".read_file")));
+                    }
+                });
+            }
+        });
+
+        let counters = discovery.cache_counters();
+        assert_eq!(counters.hits + counters.misses, 8 * 50 * 4);
+        assert!(counters.hits > counters.misses);
+        assert_eq!(discovery.cache().len(), 4);
+    }
+
+    /// Cancels its token at the first event, which discovery emits once a server is done
+    struct CancelOnEvent(CancellationToken);
+
+    impl tracing::Subscriber for CancelOnEvent {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {
+            self.0.cancel();
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn test_cancelling_mid_discovery_keeps_finished_servers() {
+        let discovery = ToolDiscovery::embedded();
+        let mcp_servers = ["docs", "notes"].into_iter().map(|name| {
+            let server = McpServer {
+                name: name.to_string(),
+                command: "npx".to_string(),
+                args: vec!["@modelcontextprotocol/server-filesystem".to_string()],
+                env: None,
+            };
+            (server.name.clone(), server)
+        }).collect();
+        let mcp_config = McpConfig { mcp_servers };
+
+        // Cancelled once "docs" is discovered, so discovery stops before "notes"
+        let cancel = CancellationToken::new();
+        let result = tracing::subscriber::with_default(CancelOnEvent(cancel.clone()), || {
+            discovery.discover_tools_cancellable(&mcp_config, &cancel)
+        });
+        assert!(matches!(result, Err(PromptError::Cancelled)));
+        assert!(discovery.cache().get("docs").is_some());
+        assert!(discovery.cache().get("notes").is_none());
     }
 }
@@ -0,0 +1,96 @@
+//! The bundled `prompts` directory compiled into the library, for hosts that cannot read
+//! it from disk, such as sandboxed Electron renderers.
+
+use crate::provider::PromptProvider;
+use crate::types::PromptError;
+use std::borrow::Cow;
+use std::path::Path;
+
+// `FILES`: (path relative to the prompts directory, content), sorted by path
+include!(concat!(env!("OUT_DIR"), "/embedded_prompts.rs"));
+
+/// Reported as the prompts directory of loaders that read the embedded prompts
+pub const PROMPTS_DIR: &str = "<embedded>";
+
+/// Content of a bundled file by its path relative to the prompts directory, e.g.
+/// `behaviors/planning.md`
+pub fn file(path: &str) -> Option<&'static str> {
+    FILES.binary_search_by(|(name, _)| (*name).cmp(path))
+        .ok()
+        .map(|index| FILES[index].1)
+}
+
+/// Write the bundled prompts to `dir` as a starting point for a prompts directory of
+/// one's own, creating the directory and its `domains/`, `behaviors/` and `tools/`.
+/// Existing files are kept unless `overwrite` is set. Returns the paths written,
+/// relative to `dir`.
+pub fn scaffold(dir: &Path, overwrite: bool) -> Result<Vec<&'static str>, PromptError> {
+    let mut written = Vec::new();
+    for category in ["domains", "behaviors", "tools"] {
+        let path = dir.join(category);
+        std::fs::create_dir_all(&path)
+            .map_err(|e| PromptError::config(format!("Failed to create {:?}: {}", path, e)).with_path(&path))?;
+    }
+    for (name, content) in FILES {
+        let path = dir.join(name);
+        if path.exists() && !overwrite {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| PromptError::config(format!("Failed to create {:?}: {}", parent, e)).with_path(parent))?;
+        }
+        std::fs::write(&path, content)
+            .map_err(|e| PromptError::config(format!("Failed to write {:?}: {}", path, e)).with_path(&path))?;
+        written.push(*name);
+    }
+    Ok(written)
+}
+
+/// Provides the bundled prompts to a `PromptLoader`
+pub struct EmbeddedPrompts;
+
+impl PromptProvider for EmbeddedPrompts {
+    fn name(&self) -> &str {
+        PROMPTS_DIR
+    }
+
+    fn read(&self, path: &str) -> Option<Cow<'_, str>> {
+        file(path).map(Cow::Borrowed)
+    }
+
+    fn paths(&self) -> Vec<String> {
+        FILES.iter().map(|(path, _)| path.to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_prompts_match_the_prompts_directory() {
+        let prompts_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/prompts");
+        for (name, content) in FILES {
+            let on_disk = std::fs::read_to_string(format!("{}/{}", prompts_dir, name)).unwrap();
+            assert_eq!(*content, on_disk, "{} is stale", name);
+        }
+        assert!(file("selection_rules.toml").is_some());
+        let behaviors = FILES.iter().filter(|(name, _)| name.starts_with("behaviors/")).count();
+        assert_eq!(behaviors, std::fs::read_dir(format!("{}/behaviors", prompts_dir)).unwrap().count());
+    }
+
+    #[test]
+    fn test_scaffold_writes_a_valid_prompts_directory() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-scaffold-{}", std::process::id()));
+        assert_eq!(scaffold(&dir, false).unwrap().len(), FILES.len());
+        let report = crate::validate::validate_prompts_dir(&dir.to_string_lossy());
+        assert!(report.is_valid(), "{:?}", report.issues);
+
+        std::fs::write(dir.join("behaviors/planning.md"), "## Mine").unwrap();
+        assert!(scaffold(&dir, false).unwrap().is_empty());
+        assert_eq!(std::fs::read_to_string(dir.join("behaviors/planning.md")).unwrap(), "## Mine");
+        assert_eq!(scaffold(&dir, true).unwrap().len(), FILES.len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,451 @@
+//! Composition state: the tool discovery and prompt file caches, the registered modules
+//! and hooks, and per-session module state. The free functions at the crate root share
+//! one engine for the whole process; hosts serving several tenants create an engine for
+//! each, so one tenant's discovered tools, modules and sessions never reach another's
+//! prompts.
+
+use crate::cache::CachePolicy;
+use crate::cancel::CancellationToken;
+use crate::composition::{request_span, PromptComposer};
+use crate::discovery::ToolDiscovery;
+use crate::hooks::{CompositionHook, HookSet};
+use crate::loader::ContentCache;
+use crate::lock;
+use crate::modules::PromptModule;
+use crate::registry::ModuleRegistry;
+use crate::session::SessionStore;
+use crate::types::*;
+use crate::Instant;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+// Each lock is held only for a lookup or an update, never while a prompt is composed,
+// so concurrent compositions contend only briefly; the discovery cache is shared
+// without a lock of its own. The state is plain data that stays
+// consistent between operations, so a thread that panicked while holding a lock leaves
+// it usable.
+pub struct Engine {
+    /// Directory the engine's prompts are read from, or the default prompts directory
+    prompts_dir: Option<String>,
+    /// Tool discovery with caching, created with the prompts directory of the first
+    /// live composition
+    discovery: OnceLock<ToolDiscovery>,
+    /// Policy of the discovery cache, applied when it is created
+    tool_cache: CachePolicy,
+    /// How long each server may take to list its tools, when discovery lists servers
+    server_listing: Option<Duration>,
+    /// Prompt files read by the engine's composers, reused while unchanged on disk
+    content: ContentCache,
+    registry: Mutex<ModuleRegistry>,
+    hooks: Mutex<HookSet>,
+    sessions: SessionStore,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// An engine reading prompts from the default prompts directory
+    pub fn new() -> Self {
+        Self {
+            prompts_dir: None,
+            discovery: OnceLock::new(),
+            tool_cache: CachePolicy::TOOLS,
+            server_listing: None,
+            content: ContentCache::new(),
+            registry: Mutex::new(ModuleRegistry::new()),
+            hooks: Mutex::new(HookSet::new()),
+            sessions: SessionStore::new(),
+        }
+    }
+
+    /// An engine reading prompts from `prompts_dir`
+    pub fn with_prompts_dir(prompts_dir: impl Into<String>) -> Self {
+        Self { prompts_dir: Some(prompts_dir.into()), ..Self::new() }
+    }
+
+    /// Keep discovered tools for the policy's time to live, for up to its limit of servers.
+    /// Unless configured, tools are discovered again after five minutes.
+    pub fn with_tool_cache(mut self, policy: CachePolicy) -> Self {
+        self.tool_cache = policy;
+        self
+    }
+
+    /// Discover tools by starting each server and listing its tools, waiting up to
+    /// `timeout` per server, rather than inferring them from the server patterns; see
+    /// `ToolDiscovery::with_server_listing`
+    pub fn with_server_listing(mut self, timeout: Duration) -> Self {
+        self.server_listing = Some(timeout);
+        self
+    }
+
+    /// Keep prompt files read from disk for the policy's time to live, up to its limit.
+    /// Unless configured, files are kept while unchanged on disk.
+    pub fn with_prompt_cache(mut self, policy: CachePolicy) -> Self {
+        self.content = ContentCache::with_policy(policy);
+        self
+    }
+
+    pub fn prompts_dir(&self) -> Option<&str> {
+        self.prompts_dir.as_deref()
+    }
+
+    /// Register a module for every subsequent composition by this engine. A module
+    /// registered under an existing name replaces it; built-in modules cannot be replaced.
+    pub fn register_module(&self, module: Arc<dyn PromptModule>) -> Result<(), PromptError> {
+        lock(&self.registry).register(module, ModuleSource::Registered)
+    }
+
+    /// Remove a registered module, returning whether it was registered
+    pub fn unregister_module(&self, name: &str) -> bool {
+        lock(&self.registry).unregister(name)
+    }
+
+    /// Enable or disable a module for every subsequent composition, returning whether the
+    /// module exists
+    pub fn set_module_enabled(&self, name: &str, enabled: bool) -> bool {
+        lock(&self.registry).set_enabled(name, enabled)
+    }
+
+    /// Name, kind, source and enabled state of every built-in and registered module
+    pub fn list_modules(&self) -> Vec<ModuleInfo> {
+        lock(&self.registry).list()
+    }
+
+    /// A copy of the engine's registry, to merge into other composers
+    pub fn registered_modules(&self) -> ModuleRegistry {
+        lock(&self.registry).clone()
+    }
+
+    /// Add a hook to every subsequent composition by this engine, replacing the hook with
+    /// the same name
+    pub fn register_hook(&self, hook: Arc<dyn CompositionHook>) {
+        lock(&self.hooks).add(hook);
+    }
+
+    /// Remove a registered hook, returning whether it was registered
+    pub fn unregister_hook(&self, name: &str) -> bool {
+        lock(&self.hooks).remove(name)
+    }
+
+    /// A copy of the engine's hooks, to merge into other composers
+    pub fn registered_hooks(&self) -> HookSet {
+        lock(&self.hooks).clone()
+    }
+
+    /// End a session started by this engine, calling `on_session_end` on its modules.
+    /// Returns whether the session existed.
+    pub fn end_session(&self, session_id: &str) -> bool {
+        self.composer(None, false).end_session(session_id)
+    }
+
+    /// Compose with live tool discovery, updating the engine's cache in place
+    pub async fn compose(&self, request: PromptRequest) -> Result<PromptResponse, PromptError> {
+        self.compose_live_in(request, self.prompts_dir.clone(), &CancellationToken::new())
+    }
+
+    /// `compose` that fails with `PromptError::Cancelled` once `cancel` is cancelled,
+    /// stopping discovery before the next server and composition before the next module.
+    /// The composition runs on the first poll, so the token is cancelled from another
+    /// thread; dropping the future does not cancel it.
+    pub async fn compose_cancellable(&self, request: PromptRequest, cancel: &CancellationToken) -> Result<PromptResponse, PromptError> {
+        self.compose_live_in(request, self.prompts_dir.clone(), cancel)
+    }
+
+    /// Compose with the cached tools, or tools inferred from the config
+    pub fn compose_cached(&self, request: PromptRequest) -> Result<PromptResponse, PromptError> {
+        self.compose_cached_in(request, self.prompts_dir.clone(), false, None)
+    }
+
+    /// Compose without starting a process or contacting the network, as
+    /// `compose_system_prompt_offline` does
+    pub fn compose_offline(&self, request: PromptRequest) -> Result<PromptResponse, PromptError> {
+        self.compose_cached_in(request, self.prompts_dir.clone(), true, None)
+    }
+
+    /// Cached composition that passes each section of the prompt to `on_section` as soon
+    /// as it is generated
+    pub fn compose_streaming(
+        &self,
+        request: PromptRequest,
+        mut on_section: impl FnMut(PromptSection)
+    ) -> Result<PromptResponse, PromptError> {
+        self.compose_cached_in(request, self.prompts_dir.clone(), false, Some(&mut on_section))
+    }
+
+    /// Explain which modules a request would get, without composing it or touching
+    /// session state
+    pub fn explain(&self, request: &PromptRequest) -> Result<Vec<ModuleDecision>, PromptError> {
+        self.explain_in(request, self.prompts_dir.clone(), false)
+    }
+
+    /// Force refresh tools for a specific server
+    pub async fn refresh_server_tools(&self, server_name: &str, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
+        self.discovery(None).refresh_server_immediate(server_name, mcp_config)
+    }
+
+    /// Forget the engine's discovered tools and prompt files
+    pub fn clear_cache(&self) {
+        self.content.clear();
+        if let Some(discovery) = self.discovery.get() {
+            discovery.clear_cache();
+        }
+    }
+
+    /// Entries, approximate bytes and hit rates of the engine's prompt file and tool
+    /// discovery caches
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            prompts: self.content.usage(),
+            tools: self.discovery.get().map(ToolDiscovery::cache_usage).unwrap_or_default(),
+        }
+    }
+
+    pub(crate) fn discovery(&self, prompts_dir: Option<&str>) -> &ToolDiscovery {
+        self.discovery.get_or_init(|| {
+            let mut discovery = match prompts_dir.or(self.prompts_dir.as_deref()) {
+                Some(dir) => ToolDiscovery::with_prompts_dir(dir.to_string()),
+                None => ToolDiscovery::new(),
+            };
+            if let Some(timeout) = self.server_listing {
+                discovery = discovery.with_server_listing(timeout);
+            }
+            discovery.set_cache_policy(self.tool_cache);
+            discovery
+        })
+    }
+
+    /// Create a composer for the prompts directory with the engine's modules, hooks and
+    /// sessions. Offline composers leave out external modules and, without a prompts
+    /// directory, read the embedded prompts.
+    pub(crate) fn composer(&self, prompts_dir: Option<String>, offline: bool) -> PromptComposer {
+        let mut composer = match prompts_dir.or_else(|| self.prompts_dir.clone()) {
+            Some(dir) => PromptComposer::with_prompts_dir(dir),
+            None if offline => PromptComposer::embedded(),
+            None => PromptComposer::new(),
+        };
+        composer.set_offline(offline);
+        composer.registry_mut().merge(&lock(&self.registry));
+        composer.hooks_mut().merge(&lock(&self.hooks));
+        composer.set_session_store(self.sessions.clone());
+        composer.set_content_cache(self.content.clone());
+        composer
+    }
+
+    pub(crate) fn compose_live_in(
+        &self,
+        request: PromptRequest,
+        prompts_dir: Option<String>,
+        cancel: &CancellationToken
+    ) -> Result<PromptResponse, PromptError> {
+        let start_time = Instant::now();
+
+        // Discover available tools, updating the shared cache in place
+        let discovery = self.discovery(prompts_dir.as_deref());
+        let tools = request_span(&request).in_scope(|| match request.force_refresh.unwrap_or(false) {
+            true => discovery.rediscover_tools(&request.mcp_config, cancel),
+            false => discovery.discover_tools_cancellable(&request.mcp_config, cancel),
+        })?;
+
+        let mut response = self.composer(prompts_dir, false).compose_cancellable(&request, &tools, cancel)?;
+
+        // Track performance, including tool discovery
+        let elapsed = start_time.elapsed();
+        response.metadata.duration_ms = elapsed.as_secs_f64() * 1000.0;
+        response.metadata.discovery = DiscoveryMode::Live;
+        if elapsed.as_millis() > 50 {
+            response.warnings.push(Warning::new(
+                WarningSeverity::Info,
+                "composer",
+                format!("Prompt composition with tool discovery took {}ms", elapsed.as_millis())
+            ));
+        }
+
+        Ok(response)
+    }
+
+    pub(crate) fn compose_cached_in(
+        &self,
+        request: PromptRequest,
+        prompts_dir: Option<String>,
+        offline: bool,
+        sink: Option<&mut dyn FnMut(PromptSection)>
+    ) -> Result<PromptResponse, PromptError> {
+        let start_time = Instant::now();
+        let tools = self.tools(&request, prompts_dir.as_deref(), offline);
+
+        let mut composer = self.composer(prompts_dir, offline);
+        let mut response = match sink {
+            Some(sink) => composer.compose_streaming(&request, &tools, sink)?,
+            None => composer.compose(&request, &tools)?,
+        };
+
+        // Track performance
+        let elapsed = start_time.elapsed();
+        response.metadata.duration_ms = elapsed.as_secs_f64() * 1000.0;
+        response.metadata.discovery = if offline { DiscoveryMode::Offline } else { DiscoveryMode::Cached };
+        if elapsed.as_millis() > 10 {
+            response.warnings.push(Warning::new(
+                WarningSeverity::Info,
+                "composer",
+                format!("Cached prompt composition took {}ms", elapsed.as_millis())
+            ));
+        }
+
+        Ok(response)
+    }
+
+    pub(crate) fn explain_in(&self, request: &PromptRequest, prompts_dir: Option<String>, offline: bool) -> Result<Vec<ModuleDecision>, PromptError> {
+        let tools = self.tools(request, prompts_dir.as_deref(), offline);
+        self.composer(prompts_dir, offline).explain(request, &tools)
+    }
+
+    /// Inferred tools offline; otherwise the cached tools, or tools inferred from the
+    /// config when nothing was discovered yet or the request forces a refresh
+    fn tools(&self, request: &PromptRequest, prompts_dir: Option<&str>, offline: bool) -> Vec<Tool> {
+        let _span = request_span(request).entered();
+        let mcp_config = &request.mcp_config;
+        if offline {
+            return offline_tools(mcp_config, prompts_dir.or(self.prompts_dir.as_deref()));
+        }
+        if request.force_refresh.unwrap_or(false) {
+            let discovery = self.discovery(prompts_dir);
+            return discovery.rediscover_tools(mcp_config, &CancellationToken::new())
+                .unwrap_or_else(|_| discovery.infer_tools_from_config_immediate(mcp_config));
+        }
+        match self.discovery.get() {
+            Some(discovery) => discovery.cached_or_inferred_tools(mcp_config),
+            // No discovery instance, create temporary one to infer from config
+            None => ToolDiscovery::new().infer_tools_from_config_immediate(mcp_config),
+        }
+    }
+}
+
+/// Tools inferred from the config alone, leaving every discovery cache untouched
+pub(crate) fn offline_tools(mcp_config: &McpConfig, prompts_dir: Option<&str>) -> Vec<Tool> {
+    let discovery = match prompts_dir {
+        Some(dir) => ToolDiscovery::with_prompts_dir(dir.to_string()),
+        None => ToolDiscovery::embedded(),
+    };
+    discovery.infer_tools_from_config_immediate(mcp_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::PromptLoader;
+
+    struct TenantModule;
+
+    impl PromptModule for TenantModule {
+        fn name(&self) -> &str {
+            "tenant_notes"
+        }
+
+        fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, _loader: &mut PromptLoader) -> Result<String, PromptError> {
+            Ok("\nTENANT NOTES:\nFollow the tenant's style guide.".to_string())
+        }
+
+        fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, _session_state: &SessionState) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_engines_keep_modules_and_discovery_apart() {
+        let tenant = Engine::new();
+        let other = Engine::new();
+        tenant.register_module(Arc::new(TenantModule)).unwrap();
+
+        let request = PromptRequest::builder()
+            .user_prompt("Read a file")
+            .mcp_server("tenant-filesystem", McpServer {
+                name: "filesystem".to_string(),
+                command: "filesystem-command".to_string(),
+                args: vec![],
+                env: None,
+            })
+            .build()
+            .unwrap();
+        assert!(tenant.compose_cached(request.clone()).unwrap().system_prompt.contains("TENANT NOTES"));
+        assert!(!other.compose_cached(request.clone()).unwrap().system_prompt.contains("TENANT NOTES"));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(tenant.refresh_server_tools("tenant-filesystem", &request.mcp_config)).unwrap();
+        assert!(!tenant.discovery(None).get_cached_tools().is_empty());
+        assert!(other.discovery.get().is_none());
+    }
+    #[test]
+    fn test_cancelled_composition_stops_before_discovery() {
+        let engine = Engine::new();
+        let request = PromptRequest::builder()
+            .user_prompt("Read a file")
+            .mcp_server("filesystem", McpServer {
+                name: "filesystem".to_string(),
+                command: "npx".to_string(),
+                args: vec!["@modelcontextprotocol/server-filesystem".to_string()],
+                env: None,
+            })
+            .build()
+            .unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let result = runtime.block_on(engine.compose_cancellable(request, &cancel));
+        assert!(matches!(result, Err(PromptError::Cancelled)));
+        assert!(engine.discovery(None).get_cached_tools().is_empty());
+    }
+    #[test]
+    fn test_tool_cache_follows_its_policy_and_forced_refreshes() {
+        let engine = Engine::new().with_tool_cache(CachePolicy::TOOLS.with_max_entries(1));
+        let server = |command: &str| McpServer { name: command.to_string(), command: command.to_string(), args: vec![], env: None };
+        let request = PromptRequest::builder()
+            .user_prompt("Read a file and search the web")
+            .mcp_server("filesystem", server("filesystem"))
+            .mcp_server("search", server("brave-search"))
+            .build()
+            .unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(engine.compose(request.clone())).unwrap();
+        let discovery = engine.discovery(None);
+        assert_eq!(discovery.cache().len(), 1);
+        let counters = discovery.cache_counters();
+
+        let mut forced = request;
+        forced.force_refresh = Some(true);
+        runtime.block_on(engine.compose(forced)).unwrap();
+        assert_eq!(discovery.cache_counters().hits, counters.hits);
+        assert_eq!(discovery.cache_counters().misses, counters.misses + 2);
+    }
+    #[test]
+    fn test_cache_stats_count_entries_until_cleared() {
+        let engine = Engine::new();
+        let request = PromptRequest::builder()
+            .user_prompt("Plan the refactor of the parser")
+            .mcp_server("filesystem", McpServer {
+                name: "filesystem".to_string(),
+                command: "filesystem".to_string(),
+                args: vec![],
+                env: None,
+            })
+            .build()
+            .unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(engine.compose(request.clone())).unwrap();
+        engine.compose_cached(request).unwrap();
+
+        let stats = engine.cache_stats();
+        assert!(stats.prompts.entries > 0 && stats.prompts.bytes > 0);
+        assert!(stats.prompts.hits > 0 && stats.prompts.misses > 0);
+        assert_eq!(stats.tools.entries, 1);
+        assert_eq!(stats.bytes(), stats.prompts.bytes + stats.tools.bytes);
+
+        engine.clear_cache();
+        let cleared = engine.cache_stats();
+        assert_eq!((cleared.prompts.entries, cleared.tools.entries, cleared.bytes()), (0, 0, 0));
+    }
+}
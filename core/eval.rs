@@ -0,0 +1,127 @@
+//! Prompts of an evaluation dataset, for scoring prompt changes against model behavior
+//! with promptfoo, Inspect and similar evaluators. A dataset is JSONL of test cases, each
+//! a `PromptRequest` with an `id` and optional `metadata`,
+//!
+//! ```json
+//! {"id": "plan-migration", "user_prompt": "Plan the migration", "metadata": {"expected": "a numbered plan"}}
+//! ```
+//!
+//! and each case composes into a line of `{"id", "system", "user"}` JSONL, with the
+//! applied modules and the case's metadata, for the evaluator to send to a model.
+
+use crate::composition::PromptComposer;
+use crate::engine::offline_tools;
+use crate::types::{PromptError, PromptRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Selection seed of cases that set none, so every run of a dataset composes the same
+/// prompts and score changes come from prompt changes
+pub const EVAL_SEED: u64 = 0;
+
+/// A test case of an evaluation dataset
+#[derive(Debug, Clone)]
+pub struct EvalCase {
+    pub id: String,
+    pub request: PromptRequest,
+    /// Passed through to the case's record, such as the expected answer
+    pub metadata: Option<Value>,
+}
+
+/// The prompts composed for a test case, as a line of evaluator input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalRecord {
+    pub id: String,
+    /// The composed system prompt
+    pub system: String,
+    /// The case's user prompt
+    pub user: String,
+    pub modules: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+}
+
+/// Parse a JSONL dataset, skipping blank lines. `mcp_config` defaults to no servers, and
+/// a case without an `id` is named `case-<line>`.
+pub fn parse_dataset(content: &str) -> Result<Vec<EvalCase>, PromptError> {
+    let mut cases: Vec<EvalCase> = Vec::new();
+    for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let invalid = |message: String| {
+            PromptError::invalid_request(format!("Invalid test case on line {}: {}", index + 1, message))
+        };
+        let mut case: Value = serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+        let Some(fields) = case.as_object_mut() else {
+            return Err(invalid("expected an object".to_string()));
+        };
+        let id = match fields.remove("id") {
+            Some(Value::String(id)) => id,
+            Some(Value::Number(id)) => id.to_string(),
+            Some(_) => return Err(invalid("id must be a string or number".to_string()).with_field("id")),
+            None => format!("case-{}", index + 1),
+        };
+        if cases.iter().any(|case| case.id == id) {
+            return Err(invalid(format!("id {} is used by an earlier case", id)).with_field("id"));
+        }
+        let metadata = fields.remove("metadata");
+        fields.entry("mcp_config").or_insert_with(|| serde_json::json!({"mcpServers": {}}));
+        let request = serde_json::from_value(case).map_err(|e| invalid(e.to_string()))?;
+        cases.push(EvalCase { id, request, metadata });
+    }
+    Ok(cases)
+}
+
+/// Compose a case with [`EVAL_SEED`] unless it sets a seed, and with tools inferred from
+/// its config and the prompts directory's server patterns, never starting an MCP server
+pub fn compose_case(composer: &mut PromptComposer, prompts_dir: &str, case: &EvalCase) -> Result<EvalRecord, PromptError> {
+    let request = PromptRequest {
+        selection_seed: Some(case.request.selection_seed.unwrap_or(EVAL_SEED)),
+        ..case.request.clone()
+    };
+    let tools = offline_tools(&request.mcp_config, Some(prompts_dir));
+    let response = composer.compose(&request, &tools)?;
+    Ok(EvalRecord {
+        id: case.id.clone(),
+        system: response.system_prompt,
+        user: request.user_prompt,
+        modules: response.applied_modules,
+        metadata: case.metadata.clone(),
+    })
+}
+
+/// Compose every case of a dataset against `prompts_dir`, returning the outcomes in the
+/// order of the cases
+pub fn compose_dataset(prompts_dir: &str, cases: &[EvalCase]) -> Vec<Result<EvalRecord, PromptError>> {
+    let mut composer = PromptComposer::with_prompts_dir(prompts_dir.to_string());
+    cases.iter().map(|case| compose_case(&mut composer, prompts_dir, case)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dataset_composes_into_evaluator_records() {
+        let dataset = r#"{"id": "plan", "user_prompt": "Plan and implement the migration, then run the tests", "metadata": {"expected": "a plan"}}
+
+{"user_prompt": "What does this function return?", "task_complexity": "Simple"}
+"#;
+        let cases = parse_dataset(dataset).unwrap();
+        let ids: Vec<&str> = cases.iter().map(|case| case.id.as_str()).collect();
+        assert_eq!(ids, ["plan", "case-3"]);
+
+        let prompts = concat!(env!("CARGO_MANIFEST_DIR"), "/prompts");
+        let records: Vec<EvalRecord> = compose_dataset(prompts, &cases).into_iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records[0].user, "Plan and implement the migration, then run the tests");
+        assert!(!records[0].system.is_empty());
+        assert_eq!(records[0].metadata, Some(serde_json::json!({"expected": "a plan"})));
+        let line: Value = serde_json::from_str(&serde_json::to_string(&records[1]).unwrap()).unwrap();
+        assert_eq!(line["id"], "case-3");
+        assert!(line.get("metadata").is_none());
+        // Seeded, so a second run composes the same prompts
+        assert_eq!(compose_dataset(prompts, &cases)[0].as_ref().unwrap(), &records[0]);
+
+        assert!(parse_dataset(r#"{"id": "a", "user_prompt": "x"}
+{"id": "a", "user_prompt": "y"}"#).is_err());
+        assert!(parse_dataset("[1, 2]").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::cancel::CancellationToken;
+use crate::loader::PromptLoader;
+use crate::modules::PromptModule;
+use crate::rules::{contains_any, ExternalModuleRules};
+use crate::types::*;
+use serde_json::json;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A module whose content is produced by an external command.
+///
+/// The command receives the request context on stdin as JSON (`user_prompt`, `tools`,
+/// `session_state`) and writes the prompt section to stdout. A command that fails, times
+/// out or prints nothing contributes no content; it only fails composition when the
+/// module is configured as `required`. A cancelled composition kills the command and
+/// fails, whether or not the module is required.
+pub struct ExternalModule {
+    rules: ExternalModuleRules,
+    user_prompt: String,
+    cancel: CancellationToken,
+}
+
+impl ExternalModule {
+    /// Create the module for one request, so the command sees the user prompt
+    pub fn new(rules: ExternalModuleRules, user_prompt: &str) -> Self {
+        Self { rules, user_prompt: user_prompt.to_string(), cancel: CancellationToken::new() }
+    }
+
+    /// Kill the command once `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn run(&self, tools: &[Tool], session_state: &SessionState) -> Result<String, PromptError> {
+        let context = json!({
+            "user_prompt": self.user_prompt,
+            "tools": tools,
+            "session_state": session_state,
+        }).to_string();
+
+        let mut child = Command::new(&self.rules.command)
+            .args(&self.rules.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| self.error(format!("failed to start '{}': {}", self.rules.command, e)))?;
+
+        // Feed stdin and drain stdout on helper threads so a chatty command cannot block us
+        let mut stdin = child.stdin.take().ok_or_else(|| self.error("stdin unavailable".to_string()))?;
+        let writer = thread::spawn(move || stdin.write_all(context.as_bytes()));
+        let mut stdout = child.stdout.take().ok_or_else(|| self.error("stdout unavailable".to_string()))?;
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            stdout.read_to_string(&mut output).map(|_| output)
+        });
+
+        let deadline = Instant::now() + Duration::from_millis(self.rules.timeout_ms);
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if self.cancel.is_cancelled() => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(PromptError::Cancelled);
+                }
+                Ok(None) if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(self.error(format!("timed out after {}ms", self.rules.timeout_ms)));
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(5)),
+                Err(e) => return Err(self.error(e.to_string())),
+            }
+        };
+
+        // A command that ignores stdin may exit before reading it; that is not an error
+        let _ = writer.join();
+        let output = reader.join()
+            .map_err(|_| self.error("stdout reader panicked".to_string()))?
+            .map_err(|e| self.error(e.to_string()))?;
+
+        if !status.success() {
+            return Err(self.error(format!("exited with {}", status)));
+        }
+        Ok(output.trim_end().to_string())
+    }
+
+    fn error(&self, message: String) -> PromptError {
+        PromptError::module_loading(format!("External module {}: {}", self.rules.name, message)).with_module(&self.rules.name)
+    }
+}
+
+impl PromptModule for ExternalModule {
+    fn name(&self) -> &str {
+        &self.rules.name
+    }
+
+    fn generate_content(&self, tools: &[Tool], session_state: &SessionState, _loader: &mut PromptLoader) -> Result<String, PromptError> {
+        match self.run(tools, session_state) {
+            Ok(content) if content.is_empty() => Ok(content),
+            Ok(content) => Ok(format!("\n{}", content)),
+            Err(e) if self.rules.required || matches!(e, PromptError::Cancelled) => Err(e),
+            Err(e) => {
+                tracing::warn!(module = %self.rules.name, "{}", e);
+                Ok(String::new())
+            }
+        }
+    }
+
+    fn applies_to(&self, _tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> bool {
+        // Modules without keywords apply to every request
+        self.rules.keywords.is_empty() || contains_any(&self.rules.keywords, user_prompt)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn rules(script: &str) -> ExternalModuleRules {
+        ExternalModuleRules {
+            name: "company_style".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            keywords: vec![],
+            timeout_ms: 2000,
+            required: false,
+        }
+    }
+
+    #[test]
+    fn test_external_command_receives_context() {
+        let module = ExternalModule::new(rules("printf 'COMPANY STYLE:\\n'; cat"), "Write the changelog");
+        let mut loader = PromptLoader::new(None);
+        let content = module.generate_content(&[], &SessionState::default(), &mut loader).unwrap();
+
+        assert!(content.starts_with("\nCOMPANY STYLE:\n{"));
+        assert!(content.contains("\"user_prompt\":\"Write the changelog\""));
+
+        let mut failing = rules("exit 3");
+        let module = ExternalModule::new(failing.clone(), "");
+        assert_eq!(module.generate_content(&[], &SessionState::default(), &mut loader).unwrap(), "");
+        failing.required = true;
+        let module = ExternalModule::new(failing, "");
+        assert!(module.generate_content(&[], &SessionState::default(), &mut loader).is_err());
+    }
+    #[test]
+    fn test_cancelling_kills_the_command() {
+        let mut slow = rules("exec sleep 30");
+        slow.timeout_ms = 60_000;
+        let cancel = CancellationToken::new();
+        let module = ExternalModule::new(slow, "").with_cancellation(cancel.clone());
+        let start = Instant::now();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+        let result = module.generate_content(&[], &SessionState::default(), &mut PromptLoader::new(None));
+        canceller.join().unwrap();
+        assert!(matches!(result, Err(PromptError::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
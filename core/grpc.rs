@@ -0,0 +1,318 @@
+//! gRPC service of the `server-grpc` feature, for running the composer as a sidecar.
+//!
+//! `proto/prompt_composer.proto` declares the service for clients in other languages;
+//! the messages below are its Rust side, and build.rs generates the tonic server and
+//! client from the method list without needing `protoc`.
+//!
+//! [`serve`] also serves the standard `grpc.health.v1.Health` service for orchestrators'
+//! probes. The server as a whole (service `""`) is serving while it answers;
+//! `prompt_composer.v1.PromptComposer` is serving only while the prompts directory
+//! validates without errors, checked every [`READINESS_INTERVAL`].
+
+use crate::service::ComposerService;
+use crate::types::{ErrorCode, McpConfig, PromptError, PromptRequest};
+use crate::validate::IssueSeverity;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tonic::{Request, Response, Status};
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+include!(concat!(env!("OUT_DIR"), "/prompt_composer.v1.PromptComposer.rs"));
+
+pub use prompt_composer_client::PromptComposerClient;
+pub use prompt_composer_server::{PromptComposer as PromptComposerService, PromptComposerServer};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ComposeRequest {
+    /// `PromptRequest` as JSON
+    #[prost(string, tag = "1")]
+    pub request_json: String,
+    /// Discover tools by starting the request's MCP servers, instead of using cached or
+    /// inferred tools
+    #[prost(bool, tag = "2")]
+    pub live: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ComposeResponse {
+    #[prost(string, tag = "1")]
+    pub system_prompt: String,
+    #[prost(string, repeated, tag = "2")]
+    pub applied_modules: Vec<String>,
+    #[prost(string, repeated, tag = "3")]
+    pub recognized_tools: Vec<String>,
+    /// The whole `PromptResponse` as JSON
+    #[prost(string, tag = "4")]
+    pub response_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExplainRequest {
+    /// `PromptRequest` as JSON
+    #[prost(string, tag = "1")]
+    pub request_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExplainResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub decisions: Vec<ModuleDecision>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModuleDecision {
+    #[prost(string, tag = "1")]
+    pub module: String,
+    #[prost(string, tag = "2")]
+    pub source: String,
+    #[prost(bool, tag = "3")]
+    pub selected: bool,
+    #[prost(float, optional, tag = "4")]
+    pub score: Option<f32>,
+    #[prost(string, tag = "5")]
+    pub reason: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RefreshServerRequest {
+    #[prost(string, tag = "1")]
+    pub server_name: String,
+    /// `McpConfig` as JSON
+    #[prost(string, tag = "2")]
+    pub mcp_config_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RefreshServerResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub tools: Vec<Tool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Tool {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub description: String,
+    #[prost(string, tag = "3")]
+    pub server: String,
+    /// Input schema as JSON; empty when the server did not report one
+    #[prost(string, tag = "4")]
+    pub schema_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListModulesRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListModulesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub modules: Vec<ModuleInfo>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModuleInfo {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub kind: String,
+    #[prost(string, tag = "3")]
+    pub source: String,
+    #[prost(bool, tag = "4")]
+    pub enabled: bool,
+    #[prost(string, optional, tag = "5")]
+    pub description: Option<String>,
+}
+
+/// The gRPC status of an error, with its error report as JSON in the details
+fn status(e: PromptError) -> Status {
+    let report = e.report();
+    let code = match report.code {
+        ErrorCode::InvalidRequest | ErrorCode::SerializationError => tonic::Code::InvalidArgument,
+        ErrorCode::ConfigError | ErrorCode::ModuleLoadingFailed => tonic::Code::FailedPrecondition,
+        ErrorCode::McpConnectionFailed | ErrorCode::ToolDiscoveryFailed | ErrorCode::HttpError => tonic::Code::Unavailable,
+        ErrorCode::Cancelled => tonic::Code::Cancelled,
+    };
+    let details = serde_json::to_vec(&report).unwrap_or_default();
+    Status::with_details(code, report.message, details.into())
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(json: &str, field: &str) -> Result<T, PromptError> {
+    serde_json::from_str(json).map_err(|e| PromptError::invalid_request(e.to_string()).with_field(field))
+}
+
+/// How often [`serve`] validates the prompts directory for the health service
+pub const READINESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The composer service behind the gRPC methods
+#[derive(Clone)]
+pub struct GrpcComposer {
+    service: ComposerService,
+}
+
+impl GrpcComposer {
+    /// A service reading prompts from `prompts_dir`, or the default prompts directory
+    pub fn new(prompts_dir: Option<String>) -> Self {
+        Self { service: ComposerService::new(prompts_dir) }
+    }
+
+    /// Validate the prompts directory and report the composer service as serving in
+    /// `reporter` when it has no errors, and as not serving otherwise
+    pub async fn report_readiness(&self, reporter: &mut HealthReporter) -> crate::service::Readiness {
+        let service = self.service.clone();
+        let readiness = tokio::task::spawn_blocking(move || service.readiness())
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        let status = if readiness.ready { ServingStatus::Serving } else { ServingStatus::NotServing };
+        reporter.set_service_status(SERVICE_NAME, status).await;
+        readiness
+    }
+}
+
+/// Name of the composer service in the health service
+const SERVICE_NAME: &str = "prompt_composer.v1.PromptComposer";
+
+#[tonic::async_trait]
+impl PromptComposerService for GrpcComposer {
+    async fn compose(&self, request: Request<ComposeRequest>) -> Result<Response<ComposeResponse>, Status> {
+        let ComposeRequest { request_json, live } = request.into_inner();
+        let request: PromptRequest = from_json(&request_json, "request_json").map_err(status)?;
+        let response = self.service.run(move |service, cancel| service.compose_cancellable(&request, live, cancel)).await.map_err(status)?;
+
+        Ok(Response::new(ComposeResponse {
+            response_json: serde_json::to_string(&response).map_err(|e| status(e.into()))?,
+            system_prompt: response.system_prompt,
+            applied_modules: response.applied_modules,
+            recognized_tools: response.recognized_tools,
+        }))
+    }
+
+    async fn explain(&self, request: Request<ExplainRequest>) -> Result<Response<ExplainResponse>, Status> {
+        let request: PromptRequest = from_json(&request.into_inner().request_json, "request_json").map_err(status)?;
+        let decisions = self.service.run(move |service, _| service.explain(&request)).await.map_err(status)?;
+
+        Ok(Response::new(ExplainResponse {
+            decisions: decisions.into_iter()
+                .map(|decision| ModuleDecision {
+                    module: decision.module,
+                    source: format!("{:?}", decision.source),
+                    selected: decision.selected,
+                    score: decision.score,
+                    reason: decision.reason,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn refresh_server(&self, request: Request<RefreshServerRequest>) -> Result<Response<RefreshServerResponse>, Status> {
+        let RefreshServerRequest { server_name, mcp_config_json } = request.into_inner();
+        let mcp_config: McpConfig = from_json(&mcp_config_json, "mcp_config_json").map_err(status)?;
+        let tools = self.service.run(move |service, _| service.refresh_server(&server_name, &mcp_config)).await.map_err(status)?;
+
+        Ok(Response::new(RefreshServerResponse {
+            tools: tools.into_iter()
+                .map(|tool| Tool {
+                    schema_json: tool.schema.map(|schema| schema.to_string()).unwrap_or_default(),
+                    name: tool.name,
+                    description: tool.description,
+                    server: tool.server,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn list_modules(&self, _request: Request<ListModulesRequest>) -> Result<Response<ListModulesResponse>, Status> {
+        let modules = self.service.list_modules();
+        Ok(Response::new(ListModulesResponse {
+            modules: modules.into_iter()
+                .map(|module| ModuleInfo {
+                    kind: format!("{:?}", module.kind),
+                    source: format!("{:?}", module.source),
+                    name: module.name,
+                    enabled: module.enabled,
+                    description: module.description,
+                })
+                .collect(),
+        }))
+    }
+}
+
+/// Serve the composer and the health service over gRPC on `addr` until the process ends
+pub async fn serve(addr: SocketAddr, prompts_dir: Option<String>) -> Result<(), PromptError> {
+    let composer = GrpcComposer::new(prompts_dir);
+    let (mut reporter, health) = tonic_health::server::health_reporter();
+    let probed = composer.clone();
+    tokio::spawn(async move {
+        let mut was_ready = true;
+        loop {
+            let readiness = probed.report_readiness(&mut reporter).await;
+            if was_ready && !readiness.ready {
+                let errors = readiness.validation.issues.iter().filter(|issue| issue.severity == IssueSeverity::Error).count();
+                tracing::warn!(prompts_dir = %readiness.validation.prompts_dir, errors, "Prompts directory is invalid; reporting not serving");
+            }
+            was_ready = readiness.ready;
+            tokio::time::sleep(READINESS_INTERVAL).await;
+        }
+    });
+
+    tonic::transport::Server::builder()
+        .add_service(health)
+        .add_service(PromptComposerServer::new(composer))
+        .serve(addr)
+        .await
+        .map_err(|e| PromptError::config(format!("gRPC server on {} failed: {}", addr, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_grpc_service_composes_and_explains() {
+        let service = GrpcComposer::new(Some(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string()));
+        let request_json = r#"{"user_prompt": "Plan and implement a refactor", "mcp_config": {"mcpServers": {}}}"#.to_string();
+
+        let response = service.compose(Request::new(ComposeRequest { request_json: request_json.clone(), live: false })).await.unwrap().into_inner();
+        assert!(!response.system_prompt.is_empty());
+        let full: crate::types::PromptResponse = serde_json::from_str(&response.response_json).unwrap();
+        assert_eq!(full.applied_modules, response.applied_modules);
+
+        let decisions = service.explain(Request::new(ExplainRequest { request_json })).await.unwrap().into_inner().decisions;
+        let selected: Vec<&str> = decisions.iter().filter(|decision| decision.selected).map(|decision| decision.module.as_str()).collect();
+        assert_eq!(selected.len(), response.applied_modules.len());
+
+        let modules = service.list_modules(Request::new(ListModulesRequest {})).await.unwrap().into_inner().modules;
+        assert!(modules.iter().any(|module| module.name == "safety" && module.kind == "Behavior"));
+
+        let invalid = service.compose(Request::new(ComposeRequest { request_json: "{}".to_string(), live: false })).await.unwrap_err();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+        let report: serde_json::Value = serde_json::from_slice(invalid.details()).unwrap();
+        assert_eq!(report["code"], "InvalidRequest");
+    }
+
+    #[tokio::test]
+    async fn test_health_service_reports_readiness_of_the_prompts_directory() {
+        let (mut reporter, health) = tonic_health::server::health_reporter();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(tonic::transport::Server::builder().add_service(health).serve_with_incoming(incoming));
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+        let mut client = tonic_health::pb::health_client::HealthClient::new(channel);
+        let check = |service: &str| tonic_health::pb::HealthCheckRequest { service: service.to_string() };
+
+        let missing = std::env::temp_dir().join(format!("prompt-composer-grpc-health-{}", std::process::id()));
+        for (prompts_dir, status) in [
+            (concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string(), ServingStatus::Serving),
+            (missing.display().to_string(), ServingStatus::NotServing),
+        ] {
+            let readiness = GrpcComposer::new(Some(prompts_dir)).report_readiness(&mut reporter).await;
+            assert_eq!(readiness.ready, status == ServingStatus::Serving);
+            let reported = client.check(check(SERVICE_NAME)).await.unwrap().into_inner().status;
+            assert_eq!(reported, tonic_health::pb::health_check_response::ServingStatus::from(status) as i32);
+        }
+        let server = client.check(check("")).await.unwrap().into_inner().status;
+        assert_eq!(server, tonic_health::pb::health_check_response::ServingStatus::Serving as i32);
+    }
+}
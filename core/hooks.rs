@@ -0,0 +1,80 @@
+//! Composition hooks: text placed around the composed modules and observers notified
+//! of every composition, for hosts that extend composition without writing a module.
+
+use crate::types::{PromptError, PromptRequest, PromptResponse};
+use std::sync::Arc;
+
+/// A hook run by every composition of the composer it is added to. Each method has a
+/// default that does nothing, so a hook implements only what it provides.
+pub trait CompositionHook: Send + Sync {
+    /// Name the hook is added and removed by, reported as `hook:<name>` in the applied
+    /// modules when it contributes text
+    fn name(&self) -> &str;
+
+    /// Text placed before the composed modules
+    fn prologue(&self, _request: &PromptRequest) -> Result<Option<String>, PromptError> {
+        Ok(None)
+    }
+
+    /// Text placed after the composed modules and tool instructions
+    fn epilogue(&self, _request: &PromptRequest) -> Result<Option<String>, PromptError> {
+        Ok(None)
+    }
+
+    /// Called with each successful composition, after the response is complete
+    fn on_compose(&self, _request: &PromptRequest, _response: &PromptResponse) {}
+}
+
+/// The hooks of a composer, run in the order they were added
+#[derive(Clone, Default)]
+pub struct HookSet {
+    hooks: Vec<Arc<dyn CompositionHook>>,
+}
+
+impl HookSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hook, replacing the one with the same name in place
+    pub fn add(&mut self, hook: Arc<dyn CompositionHook>) {
+        match self.hooks.iter_mut().find(|existing| existing.name() == hook.name()) {
+            Some(existing) => *existing = hook,
+            None => self.hooks.push(hook),
+        }
+    }
+
+    /// Remove a hook, returning whether it was added
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.hooks.len();
+        self.hooks.retain(|hook| hook.name() != name);
+        self.hooks.len() != before
+    }
+
+    /// Add every hook of `other`, replacing hooks with the same name
+    pub fn merge(&mut self, other: &HookSet) {
+        for hook in &other.hooks {
+            self.add(hook.clone());
+        }
+    }
+
+    /// Names of the hooks, in the order they run
+    pub fn names(&self) -> Vec<String> {
+        self.hooks.iter().map(|hook| hook.name().to_string()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn CompositionHook>> {
+        self.hooks.iter()
+    }
+
+    /// Notify every hook of a completed composition
+    pub fn notify(&self, request: &PromptRequest, response: &PromptResponse) {
+        for hook in &self.hooks {
+            hook.on_compose(request, response);
+        }
+    }
+}
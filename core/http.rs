@@ -0,0 +1,195 @@
+//! HTTP API of the `server` feature, for languages without bindings and for serving one
+//! prompt configuration to a fleet of assistants.
+//!
+//! | Route | Body | Returns |
+//! |---|---|---|
+//! | `POST /compose[?live=true]` | `PromptRequest` | `PromptResponse` |
+//! | `POST /explain` | `PromptRequest` | `ModuleDecision` array |
+//! | `GET /modules` | | `ModuleInfo` array |
+//! | `POST /refresh/{server}` | `McpConfig` | `Tool` array |
+//! | `GET /healthz` | | `{"status": "ok", "version": ...}` while the server answers |
+//! | `GET /readyz` | | `Readiness`, with status 503 when the prompts directory has errors |
+//! | `GET /metrics` | | Prometheus metrics, with the `metrics` feature |
+//!
+//! Bodies are the JSON of the crate's types. Failures return the error report as JSON.
+//! Orchestrators probe `/healthz` for liveness and `/readyz` for readiness, so traffic is
+//! not routed to a composer whose prompts directory or pattern configuration is broken.
+
+use crate::service::ComposerService;
+use crate::types::{ErrorCode, McpConfig, PromptError, PromptRequest};
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+
+/// A failed call as an HTTP response
+struct HttpError(PromptError);
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        let report = self.0.report();
+        let status = match report.code {
+            ErrorCode::InvalidRequest | ErrorCode::SerializationError => StatusCode::BAD_REQUEST,
+            ErrorCode::ConfigError | ErrorCode::ModuleLoadingFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::McpConnectionFailed | ErrorCode::ToolDiscoveryFailed | ErrorCode::HttpError => StatusCode::BAD_GATEWAY,
+            // Compositions are cancelled when their client disconnects, so this is never read
+            ErrorCode::Cancelled => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, Json(report)).into_response()
+    }
+}
+
+impl From<PromptError> for HttpError {
+    fn from(e: PromptError) -> Self {
+        HttpError(e)
+    }
+}
+
+/// Parse a body, reporting malformed JSON like any other invalid request
+fn from_json<T: serde::de::DeserializeOwned>(body: &[u8], field: &str) -> Result<T, HttpError> {
+    serde_json::from_slice(body).map_err(|e| PromptError::invalid_request(e.to_string()).with_field(field).into())
+}
+
+#[derive(Deserialize)]
+struct ComposeQuery {
+    /// Discover tools by starting the request's MCP servers, instead of using cached or
+    /// inferred tools
+    #[serde(default)]
+    live: bool,
+}
+
+async fn compose(
+    State(service): State<ComposerService>,
+    Query(query): Query<ComposeQuery>,
+    body: Bytes,
+) -> Result<impl IntoResponse, HttpError> {
+    let request: PromptRequest = from_json(&body, "request")?;
+    let response = service.run(move |service, cancel| service.compose_cancellable(&request, query.live, cancel)).await?;
+    Ok(Json(response))
+}
+
+async fn explain(State(service): State<ComposerService>, body: Bytes) -> Result<impl IntoResponse, HttpError> {
+    let request: PromptRequest = from_json(&body, "request")?;
+    Ok(Json(service.run(move |service, _| service.explain(&request)).await?))
+}
+
+async fn list_modules(State(service): State<ComposerService>) -> impl IntoResponse {
+    Json(service.list_modules())
+}
+
+async fn refresh_server(
+    State(service): State<ComposerService>,
+    Path(server_name): Path<String>,
+    body: Bytes,
+) -> Result<impl IntoResponse, HttpError> {
+    let mcp_config: McpConfig = from_json(&body, "mcp_config")?;
+    Ok(Json(service.run(move |service, _| service.refresh_server(&server_name, &mcp_config)).await?))
+}
+
+async fn healthz() -> impl IntoResponse {
+    Json(json!({"status": "ok", "version": env!("CARGO_PKG_VERSION")}))
+}
+
+async fn readyz(State(service): State<ComposerService>) -> Result<impl IntoResponse, HttpError> {
+    let readiness = service.run(|service, _| Ok(service.readiness())).await?;
+    let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok((status, Json(readiness)))
+}
+
+/// The API's routes, for serving them on their own or nesting them in a host application
+pub fn router(service: ComposerService) -> Router {
+    Router::new()
+        .route("/compose", post(compose))
+        .route("/explain", post(explain))
+        .route("/modules", get(list_modules))
+        .route("/refresh/:server", post(refresh_server))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(service)
+}
+
+/// `GET /metrics`, rendering the metrics for Prometheus to scrape
+#[cfg(feature = "metrics")]
+pub fn metrics_router(metrics: std::sync::Arc<crate::metrics::PrometheusMetrics>) -> Router {
+    Router::new().route("/metrics", get(move || async move {
+        ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics.render())
+    }))
+}
+
+/// Serve the composer over HTTP on `addr` until the process ends. With the `metrics`
+/// feature, every composition in the process is recorded and served at `/metrics`.
+pub async fn serve(addr: SocketAddr, prompts_dir: Option<String>) -> Result<(), PromptError> {
+    let router = router(ComposerService::new(prompts_dir));
+    #[cfg(feature = "metrics")]
+    let router = {
+        let metrics = std::sync::Arc::new(crate::metrics::PrometheusMetrics::new()?);
+        crate::metrics::set_recorder(metrics.clone());
+        router.merge(metrics_router(metrics))
+    };
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| PromptError::config(format!("Failed to listen on {}: {}", addr, e)))?;
+    axum::serve(listener, router)
+        .await
+        .map_err(|e| PromptError::config(format!("HTTP server on {} failed: {}", addr, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ModuleInfo, PromptResponse};
+
+    #[tokio::test]
+    async fn test_http_api_composes_and_lists_modules() {
+        let service = ComposerService::new(Some(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router(service)).await });
+
+        let client = reqwest::Client::new();
+        let request = serde_json::json!({"user_prompt": "Plan and implement a refactor", "mcp_config": {"mcpServers": {}}});
+        let response = client.post(format!("{}/compose", base)).json(&request).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response: PromptResponse = response.json().await.unwrap();
+        assert!(!response.system_prompt.is_empty());
+
+        let decisions: Vec<serde_json::Value> = client.post(format!("{}/explain", base)).json(&request).send().await.unwrap().json().await.unwrap();
+        assert_eq!(decisions.iter().filter(|decision| decision["selected"] == true).count(), response.applied_modules.len());
+
+        let modules: Vec<ModuleInfo> = client.get(format!("{}/modules", base)).send().await.unwrap().json().await.unwrap();
+        assert!(modules.iter().any(|module| module.name == "safety"));
+
+        let invalid = client.post(format!("{}/compose", base)).body("{}").send().await.unwrap();
+        assert_eq!(invalid.status(), 400);
+        let report: serde_json::Value = invalid.json().await.unwrap();
+        assert_eq!(report["code"], "InvalidRequest");
+
+        let missing = client.post(format!("{}/refresh/nowhere", base)).json(&serde_json::json!({"mcpServers": {}})).send().await.unwrap();
+        assert!(!missing.status().is_success());
+        let report: serde_json::Value = missing.json().await.unwrap();
+        assert!(report["message"].as_str().unwrap().contains("nowhere"));
+    }
+
+    #[tokio::test]
+    async fn test_readyz_reflects_the_prompts_directory() {
+        let missing = std::env::temp_dir().join(format!("prompt-composer-readyz-{}", std::process::id()));
+        let client = reqwest::Client::new();
+        for (prompts_dir, ready) in [(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string(), true), (missing.display().to_string(), false)] {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, router(ComposerService::new(Some(prompts_dir)))).await });
+
+            assert_eq!(client.get(format!("{}/healthz", base)).send().await.unwrap().status(), 200);
+            let response = client.get(format!("{}/readyz", base)).send().await.unwrap();
+            assert_eq!(response.status(), if ready { 200 } else { 503 });
+            let readiness: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(readiness["ready"], ready);
+            assert_eq!(readiness["issues"].as_array().unwrap().is_empty(), ready);
+        }
+    }
+}
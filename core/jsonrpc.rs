@@ -0,0 +1,91 @@
+//! JSON-RPC 2.0 over a byte stream, one message per line, as spoken by MCP's stdio
+//! transport.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Distinguishes `"id": null` from a missing id
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => serde_json::json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => serde_json::json!({"jsonrpc": "2.0", "id": id, "error": error}),
+    }
+}
+
+/// Answer one line, or return `None` for a notification
+pub fn handle_line(line: &str, handler: &mut impl FnMut(&str, Value) -> Result<Value, RpcError>) -> Option<Value> {
+    let request: Request = match serde_json::from_str::<Value>(line) {
+        Err(e) => return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string())))),
+        Ok(message) => {
+            let id = message.get("id").cloned().unwrap_or(Value::Null);
+            match serde_json::from_value(message) {
+                Ok(request) => request,
+                Err(e) => return Some(response(id, Err(RpcError::new(INVALID_REQUEST, e.to_string())))),
+            }
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        return Some(response(request.id.unwrap_or(Value::Null), Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""))));
+    }
+    let result = handler(&request.method, request.params);
+    request.id.map(|id| response(id, result))
+}
+
+/// Answer each line of `input` on `output` until the input ends
+pub fn serve(
+    input: impl BufRead,
+    mut output: impl Write,
+    mut handler: impl FnMut(&str, Value) -> Result<Value, RpcError>,
+) -> std::io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_line(&line, &mut handler) {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
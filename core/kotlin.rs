@@ -0,0 +1,133 @@
+//! Kotlin and Java bindings, generated by UniFFI when the crate is built with the `kotlin`
+//! feature:
+//!
+//! ```text
+//! cargo build --release --features kotlin
+//! cargo run --features kotlin --bin uniffi-bindgen -- generate \
+//!     --library target/release/libprompt_composer.so --language kotlin --out-dir kotlin
+//! ```
+//!
+//! Requests and responses are JSON strings, the same JSON the other bindings exchange, so
+//! hosts parse them with the JSON library they already use. Compositions that discover
+//! tools are `suspend` functions in Kotlin.
+
+use crate::types::{PromptError, PromptRequest, PromptResponse};
+
+/// A failed call, with its error report as JSON (`code`, `message` and whatever server,
+/// module, path or field it names)
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum ComposerError {
+    #[error("{description}")]
+    Failed { code: String, description: String, report_json: String },
+}
+
+impl From<PromptError> for ComposerError {
+    fn from(e: PromptError) -> Self {
+        let report = e.report();
+        ComposerError::Failed {
+            code: format!("{:?}", report.code),
+            description: report.message.clone(),
+            report_json: serde_json::to_string(&report).unwrap_or_default(),
+        }
+    }
+}
+
+fn request(request_json: &str) -> Result<PromptRequest, PromptError> {
+    serde_json::from_str(request_json).map_err(|e| PromptError::invalid_request(e.to_string()))
+}
+
+fn to_json(value: &impl serde::Serialize) -> Result<String, ComposerError> {
+    serde_json::to_string(value).map_err(|e| PromptError::from(e).into())
+}
+
+/// Compose a system prompt, discovering tools by starting the configured MCP servers
+#[uniffi::export(async_runtime = "tokio")]
+pub async fn compose_system_prompt(request_json: String, prompts_dir: Option<String>) -> Result<String, ComposerError> {
+    to_json(&crate::compose_system_prompt_with_prompts_dir(request(&request_json)?, prompts_dir).await?)
+}
+
+/// Compose a system prompt from cached tools, or tools inferred from the MCP config,
+/// without waiting for any server
+#[uniffi::export]
+pub fn compose_system_prompt_cached(request_json: String, prompts_dir: Option<String>) -> Result<String, ComposerError> {
+    to_json(&crate::compose_system_prompt_cached_with_prompts_dir(request(&request_json)?, prompts_dir)?)
+}
+
+/// Compose a system prompt without starting a process or contacting the network; without
+/// a prompts directory the prompts compiled into the library are used
+#[uniffi::export]
+pub fn compose_system_prompt_offline(request_json: String, prompts_dir: Option<String>) -> Result<String, ComposerError> {
+    to_json(&crate::compose_system_prompt_offline(request(&request_json)?, prompts_dir)?)
+}
+
+/// The modules a request would get, as a JSON array of decisions, without composing it
+#[uniffi::export]
+pub fn explain_selection(request_json: String, prompts_dir: Option<String>) -> Result<String, ComposerError> {
+    to_json(&crate::explain_selection(&request(&request_json)?, prompts_dir)?)
+}
+
+/// Rediscover the tools of one server of an MCP config, returning them as a JSON array
+#[uniffi::export(async_runtime = "tokio")]
+pub async fn refresh_server_tools(server_name: String, mcp_config_json: String) -> Result<String, ComposerError> {
+    let mcp_config = serde_json::from_str(&mcp_config_json)
+        .map_err(|e| PromptError::invalid_request(e.to_string()).with_field("mcp_config"))?;
+    to_json(&crate::refresh_server_tools(&server_name, &mcp_config).await?)
+}
+
+/// Registered modules as a JSON array of name, kind, source and enabled state
+#[uniffi::export]
+pub fn list_modules() -> Result<String, ComposerError> {
+    to_json(&crate::list_modules())
+}
+
+/// Enable or disable a registered module, returning whether it exists
+#[uniffi::export]
+pub fn set_module_enabled(name: String, enabled: bool) -> bool {
+    crate::set_module_enabled(&name, enabled)
+}
+
+/// End a session, calling `on_session_end` on its modules; returns whether it existed
+#[uniffi::export]
+pub fn end_session(session_id: String) -> bool {
+    crate::end_session(&session_id)
+}
+
+/// JSON schema of composition requests
+#[uniffi::export]
+pub fn request_schema() -> String {
+    PromptRequest::json_schema().to_string()
+}
+
+/// JSON schema of composition responses
+#[uniffi::export]
+pub fn response_schema() -> String {
+    PromptResponse::json_schema().to_string()
+}
+
+/// Version of the library
+#[uniffi::export]
+pub fn version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ErrorCode;
+
+    #[test]
+    fn test_kotlin_functions_exchange_json() {
+        let request = r#"{"user_prompt": "Plan a refactor", "mcp_config": {"mcpServers": {}}}"#.to_string();
+        let response: PromptResponse = serde_json::from_str(&compose_system_prompt_offline(request.clone(), None).unwrap()).unwrap();
+        assert!(!response.system_prompt.is_empty());
+        assert!(explain_selection(request, None).unwrap().starts_with('['));
+
+        let Err(ComposerError::Failed { code, description, report_json }) = compose_system_prompt_cached("{}".to_string(), None) else {
+            panic!("a request without user_prompt is rejected");
+        };
+        assert_eq!(code, "InvalidRequest");
+        assert!(description.contains("user_prompt"));
+        let report: serde_json::Value = serde_json::from_str(&report_json).unwrap();
+        assert_eq!(report["code"], serde_json::to_value(ErrorCode::InvalidRequest).unwrap());
+    }
+}
@@ -1,12 +1,80 @@
 pub mod types;
+pub mod builder;
 pub mod modules;
 pub mod discovery;
+pub mod mcp_client;
+pub mod embedded;
+pub mod bundle;
 pub mod loader;
+pub mod provider;
 pub mod composition;
+pub mod engine;
+pub mod cancel;
+pub mod cache;
+pub mod metrics;
+pub mod rules;
+pub mod external_module;
+pub mod registry;
+pub mod session;
+pub mod hooks;
+pub mod audit;
+pub mod typescript;
+pub mod validate;
+pub mod lint;
+pub mod coverage;
+pub mod eval;
+pub mod diff;
+pub mod render;
+pub mod report;
 
 #[cfg(feature = "python")]
 pub mod python;
 
+#[cfg(feature = "wasm-plugins")]
+pub mod wasm_plugin;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(feature = "kotlin")]
+pub mod kotlin;
+
+#[cfg(any(feature = "server", feature = "server-mcp", feature = "server-grpc", feature = "daemon"))]
+pub mod service;
+
+#[cfg(any(feature = "server-mcp", feature = "daemon", feature = "testing"))]
+pub mod jsonrpc;
+
+#[cfg(feature = "server-mcp")]
+pub mod mcp_server;
+
+#[cfg(feature = "daemon")]
+pub mod daemon;
+
+#[cfg(feature = "cli")]
+pub mod cli;
+
+#[cfg(feature = "otel")]
+pub mod otel;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "testing")]
+pub mod mcp_fixture;
+
+#[cfg(feature = "server")]
+pub mod http;
+
+#[cfg(feature = "server-grpc")]
+pub mod grpc;
+
+#[cfg(feature = "kotlin")]
+uniffi::setup_scaffolding!();
+
 // For PyO3, we need to define the module at the crate root
 #[cfg(feature = "python")]
 use pyo3::prelude::*;
@@ -17,17 +85,176 @@ fn _system_prompt_composer(_py: Python, m: &PyModule) -> PyResult<()> {
     python::python_module(_py, m)
 }
 
-// NAPI-RS bindings - must be in lib.rs for proper registration
-#[cfg(feature = "nodejs")]
-use napi_derive::napi;
+// std::time panics on wasm32-unknown-unknown, where web-time reads the browser's clock
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::{Instant, SystemTime, UNIX_EPOCH};
 
 use types::*;
 use composition::PromptComposer;
+#[cfg(feature = "nodejs")]
 use discovery::ToolDiscovery;
-use std::sync::Mutex;
+use engine::Engine;
+use modules::PromptModule;
+use hooks::{CompositionHook, HookSet};
+use registry::ModuleRegistry;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
 
-/// Global tool discovery instance with caching
-static TOOL_DISCOVERY: Mutex<Option<ToolDiscovery>> = Mutex::new(None);
+/// Engine shared by the composition functions below and every thread of the process,
+/// including Node.js worker threads, which load the addon once
+static ENGINE: LazyLock<Engine> = LazyLock::new(Engine::new);
+
+/// Lock shared state, recovering it from a thread that panicked while holding it
+fn lock<T>(state: &Mutex<T>) -> MutexGuard<'_, T> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runtime the native bindings run async composition on, created on first use and
+/// shared by every call, since a runtime per call costs milliseconds and threads
+#[cfg(any(feature = "python", feature = "nodejs", feature = "capi"))]
+fn runtime() -> std::io::Result<&'static tokio::runtime::Runtime> {
+    static RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .thread_name("prompt-composer")
+        .enable_all()
+        .build()?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Send the crate's diagnostics to stderr, filtered by `RUST_LOG` and showing warnings
+/// when it is unset, with the trace IDs of the requests they belong to. With the `otel`
+/// feature, spans are also exported when an OTLP endpoint is configured. The binaries
+/// call this first and hold the guard until they exit; hosts embedding the library
+/// install their own `tracing` subscriber instead.
+#[cfg(any(feature = "server", feature = "server-mcp", feature = "server-grpc", feature = "daemon", feature = "cli"))]
+pub fn init_logging() -> LoggingGuard {
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::{EnvFilter, Layer};
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn,prompt_composer=info"));
+    let stderr = tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_filter(filter);
+    #[cfg(feature = "otel")]
+    let provider = otel::tracer_provider_from_env();
+    #[cfg(feature = "otel")]
+    let subscriber = tracing_subscriber::registry()
+        .with(stderr)
+        .with(provider.as_ref().ok().and_then(Option::as_ref).map(otel::layer));
+    #[cfg(not(feature = "otel"))]
+    let subscriber = tracing_subscriber::registry().with(stderr);
+    // A subscriber installed before stays in place
+    let _ = subscriber.try_init();
+    #[cfg(feature = "otel")]
+    return LoggingGuard {
+        provider: provider.unwrap_or_else(|e| {
+            tracing::warn!("Not exporting spans: {}", e);
+            None
+        }),
+    };
+    #[cfg(not(feature = "otel"))]
+    LoggingGuard {}
+}
+
+/// Returned by [`init_logging`]; exports the spans not yet sent when dropped
+#[cfg(any(feature = "server", feature = "server-mcp", feature = "server-grpc", feature = "daemon", feature = "cli"))]
+#[must_use]
+pub struct LoggingGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+#[cfg(all(feature = "otel", any(feature = "server", feature = "server-mcp", feature = "server-grpc", feature = "daemon", feature = "cli")))]
+impl Drop for LoggingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            let _ = provider.shutdown();
+        }
+    }
+}
+
+/// The engine the composition functions below use
+pub fn global_engine() -> &'static Engine {
+    &ENGINE
+}
+
+/// Register a module for every subsequent composition in this process. A module
+/// registered under an existing name replaces it; built-in modules cannot be replaced.
+pub fn register_module(module: Arc<dyn PromptModule>) {
+    if let Err(e) = ENGINE.register_module(module) {
+        tracing::warn!("{}", e);
+    }
+}
+
+/// Remove a registered module, returning whether it was registered
+pub fn unregister_module(name: &str) -> bool {
+    ENGINE.unregister_module(name)
+}
+
+/// Enable or disable a module for every subsequent composition, returning whether the
+/// module exists
+pub fn set_module_enabled(name: &str, enabled: bool) -> bool {
+    ENGINE.set_module_enabled(name, enabled)
+}
+
+/// Name, kind, source and enabled state of every built-in and registered module
+pub fn list_modules() -> Vec<ModuleInfo> {
+    ENGINE.list_modules()
+}
+
+/// A copy of the registry the composition functions use, to merge into other composers
+pub fn registered_modules() -> ModuleRegistry {
+    ENGINE.registered_modules()
+}
+
+/// Add a hook to every subsequent composition in this process, replacing the hook with
+/// the same name
+pub fn register_hook(hook: Arc<dyn CompositionHook>) {
+    ENGINE.register_hook(hook);
+}
+
+/// Remove a registered hook, returning whether it was registered
+pub fn unregister_hook(name: &str) -> bool {
+    ENGINE.unregister_hook(name)
+}
+
+/// A copy of the hooks the composition functions run, to merge into other composers
+pub fn registered_hooks() -> HookSet {
+    ENGINE.registered_hooks()
+}
+
+/// Add the registered modules and hooks to a composer imported with
+/// `PromptComposer::import_config`, keeping the enabled state of the modules it knows
+#[cfg(any(feature = "python", feature = "nodejs"))]
+fn adopt_registered(composer: &mut PromptComposer) {
+    let states = composer.list_modules();
+    composer.registry_mut().merge(&registered_modules());
+    composer.hooks_mut().merge(&registered_hooks());
+    for module in states {
+        composer.set_module_enabled(&module.name, module.enabled);
+    }
+}
+
+/// Entries, approximate bytes and hit rates of the caches the composition functions
+/// share, so long-running hosts can watch them and call `clear_cache` before they grow
+/// too large
+pub fn cache_stats() -> CacheStats {
+    ENGINE.cache_stats()
+}
+
+/// Forget the discovered tools and prompt files the composition functions share
+pub fn clear_cache() {
+    ENGINE.clear_cache();
+}
+
+/// End a session started by the composition functions, calling `on_session_end` on its
+/// modules. Returns whether the session existed.
+pub fn end_session(session_id: &str) -> bool {
+    ENGINE.end_session(session_id)
+}
 
 /// Main function to compose system prompts based on MCP configuration and session state
 pub async fn compose_system_prompt(request: PromptRequest) -> Result<PromptResponse, PromptError> {
@@ -39,45 +266,7 @@ pub async fn compose_system_prompt_with_prompts_dir(
     request: PromptRequest, 
     prompts_dir: Option<String>
 ) -> Result<PromptResponse, PromptError> {
-    let start_time = std::time::Instant::now();
-    
-    // Initialize or get tool discovery instance
-    let mut discovery = {
-        let mut guard = TOOL_DISCOVERY.lock().unwrap();
-        if guard.is_none() {
-            *guard = Some(match &prompts_dir {
-                Some(dir) => ToolDiscovery::with_prompts_dir(dir.clone()),
-                None => ToolDiscovery::new(),
-            });
-        }
-        guard.take().unwrap()
-    };
-
-    // Discover available tools
-    let tools = discovery.discover_tools(&request.mcp_config).await?;
-    
-    // Put discovery back
-    {
-        let mut guard = TOOL_DISCOVERY.lock().unwrap();
-        *guard = Some(discovery);
-    }
-
-    // Create composer with appropriate prompts directory
-    let mut composer = match prompts_dir {
-        Some(dir) => PromptComposer::with_prompts_dir(dir),
-        None => PromptComposer::new(),
-    };
-
-    // Compose the prompt
-    let response = composer.compose(&request, &tools)?;
-    
-    // Track performance
-    let elapsed = start_time.elapsed();
-    if elapsed.as_millis() > 50 {
-        eprintln!("Warning: Prompt composition took {}ms", elapsed.as_millis());
-    }
-
-    Ok(response)
+    ENGINE.compose_live_in(request, prompts_dir, &cancel::CancellationToken::new())
 }
 
 /// Synchronous version that uses cached tools or infers from config
@@ -90,60 +279,46 @@ pub fn compose_system_prompt_cached_with_prompts_dir(
     request: PromptRequest, 
     prompts_dir: Option<String>
 ) -> Result<PromptResponse, PromptError> {
-    let start_time = std::time::Instant::now();
-    
-    // Get cached tools, but if cache is empty, infer from config
-    let tools = {
-        let mut guard = TOOL_DISCOVERY.lock().unwrap();
-        if let Some(ref mut discovery) = *guard {
-            let cached = discovery.get_cached_tools();
-            if cached.is_empty() {
-                // Cache is empty, infer tools from MCP config
-                discovery.infer_tools_from_config_immediate(&request.mcp_config)
-            } else {
-                cached
-            }
-        } else {
-            // No discovery instance, create temporary one to infer from config
-            let mut discovery = ToolDiscovery::new();
-            discovery.infer_tools_from_config_immediate(&request.mcp_config)
-        }
-    };
+    ENGINE.compose_cached_in(request, prompts_dir, false, None)
+}
 
-    // Create composer with appropriate prompts directory
-    let mut composer = match prompts_dir {
-        Some(dir) => PromptComposer::with_prompts_dir(dir),
-        None => PromptComposer::new(),
-    };
+/// Compose without starting a process or contacting the network, for sandboxed hosts:
+/// tools are inferred from the MCP config, external modules are left out, and prompts
+/// are read from `prompts_dir` or, when unset, from the prompts compiled into the library
+pub fn compose_system_prompt_offline(request: PromptRequest, prompts_dir: Option<String>) -> Result<PromptResponse, PromptError> {
+    ENGINE.compose_cached_in(request, prompts_dir, true, None)
+}
 
-    // Compose the prompt
-    let response = composer.compose(&request, &tools)?;
-    
-    // Track performance
-    let elapsed = start_time.elapsed();
-    if elapsed.as_millis() > 10 {
-        eprintln!("Warning: Cached prompt composition took {}ms", elapsed.as_millis());
-    }
+/// Cached composition that passes each section of the prompt to `on_section` as soon as
+/// it is generated. Concatenated in order, the sections' text is the response's prompt.
+pub fn compose_system_prompt_streaming(
+    request: PromptRequest,
+    prompts_dir: Option<String>,
+    mut on_section: impl FnMut(PromptSection)
+) -> Result<PromptResponse, PromptError> {
+    ENGINE.compose_cached_in(request, prompts_dir, false, Some(&mut on_section))
+}
 
-    Ok(response)
+/// Explain which modules a request would get, without composing it or touching session
+/// state. Tools come from the discovery cache or are inferred from the config, as for
+/// `compose_system_prompt_cached`.
+pub fn explain_selection(request: &PromptRequest, prompts_dir: Option<String>) -> Result<Vec<ModuleDecision>, PromptError> {
+    ENGINE.explain_in(request, prompts_dir, false)
 }
 
 /// Force refresh tools for a specific server
 pub async fn refresh_server_tools(server_name: &str, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
-    let mut discovery = {
-        let mut guard = TOOL_DISCOVERY.lock().unwrap();
-        guard.take().unwrap_or_else(ToolDiscovery::new)
-    };
+    ENGINE.refresh_server_tools(server_name, mcp_config).await
+}
 
-    let tools = discovery.refresh_server(server_name, mcp_config).await?;
-    
-    // Put discovery back
-    {
-        let mut guard = TOOL_DISCOVERY.lock().unwrap();
-        *guard = Some(discovery);
-    }
+/// List the domain modules available in the default prompts directory
+pub fn list_available_domains() -> Result<Vec<String>, PromptError> {
+    PromptComposer::new().list_domains()
+}
 
-    Ok(tools)
+/// List the behavior modules available in the default prompts directory
+pub fn list_available_behaviors() -> Result<Vec<String>, PromptError> {
+    PromptComposer::new().list_behaviors()
 }
 
 #[cfg(test)]
@@ -170,7 +345,19 @@ mod tests {
             }),
             domain_hints: None,
             behavior_hints: None,
-            task_complexity: Some(TaskComplexity::Simple),
+            task_complexity: Some(RequestedComplexity::Simple),
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
         };
 
         let response = compose_system_prompt(request).await.unwrap();
@@ -195,13 +382,131 @@ mod tests {
             domain_hints: None,
             behavior_hints: None,
             task_complexity: None,
+            response_format: None,
+            policy: None,
+            selection_seed: None,
+            module_versions: None,
+            variant_assignments: None,
+            workspace: None,
+            conversation: None,
+            max_tokens: None,
+            locale: None,
+            force_refresh: None,
+            trace_id: None,
+            profile: None,
         };
 
         let response = compose_system_prompt_cached(request).unwrap();
         assert!(!response.system_prompt.is_empty());
+        assert_eq!(response.metadata.discovery, DiscoveryMode::Cached);
+        assert_eq!(response.metadata.content_hash.len(), 16);
+        assert_eq!(response.metadata.version, env!("CARGO_PKG_VERSION"));
         // Note: tool recognition depends on server pattern matching
     }
 
+    #[test]
+    fn test_request_and_response_schemas() {
+        let request = PromptRequest::json_schema();
+        assert!(request["properties"]["user_prompt"].is_object());
+        assert!(request["required"].as_array().unwrap().contains(&"user_prompt".into()));
+
+        let response = PromptResponse::json_schema();
+        assert!(response["properties"]["selection_trace"].is_object());
+    }
+
+    #[test]
+    fn test_python_stubs_cover_rust_fields() {
+        let stub = include_str!("../python/system_prompt_composer/_system_prompt_composer.pyi");
+        let schemas = [
+            ("SessionState", serde_json::to_value(schemars::schema_for!(SessionState)).unwrap()),
+            ("McpConfig", serde_json::to_value(schemars::schema_for!(McpConfig)).unwrap()),
+            ("PromptRequest", PromptRequest::json_schema()),
+            ("PromptResponse", PromptResponse::json_schema()),
+            ("ModuleDecision", serde_json::to_value(schemars::schema_for!(ModuleDecision)).unwrap()),
+        ];
+        for (class, schema) in schemas {
+            let body = stub.split(&format!("class {}:", class)).nth(1).unwrap()
+                .split("\nclass ").next().unwrap();
+            for field in schema["properties"].as_object().unwrap().keys() {
+                let attribute = if field == "mcpServers" { "mcp_servers" } else { field.as_str() };
+                assert!(body.contains(&format!("\n    {}: ", attribute)), "{}.{} missing from the stubs", class, attribute);
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_compositions_share_discovery() {
+        let server = |name: &str| McpServer {
+            name: name.to_string(),
+            command: format!("{}-command", name),
+            args: vec![],
+            env: None,
+        };
+        let request = PromptRequest::builder()
+            .user_prompt("Read a file")
+            .mcp_server("concurrent-filesystem", server("filesystem"))
+            .build()
+            .unwrap();
+
+        // A thread that panics while holding the discovery lock must not break the others
+        let _ = std::thread::spawn(|| {
+            let _guard = ENGINE.discovery(None).cache_mut();
+            panic!("poisoning the discovery lock");
+        }).join();
+
+        let threads: Vec<_> = (0..8).map(|i| {
+            let request = request.clone();
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+                for _ in 0..5 {
+                    let response = match i % 3 {
+                        0 => compose_system_prompt_cached(request.clone()).unwrap(),
+                        1 => runtime.block_on(compose_system_prompt(request.clone())).unwrap(),
+                        _ => {
+                            runtime.block_on(refresh_server_tools("concurrent-filesystem", &request.mcp_config)).unwrap();
+                            explain_selection(&request, None).unwrap();
+                            continue;
+                        }
+                    };
+                    assert!(!response.system_prompt.is_empty());
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(ENGINE.discovery(None).get_cached_tools()
+            .iter().any(|tool| tool.server == "concurrent-filesystem"));
+    }
+
+    #[test]
+    fn test_conformance_vectors() {
+        // The Python and Node suites check their bindings against the same vectors, so
+        // the three surfaces compose identical prompts; UPDATE_SNAPSHOTS=1 regenerates them.
+        // Offline, tools come from the request's config alone, not from other tests' caches.
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/conformance/vectors.json");
+        let mut file: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let prompts_dir = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), file["prompts_dir"].as_str().unwrap());
+        let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+        for vector in file["vectors"].as_array_mut().unwrap() {
+            let request: PromptRequest = serde_json::from_value(vector["request"].clone()).unwrap();
+            let response = serde_json::to_value(compose_system_prompt_offline(request, Some(prompts_dir.clone())).unwrap()).unwrap();
+            let composed: serde_json::Map<_, _> = ["system_prompt", "applied_modules", "recognized_tools", "complexity_assessment"]
+                .into_iter()
+                .map(|field| (field.to_string(), response[field].clone()))
+                .collect();
+            if update {
+                vector["expected"] = composed.into();
+            } else {
+                assert_eq!(vector["expected"], serde_json::Value::from(composed), "vector {}", vector["name"]);
+            }
+        }
+        if update {
+            std::fs::write(path, serde_json::to_string_pretty(&file).unwrap() + "\n").unwrap();
+        }
+    }
+
     #[test]
     fn test_list_modules() {
         // These will only work if prompts directory exists
@@ -215,11 +520,768 @@ mod tests {
     }
 }
 
-// NAPI-RS bindings for Node.js
+// NAPI-RS bindings for Node.js - must be in lib.rs for proper registration. `#[napi]`
+// registers nothing in test builds, where the bindings would otherwise be dead code.
 #[cfg(feature = "nodejs")]
+#[cfg_attr(test, allow(dead_code))]
 mod napi_bindings {
     use super::*;
+    use napi::threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+    use napi::bindgen_prelude::AsyncTask;
+    use serde::de::DeserializeOwned;
+    use crate::loader::ContentCache;
+    use napi::{Env, JsFunction, JsUnknown, Task};
     use napi_derive::napi;
+    use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+    use std::thread::{self, ThreadId};
+    use std::time::Duration;
+
+    type JsCallback = ThreadsafeFunction<serde_json::Value, ErrorStrategy::Fatal>;
+
+    /// Wraps a module callback so that a throw, or a value of the wrong type, comes back as
+    /// `{ error }`; a threadsafe function whose return value fails to convert aborts the
+    /// process
+    const GUARD_CALLBACK: &str = r#"(callback) => (context) => {
+        try {
+            const value = callback(context);
+            return ['string', 'boolean'].includes(typeof value) ? { value } : { error: `returned ${value === null ? 'null' : typeof value}` };
+        } catch (error) {
+            return { error: String(error instanceof Error ? error.message : error) };
+        }
+    }"#;
+
+    /// A prompt module implemented by JavaScript callbacks.
+    ///
+    /// The callbacks receive the request context (`tools`, `user_prompt`, `session_state`)
+    /// and run on the JavaScript thread, so the module can only be evaluated while that
+    /// thread is free, i.e. from `composeSystemPromptAsync`.
+    struct JsModule {
+        name: String,
+        js_thread: ThreadId,
+        generate_content: JsCallback,
+        applies_to: Option<JsCallback>,
+    }
+
+    impl JsModule {
+        fn call<D: DeserializeOwned>(&self, callback: &JsCallback, context: serde_json::Value) -> Result<D, PromptError> {
+            let failed = |message: String| PromptError::module_loading(format!("JavaScript module {} {}", self.name, message)).with_module(&self.name);
+            if thread::current().id() == self.js_thread {
+                return Err(failed("can only run from composeSystemPromptAsync".to_string()));
+            }
+
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let status = callback.call_with_return_value(context, ThreadsafeFunctionCallMode::Blocking, move |outcome: serde_json::Value| {
+                let _ = sender.send(outcome);
+                Ok(())
+            });
+            if status != napi::Status::Ok {
+                return Err(failed(format!("call failed: {}", status)));
+            }
+
+            // The guard answers `{ value }`, or `{ error }` when the callback threw or
+            // returned neither a string nor a boolean
+            let outcome = receiver.recv().map_err(|_| failed("call was dropped".to_string()))?;
+            if let Some(error) = outcome.get("error").and_then(serde_json::Value::as_str) {
+                return Err(failed(format!("failed: {}", error)));
+            }
+            serde_json::from_value(outcome["value"].clone())
+                .map_err(|_| failed(format!("returned an unexpected value: {}", outcome["value"])))
+        }
+
+        fn context(tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> serde_json::Value {
+            serde_json::json!({
+                "tools": tools,
+                "user_prompt": user_prompt,
+                "session_state": session_state,
+            })
+        }
+    }
+
+    impl PromptModule for JsModule {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn generate_content(&self, tools: &[Tool], session_state: &SessionState, _loader: &mut loader::PromptLoader) -> Result<String, PromptError> {
+            self.call::<String>(&self.generate_content, Self::context(tools, "", session_state))
+        }
+
+        fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+            let Some(ref applies_to) = self.applies_to else {
+                return true;
+            };
+            match self.call::<bool>(applies_to, Self::context(tools, user_prompt, session_state)) {
+                Ok(applies) => applies,
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    false
+                }
+            }
+        }
+    }
+
+    fn threadsafe_callback(env: &Env, function: JsFunction) -> napi::Result<JsCallback> {
+        let guard: JsFunction = env.run_script(GUARD_CALLBACK)?;
+        let function: JsFunction = guard.call(None, &[function])?.try_into()?;
+        let mut callback: JsCallback = function.create_threadsafe_function(
+            0,
+            |ctx: ThreadSafeCallContext<serde_json::Value>| Ok(vec![ctx.value])
+        )?;
+        // Registered modules must not keep the Node.js process alive
+        callback.unref(env)?;
+        Ok(callback)
+    }
+
+    /// Register a module implemented in JavaScript for all subsequent async compositions
+    #[napi]
+    pub fn register_module(env: Env, name: String, generate_content: JsFunction, applies_to: Option<JsFunction>) -> napi::Result<()> {
+        let applies_to = applies_to
+            .map(|function| threadsafe_callback(&env, function))
+            .transpose()?;
+        crate::register_module(Arc::new(JsModule {
+            name,
+            js_thread: thread::current().id(),
+            generate_content: threadsafe_callback(&env, generate_content)?,
+            applies_to,
+        }));
+        Ok(())
+    }
+
+    /// Remove a registered module, returning whether it was registered
+    #[napi]
+    pub fn unregister_module(name: String) -> bool {
+        crate::unregister_module(&name)
+    }
+
+    /// Composition run on the libuv thread pool, leaving the JavaScript thread free
+    pub struct ComposeTask {
+        request: String,
+        prompts_dir: Option<String>,
+    }
+
+    impl Task for ComposeTask {
+        type Output = String;
+        type JsValue = String;
+
+        fn compute(&mut self) -> napi::Result<Self::Output> {
+            let parsed_request: types::PromptRequest = serde_json::from_str(&self.request)
+                .map_err(|e| napi::Error::from_reason(format!("Invalid JSON: {}", e)))?;
+
+            let response = compose_cached(parsed_request, self.prompts_dir.take(), false)?;
+
+            serde_json::to_string(&response)
+                .map_err(|e| napi::Error::from_reason(format!("Serialization failed: {}", e)))
+        }
+
+        fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+            Ok(output)
+        }
+    }
+
+    /// A composition failure whose reason is the JSON error report, which the JavaScript
+    /// wrapper turns into an Error with `code` and `details`
+    fn composition_error(e: PromptError) -> napi::Error {
+        let report = serde_json::to_string(&e.report()).unwrap_or_else(|_| e.to_string());
+        napi::Error::from_reason(report)
+    }
+
+    /// Telemetry listeners added with `subscribe`, with their subscription ids
+    static LISTENERS: Mutex<Vec<(u32, JsCallback)>> = Mutex::new(Vec::new());
+    static NEXT_SUBSCRIPTION: AtomicU32 = AtomicU32::new(1);
+    static NEXT_COMPOSITION: AtomicU64 = AtomicU64::new(1);
+
+    fn emit(event: serde_json::Value) {
+        let listeners = crate::lock(&LISTENERS);
+        for (_, listener) in listeners.iter() {
+            listener.call(event.clone(), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    /// Telemetry of one composition: `composeStart` when it starts, then `moduleApplied`
+    /// for each applied module, `warning` for each warning and `composeEnd` when it
+    /// finishes. Events of a composition share its `composition` number. Nothing is
+    /// recorded while no listener is subscribed.
+    struct Observation {
+        started: Option<(u64, Option<String>, std::time::Instant)>,
+    }
+
+    impl Observation {
+        fn start(request: &PromptRequest) -> Self {
+            if crate::lock(&LISTENERS).is_empty() {
+                return Self { started: None };
+            }
+            let composition = NEXT_COMPOSITION.fetch_add(1, Ordering::Relaxed);
+            let session_id = request.session_state.as_ref().and_then(|state| state.session_id.clone());
+            let servers: Vec<&String> = request.mcp_config.servers_by_name().into_iter().map(|(name, _)| name).collect();
+            emit(serde_json::json!({
+                "type": "composeStart",
+                "composition": composition,
+                "session_id": session_id,
+                "servers": servers,
+            }));
+            Self { started: Some((composition, session_id, std::time::Instant::now())) }
+        }
+
+        fn finish(self, result: napi::Result<PromptResponse>) -> napi::Result<PromptResponse> {
+            let Some((composition, session_id, start_time)) = self.started else {
+                return result;
+            };
+            let duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+            match &result {
+                Ok(response) => {
+                    for module in &response.applied_modules {
+                        emit(serde_json::json!({
+                            "type": "moduleApplied",
+                            "composition": composition,
+                            "session_id": session_id,
+                            "module": module,
+                        }));
+                    }
+                    for warning in &response.warnings {
+                        emit(serde_json::json!({
+                            "type": "warning",
+                            "composition": composition,
+                            "session_id": session_id,
+                            "severity": warning.severity,
+                            "source": warning.source,
+                            "message": warning.message,
+                        }));
+                    }
+                    emit(serde_json::json!({
+                        "type": "composeEnd",
+                        "composition": composition,
+                        "session_id": session_id,
+                        "duration_ms": duration_ms,
+                        "complexity": response.complexity_assessment,
+                        "applied_modules": response.applied_modules.len(),
+                        "warnings": response.warnings.len(),
+                    }));
+                }
+                Err(e) => {
+                    // Composition errors carry the JSON error report as their reason
+                    let error = serde_json::from_str(&e.reason).unwrap_or_else(|_| serde_json::Value::String(e.reason.clone()));
+                    emit(serde_json::json!({
+                        "type": "composeEnd",
+                        "composition": composition,
+                        "session_id": session_id,
+                        "duration_ms": duration_ms,
+                        "error": error,
+                    }));
+                }
+            }
+            result
+        }
+    }
+
+    /// Subscribe a listener to the telemetry events of every composition, returning the
+    /// subscription id. Events are delivered asynchronously on the JavaScript thread.
+    #[napi(ts_args_type = "listener: (event: CompositionEvent) => void")]
+    pub fn subscribe(env: Env, listener: JsFunction) -> napi::Result<u32> {
+        let listener = threadsafe_callback(&env, listener)?;
+        let id = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::Relaxed);
+        crate::lock(&LISTENERS).push((id, listener));
+        Ok(id)
+    }
+
+    /// Remove a telemetry listener, returning whether it was subscribed
+    #[napi]
+    pub fn unsubscribe(id: u32) -> bool {
+        let mut listeners = crate::lock(&LISTENERS);
+        let before = listeners.len();
+        listeners.retain(|(subscription, _)| *subscription != id);
+        listeners.len() != before
+    }
+
+    /// Compose with cached or inferred tools, or offline, emitting telemetry events
+    fn compose_cached(request: PromptRequest, prompts_dir: Option<String>, offline: bool) -> napi::Result<PromptResponse> {
+        let observation = Observation::start(&request);
+        observation.finish(crate::ENGINE.compose_cached_in(request, prompts_dir, offline, None).map_err(composition_error))
+    }
+
+    /// Compose a system prompt off the JavaScript thread; required when JavaScript modules are registered
+    #[napi]
+    pub fn compose_system_prompt_async(request: String, prompts_dir: Option<String>) -> AsyncTask<ComposeTask> {
+        AsyncTask::new(ComposeTask { request, prompts_dir })
+    }
+
+    /// Typed composition run on the libuv thread pool
+    pub struct ComposeObjectTask {
+        request: Option<PromptRequest>,
+        prompts_dir: Option<String>,
+        offline: bool,
+    }
+
+    impl Task for ComposeObjectTask {
+        type Output = PromptResponse;
+        type JsValue = JsUnknown;
+
+        fn compute(&mut self) -> napi::Result<Self::Output> {
+            let request = self.request.take()
+                .ok_or_else(|| napi::Error::from_reason("Composition task ran twice"))?;
+            compose_cached(request, self.prompts_dir.take(), self.offline)
+        }
+
+        fn resolve(&mut self, env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+            env.to_js_value(&output)
+        }
+    }
+
+    /// Read a request object, failing as an invalid request when it does not match the type
+    fn request_from_js(env: &Env, request: JsUnknown) -> napi::Result<PromptRequest> {
+        env.from_js_value(request)
+            .map_err(|e| composition_error(PromptError::invalid_request(e.reason)))
+    }
+
+    /// Compose a system prompt from a request object, returning the response object.
+    /// Offline, tools are only inferred, external modules are left out and the embedded
+    /// prompts are read when no prompts directory is given.
+    #[napi(ts_args_type = "request: PromptRequest, promptsDir?: string, offline?: boolean", ts_return_type = "PromptResponse")]
+    pub fn compose(env: Env, request: JsUnknown, prompts_dir: Option<String>, offline: Option<bool>) -> napi::Result<JsUnknown> {
+        let request = request_from_js(&env, request)?;
+        let response = compose_cached(request, prompts_dir, offline.unwrap_or(false))?;
+        env.to_js_value(&response)
+    }
+
+    /// Compose a system prompt from a request object off the JavaScript thread
+    #[napi(ts_args_type = "request: PromptRequest, promptsDir?: string, offline?: boolean", ts_return_type = "Promise<PromptResponse>")]
+    pub fn compose_async(env: Env, request: JsUnknown, prompts_dir: Option<String>, offline: Option<bool>) -> napi::Result<AsyncTask<ComposeObjectTask>> {
+        let request = request_from_js(&env, request)?;
+        Ok(AsyncTask::new(ComposeObjectTask { request: Some(request), prompts_dir, offline: offline.unwrap_or(false) }))
+    }
+
+    /// Run async work to completion on the libuv thread a task computes on
+    fn block_on<F: std::future::Future>(future: F) -> napi::Result<F::Output> {
+        let runtime = crate::runtime()
+            .map_err(|e| napi::Error::from_reason(format!("Failed to create async runtime: {}", e)))?;
+        Ok(runtime.block_on(future))
+    }
+
+    /// Composition after discovering the request's tools into the shared cache, run on
+    /// the libuv thread pool
+    pub struct ComposeDiscoveringTask {
+        request: Option<PromptRequest>,
+        prompts_dir: Option<String>,
+    }
+
+    impl Task for ComposeDiscoveringTask {
+        type Output = PromptResponse;
+        type JsValue = JsUnknown;
+
+        fn compute(&mut self) -> napi::Result<Self::Output> {
+            let request = self.request.take()
+                .ok_or_else(|| napi::Error::from_reason("Composition task ran twice"))?;
+            let observation = Observation::start(&request);
+            let result = block_on(crate::compose_system_prompt_with_prompts_dir(request, self.prompts_dir.take()))
+                .and_then(|result| result.map_err(composition_error));
+            observation.finish(result)
+        }
+
+        fn resolve(&mut self, env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+            env.to_js_value(&output)
+        }
+    }
+
+    /// Compose a system prompt after discovering the tools of the request's MCP servers,
+    /// off the JavaScript thread. The shared discovery infers them from the server
+    /// patterns without starting the servers; they are cached for later compositions.
+    #[napi(ts_args_type = "request: PromptRequest, promptsDir?: string", ts_return_type = "Promise<PromptResponse>")]
+    pub fn compose_full(env: Env, request: JsUnknown, prompts_dir: Option<String>) -> napi::Result<AsyncTask<ComposeDiscoveringTask>> {
+        let request = request_from_js(&env, request)?;
+        Ok(AsyncTask::new(ComposeDiscoveringTask { request: Some(request), prompts_dir }))
+    }
+
+    /// Tool inference for one server again, run on the libuv thread pool
+    pub struct RefreshServerTask {
+        server_name: String,
+        mcp_config: McpConfig,
+    }
+
+    impl Task for RefreshServerTask {
+        type Output = Vec<Tool>;
+        type JsValue = JsUnknown;
+
+        fn compute(&mut self) -> napi::Result<Self::Output> {
+            block_on(crate::refresh_server_tools(&self.server_name, &self.mcp_config))?
+                .map_err(composition_error)
+        }
+
+        fn resolve(&mut self, env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+            env.to_js_value(&output)
+        }
+    }
+
+    /// Infer one server's tools again from the server patterns, bypassing and then
+    /// updating the shared tool cache
+    #[napi(ts_args_type = "serverName: string, mcpConfig: McpConfig", ts_return_type = "Promise<Tool[]>")]
+    pub fn refresh_server_tools(env: Env, server_name: String, mcp_config: JsUnknown) -> napi::Result<AsyncTask<RefreshServerTask>> {
+        let mcp_config: McpConfig = env.from_js_value(mcp_config)
+            .map_err(|e| composition_error(PromptError::config(e.reason)))?;
+        Ok(AsyncTask::new(RefreshServerTask { server_name, mcp_config }))
+    }
+
+    /// Entries, approximate bytes and hit rates of the caches the composition functions share
+    #[napi(ts_return_type = "CacheStats")]
+    pub fn cache_stats(env: Env) -> napi::Result<JsUnknown> {
+        env.to_js_value(&crate::cache_stats())
+    }
+
+    /// Forget the discovered tools and prompt files the composition functions share
+    #[napi]
+    pub fn clear_cache() {
+        crate::clear_cache();
+    }
+
+    /// The composer, prompt file cache and tool discovery cache of one `PromptComposer`
+    /// instance, shared with the tasks it starts
+    #[derive(Clone)]
+    struct InstanceState {
+        composer: Arc<Mutex<PromptComposer>>,
+        content: ContentCache,
+        discovery: Arc<ToolDiscovery>,
+    }
+
+    impl InstanceState {
+        fn composer(&self) -> MutexGuard<'_, PromptComposer> {
+            crate::lock(&self.composer)
+        }
+
+        fn discovery(&self) -> &ToolDiscovery {
+            &self.discovery
+        }
+
+        /// Compose after discovering the request's tools into the instance's cache, or with
+        /// the cached or inferred tools
+        fn compose(&self, request: &PromptRequest, live: bool) -> napi::Result<PromptResponse> {
+            let observation = Observation::start(request);
+            observation.finish(self.compose_unobserved(request, live))
+        }
+
+        fn compose_unobserved(&self, request: &PromptRequest, live: bool) -> napi::Result<PromptResponse> {
+            let start_time = Instant::now();
+            let tools = crate::composition::request_span(request).in_scope(|| if request.force_refresh.unwrap_or(false) {
+                self.discovery().rediscover_tools(&request.mcp_config, &crate::cancel::CancellationToken::new())
+            } else if live {
+                self.discovery().discover_tools_immediate(&request.mcp_config)
+            } else {
+                Ok(self.discovery().cached_or_inferred_tools(&request.mcp_config))
+            }).map_err(composition_error)?;
+            // Compose a fork rather than under the lock, since JavaScript modules wait on
+            // the JavaScript thread, which may itself be waiting on the lock
+            let mut composer = self.composer().fork();
+            let mut response = composer.compose(request, &tools).map_err(composition_error)?;
+            response.metadata.duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+            response.metadata.discovery = match (live, composer.is_offline()) {
+                (true, _) => DiscoveryMode::Live,
+                (false, true) => DiscoveryMode::Offline,
+                (false, false) => DiscoveryMode::Cached,
+            };
+            Ok(response)
+        }
+    }
+
+    /// Options of a `PromptComposer` instance
+    #[napi(object)]
+    pub struct ComposerOptions {
+        /// Directory the prompt files are read from; the embedded prompts when unset offline
+        pub prompts_dir: Option<String>,
+        /// Infer tools only and leave out external modules, so no process is started
+        pub offline: Option<bool>,
+        /// Snapshot from `exportConfig` to recreate; other options are ignored
+        pub config: Option<String>,
+        /// Start the request's MCP servers and list their tools when discovering, waiting
+        /// up to this many milliseconds for each; tools are inferred from the server
+        /// patterns when unset, offline or when a server cannot be listed
+        pub list_servers_timeout_ms: Option<u32>,
+    }
+
+    /// Composition by a `PromptComposer` instance, run on the libuv thread pool
+    pub struct InstanceComposeTask {
+        state: InstanceState,
+        request: PromptRequest,
+        live: bool,
+    }
+
+    impl Task for InstanceComposeTask {
+        type Output = PromptResponse;
+        type JsValue = JsUnknown;
+
+        fn compute(&mut self) -> napi::Result<Self::Output> {
+            self.state.compose(&self.request, self.live)
+        }
+
+        fn resolve(&mut self, env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+            env.to_js_value(&output)
+        }
+    }
+
+    /// Tool rediscovery by a `PromptComposer` instance, run on the libuv thread pool
+    pub struct InstanceRefreshTask {
+        state: InstanceState,
+        server_name: String,
+        mcp_config: McpConfig,
+    }
+
+    impl Task for InstanceRefreshTask {
+        type Output = Vec<Tool>;
+        type JsValue = JsUnknown;
+
+        fn compute(&mut self) -> napi::Result<Self::Output> {
+            self.state.discovery().refresh_server_immediate(&self.server_name, &self.mcp_config)
+                .map_err(composition_error)
+        }
+
+        fn resolve(&mut self, env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+            env.to_js_value(&output)
+        }
+    }
+
+    /// A composer with its own prompts directory, prompt file cache, tool discovery cache,
+    /// session state, modules and hooks, for hosts that keep workspaces apart. Modules
+    /// registered with `registerModule` before it was created take part in its
+    /// compositions; those registered on the instance apply to it alone.
+    #[napi(js_name = "PromptComposer")]
+    pub struct NodePromptComposer {
+        state: InstanceState,
+    }
+
+    #[napi]
+    impl NodePromptComposer {
+        #[napi(constructor)]
+        pub fn new(options: Option<ComposerOptions>) -> napi::Result<Self> {
+            let ComposerOptions { prompts_dir, offline, config, list_servers_timeout_ms } = options
+                .unwrap_or(ComposerOptions { prompts_dir: None, offline: None, config: None, list_servers_timeout_ms: None });
+            let offline = offline.unwrap_or(false);
+            let (mut composer, discovery) = match (config, prompts_dir) {
+                (Some(config), _) => {
+                    let mut composer = PromptComposer::import_config(&config).map_err(composition_error)?;
+                    crate::adopt_registered(&mut composer);
+                    let discovery = match composer.prompts_dir() {
+                        embedded::PROMPTS_DIR | loader::MEMORY_PROMPTS_DIR => ToolDiscovery::embedded(),
+                        dir => ToolDiscovery::with_prompts_dir(dir.to_string()),
+                    };
+                    (composer, discovery)
+                }
+                (None, prompts_dir) => {
+                    let (mut composer, discovery) = match prompts_dir {
+                        Some(dir) => (PromptComposer::with_prompts_dir(dir.clone()), ToolDiscovery::with_prompts_dir(dir)),
+                        None if offline => (PromptComposer::embedded(), ToolDiscovery::embedded()),
+                        None => (PromptComposer::new(), ToolDiscovery::new()),
+                    };
+                    let discovery = match list_servers_timeout_ms {
+                        Some(timeout) if !offline => discovery.with_server_listing(Duration::from_millis(timeout.into())),
+                        _ => discovery,
+                    };
+                    composer.set_offline(offline);
+                    composer.registry_mut().merge(&crate::registered_modules());
+                    composer.hooks_mut().merge(&crate::registered_hooks());
+                    (composer, discovery)
+                }
+            };
+            let content = ContentCache::new();
+            composer.set_content_cache(content.clone());
+            Ok(Self {
+                state: InstanceState {
+                    composer: Arc::new(Mutex::new(composer)),
+                    content,
+                    discovery: Arc::new(discovery),
+                },
+            })
+        }
+
+        /// Whether this instance only infers tools and leaves out external modules
+        #[napi(getter)]
+        pub fn offline(&self) -> bool {
+            self.state.composer().is_offline()
+        }
+
+        /// Snapshot of this instance's prompts location, selection rules and module states
+        /// as JSON, for `PromptComposer.importConfig` in this or another process
+        #[napi]
+        pub fn export_config(&self) -> napi::Result<String> {
+            self.state.composer().export_config().map_err(composition_error)
+        }
+
+        /// Directory the prompt files are read from
+        #[napi(getter)]
+        pub fn prompts_dir(&self) -> String {
+            self.state.composer().prompts_dir().to_string()
+        }
+
+        /// Compose with this instance's cached tools, or tools inferred from the config
+        #[napi(ts_args_type = "request: PromptRequest", ts_return_type = "PromptResponse")]
+        pub fn compose(&self, env: Env, request: JsUnknown) -> napi::Result<JsUnknown> {
+            let request = request_from_js(&env, request)?;
+            let response = self.state.compose(&request, false)?;
+            env.to_js_value(&response)
+        }
+
+        /// Compose off the JavaScript thread; required when JavaScript modules are registered
+        #[napi(ts_args_type = "request: PromptRequest", ts_return_type = "Promise<PromptResponse>")]
+        pub fn compose_async(&self, env: Env, request: JsUnknown) -> napi::Result<AsyncTask<InstanceComposeTask>> {
+            let request = request_from_js(&env, request)?;
+            Ok(AsyncTask::new(InstanceComposeTask { state: self.state.clone(), request, live: false }))
+        }
+
+        /// Compose after discovering the tools of the request's MCP servers into this
+        /// instance's cache, off the JavaScript thread: listed by the servers when the
+        /// instance was created with `listServersTimeoutMs`, inferred otherwise
+        #[napi(ts_args_type = "request: PromptRequest", ts_return_type = "Promise<PromptResponse>")]
+        pub fn compose_full(&self, env: Env, request: JsUnknown) -> napi::Result<AsyncTask<InstanceComposeTask>> {
+            let request = request_from_js(&env, request)?;
+            Ok(AsyncTask::new(InstanceComposeTask { state: self.state.clone(), request, live: true }))
+        }
+
+        /// Discover one server's tools again, listed or inferred as `composeFull` does,
+        /// replacing them in this instance's cache
+        #[napi(ts_args_type = "serverName: string, mcpConfig: McpConfig", ts_return_type = "Promise<Tool[]>")]
+        pub fn refresh_server(&self, env: Env, server_name: String, mcp_config: JsUnknown) -> napi::Result<AsyncTask<InstanceRefreshTask>> {
+            let mcp_config: McpConfig = env.from_js_value(mcp_config)
+                .map_err(|e| composition_error(PromptError::config(e.reason)))?;
+            Ok(AsyncTask::new(InstanceRefreshTask { state: self.state.clone(), server_name, mcp_config }))
+        }
+
+        /// Decide which modules a request would get without composing it or touching
+        /// session state
+        #[napi(ts_args_type = "request: PromptRequest", ts_return_type = "ModuleDecision[]")]
+        pub fn explain(&self, env: Env, request: JsUnknown) -> napi::Result<JsUnknown> {
+            let request = request_from_js(&env, request)?;
+            let tools = self.state.discovery().cached_or_inferred_tools(&request.mcp_config);
+            let decisions = self.state.composer().explain(&request, &tools).map_err(composition_error)?;
+            env.to_js_value(&decisions)
+        }
+
+        /// Name, kind, source and enabled state of every module of this instance
+        #[napi(ts_return_type = "ModuleInfo[]")]
+        pub fn list_modules(&self, env: Env) -> napi::Result<JsUnknown> {
+            env.to_js_value(&self.state.composer().list_modules())
+        }
+
+        /// Enable or disable a module for this instance, returning whether it exists
+        #[napi]
+        pub fn set_module_enabled(&self, name: String, enabled: bool) -> bool {
+            self.state.composer().set_module_enabled(&name, enabled)
+        }
+
+        #[napi]
+        pub fn list_domains(&self) -> napi::Result<Vec<String>> {
+            self.state.composer().list_domains().map_err(composition_error)
+        }
+
+        #[napi]
+        pub fn list_behaviors(&self) -> napi::Result<Vec<String>> {
+            self.state.composer().list_behaviors().map_err(composition_error)
+        }
+
+        /// MCP servers with tool instructions in the prompts directory
+        #[napi]
+        pub fn list_tools(&self) -> napi::Result<Vec<String>> {
+            self.state.composer().list_tools().map_err(composition_error)
+        }
+
+        /// Forget cached prompt files and discovered tools, so changes on disk take effect
+        #[napi]
+        pub fn clear_cache(&self) {
+            self.state.composer().clear_cache();
+            self.state.discovery().clear_cache();
+        }
+
+        /// Entries, approximate bytes and hit rates of this instance's prompt file and tool caches
+        #[napi(ts_return_type = "CacheStats")]
+        pub fn cache_stats(&self, env: Env) -> napi::Result<JsUnknown> {
+            env.to_js_value(&CacheStats {
+                prompts: self.state.content.usage(),
+                tools: self.state.discovery().cache_usage(),
+            })
+        }
+
+        /// End a session, calling `on_session_end` on its modules; returns whether it existed
+        #[napi]
+        pub fn end_session(&self, session_id: String) -> bool {
+            self.state.composer().end_session(&session_id)
+        }
+
+        /// Register a module implemented in JavaScript for this instance's async compositions
+        #[napi]
+        pub fn register_module(&self, env: Env, name: String, generate_content: JsFunction, applies_to: Option<JsFunction>) -> napi::Result<()> {
+            let applies_to = applies_to
+                .map(|function| threadsafe_callback(&env, function))
+                .transpose()?;
+            self.state.composer().register_module(Arc::new(JsModule {
+                name,
+                js_thread: thread::current().id(),
+                generate_content: threadsafe_callback(&env, generate_content)?,
+                applies_to,
+            }));
+            Ok(())
+        }
+
+        #[napi]
+        pub fn unregister_module(&self, name: String) -> bool {
+            self.state.composer().unregister_module(&name)
+        }
+    }
+
+    /// Decide which modules a request would get without composing it, as the decisions a
+    /// composition reports in `selection_trace`
+    #[napi(ts_args_type = "request: PromptRequest, promptsDir?: string, offline?: boolean", ts_return_type = "ModuleDecision[]")]
+    pub fn explain(env: Env, request: JsUnknown, prompts_dir: Option<String>, offline: Option<bool>) -> napi::Result<JsUnknown> {
+        let request = request_from_js(&env, request)?;
+        let decisions = crate::ENGINE.explain_in(&request, prompts_dir, offline.unwrap_or(false)).map_err(composition_error)?;
+        env.to_js_value(&decisions)
+    }
+
+    /// Name, kind, source and enabled state of every built-in and registered module
+    #[napi(ts_return_type = "ModuleInfo[]")]
+    pub fn list_modules(env: Env) -> napi::Result<JsUnknown> {
+        env.to_js_value(&crate::list_modules())
+    }
+
+    /// Enable or disable a module for every subsequent composition, returning whether the
+    /// module exists
+    #[napi]
+    pub fn set_module_enabled(name: String, enabled: bool) -> bool {
+        crate::set_module_enabled(&name, enabled)
+    }
+
+    /// Compose a system prompt on a separate thread, calling `listener` on the JavaScript
+    /// thread with `{ type: 'section', section }` for each section as it is generated, then
+    /// `{ type: 'end', response }` or `{ type: 'error', error }`. Events arrive in order
+    /// and keep the process alive until the last one is delivered.
+    #[napi(ts_args_type = "request: PromptRequest, promptsDir: string | undefined | null, listener: (event: { type: 'section', section: PromptSection } | { type: 'end', response: PromptResponse } | { type: 'error', error: ErrorReport }) => void, offline?: boolean")]
+    pub fn compose_stream(env: Env, request: JsUnknown, prompts_dir: Option<String>, listener: JsFunction, offline: Option<bool>) -> napi::Result<()> {
+        let request = request_from_js(&env, request)?;
+        let listener: JsCallback = listener.create_threadsafe_function(
+            0,
+            |ctx: ThreadSafeCallContext<serde_json::Value>| Ok(vec![ctx.value])
+        )?;
+        thread::spawn(move || {
+            let observation = Observation::start(&request);
+            let mut on_section = |section: PromptSection| {
+                listener.call(serde_json::json!({ "type": "section", "section": section }), ThreadsafeFunctionCallMode::Blocking);
+            };
+            let result = crate::ENGINE.compose_cached_in(request, prompts_dir, offline.unwrap_or(false), Some(&mut on_section))
+                .map_err(composition_error);
+            let event = match observation.finish(result) {
+                Ok(response) => serde_json::json!({ "type": "end", "response": response }),
+                // Composition errors carry the JSON error report as their reason
+                Err(e) => serde_json::json!({
+                    "type": "error",
+                    "error": serde_json::from_str::<serde_json::Value>(&e.reason).unwrap_or(serde_json::Value::String(e.reason)),
+                }),
+            };
+            listener.call(event, ThreadsafeFunctionCallMode::Blocking);
+        });
+        Ok(())
+    }
+
+    /// Read an MCP client's configuration: "claude", "cursor" or "vscode" for the client's
+    /// own file, or the path of a file in the `{"mcpServers": {...}}` or
+    /// `{"servers": {...}}` format
+    #[napi(ts_return_type = "McpConfig")]
+    pub fn load_mcp_config(env: Env, source: String) -> napi::Result<JsUnknown> {
+        let config = McpConfig::load(&source).map_err(composition_error)?;
+        env.to_js_value(&config)
+    }
 
     /// Compose a system prompt using the cached version for better performance
     #[napi]
@@ -231,8 +1293,7 @@ mod napi_bindings {
             .map_err(|e| napi::Error::from_reason(format!("Invalid JSON: {}", e)))?;
         
         // Call the cached version for better performance
-        let response = crate::compose_system_prompt_cached(parsed_request)
-            .map_err(|e| napi::Error::from_reason(format!("Composition failed: {}", e)))?;
+        let response = compose_cached(parsed_request, None, false)?;
         
         // Return as JSON string
         serde_json::to_string(&response)
@@ -249,8 +1310,7 @@ mod napi_bindings {
             .map_err(|e| napi::Error::from_reason(format!("Invalid JSON: {}", e)))?;
         
         // Call the version with custom prompts directory
-        let response = crate::compose_system_prompt_cached_with_prompts_dir(parsed_request, Some(prompts_dir))
-            .map_err(|e| napi::Error::from_reason(format!("Composition failed: {}", e)))?;
+        let response = compose_cached(parsed_request, Some(prompts_dir), false)?;
         
         // Return as JSON string
         serde_json::to_string(&response)
@@ -263,6 +1323,18 @@ mod napi_bindings {
         true
     }
 
+    /// JSON schema of composition requests, as a JSON string
+    #[napi]
+    pub fn get_request_schema() -> String {
+        PromptRequest::json_schema().to_string()
+    }
+
+    /// JSON schema of composition responses, as a JSON string
+    #[napi]
+    pub fn get_response_schema() -> String {
+        PromptResponse::json_schema().to_string()
+    }
+
     /// Get status information as JSON string
     #[napi]
     pub fn get_status() -> napi::Result<String> {
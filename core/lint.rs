@@ -0,0 +1,249 @@
+//! Lint of prompt files, for content that composes without error but not as its author
+//! meant: files with no guidance left after extraction, `#` headings after the title,
+//! which extraction drops, "always X" and "never X" directives contradicting each other,
+//! sections long enough to crowd out the rest of a prompt, and control characters.
+
+use crate::composition::estimated_tokens;
+use crate::loader::{extract_guidance, markdown_sections, split_frontmatter};
+use crate::types::PromptError;
+use crate::validate::IssueSeverity;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Estimated tokens a `##` section may have before it is reported as too long
+pub const MAX_SECTION_TOKENS: usize = 500;
+
+/// The check that found a lint issue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LintRule {
+    /// No guidance is left after `extract_guidance`
+    EmptyGuidance,
+    /// A `#` heading after the title, which extraction leaves out of the prompt
+    DroppedHeading,
+    /// "always X" in one place and "never X" in another
+    ConflictingDirectives,
+    /// A section over [`MAX_SECTION_TOKENS`]
+    LongSection,
+    /// A control or bidirectional formatting character
+    ControlCharacter,
+}
+
+impl LintRule {
+    /// Name of the rule in lint output
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LintRule::EmptyGuidance => "empty-guidance",
+            LintRule::DroppedHeading => "dropped-heading",
+            LintRule::ConflictingDirectives => "conflicting-directives",
+            LintRule::LongSection => "long-section",
+            LintRule::ControlCharacter => "control-character",
+        }
+    }
+
+    fn severity(self) -> IssueSeverity {
+        match self {
+            LintRule::EmptyGuidance | LintRule::ControlCharacter => IssueSeverity::Error,
+            _ => IssueSeverity::Warning,
+        }
+    }
+}
+
+/// A problem found in a prompt file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LintIssue {
+    pub rule: LintRule,
+    pub severity: IssueSeverity,
+    pub path: String,
+    /// Line of the file the problem is on, counting from 1, when it is on one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Outcome of linting prompt files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LintReport {
+    /// Prompt files linted
+    pub files_checked: usize,
+    pub issues: Vec<LintIssue>,
+}
+
+impl LintReport {
+    /// Whether no issue is an error
+    pub fn is_valid(&self) -> bool {
+        self.issues.iter().all(|issue| issue.severity < IssueSeverity::Error)
+    }
+
+    fn push(&mut self, rule: LintRule, path: &str, line: Option<usize>, message: String) {
+        self.issues.push(LintIssue { rule, severity: rule.severity(), path: path.to_string(), line, message });
+    }
+}
+
+/// Lint the markdown files of a prompts directory's `domains`, `behaviors` and `tools`
+pub fn lint_prompts_dir(prompts_dir: &str) -> Result<LintReport, PromptError> {
+    let root = Path::new(prompts_dir);
+    if !root.is_dir() {
+        return Err(PromptError::config("Prompts directory does not exist").with_path(root));
+    }
+    let mut files = Vec::new();
+    for category in ["domains", "behaviors", "tools"] {
+        let mut paths: Vec<_> = fs::read_dir(root.join(category)).into_iter().flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("md"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| PromptError::config(format!("Failed to read prompt file: {}", e)).with_path(&path).with_source(e))?;
+            files.push((path.display().to_string(), content));
+        }
+    }
+    Ok(lint_prompts(&files))
+}
+
+/// Lint prompt files given as (path, content), checking directives against each other
+/// across all of them
+pub fn lint_prompts(files: &[(String, String)]) -> LintReport {
+    let mut report = LintReport { files_checked: files.len(), ..Default::default() };
+    // Phrase -> where it is required and where it is forbidden
+    let mut directives: BTreeMap<String, [Vec<(&str, usize)>; 2]> = BTreeMap::new();
+    for (path, content) in files {
+        lint_file(path, content, &mut report);
+        let (body, _) = split_frontmatter(content);
+        let offset = content[..content.len() - body.len()].matches('\n').count();
+        for (line, always, phrase) in directives_of(body) {
+            directives.entry(phrase).or_default()[usize::from(!always)].push((path, offset + line));
+        }
+    }
+    for (phrase, [required, forbidden]) in &directives {
+        let Some((always_path, always_line)) = required.first() else {
+            continue;
+        };
+        for (path, line) in forbidden {
+            report.push(
+                LintRule::ConflictingDirectives,
+                path,
+                Some(*line),
+                format!("\"never {}\" contradicts \"always {}\" at {}:{}", phrase, phrase, always_path, always_line),
+            );
+        }
+    }
+    report
+}
+
+fn lint_file(path: &str, content: &str, report: &mut LintReport) {
+    for (index, line) in content.lines().enumerate() {
+        let found = line.char_indices().find(|(_, c)| {
+            (c.is_control() && !matches!(c, '\t' | '\r')) || matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+        });
+        if let Some((at, c)) = found {
+            let column = line[..at].chars().count() + 1;
+            report.push(LintRule::ControlCharacter, path, Some(index + 1), format!("control character U+{:04X} at column {}", c as u32, column));
+        }
+    }
+
+    let (body, _) = split_frontmatter(content);
+    if extract_guidance(body).is_empty() {
+        report.push(
+            LintRule::EmptyGuidance,
+            path,
+            None,
+            "no guidance is left after extraction; the file has no content but # titles".to_string(),
+        );
+    }
+
+    // `extract_guidance` leaves `#` headings out as titles, so only the first should be
+    // one
+    let offset = content[..content.len() - body.len()].matches('\n').count();
+    let line_of = |at: usize| offset + body[..at].matches('\n').count() + 1;
+    let mut titled = false;
+    for (event, range) in Parser::new(body).into_offset_iter() {
+        if let Event::Start(Tag::Heading { level: HeadingLevel::H1, .. }) = event {
+            if titled {
+                let heading = body[range.clone()].lines().next().unwrap_or_default().trim();
+                report.push(LintRule::DroppedHeading, path, Some(line_of(range.start)), format!("\"{}\" is dropped; only the title may be a # heading", heading));
+            }
+            titled = true;
+        }
+    }
+    for (heading, range) in markdown_sections(body).into_iter().filter(|(heading, _)| !heading.is_empty()) {
+        let tokens = estimated_tokens(&body[range.clone()]);
+        if tokens > MAX_SECTION_TOKENS {
+            report.push(
+                LintRule::LongSection,
+                path,
+                Some(line_of(range.start)),
+                format!("section \"{}\" is about {} tokens, over the {} a section should have", heading, tokens, MAX_SECTION_TOKENS),
+            );
+        }
+    }
+}
+
+/// "always" and "never" directives outside code blocks, as (line, whether it is
+/// "always", the lowercase words up to the end of the clause)
+fn directives_of(body: &str) -> Vec<(usize, bool, String)> {
+    let mut directives = Vec::new();
+    let mut in_code = false;
+    for (index, line) in body.lines().enumerate() {
+        if line.starts_with("```") {
+            in_code = !in_code;
+        }
+        if in_code {
+            continue;
+        }
+        let line = line.to_lowercase().replace(['*', '_', '`'], "");
+        for clause in line.split(['.', ',', ';', ':', '!', '?', '(', ')']) {
+            let mut words = clause.split_whitespace();
+            while let Some(word) = words.next() {
+                let always = match word {
+                    "always" => true,
+                    "never" => false,
+                    _ => continue,
+                };
+                let phrase = words.clone().collect::<Vec<_>>().join(" ");
+                if !phrase.is_empty() {
+                    directives.push((index + 1, always, phrase));
+                }
+                break;
+            }
+        }
+    }
+    directives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_prompts_lint_clean() {
+        let report = lint_prompts_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")).unwrap();
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert!(report.files_checked > 20);
+    }
+
+    #[test]
+    fn test_lint_finds_each_problem() {
+        let long = format!("## Long\n{}\n", "- Keep going\n".repeat(200));
+        let files = [
+            ("behaviors/intro.md".to_string(), "# Intro\n\n".to_string()),
+            ("behaviors/a.md".to_string(), "---\nversion: v1\n---\n# A\n#### Aside\n## Rules\n- **Always** run the tests.\n# Extra\n```bash\n# build\n```\n".to_string()),
+            ("behaviors/b.md".to_string(), format!("# B\n## Rules\n- Never run the tests, ask first\n- Beep\u{7}\n{}", long)),
+        ];
+        let report = lint_prompts(&files);
+        assert_eq!(report.files_checked, 3);
+        assert!(!report.is_valid());
+        let found = |rule: LintRule| -> Vec<(&str, Option<usize>)> {
+            report.issues.iter().filter(|issue| issue.rule == rule).map(|issue| (issue.path.as_str(), issue.line)).collect()
+        };
+        assert_eq!(found(LintRule::EmptyGuidance), [("behaviors/intro.md", None)]);
+        assert_eq!(found(LintRule::DroppedHeading), [("behaviors/a.md", Some(8))]);
+        assert_eq!(found(LintRule::ConflictingDirectives), [("behaviors/b.md", Some(3))]);
+        assert!(report.issues.iter().any(|issue| issue.message.contains("\"always run the tests\" at behaviors/a.md:7")));
+        assert_eq!(found(LintRule::ControlCharacter), [("behaviors/b.md", Some(4))]);
+        assert_eq!(found(LintRule::LongSection), [("behaviors/b.md", Some(5))]);
+    }
+}
@@ -1,12 +1,142 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::SystemTime;
+use crate::cache::{CachePolicy, Entries};
+use crate::embedded::EmbeddedPrompts;
+use crate::provider::PromptProvider;
 use crate::types::*;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
 
-/// Loads prompt content from markdown files
+pub use crate::provider::MEMORY_PROMPTS_DIR;
+
+/// A prompt file's content after its frontmatter, and the version the frontmatter declares
+#[derive(Clone)]
+struct CachedPrompt {
+    body: Arc<str>,
+    version: Option<String>,
+}
+
+impl CachedPrompt {
+    fn approximate_size(&self) -> usize {
+        self.body.len() + self.version.as_ref().map_or(0, String::len)
+    }
+}
+
+/// Prompt files read from disk by the loaders sharing the cache, by path, with the
+/// modification time they were read at. A loader given the cache consults it before
+/// reading a file and skips the read while the file is unchanged, so composers created
+/// per call still read each file once.
+#[derive(Clone)]
+pub struct ContentCache {
+    files: Arc<RwLock<Entries<PathBuf, (SystemTime, CachedPrompt)>>>,
+    /// Lookups answered from the cache and lookups of files to read
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl Default for ContentCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentCache {
+    /// A cache holding every file until cleared
+    pub fn new() -> Self {
+        Self::with_policy(CachePolicy::UNBOUNDED)
+    }
+
+    /// A cache holding files for the policy's time to live, up to its limit
+    pub fn with_policy(policy: CachePolicy) -> Self {
+        Self {
+            files: Arc::new(RwLock::new(Entries::new(policy))),
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of prompt files held
+    pub fn len(&self) -> usize {
+        self.files.read().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Files held and their approximate bytes, with the lookups of every loader sharing
+    /// the cache
+    pub fn usage(&self) -> CacheUsage {
+        let counters = CacheCounters {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        };
+        let files = self.files.read().unwrap_or_else(PoisonError::into_inner);
+        files.usage(counters, |path, (_, cached)| path.as_os_str().len() + cached.approximate_size())
+    }
+
+    /// Forget every file, for every loader sharing the cache
+    pub fn clear(&self) {
+        self.files.write().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// The file's content if it was read at the given modification time
+    fn get(&self, path: &Path, modified: SystemTime) -> Option<CachedPrompt> {
+        let files = self.files.read().unwrap_or_else(PoisonError::into_inner);
+        let cached = files.get(path).filter(|(read_at, _)| *read_at == modified).map(|(_, cached)| cached.clone());
+        let counter = if cached.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    fn insert(&self, path: PathBuf, modified: SystemTime, cached: CachedPrompt) {
+        self.files.write().unwrap_or_else(PoisonError::into_inner).insert(path, (modified, cached));
+    }
+}
+
+/// Loads prompt content from markdown files.
+///
+/// A prompt file may exist in several versions: `programming.md` is the current version
+/// (optionally declaring `version: ...` in a frontmatter block) and `programming@v2.md`
+/// holds version `v2`. Pinned versions are loaded instead of the current file.
+///
+/// A prompt file may also have A/B variants (`planning.control.md`,
+/// `planning.experiment.md`); while a variant is active it is loaded in place of the
+/// base file when it exists. Translations (`planning.de.md`) are loaded in place of the
+/// base file while their locale is set.
+///
+/// A loader created with `PromptLoader::with_provider` reads its files from a
+/// [`PromptProvider`] instead of a directory: `PromptLoader::embedded` reads the prompts
+/// compiled into the library and `PromptLoader::from_files` files the host supplies.
 pub struct PromptLoader {
     prompts_dir: String,
-    cached_content: HashMap<String, String>,
+    /// Where files are read from when not from `prompts_dir`
+    provider: Option<Arc<dyn PromptProvider>>,
+    /// Prompt files already read, by `category:name`, split from their frontmatter
+    cached_content: Entries<String, CachedPrompt>,
+    /// Whether files are read again rather than taken from either cache
+    force_refresh: bool,
+    /// Loads answered from `cached_content` and loads that read a file
+    counters: CacheCounters,
+    /// Files read from disk by other loaders, consulted before reading one
+    shared_content: Option<ContentCache>,
+    /// Version to load per prompt file name
+    version_pins: HashMap<String, String>,
+    /// Versions of the prompt files loaded since the pins were last set
+    loaded_versions: BTreeMap<String, String>,
+    /// Loads timed since they were last taken, while profiling
+    file_loads: Option<Vec<FileLoadTiming>>,
+    /// Variant to load in place of base files, for the module being generated
+    active_variant: Option<String>,
+    /// Whether a variant file was loaded since the variant was last set
+    variant_loaded: bool,
+    /// Locale of the translations to load in place of base files
+    locale: Option<String>,
 }
 
 impl PromptLoader {
@@ -26,17 +156,176 @@ impl PromptLoader {
         
         Self {
             prompts_dir: default_dir,
-            cached_content: HashMap::new(),
+            provider: None,
+            cached_content: Entries::new(CachePolicy::UNBOUNDED),
+            force_refresh: false,
+            counters: CacheCounters::default(),
+            shared_content: None,
+            version_pins: HashMap::new(),
+            loaded_versions: BTreeMap::new(),
+            file_loads: None,
+            active_variant: None,
+            variant_loaded: false,
+            locale: None,
         }
     }
 
-    /// Load content from a specific prompt file
-    pub fn load_prompt(&mut self, category: &str, name: &str) -> Result<String, PromptError> {
+    /// Create a loader that reads every file from a provider and never from disk
+    pub fn with_provider(provider: Arc<dyn PromptProvider>) -> Self {
+        let prompts_dir = provider.name().to_string();
+        Self { provider: Some(provider), ..Self::new(Some(prompts_dir)) }
+    }
+
+    /// Create a loader for the prompts compiled into the library, which never reads the disk
+    pub fn embedded() -> Self {
+        Self::with_provider(Arc::new(EmbeddedPrompts))
+    }
+
+    /// Create a loader for prompt files held in memory, keyed by their path relative to a
+    /// prompts directory (`behaviors/planning.md`, `selection_rules.toml`)
+    pub fn from_files(files: HashMap<String, String>) -> Self {
+        Self::with_provider(Arc::new(files))
+    }
+
+    /// A loader reading the same prompts through the same shared cache, with a cache of
+    /// its own and no per-request options
+    #[cfg(feature = "nodejs")]
+    pub(crate) fn fork(&self) -> Self {
+        Self {
+            provider: self.provider.clone(),
+            shared_content: self.shared_content.clone(),
+            ..Self::new(Some(self.prompts_dir.clone()))
+        }
+    }
+
+    /// Whether this loader reads files from the prompts directory on disk
+    pub fn reads_disk(&self) -> bool {
+        self.provider.is_none()
+    }
+
+    /// Content of a file of the loader's provider by its path relative to the prompts
+    /// directory; `None` for loaders that read the disk
+    pub fn provided_file(&self, path: &str) -> Option<Cow<'_, str>> {
+        self.provider.as_ref()?.read(path)
+    }
+
+    /// Every file of the loader's provider by path, except for the prompts compiled into
+    /// the library, which any loader can read; `None` for loaders that read the disk
+    pub fn provided_files(&self) -> Option<BTreeMap<String, String>> {
+        let provider = self.provider.as_ref()?;
+        if provider.name() == crate::embedded::PROMPTS_DIR {
+            return Some(BTreeMap::new());
+        }
+        Some(provider.paths().into_iter()
+            .filter_map(|path| {
+                let content = provider.read(&path)?.into_owned();
+                Some((path, content))
+            })
+            .collect())
+    }
+
+    /// Load the given variant of prompt files where one exists, until changed
+    pub fn set_variant(&mut self, variant: Option<String>) {
+        self.active_variant = variant;
+        self.variant_loaded = false;
+    }
+
+    /// Whether a variant file was loaded since the variant was last set
+    pub fn variant_loaded(&self) -> bool {
+        self.variant_loaded
+    }
+
+    /// Load translations for the locale (`name.de.md` for `de`) where they exist, falling
+    /// back from `pt-BR` to `pt`, until changed
+    pub fn set_locale(&mut self, locale: Option<String>) {
+        self.locale = locale;
+    }
+
+    /// Pin prompt files (by file name, e.g. `planning`) to specific versions, and start
+    /// recording which versions get loaded
+    pub fn set_version_pins(&mut self, pins: HashMap<String, String>) {
+        self.version_pins = pins;
+        self.loaded_versions.clear();
+    }
+
+    /// Versions of the prompt files loaded since the pins were last set, by file name.
+    /// Files without a declared version are not listed.
+    pub fn loaded_versions(&self) -> &BTreeMap<String, String> {
+        &self.loaded_versions
+    }
+
+    /// Load content from a specific prompt file, honoring version pins. The content is
+    /// shared with the loader's cache, so loading a cached file copies nothing.
+    pub fn load_prompt(&mut self, category: &str, name: &str) -> Result<Arc<str>, PromptError> {
+        // Pinned versions take precedence over variants, and variants over translations
+        let exists = |file: &String| self.file_exists(category, file);
+        let variant = self.active_variant.as_ref()
+            .map(|variant| format!("{}.{}", name, variant))
+            .filter(exists);
+        let translation = self.locale.as_ref().and_then(|locale| {
+            let language = locale.split(['-', '_']).next().unwrap_or(locale);
+            [locale.as_str(), language].into_iter()
+                .map(|locale| format!("{}.{}", name, locale))
+                .find(exists)
+        });
+        let (content, version) = match (self.version_pins.get(name).cloned(), variant) {
+            (Some(pinned), _) => self.load_pinned(category, name, &pinned)?,
+            (None, Some(variant)) => {
+                self.variant_loaded = true;
+                self.load_file(category, &variant)?
+            }
+            (None, None) => self.load_file(category, translation.as_deref().unwrap_or(name))?,
+        };
+        if let Some(version) = version {
+            self.loaded_versions.insert(name.to_string(), version);
+        }
+        Ok(content)
+    }
+
+    /// Load a pinned version from `name@version.md`, or from `name.md` if that declares it
+    fn load_pinned(&mut self, category: &str, name: &str, pinned: &str) -> Result<(Arc<str>, Option<String>), PromptError> {
+        let versioned = format!("{}@{}", name, pinned);
+        if self.file_exists(category, &versioned) {
+            let (content, _) = self.load_file(category, &versioned)?;
+            return Ok((content, Some(pinned.to_string())));
+        }
+
+        let (content, version) = self.load_file(category, name)?;
+        if version.as_deref() == Some(pinned) {
+            Ok((content, version))
+        } else {
+            Err(PromptError::module_loading(
+                format!("Version {} of prompt file {}/{} not found", pinned, category, name)
+            ).with_prompt_file(category, &self.prompts_dir))
+        }
+    }
+
+    /// Read a prompt file, split off its frontmatter version, timing the load while
+    /// profiling
+    fn load_file(&mut self, category: &str, name: &str) -> Result<(Arc<str>, Option<String>), PromptError> {
+        let _span = tracing::debug_span!("load_prompt", category, name).entered();
+        let Some(mut file_loads) = self.file_loads.take() else {
+            return self.read_file(category, name);
+        };
+        let hits = self.counters.hits;
+        let start_time = crate::Instant::now();
+        let loaded = self.read_file(category, name);
+        file_loads.push(FileLoadTiming {
+            file: format!("{}/{}.md", category, name),
+            duration_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+            cached: self.counters.hits > hits,
+        });
+        self.file_loads = Some(file_loads);
+        loaded
+    }
+
+    fn read_file(&mut self, category: &str, name: &str) -> Result<(Arc<str>, Option<String>), PromptError> {
         let cache_key = format!("{}:{}", category, name);
         
         // Check cache first
-        if let Some(cached) = self.cached_content.get(&cache_key) {
-            return Ok(cached.clone());
+        if let Some(cached) = self.cached_content.get(cache_key.as_str()).filter(|_| !self.force_refresh) {
+            self.counters.record(true);
+            return Ok((cached.body.clone(), cached.version.clone()));
         }
 
         // Build file path
@@ -44,71 +333,149 @@ impl PromptLoader {
             .join(category)
             .join(format!("{}.md", name));
 
+        // Files another loader read are reused while unchanged on disk
+        let modified = match (&self.shared_content, self.reads_disk()) {
+            (Some(_), true) => fs::metadata(&file_path).and_then(|metadata| metadata.modified()).ok(),
+            _ => None,
+        };
+        let shared = modified.zip(self.shared_content.as_ref())
+            .filter(|_| !self.force_refresh)
+            .and_then(|(modified, shared)| shared.get(&file_path, modified));
+        if let Some(cached) = shared {
+            self.counters.record(true);
+            let loaded = (cached.body.clone(), cached.version.clone());
+            self.cached_content.insert(cache_key, cached);
+            return Ok(loaded);
+        }
+        self.counters.record(false);
+
         // Read file content
-        let content = fs::read_to_string(&file_path)
-            .map_err(|e| PromptError::ModuleLoadingFailed(
-                format!("Failed to load prompt file {:?}: {}", file_path, e)
-            ))?;
+        let content = if !self.reads_disk() {
+            self.provided_file(&format!("{}/{}.md", category, name))
+                .map(Cow::into_owned)
+                .ok_or_else(|| PromptError::module_loading(
+                    format!("Prompt file {}/{}.md not found in {}", category, name, self.prompts_dir)
+                ).with_prompt_file(category, &self.prompts_dir))?
+        } else {
+            fs::read_to_string(&file_path)
+                .map_err(|e| PromptError::module_loading(format!("Failed to load prompt file {:?}", file_path))
+                    .with_prompt_file(category, &self.prompts_dir)
+                    .with_path(&file_path)
+                    .with_source(e))?
+        };
 
         // Cache and return
-        self.cached_content.insert(cache_key, content.clone());
-        Ok(content)
+        let (body, version) = split_frontmatter(&content);
+        let cached = CachedPrompt { body: Arc::from(body), version: version.map(str::to_string) };
+        let loaded = (cached.body.clone(), cached.version.clone());
+        if let (Some(shared), Some(modified)) = (&self.shared_content, modified) {
+            shared.insert(file_path, modified, cached.clone());
+        }
+        self.cached_content.insert(cache_key, cached);
+        Ok(loaded)
     }
 
     /// Load domain-specific guidance
-    pub fn load_domain(&mut self, domain: &str) -> Result<String, PromptError> {
+    pub fn load_domain(&mut self, domain: &str) -> Result<Arc<str>, PromptError> {
         self.load_prompt("domains", domain)
     }
 
     /// Load behavioral pattern guidance
-    pub fn load_behavior(&mut self, behavior: &str) -> Result<String, PromptError> {
+    pub fn load_behavior(&mut self, behavior: &str) -> Result<Arc<str>, PromptError> {
         self.load_prompt("behaviors", behavior)
     }
 
     /// Load tool-specific guidance
-    pub fn load_tool(&mut self, tool_name: &str) -> Result<String, PromptError> {
+    pub fn load_tool(&mut self, tool_name: &str) -> Result<Arc<str>, PromptError> {
         self.load_prompt("tools", tool_name)
     }
 
+    /// Content of a prompt file as composition reads it, frontmatter removed, ignoring
+    /// version pins and variants. `category` is "domains", "behaviors" or "tools".
+    pub fn read_prompt(&mut self, category: &str, name: &str) -> Result<String, PromptError> {
+        if !["domains", "behaviors", "tools"].contains(&category) {
+            return Err(PromptError::invalid_request(format!("Unknown prompt category: {}", category)).with_field("category"));
+        }
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(PromptError::invalid_request(format!("Invalid prompt name: {:?}", name)).with_field("name"));
+        }
+        self.load_file(category, name).map(|(content, _)| content.to_string())
+    }
+
+    /// Path of the prompts directory this loader reads from
+    pub fn prompts_dir(&self) -> &str {
+        &self.prompts_dir
+    }
+
+    /// Forget loaded prompt files so later loads read them from disk again
+    pub fn clear_cache(&mut self) {
+        self.cached_content.clear();
+        if let Some(shared) = &self.shared_content {
+            shared.clear();
+        }
+    }
+
+    /// Reuse prompt files other loaders sharing the cache read from disk, and share
+    /// those this loader reads
+    pub fn set_content_cache(&mut self, cache: ContentCache) {
+        self.shared_content = Some(cache);
+    }
+
+    /// Limit how long the loader keeps files it read and how many, dropping files past
+    /// the new limit
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.cached_content.set_policy(policy);
+    }
+
+    /// Read files again instead of taking them from the caches, which are updated with
+    /// what is read, until changed
+    pub fn set_force_refresh(&mut self, force_refresh: bool) {
+        self.force_refresh = force_refresh;
+    }
+
+    /// Start or stop timing file loads
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.file_loads = profiling.then(Vec::new);
+    }
+
+    /// File loads timed since they were last taken; empty when not profiling
+    pub fn take_file_loads(&mut self) -> Vec<FileLoadTiming> {
+        self.file_loads.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Prompt file loads answered from the cache and loads that read the file
+    pub fn cache_counters(&self) -> CacheCounters {
+        self.counters
+    }
+
+    /// Files the loader keeps and their approximate bytes, with its cache counters
+    pub fn cache_usage(&self) -> CacheUsage {
+        self.cached_content.usage(self.counters, |key, cached| key.len() + cached.approximate_size())
+    }
+
     /// Check if a tool-specific prompt file exists
     pub fn has_tool_prompt(&self, tool_name: &str) -> bool {
-        let file_path = Path::new(&self.prompts_dir)
-            .join("tools")
-            .join(format!("{}.md", tool_name));
-        file_path.exists()
+        self.file_exists("tools", tool_name)
     }
 
-    /// Extract the main content from markdown (skip headers, get body)
-    pub fn extract_guidance(&self, markdown_content: &str) -> String {
-        let lines: Vec<&str> = markdown_content.lines().collect();
-        let mut guidance_lines = Vec::new();
-        let mut in_content = false;
-        
-        for line in lines {
-            // Skip title headers
-            if line.starts_with("# ") {
-                continue;
-            }
-            
-            // Start collecting after first section header or bullet point
-            if line.starts_with("## ") || line.starts_with("- ") || line.starts_with("### ") {
-                in_content = true;
-            }
-            
-            if in_content {
-                // Convert markdown headers to plain text for prompts
-                if line.starts_with("### ") {
-                    guidance_lines.push(line[4..].to_string());
-                } else if line.starts_with("## ") {
-                    let formatted = format!("{}:", &line[3..]);
-                    guidance_lines.push(formatted);
-                } else {
-                    guidance_lines.push(line.to_string());
-                }
-            }
+    /// Whether `<category>/<name>.md` exists
+    fn file_exists(&self, category: &str, name: &str) -> bool {
+        if !self.reads_disk() {
+            self.provided_file(&format!("{}/{}.md", category, name)).is_some()
+        } else {
+            Path::new(&self.prompts_dir).join(category).join(format!("{}.md", name)).exists()
         }
-        
-        guidance_lines.join("\n").trim().to_string()
+    }
+
+    /// The guidance of a prompt file's markdown, as it goes into prompts. Content is kept
+    /// as written, code blocks included, except for headings:
+    /// - `#` headings are titles and are left out
+    /// - `## Heading` becomes the line `Heading:`
+    /// - deeper headings become the line `Heading`
+    ///
+    /// Blank lines left where a title was are dropped, and the result is trimmed.
+    pub fn extract_guidance(&self, markdown_content: &str) -> String {
+        extract_guidance(markdown_content)
     }
 
     /// Get list of available domain files
@@ -127,22 +494,38 @@ impl PromptLoader {
     }
 
     fn list_category(&self, category: &str) -> Result<Vec<String>, PromptError> {
+        let Some(provider) = &self.provider else {
+            return self.list_directory(category);
+        };
+        let mut files: Vec<String> = provider.paths().iter()
+            .filter_map(|path| path.strip_prefix(category)?.strip_prefix('/')?.strip_suffix(".md"))
+            .filter(|stem| !stem.contains(['/', '@', '.']))
+            .map(str::to_string)
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    fn list_directory(&self, category: &str) -> Result<Vec<String>, PromptError> {
         let category_path = Path::new(&self.prompts_dir).join(category);
         
         let entries = fs::read_dir(&category_path)
-            .map_err(|e| PromptError::ModuleLoadingFailed(
-                format!("Failed to read {} directory: {}", category, e)
-            ))?;
+            .map_err(|e| PromptError::module_loading(format!("Failed to read {} directory", category))
+                .with_prompt_file(category, &self.prompts_dir)
+                .with_path(&category_path)
+                .with_source(e))?;
 
         let mut files = Vec::new();
         for entry in entries {
-            let entry = entry.map_err(|e| PromptError::ModuleLoadingFailed(
-                format!("Failed to read directory entry: {}", e)
-            ))?;
+            let entry = entry.map_err(|e| PromptError::module_loading("Failed to read directory entry")
+                .with_prompt_file(category, &self.prompts_dir)
+                .with_path(&category_path)
+                .with_source(e))?;
             
             let path = entry.path();
             if path.extension().and_then(|s| s.to_str()) == Some("md") {
-                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                // Versions (name@version.md) and variants (name.variant.md) are not separate prompts
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()).filter(|stem| !stem.contains(['@', '.'])) {
                     files.push(stem.to_string());
                 }
             }
@@ -154,12 +537,16 @@ impl PromptLoader {
 
     /// Check if prompts directory exists and is accessible
     pub fn validate_prompts_dir(&self) -> Result<(), PromptError> {
+        if !self.reads_disk() {
+            return Ok(());
+        }
+
         let prompts_path = Path::new(&self.prompts_dir);
         
         if !prompts_path.exists() {
-            return Err(PromptError::ConfigError(
+            return Err(PromptError::config(
                 format!("Prompts directory does not exist: {}", self.prompts_dir)
-            ));
+            ).with_path(prompts_path));
         }
 
         let domains_path = prompts_path.join("domains");
@@ -167,29 +554,138 @@ impl PromptLoader {
         let tools_path = prompts_path.join("tools");
 
         if !domains_path.exists() {
-            return Err(PromptError::ConfigError(
+            return Err(PromptError::config(
                 format!("Domains directory does not exist: {:?}", domains_path)
-            ));
+            ).with_path(&domains_path));
         }
 
         if !behaviors_path.exists() {
-            return Err(PromptError::ConfigError(
+            return Err(PromptError::config(
                 format!("Behaviors directory does not exist: {:?}", behaviors_path)
-            ));
+            ).with_path(&behaviors_path));
         }
 
         // Tools directory is optional for backward compatibility
         if !tools_path.exists() {
-            eprintln!("Note: Tools directory does not exist: {:?} (this is optional)", tools_path);
+            tracing::debug!(path = ?tools_path, "Tools directory does not exist (this is optional)");
         }
 
         Ok(())
     }
 }
 
+/// The guidance of a prompt file's markdown, as [`PromptLoader::extract_guidance`]
+/// describes
+pub(crate) fn extract_guidance(markdown_content: &str) -> String {
+    let mut guidance = String::with_capacity(markdown_content.len());
+    // End of the markdown copied to the guidance so far
+    let mut copied = 0;
+    let mut heading: Option<(HeadingLevel, String)> = None;
+    for (event, range) in Parser::new(markdown_content).into_offset_iter() {
+        match (event, &mut heading) {
+            (Event::Start(Tag::Heading { level, .. }), _) => {
+                guidance.push_str(&markdown_content[copied..range.start]);
+                copied = range.start;
+                heading = Some((level, String::new()));
+            }
+            (Event::Text(text), Some((_, heading))) => heading.push_str(&text),
+            (Event::Code(code), Some((_, heading))) => {
+                heading.push('`');
+                heading.push_str(&code);
+                heading.push('`');
+            }
+            (Event::SoftBreak | Event::HardBreak, Some((_, heading))) => heading.push(' '),
+            (Event::End(TagEnd::Heading(_)), Some((level, text))) => {
+                let source = &markdown_content[range.clone()];
+                match level {
+                    HeadingLevel::H1 => {
+                        let rest = &markdown_content[range.end..];
+                        let blank = rest.len() - rest.trim_start_matches(['\r', '\n']).len();
+                        if guidance.trim().is_empty() || guidance.ends_with("\n\n") {
+                            copied = range.end + blank;
+                        } else {
+                            copied = range.end;
+                        }
+                    }
+                    _ => {
+                        guidance.push_str(text.trim());
+                        if *level == HeadingLevel::H2 {
+                            guidance.push(':');
+                        }
+                        if source.ends_with('\n') {
+                            guidance.push('\n');
+                        }
+                        copied = range.end;
+                    }
+                }
+                heading = None;
+            }
+            _ => {}
+        }
+    }
+    guidance.push_str(&markdown_content[copied..]);
+
+    // Trim in place rather than copying the trimmed text
+    guidance.truncate(guidance.trim_end().len());
+    let leading = guidance.len() - guidance.trim_start().len();
+    guidance.drain(..leading);
+    guidance
+}
+
+/// The `##` sections of a prompt file's markdown, as (heading, range of the markdown),
+/// after the guidance before the first section, if any, under an empty heading
+pub(crate) fn markdown_sections(markdown: &str) -> Vec<(String, Range<usize>)> {
+    let mut sections: Vec<(String, Range<usize>)> = Vec::new();
+    let mut heading: Option<String> = None;
+    for (event, range) in Parser::new(markdown).into_offset_iter() {
+        match (event, &mut heading) {
+            (Event::Start(Tag::Heading { level: HeadingLevel::H2, .. }), _) => {
+                if let Some((_, last)) = sections.last_mut() {
+                    last.end = range.start;
+                } else if !extract_guidance(&markdown[..range.start]).is_empty() {
+                    sections.push((String::new(), 0..range.start));
+                }
+                sections.push((String::new(), range.start..markdown.len()));
+                heading = Some(String::new());
+            }
+            (Event::Text(text) | Event::Code(text), Some(heading)) => heading.push_str(&text),
+            (Event::End(TagEnd::Heading(HeadingLevel::H2)), Some(text)) => {
+                if let Some((last, _)) = sections.last_mut() {
+                    *last = text.trim().to_string();
+                }
+                heading = None;
+            }
+            _ => {}
+        }
+    }
+    if sections.is_empty() && !extract_guidance(markdown).is_empty() {
+        sections.push((String::new(), 0..markdown.len()));
+    }
+    sections
+}
+
+/// Separate a leading `---` frontmatter block from the content, returning the content and
+/// the block's `version` value if any
+pub(crate) fn split_frontmatter(content: &str) -> (&str, Option<&str>) {
+    let Some(rest) = content.strip_prefix("---\n") else {
+        return (content, None);
+    };
+    let Some(end) = rest.find("\n---") else {
+        return (content, None);
+    };
+
+    let version = rest[..end].lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim() == "version")
+        .map(|(_, value)| value.trim().trim_matches('"'));
+    let body = rest[end + 4..].trim_start_matches(['\r', '\n']);
+    (body, version)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_extract_guidance() {
@@ -211,5 +707,155 @@ mod tests {
         assert!(guidance.contains("Core Principles:"));
         assert!(guidance.contains("Always read files first"));
         assert!(guidance.contains("Writing"));
+
+        let guidance = loader.extract_guidance("# Deploying\n\nCheck the build first.\n\n```bash\n# tag the release\ngit tag v1\n```\n");
+        assert_eq!(guidance, "Check the build first.\n\n```bash\n# tag the release\ngit tag v1\n```");
+    }
+
+    /// A block of a prompt file, as (markdown, the line a heading becomes if it is one)
+    fn block() -> impl Strategy<Value = (String, Option<String>)> {
+        let words = || proptest::collection::vec("[a-z]{1,8}", 1..6).prop_map(|words| words.join(" "));
+        prop_oneof![
+            (1..5usize, words()).prop_map(|(level, text)| {
+                let line = match level {
+                    1 => None,
+                    2 => Some(format!("{}:", text)),
+                    _ => Some(text.clone()),
+                };
+                (format!("{} {}", "#".repeat(level), text), line)
+            }),
+            proptest::collection::vec(words(), 1..4).prop_map(|lines| (lines.join("\n"), None)),
+            proptest::collection::vec(words(), 1..4)
+                .prop_map(|items| (items.iter().map(|item| format!("- {}", item)).collect::<Vec<_>>().join("\n"), None)),
+            proptest::collection::vec(prop_oneof![words(), words().prop_map(|text| format!("# {}", text)), Just("## not a heading".to_string())], 1..4)
+                .prop_map(|lines| (format!("```\n{}\n```", lines.join("\n")), None)),
+        ]
+    }
+
+    proptest! {
+        /// Everything but headings is kept as written and in order, and headings below the
+        /// title level become lines of their own
+        #[test]
+        fn test_extract_guidance_keeps_all_but_titles(blocks in proptest::collection::vec(block(), 0..8)) {
+            let markdown = blocks.iter().map(|(block, _)| block.as_str()).collect::<Vec<_>>().join("\n\n");
+            let guidance = PromptLoader::new(None).extract_guidance(&markdown);
+            let mut rest = guidance.as_str();
+            for (block, heading) in &blocks {
+                let expected = match (block.starts_with('#'), heading) {
+                    (false, _) => block,
+                    (true, Some(heading)) => heading,
+                    (true, None) => continue,
+                };
+                let found = rest.find(expected.as_str());
+                prop_assert!(found.is_some(), "{:?} is missing from {:?}", expected, guidance);
+                rest = &rest[found.unwrap_or_default() + expected.len()..];
+            }
+        }
+
+        #[test]
+        fn test_extract_guidance_takes_any_text(markdown in any::<String>()) {
+            let guidance = PromptLoader::new(None).extract_guidance(&markdown);
+            prop_assert_eq!(guidance.trim(), guidance.as_str());
+        }
+    }
+
+    #[test]
+    fn test_in_memory_files_are_prompts() {
+        let files = HashMap::from([
+            ("behaviors/planning.md".to_string(), "## Plan\n- in memory".to_string()),
+            ("behaviors/planning.experiment.md".to_string(), "## Plan\n- experiment".to_string()),
+            ("domains/rust.md".to_string(), "## Rust\n- borrow".to_string()),
+        ]);
+        let mut loader = PromptLoader::from_files(files);
+        assert_eq!(loader.prompts_dir(), MEMORY_PROMPTS_DIR);
+        assert!(!loader.reads_disk());
+        assert!(loader.validate_prompts_dir().is_ok());
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Plan\n- in memory");
+        assert_eq!(loader.list_behaviors().unwrap(), vec!["planning"]);
+        assert_eq!(loader.list_domains().unwrap(), vec!["rust"]);
+        assert!(loader.list_tools().unwrap().is_empty());
+        assert!(loader.load_behavior("safety").is_err());
+
+        loader.set_variant(Some("experiment".to_string()));
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Plan\n- experiment");
+    }
+
+    #[test]
+    fn test_versions_and_variants_select_prompt_files() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-versions-{}", std::process::id()));
+        let behaviors = dir.join("behaviors");
+        fs::create_dir_all(&behaviors).unwrap();
+        fs::write(behaviors.join("planning.md"), "---\nversion: v3\n---\n## Plan\n- current").unwrap();
+        fs::write(behaviors.join("planning@v2.md"), "## Plan\n- older").unwrap();
+
+        let mut loader = PromptLoader::new(Some(dir.to_string_lossy().to_string()));
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Plan\n- current");
+        assert_eq!(loader.loaded_versions().get("planning").map(String::as_str), Some("v3"));
+
+        loader.set_version_pins(HashMap::from([("planning".to_string(), "v2".to_string())]));
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Plan\n- older");
+        assert_eq!(loader.loaded_versions().get("planning").map(String::as_str), Some("v2"));
+        assert_eq!(loader.list_behaviors().unwrap(), vec!["planning"]);
+
+        loader.set_version_pins(HashMap::from([("planning".to_string(), "v1".to_string())]));
+        assert!(loader.load_behavior("planning").is_err());
+
+        fs::write(behaviors.join("planning.experiment.md"), "## Plan\n- experiment").unwrap();
+        loader.set_version_pins(HashMap::new());
+        loader.set_variant(Some("experiment".to_string()));
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Plan\n- experiment");
+        assert!(loader.variant_loaded());
+        loader.set_variant(Some("control".to_string()));
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Plan\n- current");
+        assert!(!loader.variant_loaded());
+        assert_eq!(loader.list_behaviors().unwrap(), vec!["planning"]);
+
+        assert_eq!(loader.read_prompt("behaviors", "planning").unwrap(), "## Plan\n- current");
+        assert!(loader.read_prompt("behaviors", "../planning").is_err());
+        assert!(loader.read_prompt("rules", "planning").is_err());
+
+        fs::write(behaviors.join("planning.de.md"), "## Planen\n- aktuell").unwrap();
+        loader.set_variant(None);
+        loader.set_locale(Some("de-AT".to_string()));
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Planen\n- aktuell");
+        loader.set_locale(Some("fr".to_string()));
+        assert_eq!(&*loader.load_behavior("planning").unwrap(), "## Plan\n- current");
+        assert_eq!(loader.list_behaviors().unwrap(), vec!["planning"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_loaders_share_files_until_they_change() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-shared-{}", std::process::id()));
+        let behaviors = dir.join("behaviors");
+        fs::create_dir_all(&behaviors).unwrap();
+        let planning = behaviors.join("planning.md");
+        fs::write(&planning, "## Plan\n- first").unwrap();
+
+        let cache = ContentCache::new();
+        let loader = || {
+            let mut loader = PromptLoader::new(Some(dir.to_string_lossy().to_string()));
+            loader.set_content_cache(cache.clone());
+            loader
+        };
+        let mut first = loader();
+        assert_eq!(&*first.load_behavior("planning").unwrap(), "## Plan\n- first");
+        assert_eq!(first.cache_counters().misses, 1);
+        let mut second = loader();
+        assert_eq!(&*second.load_behavior("planning").unwrap(), "## Plan\n- first");
+        assert_eq!((second.cache_counters().hits, second.cache_counters().misses), (1, 0));
+        assert_eq!(cache.len(), 1);
+
+        // An edit on disk is read by the next loader, not served from the shared cache
+        fs::write(&planning, "## Plan\n- second").unwrap();
+        let later = fs::metadata(&planning).unwrap().modified().unwrap() + std::time::Duration::from_secs(1);
+        fs::File::options().write(true).open(&planning).unwrap().set_modified(later).unwrap();
+        assert_eq!(&*loader().load_behavior("planning").unwrap(), "## Plan\n- second");
+
+        // A loader keeps what it read until asked to refresh
+        assert_eq!(&*first.load_behavior("planning").unwrap(), "## Plan\n- first");
+        first.set_force_refresh(true);
+        assert_eq!(&*first.load_behavior("planning").unwrap(), "## Plan\n- second");
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
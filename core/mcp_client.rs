@@ -0,0 +1,253 @@
+//! Listing an MCP server's tools over stdio, for discovery that asks servers for their
+//! tools rather than inferring them from the server patterns (see
+//! `ToolDiscovery::with_server_listing`): the server is sent `initialize` and
+//! `tools/list`, following `nextCursor` through every page, and then stopped.
+
+use crate::cancel::CancellationToken;
+use crate::types::{McpServer, PromptError, Tool};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Protocol revision asked for in `initialize`
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Most `tools/list` pages read, so a server repeating its cursor cannot keep us listing
+const MAX_PAGES: u64 = 100;
+
+/// How often a wait for the server checks for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Start a server's command and list its tools, killing it afterwards. Fails when the
+/// server cannot be started, answers with an error or takes longer than `timeout` in
+/// all, and with `PromptError::Cancelled` once `cancel` is cancelled.
+pub fn list_server_tools(server_name: &str, server: &McpServer, timeout: Duration, cancel: &CancellationToken) -> Result<Vec<Tool>, PromptError> {
+    let child = Command::new(&server.command)
+        .args(&server.args)
+        .envs(server.env.iter().flatten())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| connection_failed(server_name, format!("failed to start '{}': {}", server.command, e)))?;
+    let mut process = ServerProcess(child);
+    let stdin = process.0.stdin.take().ok_or_else(|| connection_failed(server_name, "stdin unavailable".to_string()))?;
+    let stdout = process.0.stdout.take().ok_or_else(|| connection_failed(server_name, "stdout unavailable".to_string()))?;
+    list_tools(server_name, BufReader::new(stdout), stdin, timeout, cancel)
+}
+
+/// List the tools of a server that answers on `input` the JSON-RPC messages written to
+/// `output`, one per line, as `list_server_tools` does for a server it started
+pub fn list_tools(
+    server_name: &str,
+    input: impl BufRead + Send + 'static,
+    output: impl Write,
+    timeout: Duration,
+    cancel: &CancellationToken
+) -> Result<Vec<Tool>, PromptError> {
+    let mut connection = Connection {
+        server: server_name,
+        output,
+        messages: read_messages(input),
+        timeout,
+        deadline: Instant::now() + timeout,
+        cancel,
+    };
+    connection.request(1, "initialize", json!({
+        "protocolVersion": PROTOCOL_VERSION,
+        "capabilities": {},
+        "clientInfo": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+    }))?;
+    connection.send(json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))?;
+
+    let mut tools = Vec::new();
+    let mut cursor: Option<String> = None;
+    for id in 2..2 + MAX_PAGES {
+        let params = cursor.as_ref().map_or_else(|| json!({}), |cursor| json!({"cursor": cursor}));
+        let result = connection.request(id, "tools/list", params)?;
+        let listed = result["tools"].as_array()
+            .ok_or_else(|| discovery_failed(server_name, "tools/list result has no tools".to_string()))?;
+        for tool in listed {
+            tools.push(listed_tool(server_name, tool)
+                .ok_or_else(|| discovery_failed(server_name, format!("listed a tool without a name: {}", tool)))?);
+        }
+        cursor = result["nextCursor"].as_str().map(str::to_string);
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(tools)
+}
+
+/// A tool of a `tools/list` result as composition sees it, named `<server>.<tool>`;
+/// `None` when it has no name
+pub(crate) fn listed_tool(server_name: &str, tool: &Value) -> Option<Tool> {
+    Some(Tool {
+        name: format!("{}.{}", server_name, tool["name"].as_str()?),
+        description: tool["description"].as_str().unwrap_or_default().to_string(),
+        server: server_name.to_string(),
+        schema: tool.get("inputSchema").cloned(),
+    })
+}
+
+/// Messages read from `input` on a helper thread, so waiting for them can time out.
+/// Lines that are not JSON, such as a server's log output, are skipped.
+fn read_messages(input: impl BufRead + Send + 'static) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let Ok(message) = serde_json::from_str(&line) else {
+                continue;
+            };
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// A started server, killed when dropped
+struct ServerProcess(Child);
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+struct Connection<'a, W> {
+    server: &'a str,
+    output: W,
+    messages: Receiver<Value>,
+    timeout: Duration,
+    deadline: Instant,
+    cancel: &'a CancellationToken,
+}
+
+impl<W: Write> Connection<'_, W> {
+    fn send(&mut self, message: Value) -> Result<(), PromptError> {
+        writeln!(self.output, "{}", message)
+            .and_then(|_| self.output.flush())
+            .map_err(|e| connection_failed(self.server, format!("failed to write: {}", e)))
+    }
+
+    /// Send a request and wait for its result, passing over the server's notifications
+    /// and requests of its own
+    fn request(&mut self, id: u64, method: &str, params: Value) -> Result<Value, PromptError> {
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))?;
+        loop {
+            let mut message = self.next_message()?;
+            if message["id"].as_u64() != Some(id) || message.get("method").is_some() {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                return Err(discovery_failed(self.server, format!("{} failed: {}", method, error)));
+            }
+            return Ok(message["result"].take());
+        }
+    }
+
+    fn next_message(&self) -> Result<Value, PromptError> {
+        loop {
+            self.cancel.check()?;
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(connection_failed(self.server, format!("did not list its tools within {}ms", self.timeout.as_millis())));
+            }
+            match self.messages.recv_timeout(remaining.min(POLL_INTERVAL)) {
+                Ok(message) => return Ok(message),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(connection_failed(self.server, "closed its output".to_string()));
+                }
+            }
+        }
+    }
+}
+
+fn connection_failed(server: &str, message: String) -> PromptError {
+    PromptError::McpConnectionFailed { server: None, message: format!("MCP server {} {}", server, message) }.with_server(server)
+}
+
+fn discovery_failed(server: &str, message: String) -> PromptError {
+    PromptError::ToolDiscoveryFailed { server: None, message: format!("MCP server {} {}", server, message) }.with_server(server)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// A server started with `sh -c`
+    fn shell_server(script: &str) -> McpServer {
+        McpServer {
+            name: "files".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            env: None,
+        }
+    }
+
+    #[test]
+    fn test_lists_tools_page_by_page_and_stops_the_server() {
+        // Answers initialize, skips the notification, logs a line and pages tools/list
+        let server = shell_server(r#"
+            read line; echo '{"jsonrpc": "2.0", "id": 1, "result": {"protocolVersion": "2025-06-18", "capabilities": {"tools": {}}}}'
+            read line; echo 'listening on stdio'
+            read line; echo '{"jsonrpc": "2.0", "id": 2, "result": {"tools": [{"name": "read_file", "description": "Read a file", "inputSchema": {"type": "object"}}], "nextCursor": "2"}}'
+            read line; echo '{"jsonrpc": "2.0", "id": 3, "result": {"tools": [{"name": "write_file"}]}}'
+            exec sleep 30
+        "#);
+        let started = Instant::now();
+        let tools = list_server_tools("files", &server, Duration::from_secs(10), &CancellationToken::new()).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let names: Vec<_> = tools.iter().map(|tool| tool.name.as_str()).collect();
+        assert_eq!(names, ["files.read_file", "files.write_file"]);
+        assert_eq!(tools[0].description, "Read a file");
+        assert_eq!(tools[0].schema, Some(json!({"type": "object"})));
+    }
+
+    #[test]
+    fn test_servers_that_fail_or_hang_are_reported_and_killed() {
+        let error = list_server_tools("files", &shell_server("read line"), Duration::from_secs(10), &CancellationToken::new()).unwrap_err();
+        assert!(matches!(error, PromptError::McpConnectionFailed { .. }), "{}", error);
+        assert!(error.to_string().contains("closed its output"), "{}", error);
+
+        let missing = McpServer { command: "prompt-composer-missing-server".to_string(), ..shell_server("") };
+        let error = list_server_tools("files", &missing, Duration::from_secs(10), &CancellationToken::new()).unwrap_err();
+        assert!(error.to_string().contains("failed to start"), "{}", error);
+
+        let started = Instant::now();
+        let error = list_server_tools("files", &shell_server("exec sleep 30"), Duration::from_millis(200), &CancellationToken::new()).unwrap_err();
+        assert!(error.to_string().contains("within 200ms"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let failing = shell_server(r#"read line; echo '{"jsonrpc": "2.0", "id": 1, "error": {"code": -32602, "message": "unsupported"}}'"#);
+        let error = list_server_tools("files", &failing, Duration::from_secs(10), &CancellationToken::new()).unwrap_err();
+        assert!(matches!(error, PromptError::ToolDiscoveryFailed { .. }), "{}", error);
+    }
+
+    #[test]
+    fn test_cancelling_stops_waiting_for_the_server() {
+        let cancel = CancellationToken::new();
+        let canceller = {
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                cancel.cancel();
+            })
+        };
+        let started = Instant::now();
+        let result = list_server_tools("files", &shell_server("exec sleep 30"), Duration::from_secs(30), &cancel);
+        canceller.join().unwrap();
+        assert!(matches!(result, Err(PromptError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,217 @@
+//! Fixture MCP server of the `testing` feature, for integration tests of code that
+//! discovers and calls MCP tools: a stdio JSON-RPC responder listing the tools it is
+//! given and answering calls to them with their arguments. Tests start the
+//! `prompt-composer-fixture-mcp` binary as a server's command,
+//!
+//! ```json
+//! {"mcpServers": {"files": {"command": "prompt-composer-fixture-mcp", "args": ["--tool", "read_file=Read a file"]}}}
+//! ```
+//!
+//! or serve a [`FixtureMcpServer`] over any reader and writer in process. Discovery set
+//! to list servers (`ToolDiscovery::with_server_listing`) starts the binary and lists
+//! its tools; discovery that infers tools from the server patterns never starts it.
+
+use crate::jsonrpc::{self, RpcError};
+use crate::types::{McpServer, PromptError, Tool};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+/// Protocol revision answered when the client asks for none
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+pub const USAGE: &str = "Usage: prompt-composer-fixture-mcp [--name NAME] [--tool NAME[=DESCRIPTION]]... [--tools FILE] [--tools-json JSON]
+  --tools and --tools-json take a tools/list result: {\"tools\": [...]} or the array alone";
+
+/// An MCP server listing fixed tools
+#[derive(Debug, Clone)]
+pub struct FixtureMcpServer {
+    name: String,
+    /// Tools as `tools/list` lists them
+    tools: Vec<Value>,
+}
+
+impl FixtureMcpServer {
+    /// A server without tools, reporting `name` as its name
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), tools: Vec::new() }
+    }
+
+    /// Also list a tool taking any object of arguments
+    pub fn with_tool(mut self, name: &str, description: &str) -> Self {
+        self.tools.push(json!({"name": name, "description": description, "inputSchema": {"type": "object"}}));
+        self
+    }
+
+    /// Also list the tools of a `tools/list` result, `{"tools": [...]}` or the array
+    /// alone, such as one recorded from a real server
+    pub fn with_tools_json(mut self, tools: &str) -> Result<Self, PromptError> {
+        let invalid = |message: String| PromptError::invalid_request(format!("Invalid fixture tools: {}", message)).with_field("tools");
+        let mut listed: Value = serde_json::from_str(tools).map_err(|e| invalid(e.to_string()))?;
+        let listed = match listed.get_mut("tools") {
+            Some(listed) => listed.take(),
+            None => listed,
+        };
+        let Value::Array(listed) = listed else {
+            return Err(invalid("expected an array of tools".to_string()));
+        };
+        if let Some(unnamed) = listed.iter().position(|tool| !tool["name"].is_string()) {
+            return Err(invalid(format!("tool {} has no name", unnamed)));
+        }
+        self.tools.extend(listed);
+        Ok(self)
+    }
+
+    /// A server from the binary's command line, without the program name
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut server = Self::new("fixture");
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{} needs a value\n\n{}", arg, USAGE))?;
+            server = match arg.as_str() {
+                "--name" => Self { name: value.clone(), ..server },
+                "--tool" => {
+                    let (name, description) = value.split_once('=').unwrap_or((value, ""));
+                    server.with_tool(name, description)
+                }
+                "--tools" => {
+                    let tools = std::fs::read_to_string(value).map_err(|e| format!("Failed to read {}: {}", value, e))?;
+                    server.with_tools_json(&tools).map_err(|e| e.to_string())?
+                }
+                "--tools-json" => server.with_tools_json(value).map_err(|e| e.to_string())?,
+                _ => return Err(format!("Unexpected argument {}\n\n{}", arg, USAGE)),
+            };
+        }
+        Ok(server)
+    }
+
+    /// The MCP config entry starting this server with the fixture binary at `command`
+    pub fn config(&self, command: &str) -> McpServer {
+        McpServer {
+            name: self.name.clone(),
+            command: command.to_string(),
+            args: vec!["--name".to_string(), self.name.clone(), "--tools-json".to_string(), Value::Array(self.tools.clone()).to_string()],
+            env: None,
+        }
+    }
+
+    /// The listed tools as discovery that lists servers returns them for a server named
+    /// `server`
+    pub fn tools(&self, server: &str) -> Vec<Tool> {
+        self.tools.iter()
+            .filter_map(|tool| crate::mcp_client::listed_tool(server, tool))
+            .collect()
+    }
+
+    /// Answer one JSON-RPC method call. A call of a listed tool succeeds with its
+    /// arguments as the result.
+    pub fn handle(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": self.name, "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({"tools": self.tools})),
+            "tools/call" => {
+                let name = params["name"].as_str()
+                    .ok_or_else(|| RpcError::new(jsonrpc::INVALID_PARAMS, "tools/call needs a tool name"))?;
+                if !self.tools.iter().any(|tool| tool["name"] == name) {
+                    return Err(RpcError::new(jsonrpc::INVALID_PARAMS, format!("Unknown tool {}", name)));
+                }
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                Ok(json!({
+                    "content": [{"type": "text", "text": arguments.to_string()}],
+                    "structuredContent": {"tool": name, "arguments": arguments},
+                }))
+            }
+            _ => Err(RpcError::new(jsonrpc::METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    /// Answer each line of `input` on `output` until the input ends
+    pub fn serve(&self, input: impl BufRead, output: impl Write) -> std::io::Result<()> {
+        jsonrpc::serve(input, output, |method, params| self.handle(method, params))
+    }
+
+    /// Serve on stdin and stdout until the client closes stdin
+    pub fn serve_stdio(&self) -> std::io::Result<()> {
+        self.serve(std::io::stdin().lock(), std::io::stdout().lock())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+    use crate::cancel::CancellationToken;
+    use crate::mcp_client;
+    use std::time::Duration;
+    use crate::types::{McpConfig, PromptRequest};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_fixture_server_lists_and_calls_its_tools() {
+        let args: Vec<String> = ["--name", "files", "--tool", "read_file=Read a file", "--tools-json", r#"{"tools": [{"name": "write_file"}]}"#]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let server = FixtureMcpServer::from_args(&args).unwrap();
+        let mut input = String::new();
+        for message in [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2024-11-05", "capabilities": {}}}),
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "read_file", "arguments": {"path": "/tmp/a"}}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "delete_file"}}),
+        ] {
+            input.push_str(&format!("{}\n", message));
+        }
+        let mut output = Vec::new();
+        server.serve(input.as_bytes(), &mut output).unwrap();
+        let responses: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "files");
+        assert_eq!(responses[1]["result"]["tools"][0]["description"], "Read a file");
+        assert_eq!(responses[1]["result"]["tools"][1]["name"], "write_file");
+        assert_eq!(responses[2]["result"]["structuredContent"]["arguments"]["path"], "/tmp/a");
+        assert_eq!(responses[3]["error"]["code"], jsonrpc::INVALID_PARAMS);
+
+        let config = server.config("prompt-composer-fixture-mcp");
+        let restarted = FixtureMcpServer::from_args(&config.args).unwrap();
+        assert_eq!(restarted.tools, server.tools);
+        assert!(FixtureMcpServer::new("files").with_tools_json(r#"[{"description": "no name"}]"#).is_err());
+
+        let request = PromptRequest::builder()
+            .user_prompt("Read the config file")
+            .mcp_config(McpConfig { mcp_servers: HashMap::from([("files".to_string(), config)]) })
+            .build()
+            .unwrap();
+        let response = PromptComposer::embedded().compose(&request, &server.tools("files")).unwrap();
+        assert!(response.recognized_tools.contains(&"files.read_file".to_string()), "{:?}", response.recognized_tools);
+
+    }
+
+    #[test]
+    fn test_discovery_lists_the_fixture_tools() {
+        let server = FixtureMcpServer::new("files")
+            .with_tool("read_file", "Read a file")
+            .with_tools_json(r#"[{"name": "search", "inputSchema": {"type": "object"}}]"#)
+            .unwrap();
+        let (from_client, to_server) = std::io::pipe().unwrap();
+        let (from_server, to_client) = std::io::pipe().unwrap();
+        let serving = {
+            let server = server.clone();
+            std::thread::spawn(move || server.serve(std::io::BufReader::new(from_client), to_client))
+        };
+
+        let listed = mcp_client::list_tools("files", std::io::BufReader::new(from_server), to_server, Duration::from_secs(10), &CancellationToken::new()).unwrap();
+        serving.join().unwrap().unwrap();
+        let expected = server.tools("files");
+        assert_eq!(listed.len(), expected.len());
+        for (listed, expected) in listed.iter().zip(&expected) {
+            assert_eq!((&listed.name, &listed.description, &listed.schema), (&expected.name, &expected.description, &expected.schema));
+        }
+        assert_eq!(listed[1].schema, Some(json!({"type": "object"})));
+    }
+}
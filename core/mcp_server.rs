@@ -0,0 +1,155 @@
+//! MCP server of the `server-mcp` feature, offering composition as tools to agent hosts
+//! that already speak MCP. Hosts start it like any stdio server:
+//!
+//! ```json
+//! {"mcpServers": {"prompt-composer": {"command": "prompt-composer-mcp", "args": ["--prompts-dir", "./prompts"]}}}
+//! ```
+//!
+//! Composition uses cached or inferred tools, so a call never starts other MCP servers.
+
+use crate::jsonrpc::{self, RpcError};
+use crate::service::ComposerService;
+use crate::types::{PromptError, PromptRequest};
+use serde_json::{json, Value};
+
+/// Protocol revisions this server speaks, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+pub struct McpServer {
+    service: ComposerService,
+}
+
+impl McpServer {
+    /// A server reading prompts from `prompts_dir`, or the default prompts directory
+    pub fn new(prompts_dir: Option<String>) -> Self {
+        Self { service: ComposerService::new(prompts_dir) }
+    }
+
+    /// Answer one JSON-RPC method call
+    pub fn handle(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => {
+                let requested = params["protocolVersion"].as_str().unwrap_or_default();
+                let version = PROTOCOL_VERSIONS.iter().find(|version| **version == requested).unwrap_or(&PROTOCOL_VERSIONS[0]);
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": {"tools": {}},
+                    "serverInfo": {"name": "prompt-composer", "version": env!("CARGO_PKG_VERSION")},
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({"tools": tools()})),
+            "tools/call" => {
+                let name = params["name"].as_str()
+                    .ok_or_else(|| RpcError::new(jsonrpc::INVALID_PARAMS, "tools/call needs a tool name"))?;
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                self.call_tool(name, arguments)
+            }
+            _ => Err(RpcError::new(jsonrpc::METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    /// Run a tool; failures of the tool itself are results with `isError`, so the model sees them
+    fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, RpcError> {
+        let output = match name {
+            "compose_system_prompt" => request(arguments)
+                .and_then(|request| self.service.compose(&request, false))
+                .map(|response| (response.system_prompt.clone(), json!(response))),
+            "explain_selection" => request(arguments)
+                .and_then(|request| self.service.explain(&request))
+                .map(|decisions| json!(decisions))
+                .map(|decisions| (decisions.to_string(), json!({"decisions": decisions}))),
+            "list_domains" => self.service.list_domains()
+                .map(|domains| (json!(domains).to_string(), json!({"domains": domains}))),
+            "list_behaviors" => self.service.list_behaviors()
+                .map(|behaviors| (json!(behaviors).to_string(), json!({"behaviors": behaviors}))),
+            _ => return Err(RpcError::new(jsonrpc::INVALID_PARAMS, format!("Unknown tool {}", name))),
+        };
+        Ok(match output {
+            Ok((text, structured)) => json!({"content": [{"type": "text", "text": text}], "structuredContent": structured}),
+            Err(e) => {
+                let report = serde_json::to_string(&e.report()).unwrap_or_else(|_| e.to_string());
+                json!({"content": [{"type": "text", "text": report}], "isError": true})
+            }
+        })
+    }
+}
+
+/// A request from tool arguments; agents rarely know an MCP config, so it defaults to none
+fn request(mut arguments: Value) -> Result<PromptRequest, PromptError> {
+    if let Some(arguments) = arguments.as_object_mut() {
+        arguments.entry("mcp_config").or_insert_with(|| json!({"mcpServers": {}}));
+    }
+    serde_json::from_value(arguments).map_err(|e| PromptError::invalid_request(e.to_string()))
+}
+
+fn tools() -> Value {
+    let mut request_schema = PromptRequest::json_schema();
+    if let Some(required) = request_schema["required"].as_array_mut() {
+        required.retain(|field| field != "mcp_config");
+    }
+    let no_arguments = json!({"type": "object", "properties": {}});
+    json!([
+        {
+            "name": "compose_system_prompt",
+            "description": "Compose a system prompt for a user prompt from the behavior, domain and tool guidance that applies to it",
+            "inputSchema": request_schema,
+        },
+        {
+            "name": "explain_selection",
+            "description": "Explain which prompt modules a request would get and why, without composing it",
+            "inputSchema": request_schema,
+        },
+        {"name": "list_domains", "description": "List the domains that have guidance", "inputSchema": no_arguments},
+        {"name": "list_behaviors", "description": "List the behaviors that have guidance", "inputSchema": no_arguments},
+    ])
+}
+
+/// Serve MCP on stdin and stdout until the host closes stdin
+pub fn serve_stdio(prompts_dir: Option<String>) -> std::io::Result<()> {
+    let server = McpServer::new(prompts_dir);
+    jsonrpc::serve(std::io::stdin().lock(), std::io::stdout().lock(), |method, params| server.handle(method, params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mcp_server_offers_composition_as_tools() {
+        let server = McpServer::new(Some(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts").to_string()));
+        let mut input = String::new();
+        for message in [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2024-11-05", "capabilities": {}}}),
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "compose_system_prompt", "arguments": {"user_prompt": "Plan and implement a refactor"}}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "compose_system_prompt", "arguments": {}}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "tools/call", "params": {"name": "list_behaviors"}}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "resources/list"}),
+        ] {
+            input.push_str(&format!("{}\n", message));
+        }
+        input.push_str("not json\n");
+
+        let mut output = Vec::new();
+        jsonrpc::serve(input.as_bytes(), &mut output, |method, params| server.handle(method, params)).unwrap();
+        let responses: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(responses.len(), 7, "the notification gets no response");
+
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2024-11-05");
+        let tools: Vec<&str> = responses[1]["result"]["tools"].as_array().unwrap().iter().map(|tool| tool["name"].as_str().unwrap()).collect();
+        assert_eq!(tools, ["compose_system_prompt", "explain_selection", "list_domains", "list_behaviors"]);
+
+        let composed = &responses[2]["result"];
+        assert!(composed.get("isError").is_none());
+        assert_eq!(composed["content"][0]["text"], composed["structuredContent"]["system_prompt"]);
+        assert!(!composed["structuredContent"]["applied_modules"].as_array().unwrap().is_empty());
+
+        assert_eq!(responses[3]["result"]["isError"], true);
+        assert!(responses[3]["result"]["content"][0]["text"].as_str().unwrap().contains("InvalidRequest"));
+        assert!(responses[4]["result"]["structuredContent"]["behaviors"].as_array().unwrap().iter().any(|behavior| behavior == "planning"));
+        assert_eq!(responses[5]["error"]["code"], jsonrpc::METHOD_NOT_FOUND);
+        assert_eq!(responses[6]["error"]["code"], jsonrpc::PARSE_ERROR);
+    }
+}
@@ -0,0 +1,244 @@
+//! Measurements of composition for production deployments: compositions and their
+//! latency, prompt sizes, module activations, cache lookups and discovery failures, so
+//! deployments can alert on prompts growing or servers failing discovery. The crate
+//! reports to one recorder for the whole process, installed with [`set_recorder`]; the
+//! `metrics` feature adds [`PrometheusMetrics`], a recorder exported to Prometheus.
+
+use crate::types::{CacheCounters, ErrorCode};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+/// The cache a lookup went to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Prompts,
+    Tools,
+}
+
+impl CacheKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheKind::Prompts => "prompts",
+            CacheKind::Tools => "tools",
+        }
+    }
+}
+
+/// Receives the crate's measurements. Each method has a default that does nothing, so a
+/// recorder implements only what it exports. Methods are called on the composing
+/// thread and should return quickly.
+pub trait Metrics: Send + Sync {
+    /// A composition completed in `duration`, not counting tool discovery, with a prompt
+    /// of about `prompt_tokens` tokens
+    fn composition(&self, _duration: Duration, _prompt_tokens: usize) {}
+
+    /// A composition failed
+    fn composition_failed(&self, _code: ErrorCode) {}
+
+    /// A module contributed to a composed prompt, named as in `applied_modules`
+    fn module_applied(&self, _module: &str) {}
+
+    /// Lookups of one composition or discovery answered by a cache, and those that read a
+    /// file or discovered a server
+    fn cache_lookups(&self, _cache: CacheKind, _counters: CacheCounters) {}
+
+    /// A server's tools could not be discovered
+    fn discovery_failed(&self, _server: &str) {}
+}
+
+static RECORDER: RwLock<Option<Arc<dyn Metrics>>> = RwLock::new(None);
+
+/// Report the measurements of every composition in the process to `recorder`,
+/// replacing the recorder installed before
+pub fn set_recorder(recorder: Arc<dyn Metrics>) {
+    *RECORDER.write().unwrap_or_else(PoisonError::into_inner) = Some(recorder);
+}
+
+/// Stop reporting measurements
+pub fn clear_recorder() {
+    *RECORDER.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Pass measurements to the recorder, if one is installed
+pub(crate) fn record(measure: impl FnOnce(&dyn Metrics)) {
+    let recorder = RECORDER.read().unwrap_or_else(PoisonError::into_inner).clone();
+    if let Some(recorder) = recorder {
+        measure(recorder.as_ref());
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use self::prometheus_exporter::PrometheusMetrics;
+
+#[cfg(feature = "metrics")]
+mod prometheus_exporter {
+    use super::{CacheKind, Metrics};
+    use crate::types::{CacheCounters, ErrorCode, PromptError};
+    use prometheus::{Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+    use std::time::Duration;
+
+    /// Buckets of the prompt size histogram, in estimated tokens
+    const TOKEN_BUCKETS: &[f64] = &[250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0, 32000.0, 64000.0];
+
+    /// Buckets of the composition latency histogram, in seconds
+    const LATENCY_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+    /// A recorder keeping the measurements as Prometheus metrics named
+    /// `prompt_composer_*`, for a `/metrics` endpoint to render
+    pub struct PrometheusMetrics {
+        registry: Registry,
+        compositions: IntCounter,
+        failures: IntCounterVec,
+        modules: IntCounterVec,
+        cache_hits: IntCounterVec,
+        cache_misses: IntCounterVec,
+        discovery_failures: IntCounterVec,
+        latency: Histogram,
+        prompt_tokens: Histogram,
+    }
+
+    impl PrometheusMetrics {
+        /// Metrics in a registry of their own
+        pub fn new() -> Result<Self, PromptError> {
+            Self::with_registry(Registry::new())
+        }
+
+        /// Metrics added to `registry`, for hosts exporting their own metrics alongside.
+        /// Fails if the registry already has metrics of the same names.
+        pub fn with_registry(registry: Registry) -> Result<Self, PromptError> {
+            let counter = |name: &str, help: &str, label: &str| {
+                IntCounterVec::new(Opts::new(name, help), &[label]).map_err(registration_error)
+            };
+            let histogram = |name: &str, help: &str, buckets: &[f64]| {
+                Histogram::with_opts(HistogramOpts::new(name, help).buckets(buckets.to_vec())).map_err(registration_error)
+            };
+            let metrics = Self {
+                compositions: IntCounter::new("prompt_composer_compositions_total", "Completed compositions")
+                    .map_err(registration_error)?,
+                failures: counter("prompt_composer_composition_failures_total", "Failed compositions by error code", "code")?,
+                modules: counter("prompt_composer_module_activations_total", "Modules contributing to composed prompts", "module")?,
+                cache_hits: counter("prompt_composer_cache_hits_total", "Lookups answered by a cache", "cache")?,
+                cache_misses: counter("prompt_composer_cache_misses_total", "Lookups that read a file or discovered a server", "cache")?,
+                discovery_failures: counter("prompt_composer_discovery_failures_total", "Failed tool discoveries by server", "server")?,
+                latency: histogram("prompt_composer_composition_seconds", "Time composing, not counting discovery", LATENCY_BUCKETS)?,
+                prompt_tokens: histogram("prompt_composer_prompt_tokens", "Estimated tokens of composed prompts", TOKEN_BUCKETS)?,
+                registry,
+            };
+            let collectors: [Box<dyn prometheus::core::Collector>; 8] = [
+                Box::new(metrics.compositions.clone()),
+                Box::new(metrics.failures.clone()),
+                Box::new(metrics.modules.clone()),
+                Box::new(metrics.cache_hits.clone()),
+                Box::new(metrics.cache_misses.clone()),
+                Box::new(metrics.discovery_failures.clone()),
+                Box::new(metrics.latency.clone()),
+                Box::new(metrics.prompt_tokens.clone()),
+            ];
+            for collector in collectors {
+                metrics.registry.register(collector).map_err(registration_error)?;
+            }
+            Ok(metrics)
+        }
+
+        pub fn registry(&self) -> &Registry {
+            &self.registry
+        }
+
+        /// The registry's metrics in the Prometheus text format
+        pub fn render(&self) -> String {
+            TextEncoder::new().encode_to_string(&self.registry.gather()).unwrap_or_default()
+        }
+    }
+
+    fn registration_error(e: prometheus::Error) -> PromptError {
+        PromptError::config(format!("Failed to register metrics: {}", e))
+    }
+
+    impl Metrics for PrometheusMetrics {
+        fn composition(&self, duration: Duration, prompt_tokens: usize) {
+            self.compositions.inc();
+            self.latency.observe(duration.as_secs_f64());
+            self.prompt_tokens.observe(prompt_tokens as f64);
+        }
+
+        fn composition_failed(&self, code: ErrorCode) {
+            self.failures.with_label_values(&[&format!("{:?}", code)]).inc();
+        }
+
+        fn module_applied(&self, module: &str) {
+            self.modules.with_label_values(&[module]).inc();
+        }
+
+        fn cache_lookups(&self, cache: CacheKind, counters: CacheCounters) {
+            self.cache_hits.with_label_values(&[cache.as_str()]).inc_by(counters.hits);
+            self.cache_misses.with_label_values(&[cache.as_str()]).inc_by(counters.misses);
+        }
+
+        fn discovery_failed(&self, server: &str) {
+            self.discovery_failures.with_label_values(&[server]).inc();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_prometheus_metrics_render_measurements() {
+            let metrics = PrometheusMetrics::new().unwrap();
+            metrics.composition(Duration::from_millis(3), 1200);
+            metrics.module_applied("planning");
+            metrics.cache_lookups(CacheKind::Prompts, CacheCounters { hits: 4, misses: 1 });
+            metrics.composition_failed(ErrorCode::Cancelled);
+            let rendered = metrics.render();
+            assert!(rendered.contains("prompt_composer_compositions_total 1"));
+            assert!(rendered.contains("prompt_composer_module_activations_total{module=\"planning\"} 1"));
+            assert!(rendered.contains("prompt_composer_cache_hits_total{cache=\"prompts\"} 4"));
+            assert!(rendered.contains("prompt_composer_composition_failures_total{code=\"Cancelled\"} 1"));
+            assert!(rendered.contains("prompt_composer_prompt_tokens_bucket{le=\"2000\"} 1"));
+            assert!(PrometheusMetrics::with_registry(metrics.registry().clone()).is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+    use crate::types::PromptRequest;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorded {
+        prompts: Mutex<Vec<String>>,
+        modules: Mutex<Vec<String>>,
+    }
+
+    impl Metrics for Recorded {
+        fn composition(&self, _duration: Duration, prompt_tokens: usize) {
+            crate::lock(&self.prompts).push(format!("{} tokens", prompt_tokens));
+        }
+
+        fn module_applied(&self, module: &str) {
+            crate::lock(&self.modules).push(module.to_string());
+        }
+    }
+
+    #[test]
+    fn test_recorder_receives_compositions() {
+        let recorded = Arc::new(Recorded::default());
+        set_recorder(recorded.clone());
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement the migration, then run the tests")
+            .build()
+            .unwrap();
+        let response = PromptComposer::embedded().compose(&request, &[]).unwrap();
+        clear_recorder();
+
+        // Compositions of concurrent tests may be recorded too
+        let tokens = format!("{} tokens", response.system_prompt.len().div_ceil(4));
+        assert!(crate::lock(&recorded.prompts).contains(&tokens));
+        let modules = crate::lock(&recorded.modules);
+        assert!(response.applied_modules.iter().all(|module| modules.contains(module)));
+    }
+}
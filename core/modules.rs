@@ -1,11 +1,60 @@
 use crate::types::*;
 use crate::loader::PromptLoader;
+use crate::rules::*;
+use crate::registry::ModuleRegistry;
+use crate::session::ModuleState;
+use std::sync::Arc;
 
 /// Trait for prompt modules that provide specific guidance
 pub trait PromptModule: Send + Sync {
     fn name(&self) -> &str;
     fn generate_content(&self, tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError>;
     fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool;
+
+    /// How relevant this module is to the request, from 0.0 (not at all) to 1.0 (fully).
+    /// Modules that only implement `applies_to` score 1.0 when it returns true.
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> f32 {
+        if self.applies_to(tools, user_prompt, session_state) { 1.0 } else { 0.0 }
+    }
+
+    /// Names of modules that must be composed alongside this one
+    fn requires(&self, _session_state: &SessionState) -> &[&'static str] {
+        &[]
+    }
+
+    /// Names of modules that are dropped when this one is selected
+    fn conflicts_with(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Called the first time this module is composed in a session, with its (empty)
+    /// state for that session
+    fn on_session_start(&self, _state: &mut ModuleState) {}
+
+    /// Called when the host ends a session this module was composed in
+    fn on_session_end(&self, _state: &mut ModuleState) {}
+
+    /// Generate content with access to this module's state for the current session.
+    /// Requests without a `session_id` get fresh state each time. Stateless modules
+    /// implement `generate_content` only.
+    fn generate_stateful(
+        &self,
+        tools: &[Tool],
+        session_state: &SessionState,
+        loader: &mut PromptLoader,
+        _state: &mut ModuleState
+    ) -> Result<String, PromptError> {
+        self.generate_content(tools, session_state, loader)
+    }
+}
+
+/// Score keyword evidence: one keyword hit is a fair signal, two or more is a strong one
+fn keyword_score(hits: usize) -> f32 {
+    if hits == 0 {
+        0.0
+    } else {
+        (0.5 + 0.25 * hits as f32).min(1.0)
+    }
 }
 
 /// Basic tool usage guidance module
@@ -50,7 +99,7 @@ impl PromptModule for FilesystemModule {
         "filesystem"
     }
 
-    fn generate_content(&self, tools: &[Tool], _session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+    fn generate_content(&self, tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
         let has_filesystem_tools = tools.iter().any(|tool| {
             ToolCategory::from_tool(tool) == ToolCategory::FileSystem
         });
@@ -63,7 +112,15 @@ impl PromptModule for FilesystemModule {
         let filesystem_content = loader.load_domain("filesystem")?;
         let guidance = loader.extract_guidance(&filesystem_content);
         
-        Ok(format!("\nFILE SYSTEM GUIDANCE:\n{}", guidance))
+        let mut content = format!("\nFILE SYSTEM GUIDANCE:\n{}", guidance);
+        let files_modified = session_state.files_modified.as_deref().unwrap_or_default();
+        if !files_modified.is_empty() {
+            content.push_str("\n\nYou already modified these files this session; re-read them before editing again:\n");
+            for file in files_modified {
+                content.push_str(&format!("- {}\n", file));
+            }
+        }
+        Ok(content)
     }
 
     fn applies_to(&self, tools: &[Tool], _user_prompt: &str, _session_state: &SessionState) -> bool {
@@ -72,7 +129,28 @@ impl PromptModule for FilesystemModule {
 }
 
 /// Task planning guidance for complex tasks
-pub struct TaskPlanningModule;
+pub struct TaskPlanningModule {
+    rules: PlanningRules,
+    complexity: Option<TaskComplexity>,
+}
+
+impl TaskPlanningModule {
+    pub fn new(rules: PlanningRules) -> Self {
+        Self { rules, complexity: None }
+    }
+
+    /// Gate on an assessed complexity level instead of the planning keywords
+    pub fn with_complexity(mut self, complexity: TaskComplexity) -> Self {
+        self.complexity = Some(complexity);
+        self
+    }
+}
+
+impl Default for TaskPlanningModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().planning)
+    }
+}
 
 impl PromptModule for TaskPlanningModule {
     fn name(&self) -> &str {
@@ -95,12 +173,44 @@ impl PromptModule for TaskPlanningModule {
 
     fn applies_to(&self, _tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
         let has_plan = session_state.has_plan.unwrap_or(false);
-        !has_plan && is_complex_task(user_prompt)
+        match session_state.phase {
+            Some(ConversationPhase::Planning) => !has_plan,
+            Some(_) => false,
+            None => match &self.complexity {
+                Some(complexity) => !has_plan && *complexity >= TaskComplexity::Moderate,
+                None => !has_plan && is_complex_task(user_prompt, &self.rules),
+            },
+        }
     }
 }
 
 /// Progress monitoring for ongoing work
-pub struct ProgressMonitoringModule;
+pub struct ProgressMonitoringModule {
+    rules: ThresholdRules,
+    complexity: TaskComplexity,
+}
+
+impl ProgressMonitoringModule {
+    pub fn new(rules: ThresholdRules) -> Self {
+        Self { rules, complexity: TaskComplexity::Simple }
+    }
+
+    /// Scale the tool call threshold by the expected effort of the task
+    pub fn with_complexity(mut self, complexity: TaskComplexity) -> Self {
+        self.complexity = complexity;
+        self
+    }
+
+    fn threshold(&self) -> u32 {
+        (self.rules.tool_call_threshold as f32 * self.complexity.effort_multiplier()).round() as u32
+    }
+}
+
+impl Default for ProgressMonitoringModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().progress)
+    }
+}
 
 impl PromptModule for ProgressMonitoringModule {
     fn name(&self) -> &str {
@@ -117,22 +227,351 @@ impl PromptModule for ProgressMonitoringModule {
         let progress_content = loader.load_behavior("progress")?;
         let guidance = loader.extract_guidance(&progress_content);
 
+        let mut results = String::new();
+        let last_results = session_state.last_tool_results.as_deref().unwrap_or_default();
+        if !last_results.is_empty() {
+            results.push_str("Your most recent tool calls:\n");
+            for result in last_results {
+                let outcome = if result.success { "succeeded" } else { "failed" };
+                match &result.summary {
+                    Some(summary) => results.push_str(&format!("- {} {}: {}\n", result.tool, outcome, summary)),
+                    None => results.push_str(&format!("- {} {}\n", result.tool, outcome)),
+                }
+            }
+            results.push('\n');
+        }
+
         Ok(format!(
             "\nPROGRESS MONITORING:\n\
             Your original task was: \"{}\"\n\n\
             You've executed {} tool calls so far.\n\n\
-            {}",
-            original_task, tool_count, guidance
+            {}{}",
+            original_task, tool_count, results, guidance
         ))
     }
 
     fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, session_state: &SessionState) -> bool {
-        session_state.tool_call_count.unwrap_or(0) >= 6
+        match session_state.phase {
+            Some(ConversationPhase::Planning) | Some(ConversationPhase::Review) => false,
+            _ => session_state.tool_call_count.unwrap_or(0) >= self.threshold(),
+        }
+    }
+}
+
+/// Verification guidance for the review phase
+pub struct VerificationModule;
+
+impl PromptModule for VerificationModule {
+    fn name(&self) -> &str {
+        "verification"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        // Load verification guidance from file
+        let verification_content = loader.load_behavior("verification")?;
+        let guidance = loader.extract_guidance(&verification_content);
+
+        let mut content = String::from("\nVERIFICATION:\n");
+        if let Some(ref original_task) = session_state.original_task {
+            content.push_str(&format!("Verify the work against the original task: \"{}\"\n\n", original_task));
+        }
+        content.push_str(&guidance);
+
+        Ok(content)
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, session_state: &SessionState) -> bool {
+        session_state.phase == Some(ConversationPhase::Review)
+    }
+}
+
+/// Checkpointing guidance for long-running sessions
+pub struct CheckpointModule {
+    rules: ThresholdRules,
+}
+
+impl CheckpointModule {
+    pub fn new(rules: ThresholdRules) -> Self {
+        Self { rules }
+    }
+}
+
+impl Default for CheckpointModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().checkpoint)
+    }
+}
+
+impl PromptModule for CheckpointModule {
+    fn name(&self) -> &str {
+        "checkpoint"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let tool_count = session_state.tool_call_count.unwrap_or(0);
+
+        // Load checkpoint guidance from file
+        let checkpoint_content = loader.load_behavior("checkpoint")?;
+        let guidance = loader.extract_guidance(&checkpoint_content);
+
+        let mut content = String::from("\nCHECKPOINTING:\n");
+        if tool_count > 0 {
+            content.push_str(&format!(
+                "This is a long-running session ({} tool calls so far). Checkpoint your work now.\n\n",
+                tool_count
+            ));
+        } else {
+            content.push_str("This is a long-running session. Checkpoint your work regularly.\n\n");
+        }
+        if let Some(ref current_step) = session_state.current_step {
+            content.push_str(&format!("Current step: {}\n\n", current_step));
+        }
+        content.push_str(&guidance);
+
+        Ok(content)
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, session_state: &SessionState) -> bool {
+        session_state.is_long_running.unwrap_or(false)
+            || session_state.tool_call_count.unwrap_or(0) >= self.rules.tool_call_threshold
+    }
+}
+
+/// Error recovery guidance when tool calls keep failing
+pub struct ErrorRecoveryModule {
+    rules: ErrorRecoveryRules,
+}
+
+impl ErrorRecoveryModule {
+    pub fn new(rules: ErrorRecoveryRules) -> Self {
+        Self { rules }
+    }
+}
+
+impl Default for ErrorRecoveryModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().error_recovery)
+    }
+}
+
+impl PromptModule for ErrorRecoveryModule {
+    fn name(&self) -> &str {
+        "error_recovery"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let failures = session_state.consecutive_failures.unwrap_or(0);
+        let recent_errors = session_state.recent_errors.as_deref().unwrap_or_default();
+
+        // Load error recovery guidance from file
+        let recovery_content = loader.load_behavior("error_recovery")?;
+        let guidance = loader.extract_guidance(&recovery_content);
+
+        let mut content = String::from("\nERROR RECOVERY:\n");
+        if failures > 0 {
+            content.push_str(&format!("Your last {} tool calls failed.\n", failures));
+        }
+        if !recent_errors.is_empty() {
+            content.push_str("Recent errors:\n");
+            for error in recent_errors {
+                content.push_str(&format!("- {}\n", error));
+            }
+        }
+        if failures >= self.rules.ask_for_help_after_failures {
+            content.push_str(
+                "Stop retrying the same approach. Explain what you tried and what failed, \
+                and ask the user for help before continuing.\n"
+            );
+        }
+        content.push('\n');
+        content.push_str(&guidance);
+
+        Ok(content)
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, session_state: &SessionState) -> bool {
+        let has_recent_errors = session_state.recent_errors
+            .as_ref()
+            .is_some_and(|errors| !errors.is_empty());
+
+        has_recent_errors
+            || session_state.consecutive_failures.unwrap_or(0) >= self.rules.consecutive_failures_threshold
+    }
+}
+
+/// Safety guidance when tools capable of destructive operations are available
+pub struct SafetyModule {
+    rules: SafetyRules,
+}
+
+impl SafetyModule {
+    pub fn new(rules: SafetyRules) -> Self {
+        Self { rules }
+    }
+
+    /// Tools whose name or description has one of the destructive keywords as a word
+    fn destructive_tools<'a>(&self, tools: &'a [Tool]) -> Vec<&'a Tool> {
+        let keywords = &self.rules.destructive_keywords;
+        tools.iter().filter(|tool| {
+            contains_any_word(keywords, &tool.name) || contains_any_word(keywords, &tool.description)
+        }).collect()
+    }
+}
+
+impl Default for SafetyModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().safety)
+    }
+}
+
+impl PromptModule for SafetyModule {
+    fn name(&self) -> &str {
+        "safety"
+    }
+
+    fn generate_content(&self, tools: &[Tool], _session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let destructive_tools = self.destructive_tools(tools);
+
+        // Load safety guidance from file
+        let safety_content = loader.load_behavior("safety")?;
+        let guidance = loader.extract_guidance(&safety_content);
+
+        let mut content = String::from("\nDESTRUCTIVE OPERATION SAFETY:\n");
+        if !destructive_tools.is_empty() {
+            let names: Vec<&str> = destructive_tools.iter().map(|tool| tool.name.as_str()).collect();
+            content.push_str(&format!(
+                "These tools can modify or destroy data: {}\n\n",
+                names.join(", ")
+            ));
+        }
+        content.push_str(&guidance);
+
+        Ok(content)
+    }
+
+    fn applies_to(&self, tools: &[Tool], _user_prompt: &str, _session_state: &SessionState) -> bool {
+        !self.destructive_tools(tools).is_empty()
+    }
+}
+
+/// Memory module that surfaces facts and preferences from a host's long-term memory store
+pub struct MemoryModule;
+
+impl PromptModule for MemoryModule {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let memories: Vec<&String> = session_state.memories
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|memory| !memory.trim().is_empty())
+            .collect();
+        if memories.is_empty() {
+            return Ok(String::new());
+        }
+
+        // Load memory usage guidance from file
+        let memory_content = loader.load_behavior("memory")?;
+        let guidance = loader.extract_guidance(&memory_content);
+
+        let mut content = String::from("\nMEMORY:\nThings you remember about the user and previous sessions:\n");
+        for memory in memories {
+            // Keep multi-line memories on their own bullet
+            let memory = memory.split_whitespace().collect::<Vec<_>>().join(" ");
+            content.push_str(&format!("- {}\n", memory));
+        }
+        content.push('\n');
+        content.push_str(&guidance);
+
+        Ok(content)
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, session_state: &SessionState) -> bool {
+        session_state.memories
+            .as_ref()
+            .is_some_and(|memories| memories.iter().any(|memory| !memory.trim().is_empty()))
+    }
+}
+
+/// Delegation and reporting guidance for multi-agent setups
+pub struct AgentCoordinationModule;
+
+impl PromptModule for AgentCoordinationModule {
+    fn name(&self) -> &str {
+        "agent_coordination"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        // Load the guidance matching the agent's role from file
+        let (behavior, heading) = match session_state.agent_role {
+            Some(AgentRole::Orchestrator) => ("orchestrator", "ORCHESTRATING SUB-AGENTS"),
+            Some(AgentRole::Worker) => ("worker", "WORKING AS A SUB-AGENT"),
+            None => return Ok(String::new()),
+        };
+        let role_content = loader.load_behavior(behavior)?;
+        let guidance = loader.extract_guidance(&role_content);
+
+        Ok(format!("\n{}:\n{}", heading, guidance))
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, session_state: &SessionState) -> bool {
+        session_state.agent_role.is_some()
+    }
+
+    fn requires(&self, session_state: &SessionState) -> &[&'static str] {
+        // Orchestrators need a plan to split into sub-agent tasks
+        match session_state.agent_role {
+            Some(AgentRole::Orchestrator) => &["task_planning"],
+            _ => &[],
+        }
+    }
+}
+
+/// Concise mode that asks for brief responses and drops verbose planning guidance
+pub struct ConciseModule;
+
+impl PromptModule for ConciseModule {
+    fn name(&self) -> &str {
+        "concise"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        // Load concise mode guidance from file
+        let concise_content = loader.load_behavior("concise")?;
+        let guidance = loader.extract_guidance(&concise_content);
+
+        Ok(format!("\nCONCISE MODE:\n{}", guidance))
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, _session_state: &SessionState) -> bool {
+        // Only selected through the "concise" behavior hint
+        false
+    }
+
+    fn conflicts_with(&self) -> &[&'static str] {
+        &["task_planning"]
     }
 }
 
 /// Programming best practices module
-pub struct ProgrammingModule;
+pub struct ProgrammingModule {
+    rules: KeywordRules,
+}
+
+impl ProgrammingModule {
+    pub fn new(rules: KeywordRules) -> Self {
+        Self { rules }
+    }
+}
+
+impl Default for ProgrammingModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().programming)
+    }
+}
 
 impl PromptModule for ProgrammingModule {
     fn name(&self) -> &str {
@@ -147,26 +586,39 @@ impl PromptModule for ProgrammingModule {
         Ok(format!("\nPROGRAMMING BEST PRACTICES:\n{}", guidance))
     }
 
-    fn applies_to(&self, tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> bool {
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        self.relevance_score(tools, user_prompt, session_state) > 0.0
+    }
+
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> f32 {
         let has_file_tools = tools.iter().any(|tool| {
             ToolCategory::from_tool(tool) == ToolCategory::FileSystem
         });
-        
-        let programming_keywords = [
-            "code", "function", "class", "refactor", "implement", 
-            "debug", "fix", "python", "rust", "javascript", "api"
-        ];
-        
-        let user_prompt_lower = user_prompt.to_lowercase();
-        let mentions_programming = programming_keywords.iter()
-            .any(|keyword| user_prompt_lower.contains(keyword));
 
-        has_file_tools && mentions_programming
+        if has_file_tools {
+            keyword_score(self.rules.match_count(user_prompt))
+        } else {
+            0.0
+        }
     }
 }
 
 /// Data Analysis module
-pub struct AnalysisModule;
+pub struct AnalysisModule {
+    rules: KeywordRules,
+}
+
+impl AnalysisModule {
+    pub fn new(rules: KeywordRules) -> Self {
+        Self { rules }
+    }
+}
+
+impl Default for AnalysisModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().analysis)
+    }
+}
 
 impl PromptModule for AnalysisModule {
     fn name(&self) -> &str {
@@ -181,26 +633,39 @@ impl PromptModule for AnalysisModule {
         Ok(format!("\nDATA ANALYSIS METHODOLOGY:\n{}", guidance))
     }
 
-    fn applies_to(&self, tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> bool {
-        let analysis_keywords = [
-            "analyze", "analysis", "data", "csv", "trends", "statistics", 
-            "report", "insights", "metrics", "dashboard"
-        ];
-        
-        let user_prompt_lower = user_prompt.to_lowercase();
-        let mentions_analysis = analysis_keywords.iter()
-            .any(|keyword| user_prompt_lower.contains(keyword));
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        self.relevance_score(tools, user_prompt, session_state) > 0.0
+    }
 
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> f32 {
         let has_data_tools = tools.iter().any(|tool| {
             ToolCategory::from_tool(tool) == ToolCategory::DataAnalysis
         });
 
-        mentions_analysis || has_data_tools
+        if has_data_tools {
+            1.0
+        } else {
+            keyword_score(self.rules.match_count(user_prompt))
+        }
     }
 }
 
 /// System Administration module
-pub struct SystemModule;
+pub struct SystemModule {
+    rules: KeywordRules,
+}
+
+impl SystemModule {
+    pub fn new(rules: KeywordRules) -> Self {
+        Self { rules }
+    }
+}
+
+impl Default for SystemModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().system)
+    }
+}
 
 impl PromptModule for SystemModule {
     fn name(&self) -> &str {
@@ -215,21 +680,160 @@ impl PromptModule for SystemModule {
         Ok(format!("\nSYSTEM ADMINISTRATION GUIDANCE:\n{}", guidance))
     }
 
-    fn applies_to(&self, tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> bool {
-        let system_keywords = [
-            "server", "deployment", "infrastructure", "configuration", 
-            "security", "backup", "monitor", "admin", "service"
-        ];
-        
-        let user_prompt_lower = user_prompt.to_lowercase();
-        let mentions_system = system_keywords.iter()
-            .any(|keyword| user_prompt_lower.contains(keyword));
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        self.relevance_score(tools, user_prompt, session_state) > 0.0
+    }
 
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> f32 {
         let has_system_tools = tools.iter().any(|tool| {
             ToolCategory::from_tool(tool) == ToolCategory::SystemAdmin
         });
 
-        mentions_system || has_system_tools
+        if has_system_tools {
+            1.0
+        } else {
+            keyword_score(self.rules.match_count(user_prompt))
+        }
+    }
+}
+
+/// Web research and citation module
+pub struct WebResearchModule {
+    rules: KeywordRules,
+}
+
+impl WebResearchModule {
+    pub fn new(rules: KeywordRules) -> Self {
+        Self { rules }
+    }
+}
+
+impl Default for WebResearchModule {
+    fn default() -> Self {
+        Self::new(SelectionRules::default().web_research)
+    }
+}
+
+impl PromptModule for WebResearchModule {
+    fn name(&self) -> &str {
+        "web_research"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        // Load web research guidance from file
+        let research_content = loader.load_domain("web_research")?;
+        let guidance = loader.extract_guidance(&research_content);
+        
+        Ok(format!("\nWEB RESEARCH GUIDANCE:\n{}", guidance))
+    }
+
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        self.relevance_score(tools, user_prompt, session_state) > 0.0
+    }
+
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, _session_state: &SessionState) -> f32 {
+        let has_web_tools = tools.iter().any(|tool| {
+            ToolCategory::from_tool(tool) == ToolCategory::WebApi
+        });
+
+        if has_web_tools {
+            1.0
+        } else if tools.is_empty() {
+            0.0
+        } else {
+            keyword_score(self.rules.match_count(user_prompt))
+        }
+    }
+}
+
+/// Output format compliance module driven by the request's `response_format`
+pub struct OutputFormatModule {
+    format: ResponseFormat,
+}
+
+impl OutputFormatModule {
+    pub fn new(format: ResponseFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl PromptModule for OutputFormatModule {
+    fn name(&self) -> &str {
+        "output_format"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let behavior = match self.format {
+            ResponseFormat::Json { .. } => "format_json",
+            ResponseFormat::MarkdownTable { .. } => "format_markdown_table",
+            ResponseFormat::CodeOnly { .. } => "format_code_only",
+        };
+
+        // Load format rules from file
+        let format_content = loader.load_behavior(behavior)?;
+        let guidance = loader.extract_guidance(&format_content);
+
+        let mut content = format!("\nOUTPUT FORMAT:\n{}", guidance);
+        match self.format {
+            ResponseFormat::Json { schema: Some(ref schema) } => {
+                let schema_json = serde_json::to_string_pretty(schema)?;
+                content.push_str(&format!("\n\nYour response must validate against this JSON schema:\n{}", schema_json));
+            }
+            ResponseFormat::MarkdownTable { columns: Some(ref columns) } if !columns.is_empty() => {
+                content.push_str(&format!("\n\nThe table must have exactly these columns, in order: {}", columns.join(", ")));
+            }
+            ResponseFormat::CodeOnly { language: Some(ref language) } => {
+                content.push_str(&format!("\n\nWrite the code in {}.", language));
+            }
+            _ => {}
+        }
+
+        Ok(content)
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, _session_state: &SessionState) -> bool {
+        true // Always applies when the request sets a response format
+    }
+}
+
+/// Workspace module that states the request's working environment
+pub struct WorkspaceModule {
+    workspace: WorkspaceContext,
+}
+
+impl WorkspaceModule {
+    pub fn new(workspace: WorkspaceContext) -> Self {
+        Self { workspace }
+    }
+}
+
+impl PromptModule for WorkspaceModule {
+    fn name(&self) -> &str {
+        "workspace"
+    }
+
+    fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let facts = self.workspace.facts();
+        if facts.is_empty() {
+            return Ok(String::new());
+        }
+
+        // Load workspace guidance from file
+        let workspace_content = loader.load_behavior("workspace")?;
+        let guidance = loader.extract_guidance(&workspace_content);
+
+        let mut content = String::from("\nWORKSPACE:\n");
+        for (label, value) in facts {
+            content.push_str(&format!("- {}: {}\n", label, value));
+        }
+        content.push('\n');
+        content.push_str(&guidance);
+
+        Ok(content)
+    }
+
+    fn applies_to(&self, _tools: &[Tool], _user_prompt: &str, _session_state: &SessionState) -> bool {
+        !self.workspace.is_empty()
     }
 }
 
@@ -301,17 +905,127 @@ impl PromptModule for GenericBehaviorModule {
     }
 }
 
+/// Module declared in `selection_rules.toml`: config triggers plus a content file
+pub struct DeclarativeModule {
+    rules: DeclarativeModuleRules,
+}
+
+impl DeclarativeModule {
+    pub fn new(rules: DeclarativeModuleRules) -> Self {
+        Self { rules }
+    }
+
+    /// Whether the session and tool triggers hold; keywords are scored separately
+    fn triggers_hold(&self, tools: &[Tool], session_state: &SessionState) -> bool {
+        let rules = &self.rules;
+        let categories_hold = rules.tool_categories.is_empty()
+            || tools.iter().any(|tool| rules.tool_categories.contains(&ToolCategory::from_tool(tool)));
+        let tool_calls_hold = rules.min_tool_calls
+            .is_none_or(|min| session_state.tool_call_count.unwrap_or(0) >= min);
+        let failures_hold = rules.min_consecutive_failures
+            .is_none_or(|min| session_state.consecutive_failures.unwrap_or(0) >= min);
+        let phase_holds = rules.phases.is_empty()
+            || session_state.phase.as_ref().is_some_and(|phase| rules.phases.contains(phase));
+
+        categories_hold && tool_calls_hold && failures_hold && phase_holds
+    }
+}
+
+impl PromptModule for DeclarativeModule {
+    fn name(&self) -> &str {
+        &self.rules.name
+    }
+
+    fn generate_content(&self, _tools: &[Tool], _session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let file = self.rules.content.as_deref().unwrap_or(&self.rules.name);
+        let content: Arc<str> = match (&self.rules.text, self.rules.kind) {
+            (Some(text), _) => Arc::from(text.as_str()),
+            (None, ModuleKind::Domain) => loader.load_domain(file)?,
+            (None, _) => loader.load_behavior(file)?,
+        };
+        let heading = self.rules.heading.clone().unwrap_or_else(|| self.rules.name.to_uppercase());
+        Ok(format!("\n{}:\n{}", heading, loader.extract_guidance(&content)))
+    }
+
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        self.relevance_score(tools, user_prompt, session_state) > 0.0
+    }
+
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> f32 {
+        if !self.triggers_hold(tools, session_state) {
+            return 0.0;
+        }
+        let score = if self.rules.keywords.is_empty() {
+            1.0
+        } else {
+            let prompt_lower = user_prompt.to_lowercase();
+            let hits = self.rules.keywords.iter()
+                .filter(|keyword| prompt_lower.contains(&keyword.to_lowercase()))
+                .count();
+            keyword_score(hits)
+        };
+        score * self.rules.priority
+    }
+}
+
 /// Detect if a task is complex based on user prompt
-fn is_complex_task(user_prompt: &str) -> bool {
-    let complex_indicators = [
-        "refactor", "implement", "create a", "build", "develop", 
-        "comprehensive", "analysis", "strategy", "plan", "design",
-        "multiple", "all", "entire", "complete", "full"
-    ];
-    
-    let user_prompt_lower = user_prompt.to_lowercase();
-    complex_indicators.iter().any(|indicator| user_prompt_lower.contains(indicator))
-        || user_prompt.len() > 100 // Long prompts tend to be complex
+fn is_complex_task(user_prompt: &str, rules: &PlanningRules) -> bool {
+    contains_any(&rules.keywords, user_prompt)
+        || user_prompt.len() > rules.long_prompt_length // Long prompts tend to be complex
+}
+
+/// A module registered by an embedder, shared across compositions
+pub struct SharedModule(pub Arc<dyn PromptModule>);
+
+impl PromptModule for SharedModule {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn generate_content(&self, tools: &[Tool], session_state: &SessionState, loader: &mut PromptLoader) -> Result<String, PromptError> {
+        self.0.generate_content(tools, session_state, loader)
+    }
+
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        self.0.applies_to(tools, user_prompt, session_state)
+    }
+
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> f32 {
+        self.0.relevance_score(tools, user_prompt, session_state)
+    }
+
+    fn requires(&self, session_state: &SessionState) -> &[&'static str] {
+        self.0.requires(session_state)
+    }
+
+    fn conflicts_with(&self) -> &[&'static str] {
+        self.0.conflicts_with()
+    }
+
+    fn on_session_start(&self, state: &mut ModuleState) {
+        self.0.on_session_start(state)
+    }
+
+    fn on_session_end(&self, state: &mut ModuleState) {
+        self.0.on_session_end(state)
+    }
+
+    fn generate_stateful(
+        &self,
+        tools: &[Tool],
+        session_state: &SessionState,
+        loader: &mut PromptLoader,
+        state: &mut ModuleState
+    ) -> Result<String, PromptError> {
+        self.0.generate_stateful(tools, session_state, loader, state)
+    }
+}
+
+/// Explicit module hints from the request; absent hints fall back to auto-detection
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SelectionHints<'a> {
+    pub domains: Option<&'a [String]>,
+    pub behaviors: Option<&'a [String]>,
 }
 
 /// Module selector that determines which modules to apply
@@ -323,81 +1037,639 @@ impl ModuleSelector {
         user_prompt: &str, 
         session_state: &SessionState,
         domain_hints: Option<&[String]>,
-        behavior_hints: Option<&[String]>
+        behavior_hints: Option<&[String]>,
+        complexity: &TaskComplexity,
+        rules: &SelectionRules
     ) -> Vec<Box<dyn PromptModule>> {
+        let (modules, _trace, _warnings) = Self::select_modules_traced(
+            tools,
+            user_prompt,
+            session_state,
+            SelectionHints { domains: domain_hints, behaviors: behavior_hints },
+            complexity,
+            rules,
+            &ModuleRegistry::new()
+        );
+        modules
+    }
+
+    /// Select modules like `select_modules` from the modules in a registry, and report why
+    /// each module was or was not selected, along with settings that named modules which
+    /// do not exist or are disabled. Disabled modules are never selected.
+    ///
+    /// Registered modules are selected directly when named in a hint, and are otherwise
+    /// ranked with the auto-detected behavior modules.
+    pub fn select_modules_traced(
+        tools: &[Tool], 
+        user_prompt: &str, 
+        session_state: &SessionState,
+        hints: SelectionHints,
+        complexity: &TaskComplexity,
+        rules: &SelectionRules,
+        registry: &ModuleRegistry
+    ) -> (Vec<Box<dyn PromptModule>>, Vec<ModuleDecision>, Vec<Warning>) {
+        let mut trace = vec![];
+        let mut warnings = vec![];
+        // Non-English prompts also match their language's domain keywords
+        let history = rules.history.recent_text(session_state);
+        let localized = match &history {
+            Some(history) => rules.localized(&format!("{}\n{}", history, user_prompt)),
+            None => rules.localized(user_prompt),
+        };
+        let rules = localized.as_ref();
+
         let mut modules: Vec<Box<dyn PromptModule>> = vec![];
+        let mut domain_candidates: Vec<Box<dyn PromptModule>> = vec![];
+        let mut behavior_candidates: Vec<Box<dyn PromptModule>> = vec![];
         
         // Always include tool usage if we have tools
         if !tools.is_empty() {
-            modules.push(Box::new(ToolUsageModule));
+            if let Some(module) = registry.create("tool_usage", rules, complexity) {
+                modules.push(module);
+                trace.push(ModuleDecision::selected("tool_usage", DecisionSource::Detected, None, "tools are available"));
+            }
         }
         
         // Handle explicit domain hints first
-        if let Some(domains) = domain_hints {
+        if let Some(domains) = hints.domains {
             for domain in domains {
-                match domain.as_str() {
-                    "filesystem" => modules.push(Box::new(FilesystemModule)),
-                    "programming" => modules.push(Box::new(ProgrammingModule)),
-                    "analysis" => modules.push(Box::new(AnalysisModule)),
-                    "system" => modules.push(Box::new(SystemModule)),
-                    _ => {
+                match Self::hinted_module(registry, domain, ModuleKind::Domain, complexity, rules) {
+                    Ok(module) => {
                         // For unknown domains, create a generic domain module
-                        modules.push(Box::new(GenericDomainModule::new(domain.clone())));
+                        let module = module.unwrap_or_else(|| Box::new(GenericDomainModule::new(domain.clone())));
+                        let reason = format!("domain hint '{}'", domain);
+                        trace.push(ModuleDecision::selected(module.name(), DecisionSource::Hint, None, &reason));
+                        modules.push(module);
                     }
+                    Err(name) => trace.push(ModuleDecision::rejected(&name, DecisionSource::Hint, None, "disabled in the registry")),
                 }
             }
-        } else {
-            // Fall back to auto-detection for domain modules
-            let filesystem_module = FilesystemModule;
-            if filesystem_module.applies_to(tools, user_prompt, session_state) {
-                modules.push(Box::new(filesystem_module));
-            }
-            
-            let programming_module = ProgrammingModule;
-            if programming_module.applies_to(tools, user_prompt, session_state) {
-                modules.push(Box::new(programming_module));
-            }
-            
-            let analysis_module = AnalysisModule;
-            if analysis_module.applies_to(tools, user_prompt, session_state) {
-                modules.push(Box::new(analysis_module));
-            }
-            
-            let system_module = SystemModule;
-            if system_module.applies_to(tools, user_prompt, session_state) {
-                modules.push(Box::new(system_module));
-            }
+        } else if *complexity != TaskComplexity::Trivial {
+            // Fall back to auto-detection for domain modules; trivial tasks get none
+            domain_candidates = registry.candidates(ModuleKind::Domain, rules, complexity);
         }
         
         // Handle explicit behavior hints
-        if let Some(behaviors) = behavior_hints {
+        let behavior_section_start = modules.len();
+        if let Some(behaviors) = hints.behaviors {
             for behavior in behaviors {
-                match behavior.as_str() {
-                    "planning" => {
-                        modules.push(Box::new(TaskPlanningModule));
-                    },
-                    "progress" => {
-                        modules.push(Box::new(ProgressMonitoringModule));
-                    },
-                    _ => {
+                match Self::hinted_module(registry, behavior, ModuleKind::Behavior, complexity, rules) {
+                    Ok(module) => {
                         // For unknown behaviors, create a generic behavior module
-                        modules.push(Box::new(GenericBehaviorModule::new(behavior.clone())));
+                        let module = module.unwrap_or_else(|| Box::new(GenericBehaviorModule::new(behavior.clone())));
+                        let reason = format!("behavior hint '{}'", behavior);
+                        trace.push(ModuleDecision::selected(module.name(), DecisionSource::Hint, None, &reason));
+                        modules.push(module);
                     }
+                    Err(name) => trace.push(ModuleDecision::rejected(&name, DecisionSource::Hint, None, "disabled in the registry")),
                 }
             }
         } else {
             // Fall back to auto-detection for behavioral modules
-            let planning_module = TaskPlanningModule;
-            if planning_module.applies_to(tools, user_prompt, session_state) {
-                modules.push(Box::new(planning_module));
+            behavior_candidates = registry.candidates(ModuleKind::Behavior, rules, complexity);
+        }
+
+        // Registered modules not named in a hint compete with the auto-detected behaviors
+        if hints.behaviors.is_some() {
+            behavior_candidates.extend(
+                registry.candidates(ModuleKind::Behavior, rules, complexity).into_iter()
+                    .filter(|candidate| registry.source(candidate.name()) != Some(ModuleSource::Builtin))
+            );
+        }
+        behavior_candidates.retain(|candidate| !modules.iter().any(|module| module.name() == candidate.name()));
+        
+        // Rank auto-detected candidates; domains go before the behavior section, behaviors after it
+        let ranked = Self::rank_candidates(
+            domain_candidates,
+            behavior_candidates,
+            tools,
+            user_prompt,
+            session_state,
+            rules,
+            &mut trace
+        );
+        let (ranked_domains, ranked_behaviors): (Vec<_>, Vec<_>) = ranked.into_iter()
+            .partition(|(is_domain, _)| *is_domain);
+        modules.splice(
+            behavior_section_start..behavior_section_start,
+            ranked_domains.into_iter().map(|(_, module)| module)
+        );
+        modules.extend(ranked_behaviors.into_iter().map(|(_, module)| module));
+
+        // Apply the policy: force-enabled modules join, then disabled modules are removed
+        for name in &rules.policy.enable {
+            if modules.iter().any(|module| module.name() == name) {
+                continue;
             }
-            
-            let progress_module = ProgressMonitoringModule;
-            if progress_module.applies_to(tools, user_prompt, session_state) {
-                modules.push(Box::new(progress_module));
+            match registry.create(name, rules, complexity) {
+                Some(module) => {
+                    modules.push(module);
+                    trace.push(ModuleDecision::selected(name, DecisionSource::Policy, None, "enabled by policy"));
+                }
+                None => warnings.push(Warning::new(
+                    WarningSeverity::Warning,
+                    "selection",
+                    format!("policy enables unknown or disabled module '{}'", name)
+                )),
             }
         }
-        
+        let mut modules = Self::resolve_relationships(modules, session_state, complexity, rules, registry, &mut trace, &mut warnings);
+        modules.retain(|module| {
+            let disabled = rules.policy.disable.iter().any(|name| name == module.name());
+            if disabled {
+                ModuleDecision::reject(&mut trace, module.name(), DecisionSource::Policy, "disabled by policy");
+            }
+            !disabled
+        });
+
+        (modules, trace, warnings)
+    }
+
+    /// Create the registry module a hint refers to, or `None` for unknown hints. A hint
+    /// naming a disabled module yields that module's name as the error.
+    fn hinted_module(
+        registry: &ModuleRegistry,
+        hint: &str,
+        kind: ModuleKind,
+        complexity: &TaskComplexity,
+        rules: &SelectionRules
+    ) -> Result<Option<Box<dyn PromptModule>>, String> {
+        match registry.find_hint(hint, kind) {
+            Some(info) if !info.enabled => Err(info.name.clone()),
+            Some(info) => Ok(registry.create(&info.name, rules, complexity)),
+            None => Ok(None),
+        }
+    }
+
+    /// Add modules required by the selection and drop modules excluded by it. Required
+    /// modules are placed before the module that needs them; when two selected modules
+    /// conflict, the one earlier in the list wins.
+    fn resolve_relationships(
+        mut modules: Vec<Box<dyn PromptModule>>,
+        session_state: &SessionState,
+        complexity: &TaskComplexity,
+        rules: &SelectionRules,
+        registry: &ModuleRegistry,
+        trace: &mut Vec<ModuleDecision>,
+        warnings: &mut Vec<Warning>
+    ) -> Vec<Box<dyn PromptModule>> {
+        let mut index = 0;
+        while index < modules.len() {
+            let required: Vec<&'static str> = modules[index].requires(session_state).to_vec();
+            for name in required {
+                if modules.iter().any(|module| module.name() == name) {
+                    continue;
+                }
+                match registry.create(name, rules, complexity) {
+                    Some(module) => {
+                        let reason = format!("required by {}", modules[index].name());
+                        trace.push(ModuleDecision::selected(name, DecisionSource::Requirement, None, &reason));
+                        modules.insert(index, module);
+                        index += 1;
+                    }
+                    None => warnings.push(Warning::new(
+                        WarningSeverity::Warning,
+                        "selection",
+                        format!("module '{}' requires unknown or disabled module '{}'", modules[index].name(), name)
+                    )),
+                }
+            }
+            index += 1;
+        }
+
+        let mut excluded: Vec<(&'static str, String)> = vec![];
+        for module in &modules {
+            if !excluded.iter().any(|(name, _)| *name == module.name()) {
+                excluded.extend(module.conflicts_with().iter().map(|name| (*name, module.name().to_string())));
+            }
+        }
+        modules.retain(|module| {
+            match excluded.iter().find(|(name, _)| *name == module.name()) {
+                Some((name, excluded_by)) => {
+                    let reason = format!("conflicts with {}", excluded_by);
+                    ModuleDecision::reject(trace, name, DecisionSource::Requirement, &reason);
+                    false
+                }
+                None => true,
+            }
+        });
+
         modules
     }
+
+    /// Score candidates, drop those below the threshold, keep the best `max_modules` of
+    /// domains and behaviors together, and order each group by descending score (ties
+    /// keep their declaration order).
+    /// Domains may also score against the conversation history, at a reduced weight.
+    fn rank_candidates(
+        domain_candidates: Vec<Box<dyn PromptModule>>,
+        behavior_candidates: Vec<Box<dyn PromptModule>>,
+        tools: &[Tool],
+        user_prompt: &str,
+        session_state: &SessionState,
+        rules: &SelectionRules,
+        trace: &mut Vec<ModuleDecision>
+    ) -> Vec<(bool, Box<dyn PromptModule>)> {
+        let ranking = &rules.ranking;
+        let history = rules.history.recent_text(session_state);
+        let mut scored: Vec<(bool, f32, Box<dyn PromptModule>)> = vec![];
+        let candidates = domain_candidates.into_iter()
+            .map(|module| (true, module))
+            .chain(behavior_candidates.into_iter().map(|module| (false, module)));
+        for (is_domain, module) in candidates {
+            let mut score = module.relevance_score(tools, user_prompt, session_state);
+            if let (true, Some(history)) = (is_domain, history.as_deref()) {
+                let history_score = module.relevance_score(tools, history, session_state) * rules.history.weight;
+                score = score.max(history_score);
+            }
+            let score = score * rules.policy.weight(module.name());
+
+            if score <= 0.0 {
+                trace.push(ModuleDecision::rejected(module.name(), DecisionSource::Detected, Some(score), "not relevant"));
+            } else if score < ranking.min_score {
+                let reason = format!("score below min_score {}", ranking.min_score);
+                trace.push(ModuleDecision::rejected(module.name(), DecisionSource::Detected, Some(score), &reason));
+            } else {
+                scored.push((is_domain, score, module));
+            }
+        }
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let max_modules = ranking.max_modules.unwrap_or(usize::MAX);
+        for (rank, (_, score, module)) in scored.iter().enumerate() {
+            if rank < max_modules {
+                trace.push(ModuleDecision::selected(module.name(), DecisionSource::Detected, Some(*score), "relevant to the request"));
+            } else {
+                let reason = format!("over max_modules {}", max_modules);
+                trace.push(ModuleDecision::rejected(module.name(), DecisionSource::Detected, Some(*score), &reason));
+            }
+        }
+        scored.truncate(max_modules);
+
+        scored.into_iter().map(|(is_domain, _, module)| (is_domain, module)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_error_recovery_applies_to_failure_loops() {
+        let module = ErrorRecoveryModule::default();
+
+        let healthy = SessionState {
+            consecutive_failures: Some(1),
+            ..Default::default()
+        };
+        assert!(!module.applies_to(&[], "", &healthy));
+
+        let failing = SessionState {
+            consecutive_failures: Some(3),
+            ..Default::default()
+        };
+        assert!(module.applies_to(&[], "", &failing));
+
+        let with_errors = SessionState {
+            recent_errors: Some(vec!["ENOENT: no such file".into()]),
+            ..Default::default()
+        };
+        assert!(module.applies_to(&[], "", &with_errors));
+    }
+
+    #[test]
+    fn test_structured_session_state_targets_guidance() {
+        let state: SessionState = serde_json::from_value(serde_json::json!({
+            "recent_errors": ["timeout", {"tool": "write_file", "message": "EACCES"}],
+            "files_modified": ["src/main.rs"],
+        })).unwrap();
+        assert_eq!(state.recent_errors.as_ref().unwrap()[1].to_string(), "write_file: EACCES");
+
+        let tools = vec![Tool {
+            name: "write_file".to_string(),
+            description: String::new(),
+            server: "fs".to_string(),
+            schema: None,
+        }];
+        let mut loader = PromptLoader::new(None);
+        let content = FilesystemModule.generate_content(&tools, &state, &mut loader).unwrap();
+        assert!(content.contains("re-read them before editing again:\n- src/main.rs"));
+
+        let content = ErrorRecoveryModule::default().generate_content(&tools, &state, &mut loader).unwrap();
+        assert!(content.contains("- timeout\n- write_file: EACCES\n"));
+    }
+
+    #[test]
+    fn test_memory_skips_blank_entries_and_keeps_one_bullet_each() {
+        let blank = SessionState {
+            memories: Some(vec!["".into(), "  \n".into()]),
+            ..Default::default()
+        };
+        assert!(!MemoryModule.applies_to(&[], "", &blank));
+
+        let state = SessionState {
+            memories: Some(vec![" \t".into(), "Prefers tabs\nover spaces".into(), "Uses vim".into()]),
+            ..Default::default()
+        };
+        assert!(MemoryModule.applies_to(&[], "", &state));
+
+        let mut loader = PromptLoader::new(None);
+        let content = MemoryModule.generate_content(&[], &state, &mut loader).unwrap();
+        assert!(content.contains("previous sessions:\n- Prefers tabs over spaces\n- Uses vim\n\n"));
+    }
+
+    #[test]
+    fn test_checkpoint_applies_from_tool_call_threshold() {
+        let module = CheckpointModule::new(ThresholdRules { tool_call_threshold: 20 });
+        let calls = |count| SessionState {
+            tool_call_count: Some(count),
+            ..Default::default()
+        };
+
+        assert!(!module.applies_to(&[], "", &calls(19)));
+        assert!(module.applies_to(&[], "", &calls(20)));
+
+        let long_running = SessionState {
+            is_long_running: Some(true),
+            ..Default::default()
+        };
+        assert!(module.applies_to(&[], "", &long_running));
+
+        let mut loader = PromptLoader::new(None);
+        let content = module.generate_content(&[], &calls(20), &mut loader).unwrap();
+        assert!(content.contains("(20 tool calls so far)"));
+    }
+
+    #[test]
+    fn test_agent_coordination_follows_agent_role() {
+        let role = |agent_role| SessionState {
+            agent_role,
+            ..Default::default()
+        };
+        let orchestrator = role(Some(AgentRole::Orchestrator));
+        let worker = role(Some(AgentRole::Worker));
+        let mut loader = PromptLoader::new(None);
+
+        let content = AgentCoordinationModule.generate_content(&[], &orchestrator, &mut loader).unwrap();
+        assert!(content.starts_with("\nORCHESTRATING SUB-AGENTS:\n"));
+        assert_eq!(AgentCoordinationModule.requires(&orchestrator), &["task_planning"]);
+
+        let content = AgentCoordinationModule.generate_content(&[], &worker, &mut loader).unwrap();
+        assert!(content.starts_with("\nWORKING AS A SUB-AGENT:\n"));
+        assert!(AgentCoordinationModule.requires(&worker).is_empty());
+
+        let unassigned = role(None);
+        assert!(!AgentCoordinationModule.applies_to(&[], "Split up the work", &unassigned));
+        assert!(AgentCoordinationModule.requires(&unassigned).is_empty());
+        assert_eq!(AgentCoordinationModule.generate_content(&[], &unassigned, &mut loader).unwrap(), "");
+    }
+
+    #[test]
+    fn test_safety_applies_to_destructive_tools() {
+        let module = SafetyModule::new(SafetyRules {
+            destructive_keywords: vec!["delete".to_string()],
+        });
+        let tool = |name: &str| Tool {
+            name: name.to_string(),
+            description: String::new(),
+            server: "fs".to_string(),
+            schema: None,
+        };
+
+        assert!(!module.applies_to(&[tool("fs.read_file")], "", &SessionState::default()));
+        assert!(module.applies_to(&[tool("fs.delete_file")], "", &SessionState::default()));
+    }
+
+    #[test]
+    fn test_safety_matches_keywords_as_words_of_the_tool_name() {
+        let module = SafetyModule::default();
+        let tool = |name: &str, description: &str| Tool {
+            name: name.to_string(),
+            description: description.to_string(),
+            server: "crm".to_string(),
+            schema: None,
+        };
+        let applies = |tool: Tool| module.applies_to(&[tool], "", &SessionState::default());
+
+        assert!(!applies(tool("crm.credit_score", "Look up a customer's credit")));
+        assert!(!applies(tool("crm.list-skills", "List the skills of an agent")));
+        assert!(!applies(tool("crm.dropdownOptions", "")));
+        assert!(applies(tool("crm.edit_note", "")));
+        assert!(applies(tool("os.kill-process", "")));
+        assert!(applies(tool("crm.deleteContact", "")));
+        assert!(applies(tool("crm.archive", "Removes the contact and drops its history")));
+    }
+
+    #[test]
+    fn test_phase_gates_behavior_modules() {
+        let complex_prompt = "Refactor the entire codebase";
+
+        let planning = SessionState {
+            phase: Some(ConversationPhase::Planning),
+            ..Default::default()
+        };
+        assert!(TaskPlanningModule::default().applies_to(&[], "Fix the typo", &planning));
+
+        let execution = SessionState {
+            phase: Some(ConversationPhase::Execution),
+            tool_call_count: Some(10),
+            ..Default::default()
+        };
+        assert!(!TaskPlanningModule::default().applies_to(&[], complex_prompt, &execution));
+        assert!(ProgressMonitoringModule::default().applies_to(&[], complex_prompt, &execution));
+
+        let review = SessionState {
+            phase: Some(ConversationPhase::Review),
+            tool_call_count: Some(10),
+            ..Default::default()
+        };
+        assert!(!ProgressMonitoringModule::default().applies_to(&[], complex_prompt, &review));
+        assert!(VerificationModule.applies_to(&[], complex_prompt, &review));
+    }
+
+    #[test]
+    fn test_ranking_caps_and_orders_modules() {
+        let mut rules = SelectionRules::default();
+        rules.ranking.max_modules = Some(1);
+
+        let modules = ModuleSelector::select_modules(
+            &[],
+            "Analyze the csv data and report trends for the server",
+            &SessionState::default(),
+            None,
+            None,
+            &TaskComplexity::Simple,
+            &rules
+        );
+
+        let names: Vec<&str> = modules.iter().map(|m| m.name()).collect();
+        assert_eq!(names, vec!["analysis"]);
+    }
+
+    #[test]
+    fn test_domains_and_behaviors_share_max_modules() {
+        let mut rules: SelectionRules = toml::from_str(r#"
+            [[modules]]
+            name = "helm"
+            kind = "Domain"
+            keywords = ["helm", "chart"]
+
+            [[modules]]
+            name = "terraform"
+            kind = "Domain"
+            keywords = ["terraform"]
+
+            [[modules]]
+            name = "changelog"
+            kind = "Behavior"
+            keywords = ["changelog", "release"]
+
+            [[modules]]
+            name = "rollback"
+            kind = "Behavior"
+            keywords = ["rollback"]
+        "#).unwrap();
+        rules.ranking.max_modules = Some(2);
+        let mut registry = ModuleRegistry::new();
+        for declared in &rules.modules {
+            let module = Arc::new(DeclarativeModule::new(declared.clone()));
+            registry.register_as(module, declared.kind, ModuleSource::Config).unwrap();
+        }
+
+        let (modules, trace, _warnings) = ModuleSelector::select_modules_traced(
+            &[], "helm chart terraform changelog release rollback", &SessionState::default(),
+            SelectionHints::default(), &TaskComplexity::Simple, &rules, &registry
+        );
+
+        let names: Vec<&str> = modules.iter().map(|m| m.name()).collect();
+        assert_eq!(names, vec!["helm", "changelog"]);
+        for name in ["terraform", "rollback"] {
+            let decision = trace.iter().find(|decision| decision.module == name).unwrap();
+            assert!(!decision.selected);
+            assert_eq!(decision.reason, "over max_modules 2");
+        }
+    }
+
+    #[test]
+    fn test_recent_messages_keep_domain_active() {
+        let state = SessionState {
+            recent_messages: Some(vec![
+                "The python function in parser.rs panics on empty input".to_string(),
+                "I see the bug".to_string(),
+            ]),
+            ..Default::default()
+        };
+
+        let tools = vec![Tool {
+            name: "fs.read_file".to_string(),
+            description: "Read a file".to_string(),
+            server: "fs".to_string(),
+            schema: None,
+        }];
+        let select = |state: &SessionState| {
+            ModuleSelector::select_modules(&tools, "now do it", state, None, None, &TaskComplexity::Simple, &SelectionRules::default())
+                .iter()
+                .map(|m| m.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(select(&state).contains(&"programming".to_string()));
+        assert!(!select(&SessionState::default()).contains(&"programming".to_string()));
+    }
+
+    #[test]
+    fn test_module_requirements_and_conflicts() {
+        let orchestrator = SessionState {
+            agent_role: Some(AgentRole::Orchestrator),
+            ..Default::default()
+        };
+        let select = |hints: Option<&[String]>| {
+            ModuleSelector::select_modules(&[], "Split up the work", &orchestrator, None, hints, &TaskComplexity::Simple, &SelectionRules::default())
+                .iter()
+                .map(|m| m.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(select(None), vec!["task_planning", "agent_coordination"]);
+
+        let hints = vec!["concise".to_string(), "agent_coordination".to_string()];
+        assert_eq!(select(Some(&hints)), vec!["concise", "agent_coordination"]);
+    }
+
+    #[test]
+    fn test_declarative_module_triggers() {
+        let rules: SelectionRules = toml::from_str(r#"
+            [[modules]]
+            name = "kubernetes"
+            kind = "Domain"
+            keywords = ["kubernetes", "helm"]
+            phases = ["Execution"]
+        "#).unwrap();
+        let mut registry = ModuleRegistry::new();
+        for declared in &rules.modules {
+            let module = Arc::new(DeclarativeModule::new(declared.clone()));
+            registry.register_as(module, declared.kind, ModuleSource::Config).unwrap();
+        }
+        let select = |phase: ConversationPhase| {
+            let state = SessionState { phase: Some(phase), ..Default::default() };
+            let (modules, _trace, _warnings) = ModuleSelector::select_modules_traced(
+                &[], "Upgrade the helm chart", &state, SelectionHints::default(), &TaskComplexity::Simple, &rules, &registry
+            );
+            modules.iter().map(|m| m.name().to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(select(ConversationPhase::Execution), vec!["kubernetes"]);
+        assert!(select(ConversationPhase::Review).iter().all(|name| name != "kubernetes"));
+    }
+
+    #[test]
+    fn test_policy_enables_disables_and_reweights() {
+        let rules = SelectionRules::default();
+        let prompt = "Analyze the csv data and report trends for the server";
+        let select = |rules: &SelectionRules| {
+            ModuleSelector::select_modules(&[], prompt, &SessionState::default(), None, None, &TaskComplexity::Simple, rules)
+                .iter()
+                .map(|m| m.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(&rules), vec!["analysis", "system"]);
+
+        let policy = SelectionPolicy {
+            enable: vec!["memory".to_string(), "safety".to_string()],
+            disable: vec!["memory".to_string()],
+            weights: HashMap::from([("analysis".to_string(), 0.4), ("system".to_string(), 2.0)]),
+        };
+        assert_eq!(select(&rules.with_policy(Some(&policy))), vec!["system", "safety"]);
+    }
+
+    #[test]
+    fn test_complexity_level_gates_planning_and_scales_progress() {
+        let state = SessionState {
+            tool_call_count: Some(8),
+            ..Default::default()
+        };
+
+        let simple = TaskPlanningModule::default().with_complexity(TaskComplexity::Simple);
+        let moderate = TaskPlanningModule::default().with_complexity(TaskComplexity::Moderate);
+        assert!(!simple.applies_to(&[], "Create a new file", &state));
+        assert!(moderate.applies_to(&[], "Tidy up", &state));
+
+        let progress = |level| ProgressMonitoringModule::default().with_complexity(level);
+        assert!(progress(TaskComplexity::Simple).applies_to(&[], "", &state));
+        assert!(!progress(TaskComplexity::Complex).applies_to(&[], "", &state));
+    }
+
+    #[test]
+    fn test_web_research_applies_to_web_tools() {
+        let web_tool = Tool {
+            name: "web.fetch_url".to_string(),
+            description: "Fetch content from web URLs".to_string(),
+            server: "web".to_string(),
+            schema: None,
+        };
+
+        assert!(WebResearchModule::default().applies_to(&[web_tool], "Summarize this page", &SessionState::default()));
+        assert!(!WebResearchModule::default().applies_to(&[], "Search for the latest release", &SessionState::default()));
+    }
 }
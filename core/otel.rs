@@ -0,0 +1,102 @@
+//! OpenTelemetry export of the crate's spans, with the `otel` feature. The `compose`,
+//! `generate_module`, `discover_tools` and `load_prompt` spans, under the
+//! `prompt_request` span carrying the request's trace ID, are sent over OTLP/HTTP, so
+//! the composer's share of an assistant's latency shows up beside the model calls.
+//!
+//! The binaries export when `OTEL_EXPORTER_OTLP_ENDPOINT` or
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is set. Hosts with their own `tracing`
+//! subscriber add [`layer`] to it.
+
+use crate::types::PromptError;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Service name reported unless `OTEL_SERVICE_NAME` is set
+pub const SERVICE_NAME: &str = "prompt-composer";
+
+/// A provider exporting spans in batches to `endpoint`, or to the endpoint of the
+/// `OTEL_EXPORTER_OTLP_*` variables when `None`. Shut it down to flush the last batch.
+pub fn tracer_provider(endpoint: Option<&str>) -> Result<SdkTracerProvider, PromptError> {
+    let mut exporter = SpanExporter::builder().with_http();
+    if let Some(endpoint) = endpoint {
+        exporter = exporter.with_endpoint(endpoint);
+    }
+    let exporter = exporter.build()
+        .map_err(|e| PromptError::config(format!("Failed to create the OTLP exporter: {}", e)))?;
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name(SERVICE_NAME);
+    }
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build())
+}
+
+/// The provider of [`tracer_provider`] when an OTLP endpoint variable is set
+pub fn tracer_provider_from_env() -> Result<Option<SdkTracerProvider>, PromptError> {
+    let configured = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|variable| std::env::var_os(variable).is_some_and(|value| !value.is_empty()));
+    match configured {
+        true => tracer_provider(None).map(Some),
+        false => Ok(None),
+    }
+}
+
+/// A layer sending the crate's spans to `provider`, whatever level the host's other
+/// layers log at
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(SERVICE_NAME))
+        .with_filter(Targets::new().with_target("prompt_composer", Level::DEBUG))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+    use crate::types::PromptRequest;
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Debug, Default, Clone)]
+    struct Collected(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for Collected {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            crate::lock(&self.0).extend(batch);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_composition_spans_are_exported() {
+        let exporter = Collected::default();
+        let provider = SdkTracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement the migration")
+            .trace_id("trace-7")
+            .build()
+            .unwrap();
+        tracing::subscriber::with_default(subscriber, || PromptComposer::embedded().compose(&request, &[]).unwrap());
+
+        let spans = crate::lock(&exporter.0);
+        let names: Vec<&str> = spans.iter().map(|span| span.name.as_ref()).collect();
+        assert!(names.contains(&"compose") && names.contains(&"generate_module") && names.contains(&"load_prompt"));
+        let request_span = spans.iter().find(|span| span.name == "prompt_request").unwrap();
+        assert!(request_span.attributes.iter().any(|attribute| attribute.key.as_str() == "trace_id" && attribute.value.as_str() == "trace-7"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Prompt files from somewhere other than a directory on disk.
+//!
+//! A `PromptLoader` or `PromptComposer` created with `with_provider` reads every prompt
+//! file, and `selection_rules.toml`, through a [`PromptProvider`] instead of the file
+//! system, so bindings, tests and serverless deployments can supply prompt content
+//! directly. `HashMap<String, String>` is the in-memory provider, and
+//! [`crate::embedded::EmbeddedPrompts`] provides the prompts compiled into the library.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Reported as the prompts directory of loaders that read files held in memory
+pub const MEMORY_PROMPTS_DIR: &str = "<memory>";
+
+/// A set of prompt files addressed by their path relative to a prompts directory, with
+/// `/` separators: `behaviors/planning.md`, `tools/filesystem@v2.md`,
+/// `selection_rules.toml`
+pub trait PromptProvider: Send + Sync {
+    /// Reported in place of the prompts directory, e.g. `<memory>`
+    fn name(&self) -> &str;
+
+    /// Content of a file, or `None` when the provider does not have it
+    fn read(&self, path: &str) -> Option<Cow<'_, str>>;
+
+    /// Paths of every file the provider has, in any order
+    fn paths(&self) -> Vec<String>;
+}
+
+/// Files held in memory, keyed by path
+impl PromptProvider for HashMap<String, String> {
+    fn name(&self) -> &str {
+        MEMORY_PROMPTS_DIR
+    }
+
+    fn read(&self, path: &str) -> Option<Cow<'_, str>> {
+        self.get(path).map(|content| Cow::Borrowed(content.as_str()))
+    }
+
+    fn paths(&self) -> Vec<String> {
+        self.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+    use crate::types::PromptRequest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serves one behavior and counts the reads that reach it
+    struct CountingProvider {
+        reads: AtomicUsize,
+    }
+
+    impl PromptProvider for CountingProvider {
+        fn name(&self) -> &str {
+            "<counting>"
+        }
+
+        fn read(&self, path: &str) -> Option<Cow<'_, str>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            match path {
+                "behaviors/planning.md" => Some(Cow::Borrowed("## Plan\n- from the provider")),
+                "selection_rules.toml" => Some(Cow::Borrowed("# Defaults only\n")),
+                _ => None,
+            }
+        }
+
+        fn paths(&self) -> Vec<String> {
+            vec!["behaviors/planning.md".to_string(), "selection_rules.toml".to_string()]
+        }
+    }
+
+    #[test]
+    fn test_composer_reads_prompts_from_a_provider() {
+        let provider = Arc::new(CountingProvider { reads: AtomicUsize::new(0) });
+        let mut composer = PromptComposer::with_provider(provider.clone());
+        assert_eq!(composer.prompts_dir(), "<counting>");
+        assert_eq!(composer.list_behaviors().unwrap(), vec!["planning"]);
+        assert!(composer.list_domains().unwrap().is_empty());
+
+        let request = PromptRequest::builder()
+            .user_prompt("Plan the migration")
+            .behavior_hint("planning")
+            .build()
+            .unwrap();
+        let response = composer.compose(&request, &[]).unwrap();
+        assert!(response.system_prompt.contains("from the provider"));
+        assert!(provider.reads.load(Ordering::SeqCst) > 1);
+    }
+}
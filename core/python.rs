@@ -1,18 +1,215 @@
+// pyo3 0.20 expands magic methods such as __getattr__ into impls inside functions
+#![allow(non_local_definitions)]
+
 use pyo3::prelude::*;
-use pyo3::exceptions::PyRuntimeError;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyAttributeError, PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::hooks::CompositionHook;
+use crate::loader::PromptLoader;
+use crate::modules::PromptModule;
 use crate::types::*;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A prompt module implemented by Python callables.
+///
+/// The callables receive the request context as a dict with `tools`, `user_prompt` and
+/// `session_state`; `generate_content` returns the section text and the optional
+/// `applies_to` returns whether the module applies (it always applies when omitted).
+struct PythonModule {
+    name: String,
+    generate_content: PyObject,
+    applies_to: Option<PyObject>,
+}
+
+impl PythonModule {
+    fn call(&self, callable: &PyObject, context: serde_json::Value) -> PyResult<PyObject> {
+        Python::with_gil(|py| {
+            let context = py.import("json")?.call_method1("loads", (context.to_string(),))?;
+            callable.call1(py, (context,))
+        })
+    }
+
+    fn context(tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> serde_json::Value {
+        serde_json::json!({
+            "tools": tools,
+            "user_prompt": user_prompt,
+            "session_state": session_state,
+        })
+    }
+}
+
+impl PromptModule for PythonModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generate_content(&self, tools: &[Tool], session_state: &SessionState, _loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let context = Self::context(tools, "", session_state);
+        self.call(&self.generate_content, context)
+            .and_then(|content| Python::with_gil(|py| content.extract::<String>(py)))
+            .map_err(|e| PromptError::module_loading(format!("Python module {} failed: {}", self.name, e)).with_module(&self.name))
+    }
+
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        let Some(ref applies_to) = self.applies_to else {
+            return true;
+        };
+        let context = Self::context(tools, user_prompt, session_state);
+        match self.call(applies_to, context).and_then(|result| Python::with_gil(|py| result.is_true(py))) {
+            Ok(applies) => applies,
+            Err(e) => {
+                tracing::warn!(module = %self.name, "Python applies_to failed: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Register a module implemented in Python for all subsequent compositions
+#[pyfunction]
+#[pyo3(signature = (name, generate_content, applies_to=None))]
+fn register_module(name: &str, generate_content: PyObject, applies_to: Option<PyObject>) -> PyResult<()> {
+    crate::register_module(Arc::new(PythonModule {
+        name: name.to_string(),
+        generate_content,
+        applies_to,
+    }));
+    Ok(())
+}
+
+/// Remove a registered module, returning whether it was registered
+#[pyfunction]
+fn unregister_module(name: &str) -> PyResult<bool> {
+    Ok(crate::unregister_module(name))
+}
+
+/// A composition hook implemented by Python callables.
+///
+/// `prologue` and `epilogue` receive the `PromptRequest` and return text to place before
+/// or after the composed modules, or None; `on_compose` receives the `PromptRequest`
+/// and `PromptResponse` of each successful composition.
+struct PythonHook {
+    name: String,
+    prologue: Option<PyObject>,
+    epilogue: Option<PyObject>,
+    on_compose: Option<PyObject>,
+}
+
+impl PythonHook {
+    fn section(&self, callable: &Option<PyObject>, request: &PromptRequest) -> Result<Option<String>, PromptError> {
+        let Some(callable) = callable else {
+            return Ok(None);
+        };
+        Python::with_gil(|py| {
+            let request = Py::new(py, PyPromptRequest { inner: request.clone() })?;
+            callable.call1(py, (request,))?.extract::<Option<String>>(py)
+        })
+        .map_err(|e| PromptError::module_loading(format!("Python hook {} failed: {}", self.name, e)).with_module(&self.name))
+    }
+}
+
+impl CompositionHook for PythonHook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn prologue(&self, request: &PromptRequest) -> Result<Option<String>, PromptError> {
+        self.section(&self.prologue, request)
+    }
+
+    fn epilogue(&self, request: &PromptRequest) -> Result<Option<String>, PromptError> {
+        self.section(&self.epilogue, request)
+    }
+
+    fn on_compose(&self, request: &PromptRequest, response: &PromptResponse) {
+        let Some(ref on_compose) = self.on_compose else {
+            return;
+        };
+        let result = Python::with_gil(|py| {
+            let request = Py::new(py, PyPromptRequest { inner: request.clone() })?;
+            let response = Py::new(py, PyPromptResponse { inner: response.clone() })?;
+            on_compose.call1(py, (request, response)).map(drop)
+        });
+        if let Err(e) = result {
+            tracing::warn!(hook = %self.name, "Python on_compose failed: {}", e);
+        }
+    }
+}
+
+/// Run a prologue, epilogue or observer in all subsequent compositions; a hook
+/// registered under an existing name replaces it
+#[pyfunction]
+#[pyo3(signature = (name, prologue=None, epilogue=None, on_compose=None))]
+fn register_hook(name: &str, prologue: Option<PyObject>, epilogue: Option<PyObject>, on_compose: Option<PyObject>) -> PyResult<()> {
+    crate::register_hook(Arc::new(PythonHook { name: name.to_string(), prologue, epilogue, on_compose }));
+    Ok(())
+}
+
+/// Remove a registered hook, returning whether it was registered
+#[pyfunction]
+fn unregister_hook(name: &str) -> PyResult<bool> {
+    Ok(crate::unregister_hook(name))
+}
+
+fn composition_error(e: PromptError) -> PyErr {
+    prompt_error("Prompt composition failed", e)
+}
+
+// Raised for composition failures; all derive from RuntimeError, which was raised
+// before failures had classes of their own
+create_exception!(system_prompt_composer, PromptComposerError, PyRuntimeError, "A prompt composition failure");
+create_exception!(system_prompt_composer, ConfigError, PromptComposerError, "Invalid configuration, such as a missing prompts directory");
+create_exception!(system_prompt_composer, ModuleLoadingError, PromptComposerError, "A module or its prompt file failed to load");
+create_exception!(system_prompt_composer, DiscoveryError, PromptComposerError, "Discovering the tools of an MCP server failed");
+create_exception!(system_prompt_composer, InvalidRequestError, PromptComposerError, "A request field has a value no host should send");
+
+/// Raise a failure as the exception class for its kind, carrying `code` (e.g.
+/// "ConfigError") and `details`, a dict with the code, message and the server, module,
+/// path or field involved
+fn prompt_error(context: &str, e: PromptError) -> PyErr {
+    let message = format!("{}: {}", context, e);
+    let err = match e.code() {
+        ErrorCode::ConfigError => ConfigError::new_err(message),
+        ErrorCode::ModuleLoadingFailed => ModuleLoadingError::new_err(message),
+        ErrorCode::McpConnectionFailed | ErrorCode::ToolDiscoveryFailed | ErrorCode::HttpError => DiscoveryError::new_err(message),
+        ErrorCode::InvalidRequest => InvalidRequestError::new_err(message),
+        ErrorCode::SerializationError | ErrorCode::Cancelled => PromptComposerError::new_err(message),
+    };
+    Python::with_gil(|py| {
+        let report = serde_json::to_string(&e.report()).unwrap_or_default();
+        let attached = py.import("json")
+            .and_then(|json| json.call_method1("loads", (report,)))
+            .and_then(|details| {
+                let value = err.value(py);
+                value.setattr("code", details.get_item("code")?)?;
+                value.setattr("details", details)
+            });
+        if let Err(attach_error) = attached {
+            tracing::warn!("Failed to attach error details: {}", attach_error);
+        }
+    });
+    err
+}
+
+fn invalid_json(e: serde_json::Error) -> PyErr {
+    prompt_error("Invalid JSON request", PromptError::invalid_request(e.to_string()))
+}
 
 /// Python wrapper for the prompt composition functionality
 #[pyfunction]
-fn compose_system_prompt(request_json: &str) -> PyResult<String> {
+fn compose_system_prompt(py: Python, request_json: &str) -> PyResult<String> {
     let request: PromptRequest = serde_json::from_str(request_json)
-        .map_err(|e| PyRuntimeError::new_err(format!("Invalid JSON request: {}", e)))?;
+        .map_err(invalid_json)?;
     
-    let rt = tokio::runtime::Runtime::new()
+    let rt = crate::runtime()
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to create async runtime: {}", e)))?;
     
-    let response = rt.block_on(crate::compose_system_prompt(request))
-        .map_err(|e| PyRuntimeError::new_err(format!("Prompt composition failed: {}", e)))?;
+    let response = py.allow_threads(|| rt.block_on(crate::compose_system_prompt(request)))
+        .map_err(composition_error)?;
     
     serde_json::to_string(&response)
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to serialize response: {}", e)))
@@ -20,15 +217,15 @@ fn compose_system_prompt(request_json: &str) -> PyResult<String> {
 
 /// Python wrapper for the prompt composition with custom prompts directory
 #[pyfunction]
-fn compose_system_prompt_with_prompts_dir(request_json: &str, prompts_dir: &str) -> PyResult<String> {
+fn compose_system_prompt_with_prompts_dir(py: Python, request_json: &str, prompts_dir: &str) -> PyResult<String> {
     let request: PromptRequest = serde_json::from_str(request_json)
-        .map_err(|e| PyRuntimeError::new_err(format!("Invalid JSON request: {}", e)))?;
+        .map_err(invalid_json)?;
     
-    let rt = tokio::runtime::Runtime::new()
+    let rt = crate::runtime()
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to create async runtime: {}", e)))?;
     
-    let response = rt.block_on(crate::compose_system_prompt_with_prompts_dir(request, Some(prompts_dir.to_string())))
-        .map_err(|e| PyRuntimeError::new_err(format!("Prompt composition failed: {}", e)))?;
+    let response = py.allow_threads(|| rt.block_on(crate::compose_system_prompt_with_prompts_dir(request, Some(prompts_dir.to_string()))))
+        .map_err(composition_error)?;
     
     serde_json::to_string(&response)
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to serialize response: {}", e)))
@@ -36,12 +233,12 @@ fn compose_system_prompt_with_prompts_dir(request_json: &str, prompts_dir: &str)
 
 /// Python wrapper for cached prompt composition (faster, synchronous)
 #[pyfunction]
-fn compose_system_prompt_cached(request_json: &str) -> PyResult<String> {
+fn compose_system_prompt_cached(py: Python, request_json: &str) -> PyResult<String> {
     let request: PromptRequest = serde_json::from_str(request_json)
-        .map_err(|e| PyRuntimeError::new_err(format!("Invalid JSON request: {}", e)))?;
+        .map_err(invalid_json)?;
     
-    let response = crate::compose_system_prompt_cached(request)
-        .map_err(|e| PyRuntimeError::new_err(format!("Prompt composition failed: {}", e)))?;
+    let response = py.allow_threads(|| crate::compose_system_prompt_cached(request))
+        .map_err(composition_error)?;
     
     serde_json::to_string(&response)
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to serialize response: {}", e)))
@@ -49,33 +246,589 @@ fn compose_system_prompt_cached(request_json: &str) -> PyResult<String> {
 
 /// Python wrapper for cached prompt composition with custom prompts directory
 #[pyfunction]
-fn compose_system_prompt_cached_with_prompts_dir(request_json: &str, prompts_dir: &str) -> PyResult<String> {
+fn compose_system_prompt_cached_with_prompts_dir(py: Python, request_json: &str, prompts_dir: &str) -> PyResult<String> {
     let request: PromptRequest = serde_json::from_str(request_json)
-        .map_err(|e| PyRuntimeError::new_err(format!("Invalid JSON request: {}", e)))?;
+        .map_err(invalid_json)?;
     
-    let response = crate::compose_system_prompt_cached_with_prompts_dir(request, Some(prompts_dir.to_string()))
-        .map_err(|e| PyRuntimeError::new_err(format!("Prompt composition failed: {}", e)))?;
+    let response = py.allow_threads(|| crate::compose_system_prompt_cached_with_prompts_dir(request, Some(prompts_dir.to_string())))
+        .map_err(composition_error)?;
     
     serde_json::to_string(&response)
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to serialize response: {}", e)))
 }
 
+/// A serde type exposed to Python as a class with one attribute per serialized field
+trait PyFields: Serialize + DeserializeOwned + JsonSchema {
+    /// Python attribute names that differ from the serialized field names
+    const RENAMES: &'static [(&'static str, &'static str)] = &[];
+
+    /// Fields filled in when a keyword constructor leaves them out
+    fn constructor_defaults() -> serde_json::Map<String, serde_json::Value> {
+        serde_json::Map::new()
+    }
+
+    /// Attributes returned as one of the typed classes rather than as plain data
+    fn typed_attribute(&self, _py: Python, _name: &str) -> Option<PyResult<PyObject>> {
+        None
+    }
+
+    fn field_name(attribute: &str) -> &str {
+        Self::RENAMES.iter()
+            .find(|(python, _)| *python == attribute)
+            .map_or(attribute, |(_, field)| field)
+    }
+
+    fn attribute_names() -> Vec<String> {
+        let schema = schemars::schema_for!(Self);
+        schema.schema.object.map(|object| {
+            object.properties.keys()
+                .map(|field| Self::RENAMES.iter()
+                    .find(|(_, renamed)| renamed == field)
+                    .map_or(field.clone(), |(python, _)| python.to_string()))
+                .collect()
+        }).unwrap_or_default()
+    }
+
+    fn check_attribute(attribute: &str) -> PyResult<()> {
+        if Self::attribute_names().iter().any(|name| name == attribute) {
+            Ok(())
+        } else {
+            Err(PyAttributeError::new_err(format!("no attribute '{}'", attribute)))
+        }
+    }
+}
+
+impl PyFields for SessionState {}
+
+impl PyFields for McpConfig {
+    const RENAMES: &'static [(&'static str, &'static str)] = &[("mcp_servers", "mcpServers")];
+
+    fn constructor_defaults() -> serde_json::Map<String, serde_json::Value> {
+        serde_json::Map::from_iter([("mcpServers".to_string(), serde_json::json!({}))])
+    }
+}
+
+impl PyFields for PromptRequest {
+    fn constructor_defaults() -> serde_json::Map<String, serde_json::Value> {
+        serde_json::Map::from_iter([("mcp_config".to_string(), serde_json::json!({"mcpServers": {}}))])
+    }
+
+    fn typed_attribute(&self, py: Python, name: &str) -> Option<PyResult<PyObject>> {
+        match name {
+            "mcp_config" => Some(Py::new(py, PyMcpConfig { inner: self.mcp_config.clone() }).map(|config| config.into_py(py))),
+            "session_state" => Some(match &self.session_state {
+                Some(state) => Py::new(py, PySessionState { inner: state.clone() }).map(|state| state.into_py(py)),
+                None => Ok(py.None()),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl PyFields for PromptResponse {
+    fn typed_attribute(&self, py: Python, name: &str) -> Option<PyResult<PyObject>> {
+        match name {
+            "selection_trace" => Some(decisions_to_py(py, &self.selection_trace)),
+            _ => None,
+        }
+    }
+}
+
+impl PyFields for ModuleDecision {}
+
+fn decisions_to_py(py: Python, decisions: &[ModuleDecision]) -> PyResult<PyObject> {
+    let decisions = decisions.iter()
+        .map(|decision| Py::new(py, PyModuleDecision { inner: decision.clone() }))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(decisions.into_py(py))
+}
+
+/// Convert a Python value to JSON; instances of the typed classes convert to their fields
+fn py_to_json(value: &PyAny) -> PyResult<serde_json::Value> {
+    use serde_json::Value;
+
+    if value.is_none() {
+        return Ok(Value::Null);
+    }
+    if let Ok(flag) = value.downcast::<PyBool>() {
+        return Ok(Value::Bool(flag.is_true()));
+    }
+    if value.is_instance_of::<PyLong>() {
+        return match value.extract::<i64>() {
+            Ok(number) => Ok(Value::from(number)),
+            Err(_) => Ok(Value::from(value.extract::<u64>()?)),
+        };
+    }
+    if let Ok(number) = value.downcast::<PyFloat>() {
+        return serde_json::Number::from_f64(number.value())
+            .map(Value::Number)
+            .ok_or_else(|| PyValueError::new_err("NaN and infinite numbers are not supported"));
+    }
+    if let Ok(text) = value.downcast::<PyString>() {
+        return Ok(Value::String(text.to_str()?.to_string()));
+    }
+    if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut map = serde_json::Map::new();
+        for (key, item) in dict {
+            map.insert(key.extract::<String>()?, py_to_json(item)?);
+        }
+        return Ok(Value::Object(map));
+    }
+    if let Ok(list) = value.downcast::<PyList>() {
+        return list.iter().map(py_to_json).collect::<PyResult<Vec<_>>>().map(Value::Array);
+    }
+    if let Ok(tuple) = value.downcast::<PyTuple>() {
+        return tuple.iter().map(py_to_json).collect::<PyResult<Vec<_>>>().map(Value::Array);
+    }
+    if let Ok(state) = value.extract::<PyRef<PySessionState>>() {
+        return to_json_value(&state.inner);
+    }
+    if let Ok(config) = value.extract::<PyRef<PyMcpConfig>>() {
+        return to_json_value(&config.inner);
+    }
+    if let Ok(request) = value.extract::<PyRef<PyPromptRequest>>() {
+        return to_json_value(&request.inner);
+    }
+    if let Ok(response) = value.extract::<PyRef<PyPromptResponse>>() {
+        return to_json_value(&response.inner);
+    }
+    Err(PyTypeError::new_err(format!("cannot convert {} to JSON", value.get_type().name()?)))
+}
+
+fn to_json_value(value: &impl Serialize) -> PyResult<serde_json::Value> {
+    serde_json::to_value(value).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Convert JSON to plain Python values: dicts, lists, strings, numbers, booleans and None
+fn json_to_py(py: Python, value: &serde_json::Value) -> PyResult<PyObject> {
+    use serde_json::Value;
+
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(flag) => flag.into_py(py),
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(signed), _) => signed.into_py(py),
+            (None, Some(unsigned)) => unsigned.into_py(py),
+            _ => number.as_f64().unwrap_or_default().into_py(py),
+        },
+        Value::String(text) => text.into_py(py),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into_py(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, json_to_py(py, item)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+fn from_json_value<T: DeserializeOwned>(value: serde_json::Value, what: &str) -> PyResult<T> {
+    serde_json::from_value(value).map_err(|e| PyValueError::new_err(format!("Invalid {}: {}", what, e)))
+}
+
+/// Build a value from a dict shaped like its JSON form, filling in constructor defaults
+fn from_data<T: PyFields>(data: &PyAny, what: &str) -> PyResult<T> {
+    let mut json = py_to_json(data)?;
+    if let Some(fields) = json.as_object_mut() {
+        for (field, default) in T::constructor_defaults() {
+            fields.entry(field).or_insert(default);
+        }
+    }
+    from_json_value(json, what)
+}
+
+/// Build a value from constructor keywords, named after its Python attributes
+fn from_kwargs<T: PyFields>(kwargs: Option<&PyDict>, what: &str) -> PyResult<T> {
+    let mut fields = T::constructor_defaults();
+    for (key, value) in kwargs.into_iter().flatten() {
+        let attribute: String = key.extract()?;
+        if T::check_attribute(&attribute).is_err() {
+            return Err(PyTypeError::new_err(format!("{}() got an unexpected keyword argument '{}'", what, attribute)));
+        }
+        fields.insert(T::field_name(&attribute).to_string(), py_to_json(value)?);
+    }
+    from_json_value(serde_json::Value::Object(fields), what)
+}
+
+fn get_attribute<T: PyFields>(py: Python, value: &T, attribute: &str) -> PyResult<PyObject> {
+    T::check_attribute(attribute)?;
+    if let Some(typed) = value.typed_attribute(py, attribute) {
+        return typed;
+    }
+    let json = to_json_value(value)?;
+    match json.get(T::field_name(attribute)) {
+        Some(field) => json_to_py(py, field),
+        None => Ok(py.None()),
+    }
+}
+
+/// Replace one field, keeping the old value when the new one does not fit the field's type
+fn set_attribute<T: PyFields>(value: &mut T, attribute: &str, new_value: &PyAny) -> PyResult<()> {
+    T::check_attribute(attribute)?;
+    let mut json = to_json_value(value)?;
+    if let Some(fields) = json.as_object_mut() {
+        fields.insert(T::field_name(attribute).to_string(), py_to_json(new_value)?);
+    }
+    *value = from_json_value(json, attribute)?;
+    Ok(())
+}
+
+/// Define a Python class around a serde type: keyword constructor, one attribute per
+/// field, and conversions to and from dicts and JSON
+macro_rules! py_fields_class {
+    ($wrapper:ident, $inner:ty, $name:literal) => {
+        #[pyclass(name = $name, module = "system_prompt_composer")]
+        #[derive(Clone)]
+        struct $wrapper {
+            inner: $inner,
+        }
+
+        #[pymethods]
+        impl $wrapper {
+            #[new]
+            #[pyo3(signature = (**fields))]
+            fn new(fields: Option<&PyDict>) -> PyResult<Self> {
+                Ok(Self { inner: from_kwargs(fields, $name)? })
+            }
+
+            /// Build from a dict shaped like the JSON form
+            #[staticmethod]
+            fn from_dict(data: &PyAny) -> PyResult<Self> {
+                Ok(Self { inner: from_data(data, $name)? })
+            }
+
+            #[staticmethod]
+            fn from_json(json: &str) -> PyResult<Self> {
+                let inner = serde_json::from_str(json)
+                    .map_err(|e| PyValueError::new_err(format!("Invalid {}: {}", $name, e)))?;
+                Ok(Self { inner })
+            }
+
+            fn to_dict(&self, py: Python) -> PyResult<PyObject> {
+                json_to_py(py, &to_json_value(&self.inner)?)
+            }
+
+            fn to_json(&self) -> PyResult<String> {
+                serde_json::to_string(&self.inner).map_err(|e| PyValueError::new_err(e.to_string()))
+            }
+
+            fn __getattr__(&self, py: Python, attribute: &str) -> PyResult<PyObject> {
+                get_attribute(py, &self.inner, attribute)
+            }
+
+            fn __setattr__(&mut self, attribute: &str, value: &PyAny) -> PyResult<()> {
+                set_attribute(&mut self.inner, attribute, value)
+            }
+
+            fn __dir__(&self) -> Vec<String> {
+                let mut names = <$inner as PyFields>::attribute_names();
+                names.extend(["from_dict", "from_json", "to_dict", "to_json"].map(String::from));
+                names
+            }
+
+            fn __eq__(&self, other: &PyAny) -> PyResult<bool> {
+                Ok(match other.extract::<PyRef<$wrapper>>() {
+                    Ok(other) => to_json_value(&self.inner)? == to_json_value(&other.inner)?,
+                    Err(_) => false,
+                })
+            }
+
+            fn __repr__(&self) -> PyResult<String> {
+                Ok(format!("{}({})", $name, self.to_json()?))
+            }
+        }
+    };
+}
+
+py_fields_class!(PySessionState, SessionState, "SessionState");
+py_fields_class!(PyMcpConfig, McpConfig, "McpConfig");
+py_fields_class!(PyPromptRequest, PromptRequest, "PromptRequest");
+py_fields_class!(PyPromptResponse, PromptResponse, "PromptResponse");
+py_fields_class!(PyModuleDecision, ModuleDecision, "ModuleDecision");
+
+/// A `PromptRequest` argument, or a dict shaped like one
+fn extract_request(request: &PyAny) -> PyResult<PromptRequest> {
+    match request.extract::<PyRef<PyPromptRequest>>() {
+        Ok(request) => Ok(request.inner.clone()),
+        Err(_) => from_data(request, "PromptRequest"),
+    }
+}
+
+/// Compose a system prompt from a `PromptRequest` (or a dict shaped like one)
+#[pyfunction]
+#[pyo3(signature = (request, prompts_dir=None))]
+fn compose(py: Python, request: &PyAny, prompts_dir: Option<String>) -> PyResult<PyPromptResponse> {
+    let request = extract_request(request)?;
+
+    let rt = crate::runtime()
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to create async runtime: {}", e)))?;
+
+    let response = py.allow_threads(|| rt.block_on(crate::compose_system_prompt_with_prompts_dir(request, prompts_dir)))
+        .map_err(composition_error)?;
+    Ok(PyPromptResponse { inner: response })
+}
+
+/// Decide which modules a request would get without composing it, as the
+/// `ModuleDecision`s a composition reports in `selection_trace`
+#[pyfunction]
+#[pyo3(signature = (request, prompts_dir=None))]
+fn explain(py: Python, request: &PyAny, prompts_dir: Option<String>) -> PyResult<PyObject> {
+    let request = extract_request(request)?;
+    let decisions = py.allow_threads(|| crate::explain_selection(&request, prompts_dir))
+        .map_err(|e| prompt_error("Explaining selection failed", e))?;
+    decisions_to_py(py, &decisions)
+}
+
+/// A composer with its own prompt file cache, tool discovery cache and session state,
+/// for servers that keep tenants apart. Modules and hooks registered with
+/// `register_module` and `register_hook` before it was created take part in its
+/// compositions; those registered on the composer apply to it alone.
+///
+/// Composition and discovery run without the GIL, so threads sharing a composer wait
+/// only for each other, not for the interpreter.
+#[pyclass(name = "PromptComposer", module = "system_prompt_composer", subclass)]
+struct PyPromptComposer {
+    composer: Mutex<crate::PromptComposer>,
+    discovery: crate::discovery::ToolDiscovery,
+    runtime: &'static tokio::runtime::Runtime,
+}
+
+impl PyPromptComposer {
+    fn composer(&self) -> MutexGuard<'_, crate::PromptComposer> {
+        self.composer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn discovery(&self) -> &crate::discovery::ToolDiscovery {
+        &self.discovery
+    }
+
+    fn with_composer(composer: crate::PromptComposer) -> PyResult<Self> {
+        let runtime = crate::runtime()
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to create async runtime: {}", e)))?;
+        let discovery = match composer.prompts_dir() {
+            crate::embedded::PROMPTS_DIR | crate::loader::MEMORY_PROMPTS_DIR => crate::discovery::ToolDiscovery::embedded(),
+            dir => crate::discovery::ToolDiscovery::with_prompts_dir(dir.to_string()),
+        };
+        Ok(Self { composer: Mutex::new(composer), discovery, runtime })
+    }
+
+    /// Tools of the request's MCP servers: discovered, or inferred by offline composers
+    fn tools(&self, mcp_config: &McpConfig) -> Result<(Vec<Tool>, DiscoveryMode), PromptError> {
+        if self.composer().is_offline() {
+            return Ok((self.discovery().infer_tools_from_config_immediate(mcp_config), DiscoveryMode::Offline));
+        }
+        let tools = self.runtime.block_on(self.discovery().discover_tools(mcp_config))?;
+        Ok((tools, DiscoveryMode::Live))
+    }
+}
+
+#[pymethods]
+impl PyPromptComposer {
+    #[new]
+    #[pyo3(signature = (prompts_dir=None))]
+    fn new(prompts_dir: Option<String>) -> PyResult<Self> {
+        let mut composer = match prompts_dir {
+            Some(dir) => crate::PromptComposer::with_prompts_dir(dir),
+            None => crate::PromptComposer::new(),
+        };
+        composer.registry_mut().merge(&crate::registered_modules());
+        composer.hooks_mut().merge(&crate::registered_hooks());
+        Self::with_composer(composer)
+    }
+
+    /// Snapshot of this composer's prompts location, selection rules and module states as
+    /// JSON, for `import_config` in this or another process
+    fn export_config(&self, py: Python) -> PyResult<String> {
+        py.allow_threads(|| self.composer().export_config())
+            .map_err(|e| prompt_error("Exporting configuration failed", e))
+    }
+
+    /// Create a composer from a snapshot made by `export_config`; modules and hooks
+    /// registered in code are not part of it and are registered again by the caller
+    #[staticmethod]
+    fn import_config(py: Python, config: &str) -> PyResult<Self> {
+        let mut composer = py.allow_threads(|| crate::PromptComposer::import_config(config))
+            .map_err(|e| prompt_error("Importing configuration failed", e))?;
+        crate::adopt_registered(&mut composer);
+        Self::with_composer(composer)
+    }
+
+    /// Directory the prompt files are read from
+    #[getter]
+    fn prompts_dir(&self, py: Python) -> String {
+        py.allow_threads(|| self.composer().prompts_dir().to_string())
+    }
+
+    /// Compose a system prompt from a `PromptRequest` (or a dict shaped like one)
+    fn compose(&self, py: Python, request: &PyAny) -> PyResult<PyPromptResponse> {
+        let start_time = std::time::Instant::now();
+        let request = extract_request(request)?;
+        let (mut response, discovery) = py.allow_threads(|| {
+            let (tools, discovery) = self.tools(&request.mcp_config)?;
+            Ok::<_, PromptError>((self.composer().compose(&request, &tools)?, discovery))
+        })
+        .map_err(composition_error)?;
+        response.metadata.duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+        response.metadata.discovery = discovery;
+        Ok(PyPromptResponse { inner: response })
+    }
+
+    /// Decide which modules a request would get without composing it or touching
+    /// session state
+    fn explain(&self, py: Python, request: &PyAny) -> PyResult<PyObject> {
+        let request = extract_request(request)?;
+        let decisions = py.allow_threads(|| {
+            let (tools, _) = self.tools(&request.mcp_config)?;
+            self.composer().explain(&request, &tools)
+        })
+        .map_err(|e| prompt_error("Explaining selection failed", e))?;
+        decisions_to_py(py, &decisions)
+    }
+
+    /// Discover one server's tools again, bypassing this composer's cache
+    fn refresh_server(&self, py: Python, server_name: &str, mcp_config: &PyAny) -> PyResult<PyObject> {
+        let mcp_config: McpConfig = match mcp_config.extract::<PyRef<PyMcpConfig>>() {
+            Ok(config) => config.inner.clone(),
+            Err(_) => from_data(mcp_config, "McpConfig")?,
+        };
+        let tools = py.allow_threads(|| self.runtime.block_on(self.discovery().refresh_server(server_name, &mcp_config)))
+            .map_err(|e| prompt_error("Tool refresh failed", e))?;
+        json_to_py(py, &to_json_value(&tools)?)
+    }
+
+    fn list_domains(&self, py: Python) -> PyResult<Vec<String>> {
+        py.allow_threads(|| self.composer().list_domains())
+            .map_err(|e| prompt_error("Listing domains failed", e))
+    }
+
+    fn list_behaviors(&self, py: Python) -> PyResult<Vec<String>> {
+        py.allow_threads(|| self.composer().list_behaviors())
+            .map_err(|e| prompt_error("Listing behaviors failed", e))
+    }
+
+    /// MCP servers with tool instructions in the prompts directory
+    fn list_tools(&self, py: Python) -> PyResult<Vec<String>> {
+        py.allow_threads(|| self.composer().list_tools())
+            .map_err(|e| prompt_error("Listing tools failed", e))
+    }
+
+    /// Whether the prompts directory has tool instructions for an MCP server
+    fn has_tool_prompt(&self, py: Python, server: &str) -> bool {
+        py.allow_threads(|| self.composer().has_tool_prompt(server))
+    }
+
+    /// Content of a prompt file in "domains", "behaviors" or "tools", as composition
+    /// reads it
+    fn prompt_content(&self, py: Python, category: &str, name: &str) -> PyResult<String> {
+        py.allow_threads(|| self.composer().prompt_content(category, name))
+            .map_err(|e| prompt_error("Reading prompt failed", e))
+    }
+
+    /// Forget cached prompt files and discovered tools, so changes on disk take effect
+    fn clear_cache(&self, py: Python) {
+        py.allow_threads(|| {
+            self.composer().clear_cache();
+            self.discovery().clear_cache();
+        })
+    }
+
+    /// Entries, approximate bytes and hit rates of this composer's prompt file and tool
+    /// caches, as a dict with `prompts` and `tools`
+    fn cache_stats(&self, py: Python) -> PyResult<PyObject> {
+        let stats = py.allow_threads(|| CacheStats {
+            prompts: self.composer().cache_usage(),
+            tools: self.discovery().cache_usage(),
+        });
+        json_to_py(py, &to_json_value(&stats)?)
+    }
+
+    /// End a session, calling `on_session_end` on its modules; returns whether it existed
+    fn end_session(&self, py: Python, session_id: &str) -> bool {
+        py.allow_threads(|| self.composer().end_session(session_id))
+    }
+
+    /// Register a module implemented in Python for this composer's compositions
+    #[pyo3(signature = (name, generate_content, applies_to=None))]
+    fn register_module(&self, py: Python, name: &str, generate_content: PyObject, applies_to: Option<PyObject>) {
+        let module = Arc::new(PythonModule {
+            name: name.to_string(),
+            generate_content,
+            applies_to,
+        });
+        py.allow_threads(|| self.composer().register_module(module))
+    }
+
+    fn unregister_module(&self, py: Python, name: &str) -> bool {
+        py.allow_threads(|| self.composer().unregister_module(name))
+    }
+
+    /// Run a prologue, epilogue or observer in this composer's compositions
+    #[pyo3(signature = (name, prologue=None, epilogue=None, on_compose=None))]
+    fn register_hook(&self, py: Python, name: &str, prologue: Option<PyObject>, epilogue: Option<PyObject>, on_compose: Option<PyObject>) {
+        let hook = Arc::new(PythonHook { name: name.to_string(), prologue, epilogue, on_compose });
+        py.allow_threads(|| self.composer().hooks_mut().add(hook))
+    }
+
+    fn unregister_hook(&self, py: Python, name: &str) -> bool {
+        py.allow_threads(|| self.composer().hooks_mut().remove(name))
+    }
+
+    /// Names of the hooks this composer runs, in order
+    fn hooks(&self, py: Python) -> Vec<String> {
+        py.allow_threads(|| self.composer().hooks().names())
+    }
+}
+
+/// Read an MCP client's configuration into an `McpConfig`: "claude_desktop", "cursor" or
+/// "vscode" for the client's own file, or the path of a file in the `{"mcpServers": {...}}`
+/// or `{"servers": {...}}` format
+#[pyfunction]
+fn load_mcp_config(source: std::path::PathBuf) -> PyResult<PyMcpConfig> {
+    let config = match source.to_str() {
+        Some(source) => McpConfig::load(source),
+        None => McpConfig::from_file(&source),
+    };
+    config
+        .map(|inner| PyMcpConfig { inner })
+        .map_err(|e| prompt_error("Loading MCP config failed", e))
+}
+
 /// Python wrapper for refreshing server tools
 #[pyfunction]
-fn refresh_server_tools(server_name: &str, mcp_config_json: &str) -> PyResult<String> {
+fn refresh_server_tools(py: Python, server_name: &str, mcp_config_json: &str) -> PyResult<String> {
     let mcp_config: McpConfig = serde_json::from_str(mcp_config_json)
-        .map_err(|e| PyRuntimeError::new_err(format!("Invalid MCP config JSON: {}", e)))?;
+        .map_err(|e| prompt_error("Invalid MCP config JSON", PromptError::config(e.to_string())))?;
     
-    let rt = tokio::runtime::Runtime::new()
+    let rt = crate::runtime()
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to create async runtime: {}", e)))?;
     
-    let tools = rt.block_on(crate::refresh_server_tools(server_name, &mcp_config))
-        .map_err(|e| PyRuntimeError::new_err(format!("Tool refresh failed: {}", e)))?;
+    let tools = py.allow_threads(|| rt.block_on(crate::refresh_server_tools(server_name, &mcp_config)))
+        .map_err(|e| prompt_error("Tool refresh failed", e))?;
     
     serde_json::to_string(&tools)
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to serialize tools: {}", e)))
 }
 
+/// Entries, approximate bytes and hit rates of the caches the module-level functions
+/// share, as a dict with `prompts` and `tools`
+#[pyfunction]
+fn cache_stats(py: Python) -> PyResult<PyObject> {
+    json_to_py(py, &to_json_value(&crate::cache_stats())?)
+}
+
+/// Forget the discovered tools and prompt files the module-level functions share
+#[pyfunction]
+fn clear_cache(py: Python) {
+    py.allow_threads(crate::clear_cache)
+}
+
 /// Python wrapper for getting status information
 #[pyfunction]
 fn get_status() -> PyResult<String> {
@@ -99,6 +852,18 @@ fn get_status() -> PyResult<String> {
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to serialize status: {}", e)))
 }
 
+/// JSON schema of composition requests, as a JSON string
+#[pyfunction]
+fn get_request_schema() -> PyResult<String> {
+    Ok(crate::PromptRequest::json_schema().to_string())
+}
+
+/// JSON schema of composition responses, as a JSON string
+#[pyfunction]
+fn get_response_schema() -> PyResult<String> {
+    Ok(crate::PromptResponse::json_schema().to_string())
+}
+
 /// Test function to verify module registration
 #[pyfunction]
 fn test_tools_feature() -> PyResult<String> {
@@ -114,9 +879,37 @@ pub fn python_module(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(compose_system_prompt_cached, m)?)?;
     m.add_function(wrap_pyfunction!(compose_system_prompt_cached_with_prompts_dir, m)?)?;
     m.add_function(wrap_pyfunction!(refresh_server_tools, m)?)?;
+    m.add_function(wrap_pyfunction!(cache_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_cache, m)?)?;
+    
+    // Typed API
+    m.add_class::<PySessionState>()?;
+    m.add_class::<PyMcpConfig>()?;
+    m.add_class::<PyPromptRequest>()?;
+    m.add_class::<PyPromptResponse>()?;
+    m.add_class::<PyModuleDecision>()?;
+    m.add_function(wrap_pyfunction!(compose, m)?)?;
+    m.add_function(wrap_pyfunction!(explain, m)?)?;
+    m.add_function(wrap_pyfunction!(load_mcp_config, m)?)?;
+    m.add_class::<PyPromptComposer>()?;
+    
+    // Exceptions
+    m.add("PromptComposerError", _py.get_type::<PromptComposerError>())?;
+    m.add("ConfigError", _py.get_type::<ConfigError>())?;
+    m.add("ModuleLoadingError", _py.get_type::<ModuleLoadingError>())?;
+    m.add("DiscoveryError", _py.get_type::<DiscoveryError>())?;
+    m.add("InvalidRequestError", _py.get_type::<InvalidRequestError>())?;
+    
+    // Custom modules
+    m.add_function(wrap_pyfunction!(register_module, m)?)?;
+    m.add_function(wrap_pyfunction!(unregister_module, m)?)?;
+    m.add_function(wrap_pyfunction!(register_hook, m)?)?;
+    m.add_function(wrap_pyfunction!(unregister_hook, m)?)?;
     
-    // Status function
+    // Status and schema functions
     m.add_function(wrap_pyfunction!(get_status, m)?)?;
+    m.add_function(wrap_pyfunction!(get_request_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(get_response_schema, m)?)?;
     
     // Test function
     m.add_function(wrap_pyfunction!(test_tools_feature, m)?)?;
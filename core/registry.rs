@@ -0,0 +1,259 @@
+use crate::modules::*;
+use crate::rules::SelectionRules;
+use crate::types::*;
+use std::sync::Arc;
+
+/// Builds a built-in module for one composition from the active rules
+type BuiltinFactory = fn(&SelectionRules, &TaskComplexity) -> Box<dyn PromptModule>;
+
+#[derive(Clone)]
+enum ModuleFactory {
+    Builtin(BuiltinFactory),
+    Shared(Arc<dyn PromptModule>),
+}
+
+#[derive(Clone)]
+struct RegistryEntry {
+    info: ModuleInfo,
+    /// Hint name accepted in addition to the module name (e.g. "planning" for task_planning)
+    hint: Option<&'static str>,
+    /// Whether the module is considered during auto-detection
+    auto_detect: bool,
+    factory: ModuleFactory,
+}
+
+/// All modules known to a composer, with per-module enabled flags and metadata.
+///
+/// Built-in modules are registered in composition order: domains first, then behaviors.
+/// Modules registered by embedders follow and are ranked with the behaviors.
+#[derive(Clone)]
+pub struct ModuleRegistry {
+    entries: Vec<RegistryEntry>,
+}
+
+impl ModuleRegistry {
+    /// Create a registry holding the built-in modules
+    pub fn new() -> Self {
+        let mut registry = Self { entries: Vec::new() };
+
+        registry.builtin("tool_usage", ModuleKind::Tool, None, false,
+            "How to use the available MCP tools", |_, _| Box::new(ToolUsageModule));
+
+        registry.builtin("filesystem", ModuleKind::Domain, None, true,
+            "Working with files and directories", |_, _| Box::new(FilesystemModule));
+        registry.builtin("programming", ModuleKind::Domain, None, true,
+            "Software development practices", |rules, _| Box::new(ProgrammingModule::new(rules.programming.clone())));
+        registry.builtin("analysis", ModuleKind::Domain, None, true,
+            "Data analysis and reporting", |rules, _| Box::new(AnalysisModule::new(rules.analysis.clone())));
+        registry.builtin("system", ModuleKind::Domain, None, true,
+            "System administration and operations", |rules, _| Box::new(SystemModule::new(rules.system.clone())));
+        registry.builtin("web_research", ModuleKind::Domain, None, true,
+            "Researching and citing web sources", |rules, _| Box::new(WebResearchModule::new(rules.web_research.clone())));
+
+        registry.builtin("task_planning", ModuleKind::Behavior, Some("planning"), true,
+            "Planning complex tasks before acting", |rules, complexity| {
+                Box::new(TaskPlanningModule::new(rules.planning.clone()).with_complexity(complexity.clone()))
+            });
+        registry.builtin("progress_monitoring", ModuleKind::Behavior, Some("progress"), true,
+            "Checking progress during long tool sequences", |rules, complexity| {
+                Box::new(ProgressMonitoringModule::new(rules.progress.clone()).with_complexity(complexity.clone()))
+            });
+        registry.builtin("verification", ModuleKind::Behavior, None, true,
+            "Verifying results in the review phase", |_, _| Box::new(VerificationModule));
+        registry.builtin("checkpoint", ModuleKind::Behavior, None, true,
+            "Summarizing state in long-running sessions", |rules, _| Box::new(CheckpointModule::new(rules.checkpoint.clone())));
+        registry.builtin("error_recovery", ModuleKind::Behavior, None, true,
+            "Recovering from repeated tool failures", |rules, _| Box::new(ErrorRecoveryModule::new(rules.error_recovery.clone())));
+        registry.builtin("agent_coordination", ModuleKind::Behavior, None, true,
+            "Orchestrator and worker roles in multi-agent setups", |_, _| Box::new(AgentCoordinationModule));
+        registry.builtin("memory", ModuleKind::Behavior, None, true,
+            "Applying remembered facts and preferences", |_, _| Box::new(MemoryModule));
+        registry.builtin("safety", ModuleKind::Behavior, None, true,
+            "Confirming destructive operations", |rules, _| Box::new(SafetyModule::new(rules.safety.clone())));
+        registry.builtin("concise", ModuleKind::Behavior, None, false,
+            "Brief responses without planning guidance", |_, _| Box::new(ConciseModule));
+
+        // Built from request fields by the composer; listed so they can be disabled
+        registry.builtin("workspace", ModuleKind::Context, None, false,
+            "Working directory, platform and project from the request's workspace", |_, _| Box::new(WorkspaceModule::new(WorkspaceContext::default())));
+        registry.builtin("output_format", ModuleKind::Format, None, false,
+            "Output shape requested by response_format", |_, _| Box::new(OutputFormatModule::new(ResponseFormat::Json { schema: None })));
+
+        registry
+    }
+
+    fn builtin(
+        &mut self,
+        name: &str,
+        kind: ModuleKind,
+        hint: Option<&'static str>,
+        auto_detect: bool,
+        description: &str,
+        factory: BuiltinFactory
+    ) {
+        self.entries.push(RegistryEntry {
+            info: ModuleInfo {
+                name: name.to_string(),
+                kind,
+                source: ModuleSource::Builtin,
+                enabled: true,
+                description: Some(description.to_string()),
+            },
+            hint,
+            auto_detect,
+            factory: ModuleFactory::Builtin(factory),
+        });
+    }
+
+    /// Register a module implemented outside the crate. It replaces a registered module
+    /// with the same name; built-in modules cannot be replaced.
+    pub fn register(&mut self, module: Arc<dyn PromptModule>, source: ModuleSource) -> Result<(), PromptError> {
+        self.register_as(module, ModuleKind::Behavior, source)
+    }
+
+    /// Register a module like `register`, placing it with modules of the given kind
+    pub fn register_as(&mut self, module: Arc<dyn PromptModule>, kind: ModuleKind, source: ModuleSource) -> Result<(), PromptError> {
+        let name = module.name().to_string();
+        if self.entries.iter().any(|entry| entry.info.name == name && entry.info.source == ModuleSource::Builtin) {
+            return Err(PromptError::config(format!("Module '{}' is built in and cannot be replaced", name)).with_module(name));
+        }
+
+        self.entries.retain(|entry| entry.info.name != name);
+        self.entries.push(RegistryEntry {
+            info: ModuleInfo {
+                name,
+                kind,
+                source,
+                enabled: true,
+                description: None,
+            },
+            hint: None,
+            auto_detect: true,
+            factory: ModuleFactory::Shared(module),
+        });
+        Ok(())
+    }
+
+    /// Remove a registered module, returning whether it was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.info.name != name || entry.info.source == ModuleSource::Builtin);
+        self.entries.len() != before
+    }
+
+    /// Remove every module from the given source
+    pub(crate) fn unregister_source(&mut self, source: ModuleSource) {
+        self.entries.retain(|entry| entry.info.source != source);
+    }
+
+    /// Enable or disable a module, returning whether the module exists
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.info.name == name) {
+            Some(entry) => {
+                entry.info.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take over another registry's registered modules and enabled flags
+    pub fn merge(&mut self, other: &ModuleRegistry) {
+        for entry in &other.entries {
+            if entry.info.source == ModuleSource::Builtin {
+                self.set_enabled(&entry.info.name, entry.info.enabled);
+            } else {
+                self.entries.retain(|existing| existing.info.name != entry.info.name);
+                self.entries.push(entry.clone());
+            }
+        }
+    }
+
+    /// Whether a module is known and enabled; unknown modules count as enabled
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.entries.iter()
+            .find(|entry| entry.info.name == name)
+            .is_none_or(|entry| entry.info.enabled)
+    }
+
+    /// Where a module comes from, if it is known
+    pub fn source(&self, name: &str) -> Option<ModuleSource> {
+        self.entries.iter()
+            .find(|entry| entry.info.name == name)
+            .map(|entry| entry.info.source)
+    }
+
+    /// Name, kind, source and enabled state of every module
+    pub fn list(&self) -> Vec<ModuleInfo> {
+        self.entries.iter().map(|entry| entry.info.clone()).collect()
+    }
+
+    /// Instantiate an enabled module by name. Format and context modules are built from the request
+    /// by the composer, so they cannot be created here.
+    pub(crate) fn create(&self, name: &str, rules: &SelectionRules, complexity: &TaskComplexity) -> Option<Box<dyn PromptModule>> {
+        self.entries.iter()
+            .find(|entry| entry.info.name == name && entry.info.enabled
+                && !matches!(entry.info.kind, ModuleKind::Format | ModuleKind::Context))
+            .map(|entry| Self::instantiate(entry, rules, complexity))
+    }
+
+    /// Find the module a domain or behavior hint refers to. Built-in modules only answer
+    /// hints of their own kind; registered modules answer either.
+    pub(crate) fn find_hint(&self, hint: &str, kind: ModuleKind) -> Option<&ModuleInfo> {
+        self.entries.iter()
+            .find(|entry| match entry.factory {
+                ModuleFactory::Builtin(_) => entry.info.kind == kind && (entry.info.name == hint || entry.hint == Some(hint)),
+                ModuleFactory::Shared(_) => entry.info.name == hint,
+            })
+            .map(|entry| &entry.info)
+    }
+
+    /// Enabled auto-detected modules of a kind, in registration order
+    pub(crate) fn candidates(&self, kind: ModuleKind, rules: &SelectionRules, complexity: &TaskComplexity) -> Vec<Box<dyn PromptModule>> {
+        self.entries.iter()
+            .filter(|entry| entry.info.kind == kind && entry.auto_detect && entry.info.enabled)
+            .map(|entry| Self::instantiate(entry, rules, complexity))
+            .collect()
+    }
+
+    fn instantiate(entry: &RegistryEntry, rules: &SelectionRules, complexity: &TaskComplexity) -> Box<dyn PromptModule> {
+        match &entry.factory {
+            ModuleFactory::Builtin(factory) => factory(rules, complexity),
+            ModuleFactory::Shared(module) => Box::new(SharedModule(module.clone())),
+        }
+    }
+}
+
+impl Default for ModuleRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_lists_and_toggles_modules() {
+        let mut registry = ModuleRegistry::new();
+        let planning = registry.find_hint("planning", ModuleKind::Behavior).unwrap();
+        assert_eq!(planning.name, "task_planning");
+        assert!(registry.find_hint("planning", ModuleKind::Domain).is_none());
+
+        assert!(registry.set_enabled("analysis", false));
+        let rules = SelectionRules::default();
+        let domains: Vec<String> = registry.candidates(ModuleKind::Domain, &rules, &TaskComplexity::Simple)
+            .iter()
+            .map(|module| module.name().to_string())
+            .collect();
+        assert_eq!(domains, vec!["filesystem", "programming", "system", "web_research"]);
+
+        let info = registry.list().into_iter().find(|info| info.name == "analysis").unwrap();
+        assert!(!info.enabled);
+        assert_eq!(info.source, ModuleSource::Builtin);
+
+        assert!(registry.register(Arc::new(ConciseModule), ModuleSource::Registered).is_err());
+        assert!(!registry.unregister("analysis"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Composed prompts as the request bodies of model provider APIs, with the tools in the
+//! provider's tool definition format, for pasting into API calls or test fixtures.
+
+use crate::types::{PromptError, PromptRequest, PromptResponse, Tool};
+use serde_json::{json, Value};
+
+/// Longest tool name the providers accept
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// Provider API a prompt is rendered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderTarget {
+    /// OpenAI Chat Completions: a system message and `function` tools
+    OpenAi,
+    /// Anthropic Messages: a top-level `system` and tools with an `input_schema`
+    Anthropic,
+    /// Ollama `/api/chat`, which takes tools in the OpenAI format
+    Ollama,
+}
+
+impl RenderTarget {
+    /// Look up a target by the name used on the command line: "openai", "anthropic" or
+    /// "ollama"
+    pub fn from_name(name: &str) -> Result<Self, PromptError> {
+        match name {
+            "openai" => Ok(Self::OpenAi),
+            "anthropic" => Ok(Self::Anthropic),
+            "ollama" => Ok(Self::Ollama),
+            _ => Err(PromptError::invalid_request(format!("Unknown render target {}; expected openai, anthropic or ollama", name))
+                .with_field("target")),
+        }
+    }
+}
+
+/// Name of a tool as providers accept it: letters, digits, `_` and `-`, at most 64
+/// characters, so `filesystem.read_file` becomes `filesystem_read_file`
+pub fn tool_name(tool: &Tool) -> String {
+    tool.name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(MAX_TOOL_NAME_LEN)
+        .collect()
+}
+
+/// JSON schema of a tool's input; tools inferred without one take no arguments
+fn parameters(tool: &Tool) -> Value {
+    tool.schema.clone().unwrap_or_else(|| json!({"type": "object", "properties": {}}))
+}
+
+/// The request body for a target: the composed system prompt, the user prompt as the
+/// first user message, and the tools. The body leaves out the model, which the caller
+/// chooses.
+pub fn render(request: &PromptRequest, response: &PromptResponse, tools: &[Tool], target: RenderTarget) -> Value {
+    match target {
+        RenderTarget::OpenAi | RenderTarget::Ollama => {
+            let tools: Vec<Value> = tools.iter()
+                .map(|tool| json!({
+                    "type": "function",
+                    "function": {"name": tool_name(tool), "description": tool.description, "parameters": parameters(tool)},
+                }))
+                .collect();
+            json!({
+                "messages": [
+                    {"role": "system", "content": response.system_prompt},
+                    {"role": "user", "content": request.user_prompt},
+                ],
+                "tools": tools,
+            })
+        }
+        RenderTarget::Anthropic => {
+            let tools: Vec<Value> = tools.iter()
+                .map(|tool| json!({"name": tool_name(tool), "description": tool.description, "input_schema": parameters(tool)}))
+                .collect();
+            json!({
+                "system": response.system_prompt,
+                "messages": [{"role": "user", "content": request.user_prompt}],
+                "tools": tools,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+
+    #[test]
+    fn test_render_formats_tools_per_provider() {
+        let request = PromptRequest::builder().user_prompt("Read the README").build().unwrap();
+        let tools = vec![Tool {
+            name: "filesystem.read_file".to_string(),
+            description: "Read file contents".to_string(),
+            server: "filesystem".to_string(),
+            schema: None,
+        }];
+        let response = PromptComposer::embedded().compose(&request, &tools).unwrap();
+
+        let openai = render(&request, &response, &tools, RenderTarget::OpenAi);
+        assert_eq!(openai["messages"][0]["content"], response.system_prompt.as_str());
+        assert_eq!(openai["messages"][1]["content"], "Read the README");
+        assert_eq!(openai["tools"][0]["function"]["name"], "filesystem_read_file");
+        assert_eq!(openai["tools"][0]["function"]["parameters"]["type"], "object");
+        assert_eq!(render(&request, &response, &tools, RenderTarget::Ollama), openai);
+
+        let anthropic = render(&request, &response, &tools, RenderTarget::Anthropic);
+        assert_eq!(anthropic["system"], response.system_prompt.as_str());
+        assert_eq!(anthropic["tools"][0]["input_schema"]["type"], "object");
+        assert!(RenderTarget::from_name("gemini").is_err());
+    }
+}
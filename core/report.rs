@@ -0,0 +1,257 @@
+//! Composition reports: a composed prompt with its sections, the selection decisions
+//! behind it, token counts and warnings, as Markdown or a standalone HTML page, for
+//! sharing prompt reviews with people who do not read response JSON.
+
+use crate::composition::estimated_tokens;
+use crate::types::{PromptError, PromptResponse, PromptSection};
+use std::fmt::Write;
+
+/// Format of a composition report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// Look up a format by the name used on the command line: "markdown" (or "md") or
+    /// "html"
+    pub fn from_name(name: &str) -> Result<Self, PromptError> {
+        match name {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            _ => Err(PromptError::invalid_request(format!("Unknown report format {}; expected markdown or html", name))
+                .with_field("format")),
+        }
+    }
+}
+
+/// Parts of a report, written out in either format
+enum Block {
+    Heading(u8, String),
+    Paragraph(String),
+    Table(Vec<&'static str>, Vec<Vec<String>>),
+    Code(String),
+}
+
+impl PromptResponse {
+    /// A report of the composition, showing the prompt whole
+    pub fn to_report(&self, format: ReportFormat) -> String {
+        self.to_report_with_sections(&[], format)
+    }
+
+    /// A report of the composition with the prompt broken into the sections a streaming
+    /// composition passed on, each with its token count
+    pub fn to_report_with_sections(&self, sections: &[PromptSection], format: ReportFormat) -> String {
+        let blocks = self.report_blocks(sections);
+        match format {
+            ReportFormat::Markdown => markdown(&blocks),
+            ReportFormat::Html => html(&blocks),
+        }
+    }
+
+    fn report_blocks(&self, sections: &[PromptSection]) -> Vec<Block> {
+        let metadata = &self.metadata;
+        let mut summary = vec![
+            vec!["Estimated tokens".to_string(), estimated_tokens(&self.system_prompt).to_string()],
+            vec!["Characters".to_string(), self.system_prompt.len().to_string()],
+            vec!["Complexity".to_string(), format!("{:?}", self.complexity_assessment)],
+            vec!["Modules applied".to_string(), self.applied_modules.len().to_string()],
+            vec!["Tools recognized".to_string(), self.recognized_tools.len().to_string()],
+            vec!["Tool discovery".to_string(), format!("{:?}", metadata.discovery)],
+            vec!["Duration".to_string(), format!("{:.1} ms", metadata.duration_ms)],
+            vec!["Selection seed".to_string(), self.selection_seed.to_string()],
+            vec!["Content hash".to_string(), metadata.content_hash.clone()],
+            vec!["Composer version".to_string(), metadata.version.clone()],
+        ];
+        if let Some(trace_id) = &metadata.trace_id {
+            summary.push(vec!["Trace ID".to_string(), trace_id.clone()]);
+        }
+        let mut blocks = vec![
+            Block::Heading(1, "Prompt composition report".to_string()),
+            Block::Table(vec!["", ""], summary),
+            Block::Heading(2, "Sections".to_string()),
+        ];
+
+        match sections.is_empty() {
+            true => blocks.push(Block::Table(
+                vec!["Module"],
+                self.applied_modules.iter().map(|module| vec![module.clone()]).collect(),
+            )),
+            false => blocks.push(Block::Table(
+                vec!["Section", "Tokens", "Characters"],
+                sections.iter()
+                    .map(|section| vec![section.module.clone(), estimated_tokens(&section.text).to_string(), section.text.len().to_string()])
+                    .collect(),
+            )),
+        }
+
+        blocks.push(Block::Heading(2, "Selection decisions".to_string()));
+        blocks.push(Block::Table(
+            vec!["Module", "Source", "Selected", "Score", "Reason"],
+            self.selection_trace.iter()
+                .map(|decision| vec![
+                    decision.module.clone(),
+                    format!("{:?}", decision.source),
+                    if decision.selected { "yes" } else { "no" }.to_string(),
+                    decision.score.map(|score| format!("{:.2}", score)).unwrap_or_default(),
+                    decision.reason.clone(),
+                ])
+                .collect(),
+        ));
+
+        if !self.content_versions.is_empty() {
+            blocks.push(Block::Heading(2, "Prompt file versions".to_string()));
+            blocks.push(Block::Table(
+                vec!["File", "Version"],
+                self.content_versions.iter().map(|(file, version)| vec![file.clone(), version.clone()]).collect(),
+            ));
+        }
+
+        if let Some(profile) = &self.profile {
+            blocks.push(Block::Heading(2, "Timing".to_string()));
+            let mut rows = vec![vec!["selection".to_string(), format!("{:.2}", profile.selection_ms), String::new()]];
+            rows.extend(profile.modules.iter().map(|timing| vec![
+                timing.module.clone(),
+                format!("{:.2}", timing.duration_ms),
+                timing.file_loads.iter()
+                    .map(|load| format!("{} {:.2} ms{}", load.file, load.duration_ms, if load.cached { " (cached)" } else { "" }))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ]));
+            blocks.push(Block::Table(vec!["Part", "Milliseconds", "File loads"], rows));
+        }
+
+        blocks.push(Block::Heading(2, "Warnings".to_string()));
+        match self.warnings.is_empty() {
+            true => blocks.push(Block::Paragraph("None".to_string())),
+            false => blocks.push(Block::Table(
+                vec!["Severity", "Source", "Message"],
+                self.warnings.iter()
+                    .map(|warning| vec![format!("{:?}", warning.severity), warning.source.clone(), warning.message.clone()])
+                    .collect(),
+            )),
+        }
+
+        blocks.push(Block::Heading(2, "Prompt".to_string()));
+        match sections.is_empty() {
+            true => blocks.push(Block::Code(self.system_prompt.clone())),
+            false => {
+                for section in sections {
+                    blocks.push(Block::Heading(3, section.module.clone()));
+                    blocks.push(Block::Code(section.text.trim_start_matches('\n').to_string()));
+                }
+            }
+        }
+        blocks
+    }
+}
+
+fn markdown(blocks: &[Block]) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let mut out = String::new();
+    for block in blocks {
+        let _ = match block {
+            Block::Heading(level, text) => writeln!(out, "{} {}\n", "#".repeat(*level as usize), text),
+            Block::Paragraph(text) => writeln!(out, "{}\n", text),
+            Block::Table(headers, rows) => {
+                let _ = writeln!(out, "| {} |", headers.join(" | "));
+                let _ = writeln!(out, "|{}", "---|".repeat(headers.len()));
+                for row in rows {
+                    let row: Vec<String> = row.iter().map(|text| cell(text)).collect();
+                    let _ = writeln!(out, "| {} |", row.join(" | "));
+                }
+                writeln!(out)
+            }
+            Block::Code(text) => {
+                // A fence longer than any run of backticks in the prompt
+                let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest.max(2) + 1);
+                writeln!(out, "{}\n{}\n{}\n", fence, text.trim_end(), fence)
+            }
+        };
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+fn html(blocks: &[Block]) -> String {
+    let mut out = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Prompt composition report</title>\n",
+        "<style>body{font-family:sans-serif;max-width:60em;margin:2em auto}table{border-collapse:collapse}",
+        "td,th{border:1px solid #ccc;padding:.3em .6em;text-align:left;vertical-align:top}",
+        "pre{background:#f6f6f6;padding:1em;white-space:pre-wrap}</style>\n</head>\n<body>\n",
+    ));
+    for block in blocks {
+        let _ = match block {
+            Block::Heading(level, text) => writeln!(out, "<h{0}>{1}</h{0}>", level, escape(text)),
+            Block::Paragraph(text) => writeln!(out, "<p>{}</p>", escape(text)),
+            Block::Table(headers, rows) => {
+                out.push_str("<table>\n");
+                if headers.iter().any(|header| !header.is_empty()) {
+                    let headers: Vec<String> = headers.iter().map(|header| format!("<th>{}</th>", escape(header))).collect();
+                    let _ = writeln!(out, "<tr>{}</tr>", headers.concat());
+                }
+                for row in rows {
+                    let row: Vec<String> = row.iter().map(|text| format!("<td>{}</td>", escape(text))).collect();
+                    let _ = writeln!(out, "<tr>{}</tr>", row.concat());
+                }
+                writeln!(out, "</table>")
+            }
+            Block::Code(text) => writeln!(out, "<pre>{}</pre>", escape(text.trim_end())),
+        };
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::PromptComposer;
+    use crate::types::{PromptRequest, Warning, WarningSeverity};
+
+    #[test]
+    fn test_reports_show_sections_decisions_and_warnings() {
+        let request = PromptRequest::builder()
+            .user_prompt("Plan and implement the migration, then run the tests")
+            .profile(true)
+            .build()
+            .unwrap();
+        let mut sections = Vec::new();
+        let mut response = PromptComposer::embedded()
+            .compose_streaming(&request, &[], |section| sections.push(section))
+            .unwrap();
+        response.warnings.push(Warning::new(WarningSeverity::Warning, "composer", "Left out tool:git to stay within max_tokens 100"));
+
+        let markdown = response.to_report_with_sections(&sections, ReportFormat::Markdown);
+        assert!(markdown.starts_with("# Prompt composition report\n"));
+        assert!(markdown.contains(&format!("| Estimated tokens | {} |", estimated_tokens(&response.system_prompt))));
+        assert!(markdown.contains(&format!("### {}", sections[0].module)));
+        assert!(markdown.contains("| Warning | composer | Left out tool:git to stay within max_tokens 100 |"));
+        let decision = &response.selection_trace[0];
+        assert!(markdown.contains(&format!("| {} | {:?} |", decision.module, decision.source)));
+        assert!(markdown.contains("## Timing\n\n| Part | Milliseconds | File loads |\n|---|---|---|\n| selection |"));
+
+        let html = response.to_report(ReportFormat::Html);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>Selection decisions</h2>"));
+        assert_eq!(escape("<b> & \"c\""), "&lt;b&gt; &amp; &quot;c&quot;");
+        assert!(ReportFormat::from_name("pdf").is_err());
+    }
+}
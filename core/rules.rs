@@ -0,0 +1,692 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use crate::types::*;
+
+/// Keyword and threshold rules that decide which modules are selected.
+///
+/// Loaded from `selection_rules.toml` in the prompts directory so prompt engineers can
+/// tune triggering without rebuilding. Sections missing from the file keep their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelectionRules {
+    pub complexity: ComplexityRules,
+    pub planning: PlanningRules,
+    pub progress: ThresholdRules,
+    pub checkpoint: ThresholdRules,
+    pub error_recovery: ErrorRecoveryRules,
+    pub programming: KeywordRules,
+    pub analysis: KeywordRules,
+    pub system: KeywordRules,
+    pub web_research: KeywordRules,
+    pub safety: SafetyRules,
+    pub ranking: RankingRules,
+    pub history: HistoryRules,
+    /// Host-wide module overrides; requests can add their own on top
+    pub policy: SelectionPolicy,
+    /// Extra domain keywords for non-English prompts, keyed by language code
+    pub languages: BTreeMap<String, LanguageRules>,
+    /// Modules whose content comes from running an external command
+    pub external_modules: Vec<ExternalModuleRules>,
+    /// Modules defined entirely in config: triggers, content file and heading
+    pub modules: Vec<DeclarativeModuleRules>,
+    /// A/B variants per module name; requests that assign none are bucketed by seed
+    pub variants: BTreeMap<String, Vec<String>>,
+}
+
+/// Declaration of a module whose triggers and content are defined in config.
+///
+/// Every trigger that is set must hold for the module to apply; a module without
+/// triggers applies to every request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeclarativeModuleRules {
+    pub name: String,
+    /// Domain modules are placed with the domains, behaviors after them
+    #[serde(default = "default_declarative_kind")]
+    pub kind: ModuleKind,
+    /// File in `domains/` or `behaviors/` (by kind) without extension; defaults to the name
+    #[serde(default)]
+    pub content: Option<String>,
+    /// Guidance in markdown, used instead of a content file
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Section heading; defaults to the upper-cased name
+    #[serde(default)]
+    pub heading: Option<String>,
+    /// Applies when one of these appears in the prompt
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Applies when one of the tools falls in one of these categories
+    #[serde(default)]
+    pub tool_categories: Vec<ToolCategory>,
+    #[serde(default)]
+    pub min_tool_calls: Option<u32>,
+    #[serde(default)]
+    pub min_consecutive_failures: Option<u32>,
+    /// Applies only in these conversation phases
+    #[serde(default)]
+    pub phases: Vec<ConversationPhase>,
+    /// Multiplies the relevance score used for ranking
+    #[serde(default = "default_declarative_priority")]
+    pub priority: f32,
+}
+
+/// A plugin file in `plugins/`: declarative modules in the `[[modules]]` format
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginFile {
+    pub modules: Vec<DeclarativeModuleRules>,
+}
+
+impl PluginFile {
+    /// Read a plugin file
+    pub fn load(path: &Path) -> Result<Self, PromptError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| PromptError::config(format!("Failed to read plugin {:?}: {}", path, e)).with_path(path))?;
+
+        toml::from_str(&content)
+            .map_err(|e| PromptError::config(format!("Failed to parse plugin {:?}: {}", path, e)).with_path(path))
+    }
+}
+
+fn default_declarative_kind() -> ModuleKind {
+    ModuleKind::Behavior
+}
+
+fn default_declarative_priority() -> f32 {
+    1.0
+}
+
+/// Declaration of a module produced by an external command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalModuleRules {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The module applies when one of these appears in the prompt, or always when empty
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default = "default_external_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Fail composition instead of skipping the module when the command fails
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_external_timeout_ms() -> u64 {
+    5000
+}
+
+/// Stopwords that identify a language and the domain keywords added when it is detected
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageRules {
+    pub stopwords: Vec<String>,
+    pub programming: Vec<String>,
+    pub analysis: Vec<String>,
+    pub system: Vec<String>,
+    pub web_research: Vec<String>,
+}
+
+/// Stopwords a prompt must contain before it is attributed to a language
+const MIN_STOPWORD_MATCHES: usize = 2;
+
+/// Rules for automatic task complexity assessment.
+///
+/// Each matching rule adds its weight to a score, and the score is mapped to a level
+/// using the `*_threshold` values. Short prompts with no signals are trivial.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComplexityRules {
+    /// Whole words or phrases, matched case-insensitively on word boundaries
+    pub keywords: Vec<String>,
+    pub keyword_weight: f32,
+    /// Regular expressions with individual weights
+    pub patterns: Vec<WeightedPattern>,
+    /// Prompts longer than this many bytes add `long_prompt_weight`
+    pub long_prompt_length: usize,
+    pub long_prompt_weight: f32,
+    /// Requests with more MCP servers than this add `multiple_servers_weight`
+    pub max_simple_servers: usize,
+    pub multiple_servers_weight: f32,
+    /// Minimum score for each level above Simple
+    pub moderate_threshold: f32,
+    pub complex_threshold: f32,
+    pub epic_threshold: f32,
+    /// Prompts of at most this many bytes with a zero score are trivial
+    pub trivial_prompt_length: usize,
+}
+
+/// A regular expression rule contributing `weight` when it matches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightedPattern {
+    pub pattern: String,
+    #[serde(default = "default_pattern_weight")]
+    pub weight: f32,
+}
+
+fn default_pattern_weight() -> f32 {
+    1.0
+}
+
+/// Complexity rules compiled into regular expressions, ready to evaluate prompts
+#[derive(Debug, Clone)]
+pub struct ComplexityMatcher {
+    keywords: Vec<(String, Regex)>,
+    patterns: Vec<(Regex, f32)>,
+    rules: ComplexityRules,
+}
+
+/// Rules for the task planning module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlanningRules {
+    pub keywords: Vec<String>,
+    /// Prompts longer than this many bytes trigger planning guidance
+    pub long_prompt_length: usize,
+}
+
+/// Keywords that activate a domain module when they appear in the user prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordRules {
+    pub keywords: Vec<String>,
+}
+
+/// Tool call count at which a session-driven module activates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdRules {
+    pub tool_call_threshold: u32,
+}
+
+/// Rules for the error recovery module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErrorRecoveryRules {
+    /// Consecutive failures that activate error recovery guidance
+    pub consecutive_failures_threshold: u32,
+    /// Consecutive failures after which the model should stop and ask the user
+    pub ask_for_help_after_failures: u32,
+}
+
+/// Rules for the destructive-operation safety module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyRules {
+    /// Tools whose name or description contains one of these keywords as a word are treated
+    /// as destructive; see `contains_any_word`
+    pub destructive_keywords: Vec<String>,
+}
+
+/// How auto-detected modules are ranked and capped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingRules {
+    /// Modules scoring below this relevance are not selected
+    pub min_score: f32,
+    /// Maximum number of auto-detected modules, domains and behaviors together: the best
+    /// scoring of either kind are kept. Unlimited when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_modules: Option<usize>,
+}
+
+/// How recent conversation turns contribute to domain selection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryRules {
+    /// Number of most recent messages considered
+    pub max_messages: usize,
+    /// Multiplier applied to a domain's relevance when it only matches the history
+    pub weight: f32,
+}
+
+impl SelectionRules {
+    /// Load rules from `selection_rules.toml` in the prompts directory, falling back to
+    /// the built-in defaults when the file does not exist
+    pub fn load(prompts_dir: &str) -> Result<Self, PromptError> {
+        let rules_path = Path::new(prompts_dir).join("selection_rules.toml");
+        if !rules_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&rules_path)
+            .map_err(|e| PromptError::config(format!("Failed to read selection rules {:?}: {}", rules_path, e)).with_path(&rules_path))?;
+
+        toml::from_str(&content)
+            .map_err(|e| PromptError::config(format!("Failed to parse selection rules {:?}: {}", rules_path, e)).with_path(&rules_path))
+    }
+
+    /// Parse rules from the content of a `selection_rules.toml` that is not on disk
+    pub fn from_toml(content: &str) -> Result<Self, PromptError> {
+        toml::from_str(content)
+            .map_err(|e| PromptError::config(format!("Failed to parse selection rules: {}", e)))
+    }
+
+    /// Detect the prompt language from stopword counts, returning the best configured
+    /// language code or `None` when no language has enough matches
+    pub fn detect_language(&self, text: &str) -> Option<&str> {
+        let text_lower = text.to_lowercase();
+        let words: Vec<&str> = text_lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let mut best: Option<(&str, usize)> = None;
+        for (code, language) in &self.languages {
+            let matches = words.iter()
+                .filter(|word| language.stopwords.iter().any(|stopword| stopword == *word))
+                .count();
+            if matches >= MIN_STOPWORD_MATCHES && best.is_none_or(|(_, best_matches)| matches > best_matches) {
+                best = Some((code.as_str(), matches));
+            }
+        }
+
+        best.map(|(code, _)| code)
+    }
+
+    /// Rules with a request's policy merged over the configured one
+    pub fn with_policy(&self, policy: Option<&SelectionPolicy>) -> Cow<'_, SelectionRules> {
+        match policy {
+            Some(policy) => {
+                let mut rules = self.clone();
+                rules.policy = self.policy.merged_with(policy);
+                Cow::Owned(rules)
+            }
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// Rules with the detected language's domain keywords added to the English ones
+    pub fn localized(&self, text: &str) -> Cow<'_, SelectionRules> {
+        let Some(language) = self.detect_language(text).and_then(|code| self.languages.get(code)) else {
+            return Cow::Borrowed(self);
+        };
+
+        let mut rules = self.clone();
+        rules.programming.keywords.extend(language.programming.iter().cloned());
+        rules.analysis.keywords.extend(language.analysis.iter().cloned());
+        rules.system.keywords.extend(language.system.iter().cloned());
+        rules.web_research.keywords.extend(language.web_research.iter().cloned());
+        Cow::Owned(rules)
+    }
+}
+
+impl ComplexityRules {
+    /// Compile keywords and patterns, failing on invalid regular expressions
+    pub fn compile(&self) -> Result<ComplexityMatcher, PromptError> {
+        let keywords = self.keywords.iter()
+            .map(|keyword| {
+                let pattern = format!(r"(?i)\b{}\b", regex::escape(keyword));
+                Regex::new(&pattern)
+                    .map(|regex| (keyword.clone(), regex))
+                    .map_err(|e| PromptError::config(format!("Invalid complexity keyword {:?}: {}", keyword, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let patterns = self.patterns.iter()
+            .map(|rule| {
+                Regex::new(&format!("(?i){}", rule.pattern))
+                    .map(|regex| (regex, rule.weight))
+                    .map_err(|e| PromptError::config(format!("Invalid complexity pattern {:?}: {}", rule.pattern, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ComplexityMatcher { keywords, patterns, rules: self.clone() })
+    }
+}
+
+impl ComplexityMatcher {
+    /// Assess a prompt's complexity level along with the evidence behind it
+    pub fn assess(&self, user_prompt: &str, server_count: usize) -> (TaskComplexity, ComplexityEvidence) {
+        let evidence = self.evaluate(user_prompt, server_count);
+        let score = evidence.score;
+
+        let level = if score >= self.rules.epic_threshold {
+            TaskComplexity::Epic
+        } else if score >= self.rules.complex_threshold {
+            TaskComplexity::Complex
+        } else if score >= self.rules.moderate_threshold {
+            TaskComplexity::Moderate
+        } else if score == 0.0 && user_prompt.trim().len() <= self.rules.trivial_prompt_length {
+            TaskComplexity::Trivial
+        } else {
+            TaskComplexity::Simple
+        };
+
+        (level, evidence)
+    }
+
+    /// Score a prompt against the rules and collect the signals that matched
+    pub fn evaluate(&self, user_prompt: &str, server_count: usize) -> ComplexityEvidence {
+        let mut signals = Vec::new();
+
+        for (keyword, regex) in &self.keywords {
+            if regex.is_match(user_prompt) {
+                signals.push(ComplexitySignal {
+                    rule: "keyword".to_string(),
+                    matched: keyword.clone(),
+                    weight: self.rules.keyword_weight,
+                });
+            }
+        }
+
+        for (regex, weight) in &self.patterns {
+            if let Some(found) = regex.find(user_prompt) {
+                signals.push(ComplexitySignal {
+                    rule: "pattern".to_string(),
+                    matched: found.as_str().to_string(),
+                    weight: *weight,
+                });
+            }
+        }
+
+        if user_prompt.len() > self.rules.long_prompt_length {
+            signals.push(ComplexitySignal {
+                rule: "long_prompt".to_string(),
+                matched: format!("{} bytes", user_prompt.len()),
+                weight: self.rules.long_prompt_weight,
+            });
+        }
+
+        if server_count > self.rules.max_simple_servers {
+            signals.push(ComplexitySignal {
+                rule: "multiple_servers".to_string(),
+                matched: format!("{} servers", server_count),
+                weight: self.rules.multiple_servers_weight,
+            });
+        }
+
+        ComplexityEvidence {
+            score: signals.iter().map(|signal| signal.weight).sum(),
+            signals,
+        }
+    }
+}
+
+impl KeywordRules {
+    /// Check if any keyword appears in the text (case-insensitive)
+    pub fn matches(&self, text: &str) -> bool {
+        contains_any(&self.keywords, text)
+    }
+
+    /// Count how many distinct keywords appear in the text (case-insensitive)
+    pub fn match_count(&self, text: &str) -> usize {
+        let text_lower = text.to_lowercase();
+        self.keywords.iter().filter(|keyword| text_lower.contains(&keyword.to_lowercase())).count()
+    }
+}
+
+/// Check if any of the keywords appears in the text (case-insensitive)
+pub(crate) fn contains_any(keywords: &[String], text: &str) -> bool {
+    let text_lower = text.to_lowercase();
+    keywords.iter().any(|keyword| text_lower.contains(&keyword.to_lowercase()))
+}
+
+/// Check if any of the keywords is one of the text's words, or such a word with a plural
+/// `s` (case-insensitive). Words are split at characters other than letters and digits,
+/// such as the `_`, `-` and `.` of tool names, and where camelCase starts a new word, so
+/// "delete" matches `fs.delete_file` and `deleteFile` but "edit" does not match "credit".
+pub(crate) fn contains_any_word(keywords: &[String], text: &str) -> bool {
+    let words = words(text);
+    keywords.iter().any(|keyword| {
+        let keyword = keyword.to_lowercase();
+        words.iter().any(|word| word.strip_suffix('s').unwrap_or(word) == keyword || *word == keyword)
+    })
+}
+
+/// The lowercase words of a text, split as `contains_any_word` describes
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lowercase = false;
+    for c in text.chars() {
+        if (!c.is_alphanumeric() || (c.is_uppercase() && previous_lowercase)) && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        }
+        previous_lowercase = c.is_lowercase() || c.is_numeric();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn keywords(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+impl Default for SelectionRules {
+    fn default() -> Self {
+        Self {
+            complexity: ComplexityRules::default(),
+            planning: PlanningRules::default(),
+            progress: ThresholdRules { tool_call_threshold: 6 },
+            checkpoint: ThresholdRules { tool_call_threshold: 20 },
+            error_recovery: ErrorRecoveryRules::default(),
+            programming: KeywordRules {
+                keywords: keywords(&[
+                    "code", "function", "class", "refactor", "implement",
+                    "debug", "fix", "python", "rust", "javascript", "api",
+                ]),
+            },
+            analysis: KeywordRules {
+                keywords: keywords(&[
+                    "analyze", "analysis", "data", "csv", "trends", "statistics",
+                    "report", "insights", "metrics", "dashboard",
+                ]),
+            },
+            system: KeywordRules {
+                keywords: keywords(&[
+                    "server", "deployment", "infrastructure", "configuration",
+                    "security", "backup", "monitor", "admin", "service",
+                ]),
+            },
+            web_research: KeywordRules {
+                keywords: keywords(&[
+                    "search", "research", "look up", "latest", "news", "source",
+                    "cite", "citation", "website", "url",
+                ]),
+            },
+            safety: SafetyRules {
+                destructive_keywords: keywords(&[
+                    "delete", "remove", "write", "edit", "move", "execute", "shell",
+                    "command", "kill", "drop", "truncate", "overwrite",
+                ]),
+            },
+            ranking: RankingRules::default(),
+            history: HistoryRules::default(),
+            policy: SelectionPolicy::default(),
+            external_modules: Vec::new(),
+            modules: Vec::new(),
+            variants: BTreeMap::new(),
+            languages: BTreeMap::from([
+                ("de".to_string(), LanguageRules {
+                    stopwords: keywords(&[
+                        "der", "die", "das", "und", "ist", "nicht", "ich", "mit",
+                        "ein", "eine", "zu", "den", "von", "bitte", "für", "auf",
+                    ]),
+                    programming: keywords(&[
+                        "funktion", "klasse", "programm", "fehler", "debuggen",
+                        "umschreiben", "implementieren", "quellcode",
+                    ]),
+                    analysis: keywords(&[
+                        "analysieren", "analyse", "daten", "bericht", "statistik",
+                        "auswerten", "kennzahlen", "diagramm",
+                    ]),
+                    system: keywords(&[
+                        "dienst", "konfiguration", "bereitstellung", "sicherung",
+                        "überwachen", "infrastruktur", "installieren",
+                    ]),
+                    web_research: keywords(&[
+                        "recherchieren", "suchen", "nachrichten", "quelle", "webseite",
+                        "aktuell", "neueste",
+                    ]),
+                }),
+                ("es".to_string(), LanguageRules {
+                    stopwords: keywords(&[
+                        "el", "la", "los", "las", "de", "que", "y", "en", "un", "una",
+                        "por", "para", "con", "es", "del", "favor",
+                    ]),
+                    programming: keywords(&[
+                        "código", "codigo", "función", "funcion", "clase", "programa",
+                        "depurar", "implementar", "error",
+                    ]),
+                    analysis: keywords(&[
+                        "analizar", "análisis", "analisis", "datos", "informe",
+                        "estadísticas", "tendencias", "métricas",
+                    ]),
+                    system: keywords(&[
+                        "servidor", "despliegue", "infraestructura", "configuración",
+                        "seguridad", "respaldo", "servicio", "monitorear",
+                    ]),
+                    web_research: keywords(&[
+                        "buscar", "investigar", "noticias", "fuente", "sitio web",
+                        "últimas", "citar",
+                    ]),
+                }),
+            ]),
+        }
+    }
+}
+
+impl HistoryRules {
+    /// The most recent messages from the session joined into one text, if any
+    pub fn recent_text(&self, session_state: &SessionState) -> Option<String> {
+        let messages = session_state.recent_messages.as_ref()?;
+        let start = messages.len().saturating_sub(self.max_messages);
+        let recent = &messages[start..];
+        if recent.is_empty() {
+            None
+        } else {
+            Some(recent.join("\n"))
+        }
+    }
+}
+
+impl Default for HistoryRules {
+    fn default() -> Self {
+        Self {
+            max_messages: 5,
+            weight: 0.8,
+        }
+    }
+}
+
+impl Default for RankingRules {
+    fn default() -> Self {
+        Self {
+            min_score: 0.5,
+            max_modules: None,
+        }
+    }
+}
+
+impl Default for ComplexityRules {
+    fn default() -> Self {
+        Self {
+            keywords: keywords(&[
+                "refactor", "implement", "create", "build", "develop",
+                "comprehensive", "analysis", "strategy", "plan", "design",
+                "multiple", "all", "entire", "complete", "full", "system",
+            ]),
+            keyword_weight: 1.0,
+            patterns: Vec::new(),
+            long_prompt_length: 100,
+            long_prompt_weight: 1.0,
+            max_simple_servers: 2,
+            multiple_servers_weight: 1.0,
+            moderate_threshold: 1.0,
+            complex_threshold: 2.0,
+            epic_threshold: 5.0,
+            trivial_prompt_length: 15,
+        }
+    }
+}
+
+impl Default for PlanningRules {
+    fn default() -> Self {
+        Self {
+            keywords: keywords(&[
+                "refactor", "implement", "create a", "build", "develop",
+                "comprehensive", "analysis", "strategy", "plan", "design",
+                "multiple", "all", "entire", "complete", "full",
+            ]),
+            long_prompt_length: 100,
+        }
+    }
+}
+
+impl Default for ErrorRecoveryRules {
+    fn default() -> Self {
+        Self {
+            consecutive_failures_threshold: 2,
+            ask_for_help_after_failures: 3,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_rules_keep_defaults() {
+        let rules: SelectionRules = toml::from_str(r#"
+[progress]
+tool_call_threshold = 12
+
+[programming]
+keywords = ["kotlin"]
+"#).unwrap();
+
+        assert_eq!(rules.progress.tool_call_threshold, 12);
+        assert!(rules.programming.matches("Port this to Kotlin"));
+        assert!(!rules.programming.matches("Fix the python function"));
+        assert_eq!(rules.checkpoint.tool_call_threshold, 20);
+        assert!(rules.analysis.matches("Show me the trends"));
+    }
+
+    #[test]
+    fn test_complexity_keywords_match_whole_words() {
+        let matcher = ComplexityRules::default().compile().unwrap();
+
+        let (level, evidence) = matcher.assess("Please recreate the bug", 1);
+        assert!(evidence.signals.is_empty());
+        assert_eq!(level, TaskComplexity::Simple);
+
+        let (level, evidence) = matcher.assess("Refactor the parser", 1);
+        assert_eq!(evidence.signals[0].matched, "refactor");
+        assert_eq!(level, TaskComplexity::Moderate);
+
+        let (level, _) = matcher.assess("thanks!", 1);
+        assert_eq!(level, TaskComplexity::Trivial);
+    }
+
+    #[test]
+    fn test_language_detection_adds_domain_keywords() {
+        let rules = SelectionRules::default();
+
+        let german = "Bitte analysieren Sie die Daten und den Bericht";
+        assert_eq!(rules.detect_language(german), Some("de"));
+        assert!(!rules.analysis.matches("Bitte analysieren Sie die Zahlen"));
+        assert!(rules.localized(german).analysis.matches(german));
+
+        assert_eq!(rules.detect_language("Por favor, buscar las noticias"), Some("es"));
+        assert_eq!(rules.detect_language("Analyze the data"), None);
+        assert!(matches!(rules.localized("Analyze the data"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_shipped_rules_parse() {
+        let rules = SelectionRules::load("prompts").unwrap();
+        assert_eq!(rules.progress.tool_call_threshold, 6);
+        assert!(!rules.safety.destructive_keywords.is_empty());
+        assert!(rules.complexity.compile().is_ok());
+        assert!(rules.languages.contains_key("de"));
+    }
+}
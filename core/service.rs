@@ -0,0 +1,121 @@
+//! State of the server and daemon modes: one composer and tool discovery cache serving every call,
+//! with the modules and hooks registered in the process.
+
+use crate::cancel::CancellationToken;
+use crate::composition::{request_span, PromptComposer};
+use crate::discovery::ToolDiscovery;
+use crate::types::{CacheStats, DiscoveryMode, McpConfig, ModuleDecision, ModuleInfo, PromptError, PromptRequest, PromptResponse, Tool};
+use crate::validate::{validate_prompts_dir, ValidationReport};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// Whether a service's prompts directory is fit to compose from, for readiness probes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    /// Whether validation found no errors
+    pub ready: bool,
+    /// Validation of the prompts directory as it is on disk now, including its
+    /// `server_patterns.toml` and `selection_rules.toml`
+    #[serde(flatten)]
+    pub validation: ValidationReport,
+}
+
+#[derive(Clone)]
+pub struct ComposerService {
+    composer: Arc<Mutex<PromptComposer>>,
+    discovery: Arc<ToolDiscovery>,
+}
+
+impl ComposerService {
+    /// A service reading prompts from `prompts_dir`, or the default prompts directory
+    pub fn new(prompts_dir: Option<String>) -> Self {
+        let discovery = match &prompts_dir {
+            Some(dir) => ToolDiscovery::with_prompts_dir(dir.clone()),
+            None => ToolDiscovery::new(),
+        };
+        Self {
+            composer: Arc::new(Mutex::new(crate::global_engine().composer(prompts_dir, false))),
+            discovery: Arc::new(discovery),
+        }
+    }
+
+    /// Compose after discovering the request's tools, or with the cached or inferred tools
+    pub fn compose(&self, request: &PromptRequest, live: bool) -> Result<PromptResponse, PromptError> {
+        self.compose_cancellable(request, live, &CancellationToken::new())
+    }
+
+    /// `compose` that stops once `cancel` is cancelled
+    pub fn compose_cancellable(&self, request: &PromptRequest, live: bool, cancel: &CancellationToken) -> Result<PromptResponse, PromptError> {
+        let start_time = crate::Instant::now();
+        let tools = request_span(request).in_scope(|| match (live, request.force_refresh.unwrap_or(false)) {
+            (_, true) => self.discovery.rediscover_tools(&request.mcp_config, cancel),
+            (true, false) => self.discovery.discover_tools_cancellable(&request.mcp_config, cancel),
+            (false, false) => Ok(self.discovery.cached_or_inferred_tools(&request.mcp_config)),
+        })?;
+        let mut response = crate::lock(&self.composer).compose_cancellable(request, &tools, cancel)?;
+        response.metadata.duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+        response.metadata.discovery = if live { DiscoveryMode::Live } else { DiscoveryMode::Cached };
+        Ok(response)
+    }
+
+    /// Decide which modules a request would get with the cached or inferred tools
+    pub fn explain(&self, request: &PromptRequest) -> Result<Vec<ModuleDecision>, PromptError> {
+        let tools = self.discovery.cached_or_inferred_tools(&request.mcp_config);
+        crate::lock(&self.composer).explain(request, &tools)
+    }
+
+    /// Discover one server's tools again, replacing them in the cache
+    pub fn refresh_server(&self, server_name: &str, mcp_config: &McpConfig) -> Result<Vec<Tool>, PromptError> {
+        self.discovery.refresh_server_immediate(server_name, mcp_config)
+    }
+
+    pub fn list_modules(&self) -> Vec<ModuleInfo> {
+        crate::lock(&self.composer).list_modules()
+    }
+
+    pub fn list_domains(&self) -> Result<Vec<String>, PromptError> {
+        crate::lock(&self.composer).list_domains()
+    }
+
+    pub fn list_behaviors(&self) -> Result<Vec<String>, PromptError> {
+        crate::lock(&self.composer).list_behaviors()
+    }
+
+    /// Entries, approximate bytes and hit rates of the composer's prompt files and the
+    /// discovered tools
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            prompts: crate::lock(&self.composer).cache_usage(),
+            tools: self.discovery.cache_usage(),
+        }
+    }
+
+    /// Validate the prompts directory the service composes from. Validation reads every
+    /// file of the directory, so probes should call this every few seconds, not per request.
+    pub fn readiness(&self) -> Readiness {
+        let prompts_dir = crate::lock(&self.composer).prompts_dir().to_string();
+        let validation = validate_prompts_dir(&prompts_dir);
+        Readiness { ready: validation.is_valid(), validation }
+    }
+
+    /// Forget cached prompt files and discovered tools, so edits on disk take effect
+    pub fn clear_cache(&self) {
+        crate::lock(&self.composer).clear_cache();
+        self.discovery.clear_cache();
+    }
+
+    /// Run a call on the blocking thread pool, since composition may start MCP servers and
+    /// waits for the composer's lock. The call's token is cancelled when the returned
+    /// future is dropped, as servers do when the client disconnects.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&ComposerService, &CancellationToken) -> Result<T, PromptError> + Send + 'static,
+    ) -> Result<T, PromptError> {
+        let service = self.clone();
+        let cancel = CancellationToken::new();
+        let _cancel_on_drop = cancel.clone().cancel_on_drop();
+        tokio::task::spawn_blocking(move || call(&service, &cancel))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+}
@@ -0,0 +1,56 @@
+use crate::modules::PromptModule;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Key-value state a module keeps for one session
+pub type ModuleState = HashMap<String, serde_json::Value>;
+
+/// Per-session module state, shared by the composers that hold a clone of it.
+///
+/// State is keyed by `SessionState::session_id` and then by module name, so modules
+/// never see each other's state. Sessions stay in the store until they are ended.
+#[derive(Clone, Default)]
+pub struct SessionStore {
+    sessions: Arc<Mutex<HashMap<String, HashMap<String, ModuleState>>>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` with the module's state for the session, starting the module's part in
+    /// the session first if this is its first composition there
+    pub fn with_state<R>(&self, session_id: &str, module: &dyn PromptModule, f: impl FnOnce(&mut ModuleState) -> R) -> R {
+        let mut sessions = self.lock();
+        let modules = sessions.entry(session_id.to_string()).or_default();
+        let state = modules.entry(module.name().to_string()).or_insert_with(|| {
+            let mut state = ModuleState::new();
+            module.on_session_start(&mut state);
+            state
+        });
+        f(state)
+    }
+
+    /// Remove a session, returning each module's final state for `on_session_end`
+    pub fn end(&self, session_id: &str) -> Option<HashMap<String, ModuleState>> {
+        self.lock().remove(session_id)
+    }
+
+    /// A copy of a module's state in a session
+    pub fn get(&self, session_id: &str, module: &str) -> Option<ModuleState> {
+        self.lock().get(session_id)?.get(module).cloned()
+    }
+
+    /// Ids of the sessions that have not been ended
+    pub fn session_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.lock().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, HashMap<String, ModuleState>>> {
+        // The map holds plain data, so a panic elsewhere cannot leave it inconsistent
+        self.sessions.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
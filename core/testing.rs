@@ -0,0 +1,227 @@
+//! Snapshot testing of composed prompts, with the `testing` feature, for applications
+//! pinning the prompts they compose in their own CI. A [`PromptFixture`] composes
+//! requests against a fixture prompts directory, with a fixed selection seed and tools
+//! inferred from the config, and compares a normalized rendering of each response with a
+//! snapshot file.
+//!
+//! A missing snapshot is written and the test passes, unless `CI` is set; a snapshot that
+//! differs fails the test with a diff, and `UPDATE_SNAPSHOTS=1 cargo test` rewrites it.
+//!
+//! ```no_run
+//! # use prompt_composer::testing::PromptFixture;
+//! # use prompt_composer::types::PromptRequest;
+//! let request = PromptRequest::builder().user_prompt("Plan the migration").build().unwrap();
+//! PromptFixture::new("tests/prompts").assert_snapshot("plan_migration", &request);
+//! ```
+
+use crate::composition::PromptComposer;
+use crate::diff::unified_diff;
+use crate::engine::offline_tools;
+use crate::types::{PromptError, PromptRequest, PromptResponse, WarningSeverity};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Selection seed of requests that set none, so modules chosen at random are the same
+/// on every run
+pub const DEFAULT_SEED: u64 = 0;
+
+/// Rewrites of volatile text, applied in order before snapshots are compared
+pub struct Normalizer {
+    rules: Vec<(Regex, String)>,
+}
+
+impl Default for Normalizer {
+    /// Replaces dates and times, durations and UUIDs
+    fn default() -> Self {
+        let rules = [
+            (r"\b\d{4}-\d{2}-\d{2}(?:[T ]\d{2}:\d{2}(?::\d{2}(?:\.\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?)?\b", "[date]"),
+            (r"\b\d{1,2}:\d{2}(?::\d{2})?\b", "[time]"),
+            (r"\b\d+(?:\.\d+)? ?(?:ms|µs|s)\b", "[duration]"),
+            (r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b", "[uuid]"),
+        ];
+        Self {
+            rules: rules.into_iter()
+                .map(|(pattern, replacement)| (Regex::new(pattern).expect("the default normalization patterns compile"), replacement.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Normalizer {
+    /// A normalizer leaving text as it is
+    pub fn none() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Also replace every remaining whole number with `[n]`, for prompts showing session
+    /// counts such as tool calls or failures
+    pub fn with_counts(self) -> Self {
+        self.with_rule(r"\b\d+\b", "[n]").expect("the count pattern compiles")
+    }
+
+    /// Also replace matches of `pattern` with `replacement`, which may refer to the
+    /// pattern's groups as `$1` or `$name`
+    pub fn with_rule(mut self, pattern: &str, replacement: &str) -> Result<Self, PromptError> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| PromptError::config(format!("Invalid normalization pattern {}: {}", pattern, e)))?;
+        self.rules.push((regex, replacement.to_string()));
+        Ok(self)
+    }
+
+    pub fn normalize(&self, text: &str) -> String {
+        self.rules.iter().fold(text.to_string(), |text, (regex, replacement)| {
+            regex.replace_all(&text, replacement.as_str()).into_owned()
+        })
+    }
+}
+
+/// The parts of a response a snapshot pins: the applied modules, recognized tools,
+/// warnings other than informational ones, and the prompt
+pub fn render_snapshot(response: &PromptResponse) -> String {
+    let mut snapshot = format!(
+        "modules: {}\ntools: {}\n",
+        response.applied_modules.join(", "),
+        response.recognized_tools.join(", ")
+    );
+    for warning in response.warnings.iter().filter(|warning| warning.severity != WarningSeverity::Info) {
+        snapshot.push_str(&format!("warning: {}: {}\n", warning.source, warning.message));
+    }
+    snapshot.push_str("---\n");
+    snapshot.push_str(&response.system_prompt);
+    snapshot.push('\n');
+    snapshot
+}
+
+/// Compare `actual` with the snapshot file at `path`, panicking with a diff when they
+/// differ. Writes the file when it is missing outside CI, or when `UPDATE_SNAPSHOTS` is
+/// set.
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    let expected = std::fs::read_to_string(path).ok();
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some()
+        || (expected.is_none() && std::env::var_os("CI").is_none());
+    if update {
+        if expected.as_deref() != Some(actual) {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).unwrap_or_else(|e| panic!("Failed to create {}: {}", dir.display(), e));
+            }
+            std::fs::write(path, actual).unwrap_or_else(|e| panic!("Failed to write snapshot {}: {}", path.display(), e));
+        }
+        return;
+    }
+    let Some(expected) = expected else {
+        panic!("Snapshot {} does not exist; run the tests outside CI or with UPDATE_SNAPSHOTS=1 to write it", path.display());
+    };
+    if expected != actual {
+        panic!(
+            "Snapshot {} is stale; run with UPDATE_SNAPSHOTS=1 to accept the change\n{}",
+            path.display(),
+            unified_diff(&expected, actual, "snapshot", "composed")
+        );
+    }
+}
+
+/// Composes requests against a fixture prompts directory and asserts snapshots of them
+pub struct PromptFixture {
+    prompts_dir: String,
+    composer: PromptComposer,
+    snapshots_dir: PathBuf,
+    normalizer: Normalizer,
+}
+
+impl PromptFixture {
+    /// A fixture of `prompts_dir`, keeping snapshots in `tests/snapshots` of the crate
+    /// under test
+    pub fn new(prompts_dir: impl Into<String>) -> Self {
+        let prompts_dir = prompts_dir.into();
+        let root = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+        Self {
+            composer: PromptComposer::with_prompts_dir(prompts_dir.clone()),
+            prompts_dir,
+            snapshots_dir: root.join("tests").join("snapshots"),
+            normalizer: Normalizer::default(),
+        }
+    }
+
+    pub fn with_snapshots_dir(mut self, snapshots_dir: impl Into<PathBuf>) -> Self {
+        self.snapshots_dir = snapshots_dir.into();
+        self
+    }
+
+    pub fn with_normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Compose with [`DEFAULT_SEED`] unless the request sets a seed, and with tools
+    /// inferred from its config and the fixture's server patterns, never starting an MCP
+    /// server
+    pub fn compose(&mut self, request: &PromptRequest) -> Result<PromptResponse, PromptError> {
+        let request = PromptRequest {
+            selection_seed: Some(request.selection_seed.unwrap_or(DEFAULT_SEED)),
+            ..request.clone()
+        };
+        let tools = offline_tools(&request.mcp_config, Some(&self.prompts_dir));
+        self.composer.compose(&request, &tools)
+    }
+
+    /// The normalized snapshot of the request's composition
+    pub fn snapshot(&mut self, request: &PromptRequest) -> Result<String, PromptError> {
+        let response = self.compose(request)?;
+        Ok(self.normalizer.normalize(&render_snapshot(&response)))
+    }
+
+    /// Assert the request's composition matches the snapshot `<name>.snap`
+    pub fn assert_snapshot(&mut self, name: &str, request: &PromptRequest) {
+        let actual = self.snapshot(request).unwrap_or_else(|e| panic!("Composing snapshot {} failed: {}", name, e));
+        assert_snapshot(self.snapshots_dir.join(format!("{}.snap", name)), &actual);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{SessionState, Warning};
+
+    #[test]
+    fn test_normalizer_replaces_volatile_text() {
+        let text = "Started 2024-05-01T10:22:03Z, took 12.5 ms at 09:15 in session 6f1c2e4a-0b7d-4c1e-9a3f-2d5b8e7c9a10 after 14 tool calls";
+        assert_eq!(
+            Normalizer::default().normalize(text),
+            "Started [date], took [duration] at [time] in session [uuid] after 14 tool calls"
+        );
+        let normalizer = Normalizer::default().with_counts().with_rule(r"session (\S+)", "session <$1>").unwrap();
+        assert_eq!(normalizer.normalize(text), "Started [date], took [duration] at [time] in session <[uuid]> after [n] tool calls");
+        assert!(Normalizer::none().with_rule("(", "").is_err());
+    }
+
+    #[test]
+    fn test_fixture_snapshots_pin_the_composition() {
+        let snapshots = std::env::temp_dir().join(format!("prompt-composer-snapshots-{}", std::process::id()));
+        let mut fixture = PromptFixture::new(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts"))
+            .with_snapshots_dir(&snapshots)
+            .with_normalizer(Normalizer::default().with_counts());
+        let mut request = PromptRequest::builder()
+            .user_prompt("Plan and implement the migration, then run the tests")
+            .session_state(SessionState { tool_call_count: Some(12), ..Default::default() })
+            .build()
+            .unwrap();
+        let written = fixture.snapshot(&request).unwrap();
+        assert!(written.starts_with("modules: "));
+        std::fs::create_dir_all(&snapshots).unwrap();
+        std::fs::write(snapshots.join("migration.snap"), &written).unwrap();
+        fixture.assert_snapshot("migration", &request);
+
+        // A different count normalizes to the same snapshot; a different task does not
+        request.session_state = Some(SessionState { tool_call_count: Some(14), ..Default::default() });
+        assert_eq!(fixture.snapshot(&request).unwrap(), written);
+        request.user_prompt = "What does this function return?".to_string();
+        let stale = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| fixture.assert_snapshot("migration", &request)));
+        assert!(stale.is_err());
+
+        let mut response = fixture.compose(&request).unwrap();
+        response.warnings.push(Warning::new(WarningSeverity::Info, "composer", "Composition took 60 ms"));
+        assert!(!render_snapshot(&response).contains("60 ms"));
+        std::fs::remove_dir_all(&snapshots).unwrap();
+    }
+}
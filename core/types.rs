@@ -1,26 +1,151 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use thiserror::Error;
 
 /// Configuration for an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct McpServer {
+    /// Defaults to the server's key when loaded with `McpConfig::from_file`
+    #[serde(default)]
     pub name: String,
     pub command: String,
+    #[serde(default)]
     pub args: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env: Option<HashMap<String, String>>,
 }
 
 /// Complete MCP configuration with all servers
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct McpConfig {
     #[serde(rename = "mcpServers")]
     pub mcp_servers: HashMap<String, McpServer>,
 }
 
+impl McpConfig {
+    /// The servers in order of name, so prompts and tool lists composed from a config do
+    /// not depend on the order of its map
+    pub fn servers_by_name(&self) -> Vec<(&String, &McpServer)> {
+        let mut servers: Vec<_> = self.mcp_servers.iter().collect();
+        servers.sort_by_key(|(name, _)| *name);
+        servers
+    }
+
+    /// Read an MCP configuration file in the `{"mcpServers": {...}}` format used by MCP
+    /// clients, or the `{"servers": {...}}` format of VS Code. Servers without a `name`
+    /// are named after their key; servers reached by URL rather than launched with a
+    /// `command` are skipped with a warning, and a file without servers has none.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, PromptError> {
+        #[derive(Deserialize)]
+        struct ClientConfig {
+            #[serde(rename = "mcpServers", alias = "servers", default)]
+            mcp_servers: HashMap<String, serde_json::Value>,
+        }
+
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| PromptError::config(format!("Failed to read MCP config {:?}", path)).with_path(path).with_source(e))?;
+        let client: ClientConfig = serde_json::from_str(&content)
+            .map_err(|e| PromptError::config(format!("Failed to parse MCP config {:?}", path)).with_path(path).with_source(e))?;
+
+        let mut mcp_servers = HashMap::new();
+        for (key, value) in client.mcp_servers {
+            if value.get("command").is_none() {
+                tracing::warn!(server = %key, config = ?path, "MCP server has no command; skipping");
+                continue;
+            }
+            let mut server: McpServer = serde_json::from_value(value)
+                .map_err(|e| PromptError::config(format!("Invalid MCP server {} in {:?}: {}", key, path, e)).with_path(path).with_server(&key))?;
+            if server.name.is_empty() {
+                server.name = key.clone();
+            }
+            mcp_servers.insert(key, server);
+        }
+        Ok(Self { mcp_servers })
+    }
+
+    /// Read the configuration of an MCP client, named as by `McpClient::from_name`, or
+    /// the configuration file at `source` when it names no client
+    pub fn load(source: &str) -> Result<Self, PromptError> {
+        match McpClient::from_name(source) {
+            Some(client) => Self::from_client(client),
+            None => Self::from_file(source),
+        }
+    }
+
+    /// Read the configuration file of an installed MCP client
+    pub fn from_client(client: McpClient) -> Result<Self, PromptError> {
+        let path = client.config_path().ok_or_else(|| {
+            PromptError::config(format!("Cannot locate the {} configuration: home directory is unknown", client.name()))
+        })?;
+        Self::from_file(path)
+    }
+}
+
+/// MCP clients whose configuration files `McpConfig::from_client` can find
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McpClient {
+    ClaudeDesktop,
+    Cursor,
+    VsCode,
+}
+
+impl McpClient {
+    /// Look up a client by the name used in bindings: "claude_desktop" (or "claude"),
+    /// "cursor" or "vscode"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "claude_desktop" | "claude" => Some(Self::ClaudeDesktop),
+            "cursor" => Some(Self::Cursor),
+            "vscode" => Some(Self::VsCode),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ClaudeDesktop => "claude_desktop",
+            Self::Cursor => "cursor",
+            Self::VsCode => "vscode",
+        }
+    }
+
+    /// Where the client keeps its MCP configuration on this platform. Cursor and VS Code
+    /// read a project's `.cursor/mcp.json` or `.vscode/mcp.json` before the user's own.
+    pub fn config_path(&self) -> Option<std::path::PathBuf> {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(std::path::PathBuf::from);
+        // Application data: ~/Library/Application Support, %APPDATA% or ~/.config
+        let app_data = || if cfg!(target_os = "macos") {
+            Some(home.clone()?.join("Library").join("Application Support"))
+        } else if cfg!(windows) {
+            std::env::var_os("APPDATA").map(std::path::PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(std::path::PathBuf::from)
+                .or_else(|| home.clone().map(|home| home.join(".config")))
+        };
+        match self {
+            Self::ClaudeDesktop => Some(app_data()?.join("Claude").join("claude_desktop_config.json")),
+            Self::Cursor => {
+                let project = std::path::Path::new(".cursor").join("mcp.json");
+                if project.is_file() {
+                    return Some(project);
+                }
+                Some(home?.join(".cursor").join("mcp.json"))
+            }
+            Self::VsCode => {
+                let project = std::path::Path::new(".vscode").join("mcp.json");
+                if project.is_file() {
+                    return Some(project);
+                }
+                Some(app_data()?.join("Code").join("User").join("mcp.json"))
+            }
+        }
+    }
+}
+
 /// Information about an available tool from an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Tool {
     pub name: String,
     pub description: String,
@@ -28,9 +153,20 @@ pub struct Tool {
     pub schema: Option<serde_json::Value>,
 }
 
+impl Tool {
+    /// Approximate bytes of the tool's text and schema
+    pub(crate) fn approximate_size(&self) -> usize {
+        let schema = self.schema.as_ref().map_or(0, |schema| schema.to_string().len());
+        self.name.len() + self.description.len() + self.server.len() + schema
+    }
+}
+
 /// Current session state for context-aware prompt generation
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 pub struct SessionState {
+    /// Identifies the conversation across turns, so modules can keep per-session state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_count: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -41,24 +177,169 @@ pub struct SessionState {
     pub last_action: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub current_step: Option<String>,
+    /// Recent tool errors; plain strings are accepted as errors without a tool name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent_errors: Option<Vec<ToolError>>,
+    /// Files written or edited earlier in the session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files_modified: Option<Vec<String>>,
+    /// Outcomes of the most recent tool calls, oldest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_tool_results: Option<Vec<ToolResultSummary>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consecutive_failures: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_long_running: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memories: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_role: Option<AgentRole>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase: Option<ConversationPhase>,
+    /// Recent conversation turns, oldest first, used to keep domains from earlier turns active
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent_messages: Option<Vec<String>>,
+}
+
+/// An error reported by a tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(from = "ToolErrorRepr")]
+pub struct ToolError {
+    /// Tool that failed, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    pub message: String,
+}
+
+/// Accepts the plain error strings of earlier releases as well as structured errors
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum ToolErrorRepr {
+    Message(String),
+    Detailed {
+        #[serde(default)]
+        tool: Option<String>,
+        message: String,
+    },
+}
+
+impl From<ToolErrorRepr> for ToolError {
+    fn from(repr: ToolErrorRepr) -> Self {
+        match repr {
+            ToolErrorRepr::Message(message) => Self { tool: None, message },
+            ToolErrorRepr::Detailed { tool, message } => Self { tool, message },
+        }
+    }
+}
+
+impl From<&str> for ToolError {
+    fn from(message: &str) -> Self {
+        Self { tool: None, message: message.to_string() }
+    }
+}
+
+impl std::fmt::Display for ToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.tool {
+            Some(tool) => write!(f, "{}: {}", tool, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Short outcome of a tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ToolResultSummary {
+    pub tool: String,
+    pub success: bool,
+    /// One-line description of the result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
 }
 
-/// Task complexity assessment
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Phase of the conversation, used to pick behavior guidance that fits the current work
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ConversationPhase {
+    Exploration,
+    Planning,
+    Execution,
+    Review,
+}
+
+/// Role of the agent receiving the composed prompt in a multi-agent setup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum AgentRole {
+    /// Plans the work and delegates pieces of it to sub-agents
+    Orchestrator,
+    /// Executes a delegated piece of work and reports back
+    Worker,
+}
+
+/// Assessed task complexity, ordered from least to most effort
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema, Default)]
 pub enum TaskComplexity {
+    Trivial,
+    #[default]
+    Simple,
+    Moderate,
+    Complex,
+    Epic,
+}
+
+impl TaskComplexity {
+    /// Relative amount of work expected at this level, used to scale session thresholds
+    pub fn effort_multiplier(&self) -> f32 {
+        match self {
+            TaskComplexity::Trivial => 0.5,
+            TaskComplexity::Simple => 1.0,
+            TaskComplexity::Moderate => 1.5,
+            TaskComplexity::Complex => 2.0,
+            TaskComplexity::Epic => 3.0,
+        }
+    }
+}
+
+/// Complexity a request asks for. `Auto`, like leaving it unset, has the composer
+/// assess it; the response always carries a resolved [`TaskComplexity`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Default)]
+pub enum RequestedComplexity {
+    #[default]
     Auto,
+    Trivial,
     Simple,
+    Moderate,
     Complex,
+    Epic,
+}
+
+impl RequestedComplexity {
+    /// The requested level, or `None` when it is to be assessed
+    pub fn level(&self) -> Option<TaskComplexity> {
+        match self {
+            RequestedComplexity::Auto => None,
+            RequestedComplexity::Trivial => Some(TaskComplexity::Trivial),
+            RequestedComplexity::Simple => Some(TaskComplexity::Simple),
+            RequestedComplexity::Moderate => Some(TaskComplexity::Moderate),
+            RequestedComplexity::Complex => Some(TaskComplexity::Complex),
+            RequestedComplexity::Epic => Some(TaskComplexity::Epic),
+        }
+    }
 }
 
-impl Default for TaskComplexity {
-    fn default() -> Self {
-        TaskComplexity::Auto
+impl From<TaskComplexity> for RequestedComplexity {
+    fn from(complexity: TaskComplexity) -> Self {
+        match complexity {
+            TaskComplexity::Trivial => RequestedComplexity::Trivial,
+            TaskComplexity::Simple => RequestedComplexity::Simple,
+            TaskComplexity::Moderate => RequestedComplexity::Moderate,
+            TaskComplexity::Complex => RequestedComplexity::Complex,
+            TaskComplexity::Epic => RequestedComplexity::Epic,
+        }
     }
 }
 
 /// Request for prompt composition
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PromptRequest {
     pub user_prompt: String,
     pub mcp_config: McpConfig,
@@ -69,37 +350,699 @@ pub struct PromptRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub behavior_hints: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub task_complexity: Option<TaskComplexity>,
+    pub task_complexity: Option<RequestedComplexity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Per-tenant overrides applied on top of the configured selection policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<SelectionPolicy>,
+    /// Seed for randomized selection steps; the response echoes the seed that was used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection_seed: Option<u64>,
+    /// Prompt file versions to compose, keyed by file name (e.g. `planning` for task_planning)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_versions: Option<HashMap<String, String>>,
+    /// A/B variant to compose per module name; unassigned modules with configured
+    /// variants are bucketed by the selection seed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant_assignments: Option<HashMap<String, String>>,
+    /// Environment the assistant works in, stated in the prompt so it need not guess
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<WorkspaceContext>,
+    /// Earlier turns of the conversation, oldest first. Fills in session state the
+    /// host did not send, and guidance already given in a system turn is not repeated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversation: Option<Vec<Message>>,
+    /// Approximate size limit of the system prompt, in tokens of about four characters.
+    /// Sections that would exceed it are left out with a warning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Language of the prompt files to compose (e.g. `de` or `pt-BR`): `name.<locale>.md`
+    /// is read in place of `name.md` where it exists, trying the language without its
+    /// region next
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Discover tools and read prompt files again instead of using cached ones, updating
+    /// the caches with what is found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force_refresh: Option<bool>,
+    /// Identifier of the host's trace or request, recorded on the composition's log
+    /// events and echoed in the response metadata, to correlate the composition with the
+    /// model calls that use its prompt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Time each module and prompt file load, reporting them in the response's `profile`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<bool>,
+}
+
+/// One turn of a conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Message {
+    pub role: MessageRole,
+    pub content: String,
+}
+
+/// Who produced a conversation turn; the lowercase names used by chat APIs are accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum MessageRole {
+    #[serde(alias = "system")]
+    System,
+    #[serde(alias = "user")]
+    User,
+    #[serde(alias = "assistant")]
+    Assistant,
+    /// Output of a tool call
+    #[serde(alias = "tool")]
+    Tool,
+}
+
+impl PromptRequest {
+    /// JSON schema of the request, for validating payloads and generating typed clients
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(PromptRequest)).unwrap_or_default()
+    }
+}
+
+/// Where the host runs the assistant; every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct WorkspaceContext {
+    /// Working directory relative paths resolve against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+    /// Kind of project in the working directory (e.g. "rust", "node")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_type: Option<String>,
+}
+
+impl WorkspaceContext {
+    /// Whether no field is set
+    pub fn is_empty(&self) -> bool {
+        self.facts().is_empty()
+    }
+
+    /// Set fields as (label, value) pairs, skipping blank values
+    pub fn facts(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("Working directory", &self.cwd),
+            ("Operating system", &self.os),
+            ("Shell", &self.shell),
+            ("Git branch", &self.git_branch),
+            ("Project type", &self.project_type),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| {
+            let value = value.as_deref()?.trim();
+            (!value.is_empty()).then_some((label, value))
+        })
+        .collect()
+    }
+}
+
+/// Overrides applied to module selection after auto-detection, keyed by module name
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SelectionPolicy {
+    /// Modules always composed, whether or not they were detected
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub enable: Vec<String>,
+    /// Modules never composed; takes precedence over `enable` and explicit hints
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disable: Vec<String>,
+    /// Multipliers applied to auto-detected modules' relevance scores before ranking
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub weights: HashMap<String, f32>,
+}
+
+impl SelectionPolicy {
+    /// Combine with another policy; its lists are added and its weights take precedence
+    pub fn merged_with(&self, other: &SelectionPolicy) -> SelectionPolicy {
+        let mut merged = self.clone();
+        merged.enable.extend(other.enable.iter().cloned());
+        merged.disable.extend(other.disable.iter().cloned());
+        merged.weights.extend(other.weights.iter().map(|(name, weight)| (name.clone(), *weight)));
+        merged
+    }
+
+    /// Relevance multiplier for a module, 1.0 unless reweighted
+    pub fn weight(&self, module_name: &str) -> f32 {
+        self.weights.get(module_name).copied().unwrap_or(1.0)
+    }
+}
+
+/// Required shape of the model's output
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type")]
+pub enum ResponseFormat {
+    /// A single JSON document, optionally conforming to a JSON schema
+    Json {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        schema: Option<serde_json::Value>,
+    },
+    /// A markdown table, optionally with a fixed set of columns
+    MarkdownTable {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        columns: Option<Vec<String>>,
+    },
+    /// Only source code, optionally in a specific language
+    CodeOnly {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
 }
 
 /// Response containing the composed system prompt and metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PromptResponse {
     pub system_prompt: String,
     pub applied_modules: Vec<String>,
     pub recognized_tools: Vec<String>,
     pub complexity_assessment: TaskComplexity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub complexity_evidence: Option<ComplexityEvidence>,
+    /// Seed used for selection; pass it back as `selection_seed` to reproduce this response
+    pub selection_seed: u64,
+    /// Why each candidate module was selected or skipped, in evaluation order
+    pub selection_trace: Vec<ModuleDecision>,
+    /// Versions of the prompt files composed, for files that declare one
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub content_versions: BTreeMap<String, String>,
+    /// Problems that did not stop composition, for the host to display or log
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    #[serde(default)]
+    pub metadata: ResponseMetadata,
+    /// Where the composition spent its time, when the request set `profile`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<CompositionProfile>,
+}
+
+/// Time spent on each part of a composition, not counting tool discovery
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CompositionProfile {
+    /// Time deciding which modules to compose
+    pub selection_ms: f64,
+    /// Hook sections, modules and tool instructions in the order they were generated,
+    /// including those that contributed nothing
+    pub modules: Vec<ModuleTiming>,
+}
+
+/// Time one part of a composition took to generate its text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ModuleTiming {
+    /// Named as in `applied_modules`
+    pub module: String,
+    /// Time generating the text, including its file loads
+    pub duration_ms: f64,
+    /// Prompt files loaded while generating it, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_loads: Vec<FileLoadTiming>,
+}
+
+/// Time one prompt file took to load
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FileLoadTiming {
+    /// Path relative to the prompts directory, e.g. `behaviors/planning.md`
+    pub file: String,
+    pub duration_ms: f64,
+    /// Whether the content came from a cache instead of the file
+    pub cached: bool,
+}
+
+/// Part of a system prompt passed on while it is composed. Concatenated in order, the
+/// sections' text is the `system_prompt` of the response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PromptSection {
+    /// What produced the text, named as in `applied_modules`, or "composer" for the
+    /// general guidance given when no module produced any
+    pub module: String,
+    /// The text, preceded by the separator from the previous section
+    pub text: String,
+}
+
+/// How a response was produced, for hosts that cache prompts or monitor latency
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ResponseMetadata {
+    /// Time spent composing, including tool discovery when it was part of the call
+    pub duration_ms: f64,
+    /// Where the tools the prompt was composed for came from
+    pub discovery: DiscoveryMode,
+    /// Hex FNV-1a hash of `system_prompt`; equal prompts have equal hashes
+    pub content_hash: String,
+    /// Version of prompt-composer that composed the prompt
+    pub version: String,
+    /// The request's `trace_id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+/// Where the tools for a composition came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum DiscoveryMode {
+    /// Passed to `PromptComposer::compose` by the caller
+    #[default]
+    Provided,
+    /// Discovered for this request and stored in the tool cache: listed by the MCP servers
+    /// when discovery lists servers, inferred from the server patterns otherwise
+    Live,
+    /// Taken from the tool cache, or inferred from the MCP config when the cache is empty
+    Cached,
+    /// Inferred from the MCP config alone, without starting or contacting any server
+    Offline,
+}
+
+impl PromptResponse {
+    /// JSON schema of the response, for validating payloads and generating typed clients
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(PromptResponse)).unwrap_or_default()
+    }
+}
+
+/// Lookups a cache answered and lookups that had to load or discover, since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct CacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheCounters {
+    /// Share of lookups answered from the cache, or `None` before the first lookup
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+
+    /// Lookups counted after `earlier` was taken
+    pub(crate) fn since(self, earlier: CacheCounters) -> CacheCounters {
+        CacheCounters { hits: self.hits.saturating_sub(earlier.hits), misses: self.misses.saturating_sub(earlier.misses) }
+    }
+
+    pub(crate) fn record(&mut self, hit: bool) {
+        match hit {
+            true => self.hits += 1,
+            false => self.misses += 1,
+        }
+    }
+}
+
+/// Size of a cache and how well it answers lookups
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CacheUsage {
+    pub entries: usize,
+    /// Approximate bytes of the keys and content held, not counting the cache's own
+    /// bookkeeping
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Share of lookups answered from the cache, absent before the first lookup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hit_rate: Option<f64>,
+}
+
+impl CacheUsage {
+    pub fn new(entries: usize, bytes: usize, counters: CacheCounters) -> Self {
+        Self { entries, bytes, hits: counters.hits, misses: counters.misses, hit_rate: counters.hit_rate() }
+    }
+}
+
+/// Memory held by the prompt file and tool discovery caches, for hosts that watch it
+/// and clear the caches before they grow too large
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CacheStats {
+    pub prompts: CacheUsage,
+    pub tools: CacheUsage,
+}
+
+impl CacheStats {
+    /// Approximate bytes held by both caches
+    pub fn bytes(&self) -> usize {
+        self.prompts.bytes + self.tools.bytes
+    }
+}
+
+/// A problem that did not stop composition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Warning {
+    pub severity: WarningSeverity,
+    /// Part of composition that raised it: "composer", "selection", or `tool:<server>`
+    pub source: String,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(severity: WarningSeverity, source: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { severity, source: source.into(), message: message.into() }
+    }
+}
+
+/// How much a warning matters to the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+pub enum WarningSeverity {
+    /// Worth logging, such as slow composition
+    Info,
+    /// Part of the prompt is missing or a setting had no effect
+    Warning,
+}
+
+/// Why automatic complexity assessment reached its result
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ComplexityEvidence {
+    pub score: f32,
+    pub signals: Vec<ComplexitySignal>,
+}
+
+/// A single rule that contributed to the complexity score
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ComplexitySignal {
+    /// Kind of rule: `keyword`, `pattern`, `long_prompt`, or `multiple_servers`
+    pub rule: String,
+    /// Text that matched, or a description of the measured value
+    pub matched: String,
+    pub weight: f32,
+}
+
+/// How a module came to be considered during selection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum DecisionSource {
+    /// Requested through domain or behavior hints
+    Hint,
+    /// Auto-detected from the tools, prompt and session state
+    Detected,
+    /// Enabled or disabled by the selection policy
+    Policy,
+    /// Added or removed by another module's `requires` or `conflicts_with`
+    Requirement,
+    /// Driven by a request field such as `response_format`
+    Request,
+}
+
+/// Outcome of evaluating one module during selection
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ModuleDecision {
+    pub module: String,
+    pub source: DecisionSource,
+    pub selected: bool,
+    /// Relevance score, for auto-detected modules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    pub reason: String,
+}
+
+impl ModuleDecision {
+    pub fn selected(module: &str, source: DecisionSource, score: Option<f32>, reason: &str) -> Self {
+        Self { module: module.to_string(), source, selected: true, score, reason: reason.to_string() }
+    }
+
+    pub fn rejected(module: &str, source: DecisionSource, score: Option<f32>, reason: &str) -> Self {
+        Self { module: module.to_string(), source, selected: false, score, reason: reason.to_string() }
+    }
+
+    /// Record that a previously selected module was dropped by a later rule
+    pub(crate) fn reject(trace: &mut Vec<ModuleDecision>, module: &str, source: DecisionSource, reason: &str) {
+        let score = trace.iter().rev()
+            .find(|decision| decision.module == module && decision.selected)
+            .and_then(|decision| decision.score);
+        trace.push(Self::rejected(module, source, score, reason));
+    }
 }
 
-/// Error types for prompt composition
+/// Where a module sits in the composed prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ModuleKind {
+    /// Guidance on using the available tools
+    Tool,
+    /// Domain knowledge such as programming or analysis
+    Domain,
+    /// Behavioral guidance such as planning or safety
+    Behavior,
+    /// Output shape requested by the caller
+    Format,
+    /// Facts supplied with the request, such as the workspace
+    Context,
+}
+
+/// Where a module's implementation comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ModuleSource {
+    /// Shipped with the crate
+    Builtin,
+    /// Registered by the embedding application or a plugin
+    Registered,
+    /// Backed by an external command declared in selection_rules.toml
+    External,
+    /// Defined declaratively in selection_rules.toml
+    Config,
+    /// Loaded from a file in the plugins directory
+    Plugin,
+}
+
+/// Metadata about a module known to a composer
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ModuleInfo {
+    pub name: String,
+    pub kind: ModuleKind,
+    pub source: ModuleSource,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Error a [`PromptError`] wraps as its cause
+pub type ErrorSource = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Error types for prompt composition. Besides the message, variants name the server,
+/// module, file or request field involved when it is known. Module loading and
+/// configuration errors keep the error that caused them as their `source`, whose message
+/// their own ends with.
 #[derive(Debug, Error)]
 pub enum PromptError {
-    #[error("MCP server connection failed: {0}")]
-    McpConnectionFailed(String),
-    #[error("Tool discovery failed: {0}")]
-    ToolDiscoveryFailed(String),
-    #[error("Module loading failed: {0}")]
-    ModuleLoadingFailed(String),
+    #[error("MCP server connection failed: {message}")]
+    McpConnectionFailed { server: Option<String>, message: String },
+    #[error("Tool discovery failed: {message}")]
+    ToolDiscoveryFailed { server: Option<String>, message: String },
+    #[error("Module loading failed: {message}{}", caused_by(.source))]
+    ModuleLoadingFailed {
+        /// Module whose content was being generated
+        module: Option<String>,
+        path: Option<String>,
+        /// Where the prompt file that failed to load was looked for
+        location: Option<Box<PromptFileLocation>>,
+        message: String,
+        source: Option<ErrorSource>,
+    },
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
     #[error("HTTP request failed: {0}")]
     HttpError(#[from] reqwest::Error),
-    #[error("Invalid configuration: {0}")]
-    ConfigError(String),
+    #[error("Invalid configuration: {message}{}", caused_by(.source))]
+    ConfigError {
+        path: Option<String>,
+        server: Option<String>,
+        module: Option<String>,
+        message: String,
+        source: Option<ErrorSource>,
+    },
+    #[error("Invalid request: {message}")]
+    InvalidRequest { field: Option<String>, message: String },
+    #[error("Composition cancelled")]
+    Cancelled,
+}
+
+impl PromptError {
+    pub fn module_loading(message: impl Into<String>) -> Self {
+        PromptError::ModuleLoadingFailed {
+            module: None,
+            path: None,
+            location: None,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        PromptError::ConfigError { path: None, server: None, module: None, message: message.into(), source: None }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        PromptError::InvalidRequest { field: None, message: message.into() }
+    }
+
+    /// Record the module involved, on variants that name one
+    pub fn with_module(mut self, name: impl Into<String>) -> Self {
+        if let PromptError::ModuleLoadingFailed { module, .. } | PromptError::ConfigError { module, .. } = &mut self {
+            *module = Some(name.into());
+        }
+        self
+    }
+
+    /// Record the file involved, on variants that name one
+    pub fn with_path(mut self, file: impl AsRef<std::path::Path>) -> Self {
+        if let PromptError::ModuleLoadingFailed { path, .. } | PromptError::ConfigError { path, .. } = &mut self {
+            *path = Some(file.as_ref().display().to_string());
+        }
+        self
+    }
+
+    /// Record the category of the prompt file involved and the prompts directory it was
+    /// looked for in, on module loading errors
+    pub fn with_prompt_file(mut self, file_category: impl Into<String>, dir: impl Into<String>) -> Self {
+        if let PromptError::ModuleLoadingFailed { location, .. } = &mut self {
+            *location = Some(Box::new(PromptFileLocation { category: file_category.into(), prompts_dir: dir.into() }));
+        }
+        self
+    }
+
+    /// Keep the error that caused this one, on variants that wrap a cause
+    pub fn with_source(mut self, cause: impl Into<ErrorSource>) -> Self {
+        if let PromptError::ModuleLoadingFailed { source, .. } | PromptError::ConfigError { source, .. } = &mut self {
+            *source = Some(cause.into());
+        }
+        self
+    }
+
+    /// Record the MCP server involved, on variants that name one
+    pub fn with_server(mut self, name: impl Into<String>) -> Self {
+        if let PromptError::McpConnectionFailed { server, .. }
+            | PromptError::ToolDiscoveryFailed { server, .. }
+            | PromptError::ConfigError { server, .. } = &mut self
+        {
+            *server = Some(name.into());
+        }
+        self
+    }
+
+    /// Record the request field involved, on variants that name one
+    pub fn with_field(mut self, name: impl Into<String>) -> Self {
+        if let PromptError::InvalidRequest { field, .. } = &mut self {
+            *field = Some(name.into());
+        }
+        self
+    }
+
+    /// Stable code of the error kind, for callers that branch on it
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            PromptError::McpConnectionFailed { .. } => ErrorCode::McpConnectionFailed,
+            PromptError::ToolDiscoveryFailed { .. } => ErrorCode::ToolDiscoveryFailed,
+            PromptError::ModuleLoadingFailed { .. } => ErrorCode::ModuleLoadingFailed,
+            PromptError::SerializationError(_) => ErrorCode::SerializationError,
+            PromptError::HttpError(_) => ErrorCode::HttpError,
+            PromptError::ConfigError { .. } => ErrorCode::ConfigError,
+            PromptError::InvalidRequest { .. } => ErrorCode::InvalidRequest,
+            PromptError::Cancelled => ErrorCode::Cancelled,
+        }
+    }
+
+    /// The error as plain data: its code, message and whatever it names
+    pub fn report(&self) -> ErrorReport {
+        let mut report = ErrorReport {
+            code: self.code(),
+            message: self.to_string(),
+            server: None,
+            module: None,
+            category: None,
+            path: None,
+            prompts_dir: None,
+            field: None,
+            causes: Vec::new(),
+        };
+        let mut cause = std::error::Error::source(self);
+        while let Some(error) = cause {
+            report.causes.push(error.to_string());
+            cause = error.source();
+        }
+        match self {
+            PromptError::McpConnectionFailed { server, .. } | PromptError::ToolDiscoveryFailed { server, .. } => {
+                report.server = server.clone();
+            }
+            PromptError::ModuleLoadingFailed { module, path, location, .. } => {
+                report.module = module.clone();
+                report.path = path.clone();
+                if let Some(location) = location {
+                    report.category = Some(location.category.clone());
+                    report.prompts_dir = Some(location.prompts_dir.clone());
+                }
+            }
+            PromptError::ConfigError { path, server, module, .. } => {
+                report.path = path.clone();
+                report.server = server.clone();
+                report.module = module.clone();
+            }
+            PromptError::InvalidRequest { field, .. } => report.field = field.clone(),
+            PromptError::SerializationError(_) | PromptError::HttpError(_) | PromptError::Cancelled => {}
+        }
+        report
+    }
+}
+
+/// Where a prompt file was looked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptFileLocation {
+    /// "domains", "behaviors" or "tools"
+    pub category: String,
+    /// Prompts directory, or provider, searched
+    pub prompts_dir: String,
+}
+
+/// ": <cause>" for an error with a cause, for its message to end with
+fn caused_by(source: &Option<ErrorSource>) -> String {
+    source.as_ref().map(|source| format!(": {}", source)).unwrap_or_default()
+}
+
+impl Serialize for PromptError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.report().serialize(serializer)
+    }
+}
+
+/// Machine-readable kind of a [`PromptError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ErrorCode {
+    McpConnectionFailed,
+    ToolDiscoveryFailed,
+    ModuleLoadingFailed,
+    SerializationError,
+    HttpError,
+    ConfigError,
+    InvalidRequest,
+    /// The host cancelled the composition
+    Cancelled,
+}
+
+/// Serialized form of a [`PromptError`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorReport {
+    pub code: ErrorCode,
+    /// Human-readable description, the error's `Display` output
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// Category of the prompt file that failed to load
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Prompts directory, or provider, the file was looked for in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompts_dir: Option<String>,
+    /// Request field that failed validation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Messages of the errors that caused this one, the direct cause first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
 }
 
 /// Categories of tools for prompt module selection
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum ToolCategory {
     FileSystem,
     WebApi,
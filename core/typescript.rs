@@ -0,0 +1,186 @@
+//! TypeScript definitions generated from the JSON schemas of the request and response
+//! types, so the Node.js typings cannot drift from the Rust types.
+//!
+//! `node/index.d.ts` embeds the output between the `GENERATED TYPES` markers; a test
+//! fails when it is stale, and `UPDATE_TYPESCRIPT=1 cargo test` rewrites it.
+
+use crate::types::{CacheStats, ErrorReport, ModuleInfo, PromptRequest, PromptResponse, PromptSection, Tool};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Marker lines around the generated section of `node/index.d.ts`
+pub const BEGIN_MARKER: &str = "// BEGIN GENERATED TYPES (prompt_composer::typescript::definitions)";
+pub const END_MARKER: &str = "// END GENERATED TYPES";
+
+/// Interfaces and type aliases for `PromptRequest`, `PromptResponse`, `ErrorReport`,
+/// `Tool`, `PromptSection`, `ModuleInfo`, `CacheStats` and every type they contain
+pub fn definitions() -> String {
+    let roots = [
+        ("PromptRequest", PromptRequest::json_schema()),
+        ("PromptResponse", PromptResponse::json_schema()),
+        ("ErrorReport", serde_json::to_value(schemars::schema_for!(ErrorReport)).unwrap_or_default()),
+        ("Tool", serde_json::to_value(schemars::schema_for!(Tool)).unwrap_or_default()),
+        ("PromptSection", serde_json::to_value(schemars::schema_for!(PromptSection)).unwrap_or_default()),
+        ("ModuleInfo", serde_json::to_value(schemars::schema_for!(ModuleInfo)).unwrap_or_default()),
+        ("CacheStats", serde_json::to_value(schemars::schema_for!(CacheStats)).unwrap_or_default()),
+    ];
+
+    let mut declarations = Vec::new();
+    let mut definitions = BTreeMap::new();
+    for (name, mut schema) in roots {
+        if let Some(Value::Object(nested)) = schema.as_object_mut().and_then(|schema| schema.remove("definitions")) {
+            definitions.extend(nested);
+        }
+        declarations.push(declaration(name, &schema));
+    }
+    declarations.extend(definitions.iter().map(|(name, schema)| declaration(name, schema)));
+    declarations.join("\n\n")
+}
+
+/// `export interface` for object schemas, `export type` for everything else
+fn declaration(name: &str, schema: &Value) -> String {
+    let doc = doc_comment(schema, "");
+    match schema.get("properties").and_then(Value::as_object) {
+        Some(properties) if schema.get("oneOf").is_none() => {
+            format!("{}export interface {} {}", doc, name, object_body(properties, schema, ""))
+        }
+        _ => format!("{}export type {} = {};", doc, name, type_of(schema, "")),
+    }
+}
+
+fn object_body(properties: &Map<String, Value>, schema: &Value, indent: &str) -> String {
+    let required: Vec<&str> = schema.get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let inner = format!("{}  ", indent);
+    let fields: Vec<String> = properties.iter()
+        .map(|(field, property)| {
+            let optional = !required.contains(&field.as_str()) || is_nullable(property);
+            format!(
+                "{}{}{}{}: {};",
+                doc_comment(property, &inner),
+                inner,
+                field,
+                if optional { "?" } else { "" },
+                type_of(property, &inner)
+            )
+        })
+        .collect();
+    format!("{{\n{}\n{}}}", fields.join("\n"), indent)
+}
+
+/// TypeScript type of a schema, leaving out `null`: optional fields are marked with `?`
+fn type_of(schema: &Value, indent: &str) -> String {
+    let Some(object) = schema.as_object() else {
+        // `true` accepts anything
+        return "unknown".to_string();
+    };
+    if let Some(reference) = object.get("$ref").and_then(Value::as_str) {
+        return reference.rsplit('/').next().unwrap_or(reference).to_string();
+    }
+    if let Some(values) = object.get("enum").and_then(Value::as_array) {
+        return union(values.iter().map(|value| match value.as_str() {
+            Some(text) => format!("'{}'", text),
+            None => value.to_string(),
+        }));
+    }
+    for combinator in ["allOf", "anyOf", "oneOf"] {
+        if let Some(variants) = object.get(combinator).and_then(Value::as_array) {
+            return union(variants.iter().filter(|variant| !is_null(variant)).map(|variant| type_of(variant, indent)));
+        }
+    }
+
+    let kind = match object.get("type") {
+        Some(Value::String(kind)) => kind.as_str(),
+        Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).find(|kind| *kind != "null").unwrap_or("null"),
+        _ => return "unknown".to_string(),
+    };
+    match kind {
+        "string" => "string".to_string(),
+        "integer" | "number" => "number".to_string(),
+        "boolean" => "boolean".to_string(),
+        "array" => {
+            let items = object.get("items").map_or_else(|| "unknown".to_string(), |items| type_of(items, indent));
+            if items.contains(' ') { format!("({})[]", items) } else { format!("{}[]", items) }
+        }
+        "object" => match (object.get("properties").and_then(Value::as_object), object.get("additionalProperties")) {
+            (Some(properties), _) => object_body(properties, schema, indent),
+            (None, Some(values)) => format!("{{ [key: string]: {} }}", type_of(values, indent)),
+            (None, None) => "object".to_string(),
+        },
+        _ => "null".to_string(),
+    }
+}
+
+fn union(types: impl Iterator<Item = String>) -> String {
+    let mut members: Vec<String> = Vec::new();
+    for member in types {
+        if !members.contains(&member) {
+            members.push(member);
+        }
+    }
+    members.join(" | ")
+}
+
+fn is_null(schema: &Value) -> bool {
+    schema.get("type").and_then(Value::as_str) == Some("null")
+}
+
+fn is_nullable(schema: &Value) -> bool {
+    let null_type = schema.get("type")
+        .and_then(Value::as_array)
+        .is_some_and(|kinds| kinds.iter().any(|kind| kind == "null"));
+    let null_variant = schema.get("anyOf")
+        .and_then(Value::as_array)
+        .is_some_and(|variants| variants.iter().any(is_null));
+    null_type || null_variant
+}
+
+/// JSDoc for a schema's description, with rustdoc links reduced to code spans
+fn doc_comment(schema: &Value, indent: &str) -> String {
+    let Some(description) = schema.get("description").and_then(Value::as_str) else {
+        return String::new();
+    };
+    let description = description.replace("[`", "`").replace("`]", "`");
+    let lines: Vec<&str> = description.lines().collect();
+    match lines.as_slice() {
+        [line] => format!("{}/** {} */\n", indent, line),
+        _ => {
+            let body: String = lines.iter()
+                .map(|line| format!("{} *{}{}\n", indent, if line.is_empty() { "" } else { " " }, line))
+                .collect();
+            format!("{}/**\n{}{} */\n", indent, body, indent)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_typings_are_generated() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/node/index.d.ts");
+        let typings = std::fs::read_to_string(path).unwrap();
+        let (before, rest) = typings.split_once(BEGIN_MARKER).expect("index.d.ts has the begin marker");
+        let (current, after) = rest.split_once(END_MARKER).expect("index.d.ts has the end marker");
+        let generated = format!("\n{}\n", definitions());
+
+        if std::env::var_os("UPDATE_TYPESCRIPT").is_some() {
+            std::fs::write(path, format!("{}{}{}{}{}", before, BEGIN_MARKER, generated, END_MARKER, after)).unwrap();
+            return;
+        }
+        assert!(current == generated, "node/index.d.ts is stale; run `UPDATE_TYPESCRIPT=1 cargo test`");
+    }
+
+    #[test]
+    fn test_typescript_types() {
+        let definitions = definitions();
+        assert!(definitions.contains("export interface PromptRequest {"));
+        assert!(definitions.contains("  user_prompt: string;"));
+        assert!(definitions.contains("  session_state?: SessionState;"));
+        assert!(definitions.contains("export type TaskComplexity = 'Trivial' | 'Simple' | 'Moderate' | 'Complex' | 'Epic';"));
+        assert!(definitions.contains("  module_versions?: { [key: string]: string };"));
+    }
+}
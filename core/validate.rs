@@ -0,0 +1,253 @@
+//! Checks of a prompts directory, for catching broken content before it is composed,
+//! e.g. in the CI of a repository of prompts.
+
+use crate::discovery::ToolDiscovery;
+use crate::loader::{split_frontmatter, PromptLoader};
+use crate::rules::{DeclarativeModuleRules, PluginFile, SelectionRules};
+use crate::types::ModuleKind;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Prompt files the built-in modules load, as (module, category, file)
+const BUILTIN_FILES: &[(&str, &str, &str)] = &[
+    ("tool_usage", "behaviors", "tools"),
+    ("filesystem", "domains", "filesystem"),
+    ("task_planning", "behaviors", "planning"),
+    ("progress_monitoring", "behaviors", "progress"),
+    ("verification", "behaviors", "verification"),
+    ("checkpoint", "behaviors", "checkpoint"),
+    ("error_recovery", "behaviors", "error_recovery"),
+    ("safety", "behaviors", "safety"),
+    ("memory", "behaviors", "memory"),
+    ("agent_coordination", "behaviors", "orchestrator"),
+    ("agent_coordination", "behaviors", "worker"),
+    ("concise", "behaviors", "concise"),
+    ("programming", "domains", "programming"),
+    ("analysis", "domains", "analysis"),
+    ("system", "domains", "system"),
+    ("web_research", "domains", "web_research"),
+    ("output_format", "behaviors", "format_json"),
+    ("output_format", "behaviors", "format_markdown_table"),
+    ("output_format", "behaviors", "format_code_only"),
+    ("workspace", "behaviors", "workspace"),
+];
+
+/// How much a problem found by validation matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum IssueSeverity {
+    /// Composition still works, but may not do what the author intended
+    Warning,
+    /// Composition fails or leaves out content
+    Error,
+}
+
+/// A problem found in a prompts directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub severity: IssueSeverity,
+    /// File or directory the problem is in
+    pub path: String,
+    pub message: String,
+}
+
+/// Outcome of validating a prompts directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub prompts_dir: String,
+    /// Prompt and configuration files checked
+    pub files_checked: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Whether no issue is an error
+    pub fn is_valid(&self) -> bool {
+        self.issues.iter().all(|issue| issue.severity < IssueSeverity::Error)
+    }
+
+    fn push(&mut self, severity: IssueSeverity, path: impl AsRef<Path>, message: impl Into<String>) {
+        let path = path.as_ref().display().to_string();
+        self.issues.push(ValidationIssue { severity, path, message: message.into() });
+    }
+}
+
+/// Check a prompts directory: its layout, that every prompt file reads and has guidance
+/// left after `extract_guidance`, that its selection rules, plugins and server patterns
+/// parse, and that the prompt files modules load exist. Modules disabled by the
+/// directory's selection policy are not required to have their files.
+pub fn validate_prompts_dir(prompts_dir: &str) -> ValidationReport {
+    let mut report = ValidationReport { prompts_dir: prompts_dir.to_string(), ..Default::default() };
+    let root = Path::new(prompts_dir);
+    if !root.is_dir() {
+        report.push(IssueSeverity::Error, root, "prompts directory does not exist");
+        return report;
+    }
+
+    let loader = PromptLoader::new(Some(prompts_dir.to_string()));
+    for category in ["domains", "behaviors", "tools"] {
+        let dir = root.join(category);
+        if !dir.is_dir() {
+            match category {
+                "tools" => report.push(IssueSeverity::Warning, &dir, "tools directory does not exist; no server gets tool instructions"),
+                _ => report.push(IssueSeverity::Error, &dir, format!("{} directory does not exist", category)),
+            }
+            continue;
+        }
+        check_prompt_files(&dir, &loader, &mut report);
+    }
+
+    let rules = check_selection_rules(root, &mut report);
+    check_server_patterns(root, &mut report);
+
+    let disabled = &rules.policy.disable;
+    for (module, category, file) in BUILTIN_FILES {
+        if !disabled.iter().any(|name| name == module) {
+            require_file(root, category, file, &format!("built-in module '{}'", module), &mut report);
+        }
+    }
+    for declared in rules.modules.iter().filter(|declared| !disabled.contains(&declared.name)) {
+        require_declared_file(root, declared, "selection_rules.toml", &mut report);
+    }
+
+    let plugins_dir = root.join("plugins");
+    let mut plugins: Vec<_> = fs::read_dir(&plugins_dir).into_iter().flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("toml"))
+        .collect();
+    plugins.sort();
+    for path in plugins {
+        report.files_checked += 1;
+        match PluginFile::load(&path) {
+            Ok(plugin) => {
+                let source = path.display().to_string();
+                for declared in &plugin.modules {
+                    require_declared_file(root, declared, &source, &mut report);
+                }
+            }
+            Err(e) => report.push(IssueSeverity::Error, &path, e.to_string()),
+        }
+    }
+
+    report
+}
+
+/// Read every markdown file of a category, reporting files that do not parse or yield
+/// no guidance
+fn check_prompt_files(dir: &Path, loader: &PromptLoader, report: &mut ValidationReport) {
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+        Err(e) => return report.push(IssueSeverity::Error, dir, format!("cannot read directory: {}", e)),
+    };
+    paths.retain(|path| path.extension().and_then(|s| s.to_str()) == Some("md"));
+    paths.sort();
+
+    for path in paths {
+        report.files_checked += 1;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                report.push(IssueSeverity::Error, &path, format!("cannot read file: {}", e));
+                continue;
+            }
+        };
+        if content.starts_with("---\n") && !content["---\n".len()..].contains("\n---") {
+            report.push(IssueSeverity::Error, &path, "frontmatter block is not closed with ---");
+            continue;
+        }
+        let (body, _) = split_frontmatter(&content);
+        if loader.extract_guidance(body).is_empty() {
+            report.push(IssueSeverity::Error, &path, "no guidance is left after extraction; the file has no content but # titles");
+        }
+    }
+}
+
+/// Parse `selection_rules.toml`, returning the rules composition would use
+fn check_selection_rules(root: &Path, report: &mut ValidationReport) -> SelectionRules {
+    let path = root.join("selection_rules.toml");
+    if !path.exists() {
+        return SelectionRules::default();
+    }
+    report.files_checked += 1;
+    let rules = match SelectionRules::load(&root.to_string_lossy()) {
+        Ok(rules) => rules,
+        Err(e) => {
+            report.push(IssueSeverity::Error, &path, e.to_string());
+            return SelectionRules::default();
+        }
+    };
+    if let Err(e) = rules.complexity.compile() {
+        report.push(IssueSeverity::Error, &path, e.to_string());
+    }
+    rules
+}
+
+fn check_server_patterns(root: &Path, report: &mut ValidationReport) {
+    let path = root.join("server_patterns.toml");
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return report.push(
+            IssueSeverity::Warning,
+            &path,
+            "server patterns file does not exist; every server gets a generic execute tool"
+        ),
+    };
+    report.files_checked += 1;
+    if let Err(e) = ToolDiscovery::with_server_patterns(&content) {
+        report.push(IssueSeverity::Error, &path, e.to_string());
+    }
+}
+
+fn require_declared_file(root: &Path, declared: &DeclarativeModuleRules, source: &str, report: &mut ValidationReport) {
+    if declared.text.is_some() {
+        return;
+    }
+    let category = match declared.kind {
+        ModuleKind::Domain => "domains",
+        _ => "behaviors",
+    };
+    let file = declared.content.as_deref().unwrap_or(&declared.name);
+    require_file(root, category, file, &format!("module '{}' declared in {}", declared.name, source), report);
+}
+
+fn require_file(root: &Path, category: &str, file: &str, referrer: &str, report: &mut ValidationReport) {
+    let path = root.join(category).join(format!("{}.md", file));
+    if !path.is_file() {
+        report.push(IssueSeverity::Error, &path, format!("prompt file loaded by {} does not exist", referrer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_prompts_are_valid() {
+        let report = validate_prompts_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts"));
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert!(report.files_checked > 20);
+    }
+
+    #[test]
+    fn test_broken_prompts_are_reported() {
+        let dir = std::env::temp_dir().join(format!("prompt-composer-validate-{}", std::process::id()));
+        fs::create_dir_all(dir.join("behaviors")).unwrap();
+        fs::create_dir_all(dir.join("domains")).unwrap();
+        fs::write(dir.join("behaviors/planning.md"), "# Planning\n\n").unwrap();
+        fs::write(dir.join("behaviors/safety.md"), "---\nversion: v1\n## Safety\n- Ask first").unwrap();
+        fs::write(dir.join("selection_rules.toml"), "[[modules]]\nname = \"style\"\n\n[policy]\ndisable = [\"tool_usage\"]\n").unwrap();
+
+        let report = validate_prompts_dir(&dir.to_string_lossy());
+        assert!(!report.is_valid());
+        let message_for = |file: &str| report.issues.iter()
+            .find(|issue| issue.path.ends_with(file))
+            .map(|issue| issue.message.as_str());
+        assert!(message_for("planning.md").unwrap().contains("no guidance"));
+        assert!(message_for("safety.md").unwrap().contains("frontmatter"));
+        assert!(message_for("style.md").unwrap().contains("selection_rules.toml"));
+        assert!(message_for("programming.md").unwrap().contains("'programming'"));
+        assert!(message_for("tools.md").is_none(), "disabled modules need no files");
+        assert!(report.issues.iter().any(|issue| issue.severity == IssueSeverity::Warning && issue.path.ends_with("tools")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
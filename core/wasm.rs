@@ -0,0 +1,214 @@
+//! Browser bindings, built for wasm32-unknown-unknown with the `wasm` feature.
+//!
+//! Composition here never starts a process or reads a file system: tools are inferred
+//! from the request's MCP config, external modules are left out, and prompts come from
+//! the prompts compiled into the library, files the host passes in, or files fetched
+//! with the global `fetch`.
+
+use crate::composition::PromptComposer;
+use crate::discovery::ToolDiscovery;
+use crate::types::{DiscoveryMode, PromptError, PromptRequest, PromptResponse};
+use js_sys::{Function, Promise, Reflect};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+/// Convert to plain JavaScript objects through JSON, so 64-bit integers such as selection
+/// seeds become numbers like they do in the Node.js bindings rather than failing
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| JsValue::from(JsError::new(&format!("Serialization failed: {}", e))))?;
+    js_sys::JSON::parse(&json)
+}
+
+/// A JavaScript `Error` for a failure, carrying its error report as `code` and `details`
+/// like the errors of the Node.js bindings
+fn composition_error(e: PromptError) -> JsValue {
+    let report = e.report();
+    let error = js_sys::Error::new(&format!("Composition failed: {}", report.message));
+    if let (Ok(code), Ok(details)) = (to_js(&report.code), to_js(&report)) {
+        let _ = Reflect::set(&error, &"code".into(), &code);
+        let _ = Reflect::set(&error, &"details".into(), &details);
+    }
+    error.into()
+}
+
+/// Read a value from a JavaScript object or its JSON, with serde_json's error messages
+fn from_js<T: DeserializeOwned>(value: &JsValue) -> Result<T, String> {
+    let json = match value.as_string() {
+        Some(json) => json,
+        None => js_sys::JSON::stringify(value)
+            .map_err(|_| "value cannot be converted to JSON".to_string())?
+            .into(),
+    };
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// Read a request object or its JSON
+fn request_from_js(request: JsValue) -> Result<PromptRequest, JsValue> {
+    from_js(&request).map_err(|e| composition_error(PromptError::invalid_request(e)))
+}
+
+/// A composer with its own prompts, modules and session state
+#[wasm_bindgen(js_name = PromptComposer)]
+pub struct WasmPromptComposer {
+    composer: PromptComposer,
+    discovery: ToolDiscovery,
+}
+
+impl WasmPromptComposer {
+    fn embedded() -> Self {
+        let mut composer = PromptComposer::embedded();
+        composer.set_offline(true);
+        Self { composer, discovery: ToolDiscovery::embedded() }
+    }
+
+    fn from_files(files: HashMap<String, String>) -> Result<Self, PromptError> {
+        let discovery = match files.get("server_patterns.toml") {
+            Some(patterns) => ToolDiscovery::with_server_patterns(patterns)?,
+            None => ToolDiscovery::embedded(),
+        };
+        let mut composer = PromptComposer::from_files(files);
+        composer.set_offline(true);
+        Ok(Self { composer, discovery })
+    }
+
+    fn compose_request(&mut self, request: &PromptRequest) -> Result<PromptResponse, PromptError> {
+        let start_time = crate::Instant::now();
+        let tools = self.discovery.infer_tools_from_config_immediate(&request.mcp_config);
+        let mut response = self.composer.compose(request, &tools)?;
+        response.metadata.duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+        response.metadata.discovery = DiscoveryMode::Offline;
+        Ok(response)
+    }
+}
+
+#[wasm_bindgen(js_class = PromptComposer)]
+impl WasmPromptComposer {
+    /// A composer for prompt files keyed by their path relative to a prompts directory,
+    /// e.g. `{ "behaviors/planning.md": "..." }`, or for the prompts compiled into the
+    /// library when no files are given
+    #[wasm_bindgen(constructor)]
+    pub fn new(files: JsValue) -> Result<WasmPromptComposer, JsValue> {
+        if files.is_undefined() || files.is_null() {
+            return Ok(Self::embedded());
+        }
+        let files: HashMap<String, String> = from_js(&files)
+            .map_err(|e| composition_error(PromptError::config(format!("Prompt files must map paths to text: {}", e))))?;
+        Self::from_files(files).map_err(composition_error)
+    }
+
+    /// Fetch prompt files from `baseUrl`, a URL ending in `/` laid out like the prompts
+    /// directory, with the global `fetch`. `paths` defaults to the files of the bundled
+    /// prompts; files the server does not have are left out.
+    #[wasm_bindgen(js_name = fromUrl)]
+    pub async fn from_url(base_url: String, paths: Option<Vec<String>>) -> Result<WasmPromptComposer, JsValue> {
+        let paths = paths.unwrap_or_else(|| crate::embedded::FILES.iter().map(|(path, _)| path.to_string()).collect());
+
+        // Start every request before awaiting any of them
+        let global = js_sys::global();
+        let fetch: Function = Reflect::get(&global, &"fetch".into())?.dyn_into()?;
+        let mut responses = Vec::new();
+        for path in paths {
+            let url = format!("{}{}", base_url, path);
+            let response: Promise = fetch.call1(&global, &url.into())?.dyn_into()?;
+            responses.push((path, response));
+        }
+
+        let mut files = HashMap::new();
+        for (path, response) in responses {
+            let response = JsFuture::from(response).await?;
+            if !Reflect::get(&response, &"ok".into())?.is_truthy() {
+                continue;
+            }
+            let text: Function = Reflect::get(&response, &"text".into())?.dyn_into()?;
+            let text: Promise = text.call0(&response)?.dyn_into()?;
+            if let Some(content) = JsFuture::from(text).await?.as_string() {
+                files.insert(path, content);
+            }
+        }
+        Self::from_files(files).map_err(composition_error)
+    }
+
+    /// Where the prompts come from: `<embedded>` or `<memory>`
+    #[wasm_bindgen(getter, js_name = promptsDir)]
+    pub fn prompts_dir(&self) -> String {
+        self.composer.prompts_dir().to_string()
+    }
+
+    /// Compose a system prompt from a request object or its JSON
+    pub fn compose(&mut self, request: JsValue) -> Result<JsValue, JsValue> {
+        let request = request_from_js(request)?;
+        to_js(&self.compose_request(&request).map_err(composition_error)?)
+    }
+
+    /// Decide which modules a request would get without composing it
+    pub fn explain(&mut self, request: JsValue) -> Result<JsValue, JsValue> {
+        let request = request_from_js(request)?;
+        let tools = self.discovery.infer_tools_from_config_immediate(&request.mcp_config);
+        to_js(&self.composer.explain(&request, &tools).map_err(composition_error)?)
+    }
+
+    /// Name, kind, source and enabled state of every module
+    #[wasm_bindgen(js_name = listModules)]
+    pub fn list_modules(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.composer.list_modules())
+    }
+
+    /// Enable or disable a module, returning whether it exists
+    #[wasm_bindgen(js_name = setModuleEnabled)]
+    pub fn set_module_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        self.composer.set_module_enabled(name, enabled)
+    }
+
+    #[wasm_bindgen(js_name = listDomains)]
+    pub fn list_domains(&self) -> Result<Vec<String>, JsValue> {
+        self.composer.list_domains().map_err(composition_error)
+    }
+
+    #[wasm_bindgen(js_name = listBehaviors)]
+    pub fn list_behaviors(&self) -> Result<Vec<String>, JsValue> {
+        self.composer.list_behaviors().map_err(composition_error)
+    }
+
+    /// End a session, calling `on_session_end` on its modules; returns whether it existed
+    #[wasm_bindgen(js_name = endSession)]
+    pub fn end_session(&mut self, session_id: &str) -> bool {
+        self.composer.end_session(session_id)
+    }
+}
+
+thread_local! {
+    /// Composer of the module-level functions, so their sessions persist between calls
+    static DEFAULT_COMPOSER: RefCell<WasmPromptComposer> = RefCell::new(WasmPromptComposer::embedded());
+}
+
+/// Compose a system prompt from a request object or its JSON with the prompts compiled
+/// into the library
+#[wasm_bindgen(js_name = composeSystemPrompt)]
+pub fn compose_system_prompt(request: JsValue) -> Result<JsValue, JsValue> {
+    let request = request_from_js(request)?;
+    let response = DEFAULT_COMPOSER.with(|composer| composer.borrow_mut().compose_request(&request));
+    to_js(&response.map_err(composition_error)?)
+}
+
+/// JSON schema of the request object
+#[wasm_bindgen(js_name = getRequestSchema)]
+pub fn get_request_schema() -> Result<JsValue, JsValue> {
+    to_js(&PromptRequest::json_schema())
+}
+
+/// JSON schema of the response object
+#[wasm_bindgen(js_name = getResponseSchema)]
+pub fn get_response_schema() -> Result<JsValue, JsValue> {
+    to_js(&PromptResponse::json_schema())
+}
+
+/// Version of the library
+#[wasm_bindgen]
+pub fn version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
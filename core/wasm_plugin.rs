@@ -0,0 +1,170 @@
+use crate::loader::PromptLoader;
+use crate::modules::PromptModule;
+use crate::types::*;
+use serde_json::json;
+use std::sync::Mutex;
+use wasmtime::{Engine, Instance, Memory, Module, Store};
+
+/// A prompt module implemented by a compiled WASM plugin.
+///
+/// Plugins export `memory` and the following functions. Strings are UTF-8 and returned as
+/// an `i64` packing the pointer in the high 32 bits and the length in the low 32 bits.
+/// The context passed to `applies_to`, `relevance_score` and `generate_content` is a JSON
+/// object with `tools`, `user_prompt` and `session_state`, written into a buffer the host
+/// obtains from `alloc`.
+///
+/// - `alloc(len: i32) -> i32`
+/// - `name() -> i64`
+/// - `applies_to(ptr: i32, len: i32) -> i32` (non-zero when the module applies)
+/// - `relevance_score(ptr: i32, len: i32) -> f32` (optional)
+/// - `generate_content(ptr: i32, len: i32) -> i64`
+pub struct WasmModule {
+    name: String,
+    runtime: Mutex<WasmRuntime>,
+}
+
+struct WasmRuntime {
+    store: Store<()>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl WasmModule {
+    /// Load a plugin from a `.wasm` (or `.wat`) file
+    pub fn load(path: &str) -> Result<Self, PromptError> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| PromptError::module_loading(format!("Failed to read WASM plugin {}: {}", path, e)).with_path(path))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Instantiate a plugin from its binary or text representation
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PromptError> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, bytes).map_err(plugin_error)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).map_err(plugin_error)?;
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| PromptError::module_loading("WASM plugin does not export memory"))?;
+
+        let mut runtime = WasmRuntime { store, instance, memory };
+        let name_fn = runtime.instance.get_typed_func::<(), i64>(&mut runtime.store, "name").map_err(plugin_error)?;
+        let packed = name_fn.call(&mut runtime.store, ()).map_err(plugin_error)?;
+        let name = runtime.read_string(packed)?;
+
+        Ok(Self { name, runtime: Mutex::new(runtime) })
+    }
+
+    fn context(tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> String {
+        json!({
+            "tools": tools,
+            "user_prompt": user_prompt,
+            "session_state": session_state,
+        }).to_string()
+    }
+
+    fn call<R: wasmtime::WasmResults>(&self, export: &str, context: &str) -> Result<R, PromptError> {
+        let mut guard = crate::lock(&self.runtime);
+        let runtime = &mut *guard;
+        let (ptr, len) = runtime.write_string(context)?;
+        let func = runtime.instance.get_typed_func::<(i32, i32), R>(&mut runtime.store, export).map_err(plugin_error)?;
+        func.call(&mut runtime.store, (ptr, len)).map_err(plugin_error)
+    }
+
+    fn read_output(&self, packed: i64) -> Result<String, PromptError> {
+        let mut runtime = crate::lock(&self.runtime);
+        runtime.read_string(packed)
+    }
+}
+
+impl WasmRuntime {
+    fn write_string(&mut self, text: &str) -> Result<(i32, i32), PromptError> {
+        let len = text.len() as i32;
+        let alloc = self.instance.get_typed_func::<i32, i32>(&mut self.store, "alloc").map_err(plugin_error)?;
+        let ptr = alloc.call(&mut self.store, len).map_err(plugin_error)?;
+        self.memory.write(&mut self.store, ptr as usize, text.as_bytes()).map_err(plugin_error)?;
+        Ok((ptr, len))
+    }
+
+    fn read_string(&mut self, packed: i64) -> Result<String, PromptError> {
+        let ptr = (packed >> 32) as u32 as usize;
+        let len = packed as u32 as usize;
+        let mut buffer = vec![0u8; len];
+        self.memory.read(&self.store, ptr, &mut buffer).map_err(plugin_error)?;
+        String::from_utf8(buffer)
+            .map_err(|e| PromptError::module_loading(format!("WASM plugin returned invalid UTF-8: {}", e)))
+    }
+}
+
+fn plugin_error(e: impl std::fmt::Display) -> PromptError {
+    PromptError::module_loading(format!("WASM plugin error: {}", e))
+}
+
+impl PromptModule for WasmModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generate_content(&self, tools: &[Tool], session_state: &SessionState, _loader: &mut PromptLoader) -> Result<String, PromptError> {
+        let context = Self::context(tools, "", session_state);
+        let packed = self.call::<i64>("generate_content", &context)?;
+        self.read_output(packed)
+    }
+
+    fn applies_to(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> bool {
+        let context = Self::context(tools, user_prompt, session_state);
+        match self.call::<i32>("applies_to", &context) {
+            Ok(applies) => applies != 0,
+            Err(e) => {
+                tracing::warn!(module = %self.name, "applies_to failed: {}", e);
+                false
+            }
+        }
+    }
+
+    fn relevance_score(&self, tools: &[Tool], user_prompt: &str, session_state: &SessionState) -> f32 {
+        let context = Self::context(tools, user_prompt, session_state);
+        match self.call::<f32>("relevance_score", &context) {
+            Ok(score) => score,
+            // The export is optional; fall back to applies_to
+            Err(_) => if self.applies_to(tools, user_prompt, session_state) { 1.0 } else { 0.0 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLUGIN: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (data (i32.const 0) "wasm_notes")
+  (data (i32.const 16) "\nWASM NOTES:\nFrom a plugin.")
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+  (func (export "name") (result i64)
+    (i64.const 10))
+  (func (export "applies_to") (param i32 i32) (result i32)
+    (i32.const 1))
+  (func (export "generate_content") (param i32 i32) (result i64)
+    (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 27))))
+"#;
+
+    #[test]
+    fn test_wasm_plugin_generates_content() {
+        let module = WasmModule::from_bytes(PLUGIN.as_bytes()).unwrap();
+        assert_eq!(module.name(), "wasm_notes");
+
+        let state = SessionState::default();
+        assert!(module.applies_to(&[], "anything", &state));
+        assert_eq!(module.relevance_score(&[], "anything", &state), 1.0);
+
+        let mut loader = PromptLoader::new(None);
+        let content = module.generate_content(&[], &state, &mut loader).unwrap();
+        assert_eq!(content, "\nWASM NOTES:\nFrom a plugin.");
+    }
+}
@@ -0,0 +1,24 @@
+#!/bin/bash
+
+# Regenerate include/prompt_composer.h from core/capi.rs; run after changing the C API
+
+set -e
+
+if [ ! -f "Cargo.toml" ]; then
+    echo "Error: Must run from prompt-composer directory"
+    exit 1
+fi
+
+# The build script writes the header to its OUT_DIR
+out_dir=$(cargo build --features capi --message-format=json \
+    | grep '"reason":"build-script-executed"' \
+    | grep '"package_id":"[^"]*prompt-composer' \
+    | sed -n 's/.*"out_dir":"\([^"]*\)".*/\1/p' \
+    | tail -n 1)
+if [ -z "$out_dir" ]; then
+    echo "Error: cargo did not report the build script's OUT_DIR"
+    exit 1
+fi
+
+cp "$out_dir/prompt_composer.h" include/prompt_composer.h
+echo "✅ Wrote include/prompt_composer.h"
@@ -0,0 +1,87 @@
+/* Generated by cbindgen from core/capi.rs; do not edit. */
+
+#ifndef PROMPT_COMPOSER_H
+#define PROMPT_COMPOSER_H
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Compose a system prompt with the tools of the request's MCP servers, inferred from the
+// server patterns of the prompts directory without starting the servers and kept in the
+// shared tool cache. `prompts_dir` may be `NULL` for the default directory.
+//
+// # Safety
+// Arguments are `NULL` or NUL-terminated strings; `request_json` must not be `NULL`.
+char *pc_compose_system_prompt(const char *request_json, const char *prompts_dir);
+
+// Compose a system prompt from cached tools, or tools inferred from the MCP config,
+// without waiting for any server
+//
+// # Safety
+// As for [`pc_compose_system_prompt`].
+char *pc_compose_system_prompt_cached(const char *request_json, const char *prompts_dir);
+
+// Compose a system prompt without starting a process or contacting the network. With a
+// `NULL` `prompts_dir` the prompts compiled into the library are used.
+//
+// # Safety
+// As for [`pc_compose_system_prompt`].
+char *pc_compose_system_prompt_offline(const char *request_json, const char *prompts_dir);
+
+// The modules a request would get, as a JSON array of decisions, without composing it
+//
+// # Safety
+// As for [`pc_compose_system_prompt`].
+char *pc_explain_selection(const char *request_json, const char *prompts_dir);
+
+// Infer the tools of one server of an MCP config again from the server patterns,
+// replacing its cached tools, and return them as a JSON array
+//
+// # Safety
+// Both arguments are NUL-terminated strings.
+char *pc_refresh_server_tools(const char *server_name, const char *mcp_config_json);
+
+// Registered modules as a JSON array of name, kind, source and enabled state
+char *pc_list_modules(void);
+
+// Enable or disable a registered module, returning whether it exists
+//
+// # Safety
+// `name` is a NUL-terminated string.
+bool pc_set_module_enabled(const char *name, bool enabled);
+
+// End a session, calling `on_session_end` on its modules; returns whether it existed
+//
+// # Safety
+// `session_id` is a NUL-terminated string.
+bool pc_end_session(const char *session_id);
+
+// JSON schema of composition requests
+char *pc_request_schema(void);
+
+// JSON schema of composition responses
+char *pc_response_schema(void);
+
+// Version of the library, a static string the caller must not free
+const char *pc_version(void);
+
+// Error report of the last call on this thread that returned `NULL`, as JSON with
+// `code`, `message` and whatever server, module, path or field it names; `NULL` when
+// the last call succeeded
+char *pc_last_error(void);
+
+// Release a string returned by the library
+//
+// # Safety
+// `text` is `NULL` or a string returned by a `pc_` function other than `pc_version`,
+// and is not used afterwards.
+void pc_string_free(char *text);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PROMPT_COMPOSER_H */
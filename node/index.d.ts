@@ -4,43 +4,333 @@
 /** 
  * System prompt composer - intelligent system prompt generation for AI assistants
  * Direct native bindings with minimal JavaScript wrapper layer
+ *
+ * Every function may be called concurrently from the main thread and worker threads.
+ * The tool discovery cache, registered modules and hooks, session state and event
+ * listeners of the module-level functions are shared by all threads of the process. A
+ * module registered in one thread runs on that thread's event loop, so unregister it
+ * before the thread exits. PromptComposer instances keep their own caches and state, so
+ * create one per worker for independent state.
  */
 
-/** Request object for prompt composition */
+// BEGIN GENERATED TYPES (prompt_composer::typescript::definitions)
+/** Request for prompt composition */
 export interface PromptRequest {
-  user_prompt: string;
-  mcp_config: {
-    mcpServers: { [key: string]: McpServer };
-  };
-  session_state?: SessionState;
-  domain_hints?: string[];
   behavior_hints?: string[];
-  task_complexity?: 'Simple' | 'Complex';
+  /** Earlier turns of the conversation, oldest first. Fills in session state the host did not send, and guidance already given in a system turn is not repeated. */
+  conversation?: Message[];
+  domain_hints?: string[];
+  /** Discover tools and read prompt files again instead of using cached ones, updating the caches with what is found */
+  force_refresh?: boolean;
+  /** Language of the prompt files to compose (e.g. `de` or `pt-BR`): `name.<locale>.md` is read in place of `name.md` where it exists, trying the language without its region next */
+  locale?: string;
+  /** Approximate size limit of the system prompt, in tokens of about four characters. Sections that would exceed it are left out with a warning. */
+  max_tokens?: number;
+  mcp_config: McpConfig;
+  /** Prompt file versions to compose, keyed by file name (e.g. `planning` for task_planning) */
+  module_versions?: { [key: string]: string };
+  /** Per-tenant overrides applied on top of the configured selection policy */
+  policy?: SelectionPolicy;
+  /** Time each module and prompt file load, reporting them in the response's `profile` */
+  profile?: boolean;
+  response_format?: ResponseFormat;
+  /** Seed for randomized selection steps; the response echoes the seed that was used */
+  selection_seed?: number;
+  session_state?: SessionState;
+  task_complexity?: RequestedComplexity;
+  /** Identifier of the host's trace or request, recorded on the composition's log events and echoed in the response metadata, to correlate the composition with the model calls that use its prompt */
+  trace_id?: string;
+  user_prompt: string;
+  /** A/B variant to compose per module name; unassigned modules with configured variants are bucketed by the selection seed */
+  variant_assignments?: { [key: string]: string };
+  /** Environment the assistant works in, stated in the prompt so it need not guess */
+  workspace?: WorkspaceContext;
 }
 
-/** MCP server configuration */
-export interface McpServer {
+/** Response containing the composed system prompt and metadata */
+export interface PromptResponse {
+  applied_modules: string[];
+  complexity_assessment: TaskComplexity;
+  complexity_evidence?: ComplexityEvidence;
+  /** Versions of the prompt files composed, for files that declare one */
+  content_versions?: { [key: string]: string };
+  metadata?: ResponseMetadata;
+  /** Where the composition spent its time, when the request set `profile` */
+  profile?: CompositionProfile;
+  recognized_tools: string[];
+  /** Seed used for selection; pass it back as `selection_seed` to reproduce this response */
+  selection_seed: number;
+  /** Why each candidate module was selected or skipped, in evaluation order */
+  selection_trace: ModuleDecision[];
+  system_prompt: string;
+  /** Problems that did not stop composition, for the host to display or log */
+  warnings?: Warning[];
+}
+
+/** Serialized form of a `PromptError` */
+export interface ErrorReport {
+  /** Category of the prompt file that failed to load */
+  category?: string;
+  /** Messages of the errors that caused this one, the direct cause first */
+  causes?: string[];
+  code: ErrorCode;
+  /** Request field that failed validation */
+  field?: string;
+  /** Human-readable description, the error's `Display` output */
+  message: string;
+  module?: string;
+  path?: string;
+  /** Prompts directory, or provider, the file was looked for in */
+  prompts_dir?: string;
+  server?: string;
+}
+
+/** Information about an available tool from an MCP server */
+export interface Tool {
+  description: string;
+  name: string;
+  schema?: unknown;
+  server: string;
+}
+
+/** Part of a system prompt passed on while it is composed. Concatenated in order, the sections' text is the `system_prompt` of the response. */
+export interface PromptSection {
+  /** What produced the text, named as in `applied_modules`, or "composer" for the general guidance given when no module produced any */
+  module: string;
+  /** The text, preceded by the separator from the previous section */
+  text: string;
+}
+
+/** Metadata about a module known to a composer */
+export interface ModuleInfo {
+  description?: string;
+  enabled: boolean;
+  kind: ModuleKind;
   name: string;
+  source: ModuleSource;
+}
+
+/** Memory held by the prompt file and tool discovery caches, for hosts that watch it and clear the caches before they grow too large */
+export interface CacheStats {
+  prompts: CacheUsage;
+  tools: CacheUsage;
+}
+
+/** Role of the agent receiving the composed prompt in a multi-agent setup */
+export type AgentRole = 'Orchestrator' | 'Worker';
+
+/** Size of a cache and how well it answers lookups */
+export interface CacheUsage {
+  /** Approximate bytes of the keys and content held, not counting the cache's own bookkeeping */
+  bytes: number;
+  entries: number;
+  /** Share of lookups answered from the cache, absent before the first lookup */
+  hit_rate?: number;
+  hits: number;
+  misses: number;
+}
+
+/** Why automatic complexity assessment reached its result */
+export interface ComplexityEvidence {
+  score: number;
+  signals: ComplexitySignal[];
+}
+
+/** A single rule that contributed to the complexity score */
+export interface ComplexitySignal {
+  /** Text that matched, or a description of the measured value */
+  matched: string;
+  /** Kind of rule: `keyword`, `pattern`, `long_prompt`, or `multiple_servers` */
+  rule: string;
+  weight: number;
+}
+
+/** Time spent on each part of a composition, not counting tool discovery */
+export interface CompositionProfile {
+  /** Hook sections, modules and tool instructions in the order they were generated, including those that contributed nothing */
+  modules: ModuleTiming[];
+  /** Time deciding which modules to compose */
+  selection_ms: number;
+}
+
+/** Phase of the conversation, used to pick behavior guidance that fits the current work */
+export type ConversationPhase = 'Exploration' | 'Planning' | 'Execution' | 'Review';
+
+/** How a module came to be considered during selection */
+export type DecisionSource = 'Hint' | 'Detected' | 'Policy' | 'Requirement' | 'Request';
+
+/** Where the tools for a composition came from */
+export type DiscoveryMode = 'Provided' | 'Live' | 'Cached' | 'Offline';
+
+/** Machine-readable kind of a `PromptError` */
+export type ErrorCode = 'McpConnectionFailed' | 'ToolDiscoveryFailed' | 'ModuleLoadingFailed' | 'SerializationError' | 'HttpError' | 'ConfigError' | 'InvalidRequest' | 'Cancelled';
+
+/** Time one prompt file took to load */
+export interface FileLoadTiming {
+  /** Whether the content came from a cache instead of the file */
+  cached: boolean;
+  duration_ms: number;
+  /** Path relative to the prompts directory, e.g. `behaviors/planning.md` */
+  file: string;
+}
+
+/** Complete MCP configuration with all servers */
+export interface McpConfig {
+  mcpServers: { [key: string]: McpServer };
+}
+
+/** Configuration for an MCP server */
+export interface McpServer {
+  args?: string[];
   command: string;
-  args: string[];
   env?: { [key: string]: string };
+  /** Defaults to the server's key when loaded with `McpConfig::from_file` */
+  name?: string;
+}
+
+/** One turn of a conversation */
+export interface Message {
+  content: string;
+  role: MessageRole;
 }
 
-/** Session state for context-aware prompts */
+/** Who produced a conversation turn; the lowercase names used by chat APIs are accepted */
+export type MessageRole = 'System' | 'User' | 'Assistant' | 'Tool';
+
+/** Outcome of evaluating one module during selection */
+export interface ModuleDecision {
+  module: string;
+  reason: string;
+  /** Relevance score, for auto-detected modules */
+  score?: number;
+  selected: boolean;
+  source: DecisionSource;
+}
+
+/** Where a module sits in the composed prompt */
+export type ModuleKind = 'Tool' | 'Domain' | 'Behavior' | 'Format' | 'Context';
+
+/** Where a module's implementation comes from */
+export type ModuleSource = 'Builtin' | 'Registered' | 'External' | 'Config' | 'Plugin';
+
+/** Time one part of a composition took to generate its text */
+export interface ModuleTiming {
+  /** Time generating the text, including its file loads */
+  duration_ms: number;
+  /** Prompt files loaded while generating it, in order */
+  file_loads?: FileLoadTiming[];
+  /** Named as in `applied_modules` */
+  module: string;
+}
+
+/** Complexity a request asks for. `Auto`, like leaving it unset, has the composer assess it; the response always carries a resolved `TaskComplexity`. */
+export type RequestedComplexity = 'Auto' | 'Trivial' | 'Simple' | 'Moderate' | 'Complex' | 'Epic';
+
+/** Required shape of the model's output */
+export type ResponseFormat = {
+  schema?: unknown;
+  type: 'Json';
+} | {
+  columns?: string[];
+  type: 'MarkdownTable';
+} | {
+  language?: string;
+  type: 'CodeOnly';
+};
+
+/** How a response was produced, for hosts that cache prompts or monitor latency */
+export interface ResponseMetadata {
+  /** Hex FNV-1a hash of `system_prompt`; equal prompts have equal hashes */
+  content_hash: string;
+  /** Where the tools the prompt was composed for came from */
+  discovery: DiscoveryMode;
+  /** Time spent composing, including tool discovery when it was part of the call */
+  duration_ms: number;
+  /** The request's `trace_id` */
+  trace_id?: string;
+  /** Version of prompt-composer that composed the prompt */
+  version: string;
+}
+
+/** Overrides applied to module selection after auto-detection, keyed by module name */
+export interface SelectionPolicy {
+  /** Modules never composed; takes precedence over `enable` and explicit hints */
+  disable?: string[];
+  /** Modules always composed, whether or not they were detected */
+  enable?: string[];
+  /** Multipliers applied to auto-detected modules' relevance scores before ranking */
+  weights?: { [key: string]: number };
+}
+
+/** Current session state for context-aware prompt generation */
 export interface SessionState {
-  tool_call_count?: number;
-  original_task?: string;
+  agent_role?: AgentRole;
+  consecutive_failures?: number;
+  current_step?: string;
+  /** Files written or edited earlier in the session */
+  files_modified?: string[];
   has_plan?: boolean;
+  is_long_running?: boolean;
   last_action?: string;
-  current_step?: string;
+  /** Outcomes of the most recent tool calls, oldest first */
+  last_tool_results?: ToolResultSummary[];
+  memories?: string[];
+  original_task?: string;
+  phase?: ConversationPhase;
+  /** Recent tool errors; plain strings are accepted as errors without a tool name */
+  recent_errors?: ToolError[];
+  /** Recent conversation turns, oldest first, used to keep domains from earlier turns active */
+  recent_messages?: string[];
+  /** Identifies the conversation across turns, so modules can keep per-session state */
+  session_id?: string;
+  tool_call_count?: number;
 }
 
-/** Response from prompt composition */
-export interface PromptResponse {
-  system_prompt: string;
-  applied_modules: string[];
-  recognized_tools: string[];
-  complexity_assessment: 'Simple' | 'Complex';
+/** Assessed task complexity, ordered from least to most effort */
+export type TaskComplexity = 'Trivial' | 'Simple' | 'Moderate' | 'Complex' | 'Epic';
+
+/** An error reported by a tool call */
+export interface ToolError {
+  message: string;
+  /** Tool that failed, when known */
+  tool?: string;
+}
+
+/** Short outcome of a tool call */
+export interface ToolResultSummary {
+  success: boolean;
+  /** One-line description of the result */
+  summary?: string;
+  tool: string;
+}
+
+/** A problem that did not stop composition */
+export interface Warning {
+  message: string;
+  severity: WarningSeverity;
+  /** Part of composition that raised it: "composer", "selection", or `tool:<server>` */
+  source: string;
+}
+
+/** How much a warning matters to the host */
+export type WarningSeverity = 'Info' | 'Warning';
+
+/** Where the host runs the assistant; every field is optional */
+export interface WorkspaceContext {
+  /** Working directory relative paths resolve against */
+  cwd?: string;
+  git_branch?: string;
+  os?: string;
+  /** Kind of project in the working directory (e.g. "rust", "node") */
+  project_type?: string;
+  shell?: string;
+}
+// END GENERATED TYPES
+
+/** Error thrown when composition fails */
+export interface CompositionError extends Error {
+  code: ErrorCode;
+  details: ErrorReport;
 }
 
 /** Status information */
@@ -52,11 +342,254 @@ export interface StatusResponse {
   version: string;
 }
 
+/**
+ * Per-call options of the compose functions. Passing a string instead is the same as
+ * passing { promptsDir }.
+ */
+export interface ComposeOptions {
+  /**
+   * Directory the prompt files are read from; the bundled prompts when unset, or the
+   * prompts compiled into the addon when offline
+   */
+  promptsDir?: string;
+  /** Approximate size limit of the system prompt in tokens; sets the request's max_tokens */
+  maxTokens?: number;
+  /** Output shape to ask for; sets the request's response_format */
+  outputFormat?: ResponseFormat;
+  /** Language of the prompt files to compose, e.g. 'de' or 'pt-BR'; sets the request's locale */
+  locale?: string;
+  /**
+   * Where tools come from: 'cached' (default) uses discovered tools or infers them from
+   * the config, 'live' discovers them for the request first, which lists them from the
+   * MCP servers only for a PromptComposer created with listServersTimeoutMs and infers
+   * them from the server patterns otherwise. 'live' is only available to asynchronous
+   * compositions.
+   */
+  cache?: 'cached' | 'live';
+  /**
+   * Compose without starting a process or contacting the network, for sandboxed hosts
+   * such as Electron renderers: tools are only inferred from the config, external modules
+   * are left out, and no prompt files are read unless promptsDir is given. Cannot be
+   * combined with cache 'live'; responses report discovery 'Offline'.
+   */
+  offline?: boolean;
+}
+
 /** 
  * Compose a system prompt based on request object or JSON string
- * Accepts either a PromptRequest object or JSON string representation
+ * Accepts either a PromptRequest object or JSON string representation.
+ * Throws a CompositionError when composition fails
+ */
+export declare function composeSystemPrompt(request: PromptRequest | string, options?: ComposeOptions | string): PromptResponse;
+
+/**
+ * Compose a system prompt off the JavaScript thread.
+ * Required when modules are registered with registerModule.
+ */
+export declare function composeSystemPromptAsync(request: PromptRequest | string, options?: ComposeOptions | string): Promise<PromptResponse>;
+
+/**
+ * Compose a system prompt after discovering the tools of the request's MCP servers,
+ * off the JavaScript thread: composeSystemPromptAsync with cache 'live'. The shared
+ * discovery infers them from the server patterns without starting the servers; use a
+ * PromptComposer with listServersTimeoutMs to list them from the servers.
+ * composeSystemPrompt only uses cached or inferred tools; the tools discovered here are
+ * cached for it.
+ */
+export declare function composeSystemPromptFull(request: PromptRequest | string, options?: ComposeOptions | string): Promise<PromptResponse>;
+
+/**
+ * Compose a system prompt off the JavaScript thread, yielding its sections as they are
+ * generated so token counting and caching can start before it is complete. Concatenated
+ * in order, the sections' text is the system prompt; the generator's return value is the
+ * PromptResponse.
+ */
+export declare function composeSystemPromptStream(
+  request: PromptRequest | string,
+  options?: ComposeOptions | string
+): AsyncGenerator<PromptSection, PromptResponse, undefined>;
+
+/**
+ * Decide which modules a request would get without composing it: the decisions a
+ * composition reports in selection_trace, for showing users which guidance is active
+ */
+export declare function explainComposition(request: PromptRequest | string, options?: ComposeOptions | string): ModuleDecision[];
+
+/** Name, kind, source and enabled state of every built-in and registered module */
+export declare function listModules(): ModuleInfo[];
+
+/**
+ * Enable or disable a module for every subsequent composition, returning whether the
+ * module exists
  */
-export declare function composeSystemPrompt(request: PromptRequest | string): PromptResponse;
+export declare function setModuleEnabled(name: string, enabled: boolean): boolean;
+
+/** Infer one server's tools again from the server patterns, replacing its cached tools */
+export declare function refreshServerTools(serverName: string, mcpConfig: McpConfig): Promise<Tool[]>;
+
+/** Entries, approximate bytes and hit rates of the caches the composition functions share */
+export declare function cacheStats(): CacheStats;
+
+/** Forget the discovered tools and prompt files the composition functions share */
+export declare function clearCache(): void;
+
+/** Fields shared by the events of one composition */
+interface CompositionEventBase {
+  /** Number shared by the events of one composition */
+  composition: number;
+  session_id: string | null;
+}
+
+export interface ComposeStartEvent extends CompositionEventBase {
+  type: 'composeStart';
+  /** Names of the request's MCP servers */
+  servers: string[];
+}
+
+export interface ComposeEndEvent extends CompositionEventBase {
+  type: 'composeEnd';
+  duration_ms: number;
+  complexity?: TaskComplexity;
+  applied_modules?: number;
+  warnings?: number;
+  /** Set when composition failed */
+  error?: ErrorReport;
+}
+
+export interface ModuleAppliedEvent extends CompositionEventBase {
+  type: 'moduleApplied';
+  module: string;
+}
+
+export interface WarningEvent extends CompositionEventBase, Warning {
+  type: 'warning';
+}
+
+export type CompositionEvent = ComposeStartEvent | ComposeEndEvent | ModuleAppliedEvent | WarningEvent;
+
+/**
+ * Emits the telemetry events of every composition: composeStart, then moduleApplied for
+ * each applied module and warning for each warning, then composeEnd. Events arrive
+ * asynchronously on the JavaScript thread, after synchronous compositions return, and
+ * do not keep the process alive.
+ */
+export declare const compositionEvents: {
+  on(event: 'composeStart', listener: (event: ComposeStartEvent) => void): typeof compositionEvents;
+  on(event: 'composeEnd', listener: (event: ComposeEndEvent) => void): typeof compositionEvents;
+  on(event: 'moduleApplied', listener: (event: ModuleAppliedEvent) => void): typeof compositionEvents;
+  on(event: 'warning', listener: (event: WarningEvent) => void): typeof compositionEvents;
+  once(event: CompositionEvent['type'], listener: (event: CompositionEvent) => void): typeof compositionEvents;
+  off(event: CompositionEvent['type'], listener: (event: CompositionEvent) => void): typeof compositionEvents;
+} & import('events').EventEmitter;
+
+/** Where loadMcpConfig reads the MCP configuration from */
+export interface McpConfigSource {
+  /**
+   * A client whose own configuration file is located for this platform and project, or
+   * the path of a file in the `{"mcpServers": {...}}` or VS Code `{"servers": {...}}` format
+   */
+  source: 'claude' | 'vscode' | 'cursor' | string;
+}
+
+/**
+ * Read an MCP client's configuration into the McpConfig of a request. Servers reached by
+ * URL rather than launched with a command are skipped.
+ */
+export declare function loadMcpConfig(options: McpConfigSource | string): McpConfig;
+
+/** Options of a PromptComposer instance */
+export interface ComposerOptions {
+  /**
+   * Directory the prompt files are read from; the bundled prompts when unset, or the
+   * prompts compiled into the addon when offline
+   */
+  promptsDir?: string;
+  /** Compose every request offline, as the offline option of the compose functions does */
+  offline?: boolean;
+  /** Snapshot from `exportConfig` to recreate; other options are ignored */
+  config?: string;
+  /**
+   * Start the request's MCP servers and list their tools over stdio when discovering,
+   * waiting up to this many milliseconds for each. Tools are inferred from the server
+   * patterns when unset, offline, or for a server that fails to start or answer.
+   */
+  listServersTimeoutMs?: number;
+}
+
+/**
+ * A composer with its own prompts directory, prompt file cache, tool discovery cache,
+ * session state and modules, for hosts that keep workspaces apart. Modules registered
+ * with registerModule before it was created take part in its compositions; those
+ * registered on the instance apply to it alone. Its methods take the per-call options
+ * except promptsDir and offline, which are fixed by the constructor. Compositions of one instance run
+ * one at a time; a synchronous call waits for an async one in progress.
+ */
+export declare class PromptComposer {
+  constructor(options?: ComposerOptions);
+  /** Directory the prompt files are read from; '<embedded>' for the compiled-in prompts */
+  readonly promptsDir: string;
+  /** Compose with this instance's cached tools, or tools inferred from the config */
+  compose(request: PromptRequest | string, options?: ComposeOptions): PromptResponse;
+  /** Compose off the JavaScript thread; required when modules are registered on the instance */
+  composeAsync(request: PromptRequest | string, options?: ComposeOptions): Promise<PromptResponse>;
+  /**
+   * Compose after discovering the tools of the request's MCP servers into this instance's
+   * cache: listed by the servers with listServersTimeoutMs, inferred otherwise
+   */
+  composeFull(request: PromptRequest | string, options?: ComposeOptions): Promise<PromptResponse>;
+  /** Decide which modules a request would get without composing it or touching session state */
+  explainComposition(request: PromptRequest | string, options?: ComposeOptions): ModuleDecision[];
+  /** Name, kind, source and enabled state of every module of this instance */
+  listModules(): ModuleInfo[];
+  /** Enable or disable a module for this instance, returning whether it exists */
+  setModuleEnabled(name: string, enabled: boolean): boolean;
+  /**
+   * Snapshot of this instance's prompts location, selection rules, offline mode and module
+   * states as JSON. Modules registered from JavaScript are not part of it.
+   */
+  exportConfig(): string;
+  /** Recreate a composer from a snapshot made by `exportConfig`, in this or another process */
+  static importConfig(config: string): PromptComposer;
+  /** Discover one server's tools again as composeFull does, replacing them in this instance's cache */
+  refreshServer(serverName: string, mcpConfig: McpConfig): Promise<Tool[]>;
+  listDomains(): string[];
+  listBehaviors(): string[];
+  /** MCP servers with tool instructions in the prompts directory */
+  listTools(): string[];
+  /** Forget cached prompt files and discovered tools, so changes on disk take effect */
+  clearCache(): void;
+  /** Entries, approximate bytes and hit rates of this instance's prompt file and tool caches */
+  cacheStats(): CacheStats;
+  /** End a session, calling on_session_end on its modules; returns whether it existed */
+  endSession(sessionId: string): boolean;
+  registerModule(
+    name: string,
+    generateContent: (context: ModuleContext) => string,
+    appliesTo?: (context: ModuleContext) => boolean
+  ): void;
+  unregisterModule(name: string): boolean;
+}
+
+/** Request context passed to JavaScript module callbacks */
+export interface ModuleContext {
+  tools: { name: string; description: string; server: string }[];
+  /** Empty when generating content */
+  user_prompt: string;
+  session_state: SessionState;
+}
+
+/**
+ * Register a module implemented in JavaScript. It takes part in composeSystemPromptAsync;
+ * without applies_to it applies to every request.
+ */
+export declare function registerModule(
+  name: string,
+  generateContent: (context: ModuleContext) => string,
+  appliesTo?: (context: ModuleContext) => boolean
+): void;
+
+/** Remove a registered module, returning whether it was registered */
+export declare function unregisterModule(name: string): boolean;
 
 /** List available domain modules */
 export declare function listAvailableDomains(): string[];
@@ -69,3 +602,9 @@ export declare function isAvailable(): boolean;
 
 /** Get status information including available domains and behaviors */
 export declare function getStatus(): StatusResponse;
+
+/** JSON schema of PromptRequest, generated from the Rust types */
+export declare function getRequestSchema(): object;
+
+/** JSON schema of PromptResponse, generated from the Rust types */
+export declare function getResponseSchema(): object;
@@ -6,6 +6,7 @@
 
 const { existsSync, readFileSync } = require('fs')
 const { join } = require('path')
+const { EventEmitter } = require('events')
 
 const { platform, arch } = process
 
@@ -311,17 +312,292 @@ if (!nativeBinding) {
 }
 
 const { 
-  composeSystemPromptWithPromptsDir,
+  compose: nativeCompose,
+  composeAsync: nativeComposeAsync,
+  composeFull: nativeComposeFull,
+  refreshServerTools: nativeRefreshServerTools,
+  PromptComposer: NativePromptComposer,
+  subscribe: nativeSubscribe,
+  loadMcpConfig: nativeLoadMcpConfig,
+  composeStream: nativeComposeStream,
+  explain: nativeExplain,
+  listModules,
+  setModuleEnabled,
+  unsubscribe: nativeUnsubscribe,
+  registerModule,
+  unregisterModule,
   isAvailable, 
-  getStatus: nativeGetStatus 
+  getStatus: nativeGetStatus,
+  getRequestSchema: nativeGetRequestSchema,
+  getResponseSchema: nativeGetResponseSchema
 } = nativeBinding
 
-// Minimal convenience functions for data marshaling only
-function composeSystemPrompt(request) {
-  const requestJson = typeof request === 'string' ? request : JSON.stringify(request);
-  const promptsDir = join(__dirname, 'prompts');
-  const responseJson = composeSystemPromptWithPromptsDir(requestJson, promptsDir);
-  return JSON.parse(responseJson);
+// Composition failures carry a JSON error report; rethrow them with code and details
+function rethrowCompositionError(error, context = 'Composition failed') {
+  let report;
+  try {
+    report = JSON.parse(error.message);
+  } catch (_) {
+    throw error;
+  }
+  const composed = new Error(`${context}: ${report.message}`);
+  composed.code = report.code;
+  composed.details = report;
+  throw composed;
+}
+
+// Requests are passed to the native functions as objects; JSON strings are parsed first
+function toRequest(request) {
+  return typeof request === 'string' ? JSON.parse(request) : request;
+}
+
+// Per-call options, with defaults filled in. A string is the prompts directory, as the
+// second argument was before options existed.
+function toOptions(options) {
+  const { offline = false, cache = 'cached', ...rest } =
+    typeof options === 'string' ? { promptsDir: options } : options || {};
+  if (cache !== 'cached' && cache !== 'live') {
+    throw new TypeError(`cache must be 'cached' or 'live', got ${JSON.stringify(cache)}`);
+  }
+  if (offline && cache === 'live') {
+    throw new TypeError("Offline composition infers tools from the config; cache: 'live' is not available");
+  }
+  // Offline, the prompts compiled into the addon are read unless a directory is given
+  const { promptsDir = offline ? undefined : join(__dirname, 'prompts') } = rest;
+  return { ...rest, promptsDir, cache, offline };
+}
+
+// The request with the options that are request fields applied
+function withOptions(request, options) {
+  const overrides = {};
+  if (options.maxTokens !== undefined) overrides.max_tokens = options.maxTokens;
+  if (options.outputFormat !== undefined) overrides.response_format = options.outputFormat;
+  if (options.locale !== undefined) overrides.locale = options.locale;
+  return { ...toRequest(request), ...overrides };
+}
+
+// Discovery for the request may start servers to list them, so only asynchronous calls offer it
+function requireCachedTools(options, caller) {
+  if (options.cache === 'live') {
+    throw new TypeError(`${caller} composes with cached tools; use composeSystemPromptAsync for cache: 'live'`);
+  }
+}
+
+function composeSystemPrompt(request, options) {
+  options = toOptions(options);
+  requireCachedTools(options, 'composeSystemPrompt');
+  try {
+    return nativeCompose(withOptions(request, options), options.promptsDir, options.offline);
+  } catch (error) {
+    rethrowCompositionError(error);
+  }
+}
+
+async function composeSystemPromptAsync(request, options) {
+  options = toOptions(options);
+  const compose = options.cache === 'live' ? nativeComposeFull : nativeComposeAsync;
+  try {
+    return await compose(withOptions(request, options), options.promptsDir, options.offline);
+  } catch (error) {
+    rethrowCompositionError(error);
+  }
+}
+
+// Discovers the tools of the request's MCP servers before composing; the shared discovery
+// infers them from the server patterns
+async function composeSystemPromptFull(request, options) {
+  return composeSystemPromptAsync(request, { ...toOptions(options), cache: 'live' });
+}
+
+async function refreshServerTools(serverName, mcpConfig) {
+  try {
+    return await nativeRefreshServerTools(serverName, mcpConfig);
+  } catch (error) {
+    rethrowCompositionError(error);
+  }
+}
+
+// Telemetry of every composition. The native listener is subscribed only while one of
+// the composition events has a listener, so compositions record nothing otherwise.
+const COMPOSITION_EVENTS = ['composeStart', 'composeEnd', 'moduleApplied', 'warning'];
+const compositionEvents = new EventEmitter();
+let subscription = null;
+
+compositionEvents.on('removeListener', () => {
+  const listening = COMPOSITION_EVENTS.some((name) => compositionEvents.listenerCount(name) > 0);
+  if (subscription !== null && !listening) {
+    nativeUnsubscribe(subscription);
+    subscription = null;
+  }
+});
+
+compositionEvents.on('newListener', (name) => {
+  if (subscription === null && COMPOSITION_EVENTS.includes(name)) {
+    subscription = nativeSubscribe((event) => compositionEvents.emit(event.type, event));
+  }
+});
+
+// A composer with its own prompts directory, caches, session state and modules
+class PromptComposer {
+  #native;
+  #offline;
+
+  constructor(options = {}) {
+    const { offline = false, config, promptsDir = offline || config ? undefined : join(__dirname, 'prompts') } = options;
+    try {
+      this.#native = new NativePromptComposer({ ...options, promptsDir, offline });
+    } catch (error) {
+      rethrowCompositionError(error, 'Importing configuration failed');
+    }
+    this.#offline = this.#native.offline;
+  }
+
+  // Recreate a composer from a snapshot made by exportConfig
+  static importConfig(config) {
+    return new PromptComposer({ config });
+  }
+
+  // Per-call options; the prompts directory and offline mode are fixed by the constructor
+  #options(options) {
+    return toOptions({ ...(typeof options === 'string' ? {} : options), offline: this.#offline });
+  }
+
+  get promptsDir() {
+    return this.#native.promptsDir;
+  }
+
+  // The instance's prompts directory applies; promptsDir in options is ignored
+  compose(request, options) {
+    options = this.#options(options);
+    requireCachedTools(options, 'compose');
+    try {
+      return this.#native.compose(withOptions(request, options));
+    } catch (error) {
+      rethrowCompositionError(error);
+    }
+  }
+
+  async composeAsync(request, options) {
+    options = this.#options(options);
+    try {
+      const native = this.#native;
+      const compose = options.cache === 'live' ? native.composeFull : native.composeAsync;
+      return await compose.call(native, withOptions(request, options));
+    } catch (error) {
+      rethrowCompositionError(error);
+    }
+  }
+
+  async composeFull(request, options) {
+    return this.composeAsync(request, { ...this.#options(options), cache: 'live' });
+  }
+
+  async refreshServer(serverName, mcpConfig) {
+    try {
+      return await this.#native.refreshServer(serverName, mcpConfig);
+    } catch (error) {
+      rethrowCompositionError(error);
+    }
+  }
+
+  explainComposition(request, options) {
+    try {
+      return this.#native.explain(withOptions(request, this.#options(options)));
+    } catch (error) {
+      rethrowCompositionError(error, 'Explaining composition failed');
+    }
+  }
+
+  listModules() {
+    return this.#native.listModules();
+  }
+
+  exportConfig() {
+    return this.#native.exportConfig();
+  }
+
+  setModuleEnabled(name, enabled) {
+    return this.#native.setModuleEnabled(name, enabled);
+  }
+
+  listDomains() {
+    return this.#native.listDomains();
+  }
+
+  listBehaviors() {
+    return this.#native.listBehaviors();
+  }
+
+  listTools() {
+    return this.#native.listTools();
+  }
+
+  clearCache() {
+    this.#native.clearCache();
+  }
+
+  endSession(sessionId) {
+    return this.#native.endSession(sessionId);
+  }
+
+  registerModule(name, generateContent, appliesTo) {
+    this.#native.registerModule(name, generateContent, appliesTo);
+  }
+
+  unregisterModule(name) {
+    return this.#native.unregisterModule(name);
+  }
+}
+
+function explainComposition(request, options) {
+  options = toOptions(options);
+  try {
+    return nativeExplain(withOptions(request, options), options.promptsDir, options.offline);
+  } catch (error) {
+    rethrowCompositionError(error, 'Explaining composition failed');
+  }
+}
+
+// Yields the sections of the prompt as they are generated; the generator's return value
+// is the PromptResponse
+async function* composeSystemPromptStream(request, options) {
+  options = toOptions(options);
+  requireCachedTools(options, 'composeSystemPromptStream');
+  const events = [];
+  let wake = null;
+  nativeComposeStream(withOptions(request, options), options.promptsDir, (event) => {
+    events.push(event);
+    if (wake) {
+      wake();
+      wake = null;
+    }
+  }, options.offline);
+  for (;;) {
+    if (events.length === 0) {
+      await new Promise((resolve) => { wake = resolve; });
+    }
+    const event = events.shift();
+    if (event.type === 'section') {
+      yield event.section;
+    } else if (event.type === 'error') {
+      rethrowCompositionError(new Error(JSON.stringify(event.error)));
+    } else {
+      return event.response;
+    }
+  }
+}
+
+// Accepts { source } or the source itself: a client name or a config file path
+function loadMcpConfig(options) {
+  const source = typeof options === 'string' ? options : options && options.source;
+  if (typeof source !== 'string') {
+    throw new TypeError("loadMcpConfig expects { source: 'claude' | 'vscode' | 'cursor' | path }");
+  }
+  try {
+    return nativeLoadMcpConfig(source);
+  } catch (error) {
+    rethrowCompositionError(error, 'Loading MCP config failed');
+  }
 }
 
 function getStatus() {
@@ -329,16 +605,36 @@ function getStatus() {
   return JSON.parse(statusJson);
 }
 
+function getRequestSchema() {
+  return JSON.parse(nativeGetRequestSchema());
+}
+
+function getResponseSchema() {
+  return JSON.parse(nativeGetResponseSchema());
+}
+
 function composeSystemPromptWithCustomDir(request, promptsDir) {
-  const requestJson = typeof request === 'string' ? request : JSON.stringify(request);
-  const responseJson = composeSystemPromptWithPromptsDir(requestJson, promptsDir);
-  return JSON.parse(responseJson);
+  return composeSystemPrompt(request, { promptsDir });
 }
 
 // Export the minimal API
 module.exports = {
+  PromptComposer,
+  compositionEvents,
   composeSystemPrompt,
   composeSystemPromptWithCustomDir,
+  composeSystemPromptAsync,
+  composeSystemPromptFull,
+  composeSystemPromptStream,
+  explainComposition,
+  listModules,
+  setModuleEnabled,
+  refreshServerTools,
+  loadMcpConfig,
+  registerModule,
+  unregisterModule,
   isAvailable,
-  getStatus
+  getStatus,
+  getRequestSchema,
+  getResponseSchema
 }
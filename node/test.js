@@ -7,6 +7,12 @@
 
 const { 
   composeSystemPrompt, 
+  PromptComposer,
+  compositionEvents,
+  composeSystemPromptAsync,
+  composeSystemPromptStream,
+  explainComposition,
+  listModules,
   isAvailable, 
   getStatus 
 } = require('./index.js');
@@ -198,8 +204,204 @@ async function runTests() {
     assert(fs.existsSync(path.join(promptsDir, 'domains')), 'Domains directory exists');
     assert(fs.existsSync(path.join(promptsDir, 'behaviors')), 'Behaviors directory exists');
 
-    // Test 10: Performance test
-    log(colors.bold, '\n10. Testing performance...');
+    // Test 8: Isolated composer instances
+    log(colors.bold, '\n8. Testing PromptComposer instances...');
+    const first = new PromptComposer();
+    const second = new PromptComposer({ promptsDir });
+    assert(second.promptsDir === promptsDir, 'Instance uses its prompts directory');
+    first.registerModule('workspace-note', () => 'FIRST WORKSPACE ONLY');
+    const instanceRequest = { user_prompt: 'Write a function', mcp_config: { mcpServers: {} } };
+    const firstResponse = await first.composeAsync(instanceRequest);
+    const secondResponse = await second.composeAsync(instanceRequest);
+    assert(firstResponse.system_prompt.includes('FIRST WORKSPACE ONLY'), 'Instance module applies to its instance');
+    assert(!secondResponse.system_prompt.includes('FIRST WORKSPACE ONLY'), 'Instance module does not leak to other instances');
+    assert(Array.isArray(second.listDomains()), 'Instance lists its domains');
+
+    const rejection = async (composer, request) => {
+      try {
+        await composer.composeAsync(request);
+        return null;
+      } catch (error) {
+        return error;
+      }
+    };
+    const throwing = new PromptComposer();
+    throwing.registerModule('release-notes', () => { throw new Error('changelog missing'); });
+    const thrown = await rejection(throwing, instanceRequest);
+    assert(thrown && thrown.code === 'ModuleLoadingFailed' && thrown.message.includes('changelog missing'), 'A module that throws fails composition', thrown && thrown.message);
+    const mistyped = new PromptComposer();
+    mistyped.registerModule('release-notes', () => 42);
+    const wrongType = await rejection(mistyped, instanceRequest);
+    assert(wrongType && wrongType.code === 'ModuleLoadingFailed' && wrongType.message.includes('returned number'), 'A module returning a non-string fails composition', wrongType && wrongType.message);
+    try {
+      mistyped.compose(instanceRequest);
+      assert(false, 'Synchronous composition rejects JavaScript modules');
+    } catch (error) {
+      assert(error.message.includes('can only run from composeSystemPromptAsync'), 'Synchronous composition rejects JavaScript modules', error.message);
+    }
+    const picky = new PromptComposer();
+    picky.registerModule('release-notes', () => 'RELEASE NOTES', () => { throw new Error('no release'); });
+    assert(!(await picky.composeAsync(instanceRequest)).applied_modules.includes('release-notes'), 'A module whose appliesTo throws is left out');
+    const reentrant = new PromptComposer({ promptsDir });
+    reentrant.registerModule('module-count', () => `MODULES: ${reentrant.listModules().length}, DOMAINS: ${reentrant.listDomains().length}`);
+    const reentered = await reentrant.composeAsync(instanceRequest);
+    assert(/MODULES: \d+, DOMAINS: \d+/.test(reentered.system_prompt), 'A module can call its instance while it composes');
+
+    // An MCP server answering initialize and tools/list on stdio
+    const listedServer = `
+      const lines = require('readline').createInterface({ input: process.stdin });
+      lines.on('line', (line) => {
+        const { id, method } = JSON.parse(line);
+        const result = method === 'initialize' ? { protocolVersion: '2025-06-18', capabilities: { tools: {} } }
+          : method === 'tools/list' ? { tools: [{ name: 'search_tickets', description: 'Search the tickets' }] } : null;
+        if (result) console.log(JSON.stringify({ jsonrpc: '2.0', id, result }));
+      });`;
+    const listedRequest = {
+      user_prompt: 'Find my tickets',
+      mcp_config: { mcpServers: { tracker: { name: 'tracker', command: process.execPath, args: ['-e', listedServer] } } }
+    };
+    const listing = new PromptComposer({ promptsDir, listServersTimeoutMs: 10000 });
+    const listed = await listing.composeFull(listedRequest);
+    assert(listed.recognized_tools.includes('tracker.search_tickets'), 'An instance listing servers composes with their listed tools', JSON.stringify(listed.recognized_tools));
+    const inferred = await new PromptComposer({ promptsDir }).composeFull(listedRequest);
+    assert(!inferred.recognized_tools.includes('tracker.search_tickets'), 'An instance infers tools unless it lists servers', JSON.stringify(inferred.recognized_tools));
+    const refreshed = await listing.refreshServer('tracker', listedRequest.mcp_config);
+    assert(refreshed.map((tool) => tool.name).join() === 'tracker.search_tickets', 'Refreshing a server lists its tools again', JSON.stringify(refreshed));
+
+    // Test 9: Composition telemetry
+    log(colors.bold, '\n9. Testing composition events...');
+    const events = [];
+    const record = (event) => events.push(event);
+    compositionEvents.on('composeStart', record);
+    compositionEvents.on('moduleApplied', record);
+    compositionEvents.on('composeEnd', record);
+    const observed = await first.composeAsync(instanceRequest);
+    await new Promise((resolve) => setImmediate(resolve));
+    compositionEvents.off('composeStart', record);
+    compositionEvents.off('moduleApplied', record);
+    compositionEvents.off('composeEnd', record);
+    const types = events.map((event) => event.type);
+    assert(types[0] === 'composeStart' && types[types.length - 1] === 'composeEnd', 'Composition is bracketed by start and end events', types.join(', '));
+    assert(events.filter((event) => event.type === 'moduleApplied').length === observed.applied_modules.length, 'Each applied module is reported');
+    assert(new Set(events.map((event) => event.composition)).size === 1, 'Events share the composition number');
+
+    // Test 10: Streaming composition
+    log(colors.bold, '\n10. Testing streaming composition...');
+    const streamRequest = { ...instanceRequest, domain_hints: ['programming'], behavior_hints: ['planning'] };
+    const stream = composeSystemPromptStream(streamRequest);
+    const streamed = [];
+    let next = await stream.next();
+    while (!next.done) {
+      streamed.push(next.value);
+      next = await stream.next();
+    }
+    assert(streamed.length > 1, 'Sections are streamed', `${streamed.length} sections`);
+    assert(streamed.map((section) => section.text).join('') === next.value.system_prompt, 'Sections make up the system prompt');
+
+    // Test 11: Explain mode and module listing
+    log(colors.bold, '\n11. Testing explainComposition() and listModules()...');
+    const decisions = explainComposition(streamRequest);
+    const selected = decisions.filter((decision) => decision.selected).map((decision) => decision.module);
+    assert(selected.includes('task_planning'), 'Explain reports the hinted behavior', selected.join(', '));
+    const modules = listModules();
+    assert(modules.some((module) => module.name === 'task_planning' && module.kind === 'Behavior' && module.enabled), 'Modules are listed with kind and state');
+    assert(first.setModuleEnabled('task_planning', false), 'Instance module can be disabled');
+    assert(!first.explainComposition(streamRequest).some((decision) => decision.module === 'task_planning' && decision.selected), 'Disabled module is not selected');
+
+    // Test 12: Per-call options
+    log(colors.bold, '\n12. Testing per-call options...');
+    const full = composeSystemPrompt(streamRequest);
+    const limited = composeSystemPrompt(streamRequest, { promptsDir, maxTokens: 100 });
+    assert(limited.system_prompt.length <= 400 && limited.system_prompt.length < full.system_prompt.length, 'maxTokens limits the prompt', `${limited.system_prompt.length} characters`);
+    const formatted = composeSystemPrompt(instanceRequest, { outputFormat: { type: 'Json' } });
+    assert(formatted.applied_modules.includes('output_format'), 'outputFormat requests an output format');
+    try {
+      composeSystemPrompt(instanceRequest, { cache: 'live' });
+      assert(false, 'Synchronous composition rejects live discovery');
+    } catch (error) {
+      assert(error instanceof TypeError, 'Synchronous composition rejects live discovery');
+    }
+    const live = await composeSystemPromptAsync(instanceRequest, { cache: 'live' });
+    assert(live.metadata.discovery === 'Live', 'cache: live discovers tools');
+
+    // Test 13: Worker threads
+    log(colors.bold, '\n13. Testing concurrent composition from worker threads...');
+    const { Worker } = require('worker_threads');
+    const workerSource = `
+      const { parentPort, workerData } = require('worker_threads');
+      const { composeSystemPrompt, composeSystemPromptAsync, PromptComposer } = require(workerData.index);
+      (async () => {
+        const composer = new PromptComposer();
+        const prompts = [];
+        for (let i = 0; i < 5; i++) {
+          prompts.push(composeSystemPrompt(workerData.request).system_prompt);
+          prompts.push((await composeSystemPromptAsync(workerData.request, { cache: 'live' })).system_prompt);
+          prompts.push(composer.compose(workerData.request).system_prompt);
+        }
+        parentPort.postMessage(prompts);
+      })().catch((error) => parentPort.postMessage({ error: error.message }));
+    `;
+    const workerRequest = {
+      user_prompt: 'Read the config file',
+      mcp_config: { mcpServers: { filesystem: { name: 'filesystem', command: 'npx', args: ['@modelcontextprotocol/server-filesystem'] } } }
+    };
+    const workerResults = await Promise.all(Array.from({ length: 4 }, () => new Promise((resolve, reject) => {
+      const worker = new Worker(workerSource, { eval: true, workerData: { index: require.resolve('./index.js'), request: workerRequest } });
+      worker.once('message', resolve);
+      worker.once('error', reject);
+    })));
+    const expected = composeSystemPrompt(workerRequest).system_prompt;
+    assert(workerResults.every(Array.isArray), 'Workers compose without errors', workerResults.filter((result) => result.error).map((result) => result.error).join('; '));
+    assert(workerResults.flat().every((prompt) => prompt === expected), 'Workers compose the same prompt as the main thread');
+
+    // Test 14: Offline composition
+    log(colors.bold, '\n14. Testing offline composition...');
+    const offline = composeSystemPrompt(workerRequest, { offline: true });
+    assert(offline.metadata.discovery === 'Offline', 'Offline composition reports its discovery mode');
+    assert(offline.applied_modules.join() === composeSystemPrompt(workerRequest).applied_modules.join(), 'Embedded prompts select the same modules', offline.applied_modules.join(', '));
+    try {
+      await composeSystemPromptAsync(workerRequest, { offline: true, cache: 'live' });
+      assert(false, 'Offline composition rejects live discovery');
+    } catch (error) {
+      assert(error instanceof TypeError, 'Offline composition rejects live discovery');
+    }
+    const offlineComposer = new PromptComposer({ offline: true });
+    assert(offlineComposer.promptsDir === '<embedded>', 'Offline instance reads the embedded prompts');
+    assert((await offlineComposer.composeAsync(workerRequest)).metadata.discovery === 'Offline', 'Offline instance composes offline');
+
+    // Test 15: Configuration snapshots
+    log(colors.bold, '\n15. Testing exportConfig() and importConfig()...');
+    offlineComposer.setModuleEnabled('safety', false);
+    const snapshot = offlineComposer.exportConfig();
+    const imported = PromptComposer.importConfig(snapshot);
+    assert(imported.exportConfig() === snapshot, 'Imported composer exports the same snapshot');
+    assert(imported.promptsDir === '<embedded>', 'Imported composer reads the same prompts');
+    assert(imported.compose(workerRequest).system_prompt === offlineComposer.compose(workerRequest).system_prompt, 'Imported composer composes the same prompt');
+    try {
+      PromptComposer.importConfig('{"format": 99}');
+      assert(false, 'Invalid snapshots are rejected');
+    } catch (error) {
+      assert(error.code === 'ConfigError', 'Invalid snapshots are rejected', error.message);
+    }
+
+    // Test 16: Conformance vectors shared with the Rust and Python suites. Offline, tools
+    // come from each request's config rather than the tools earlier tests cached.
+    log(colors.bold, '\n16. Testing conformance vectors...');
+    const conformance = JSON.parse(fs.readFileSync(path.join(__dirname, '..', 'conformance', 'vectors.json'), 'utf8'));
+    const conformanceDir = path.join(__dirname, '..', conformance.prompts_dir);
+    const conformanceComposer = new PromptComposer({ promptsDir: conformanceDir, offline: true });
+    for (const vector of conformance.vectors) {
+      const responses = [
+        composeSystemPrompt(vector.request, { promptsDir: conformanceDir, offline: true }),
+        conformanceComposer.compose(vector.request),
+      ];
+      const differing = Object.keys(vector.expected).filter((field) =>
+        responses.some((response) => JSON.stringify(response[field]) !== JSON.stringify(vector.expected[field])));
+      assert(differing.length === 0, `Vector ${vector.name} composes as in Rust`, differing.length ? `Differs in ${differing.join(', ')}` : '');
+    }
+
+    // Test 17: Performance test
+    log(colors.bold, '\n17. Testing performance...');
     const start = Date.now();
     const perfRequest = {
       user_prompt: 'Performance test request',
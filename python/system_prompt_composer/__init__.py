@@ -9,6 +9,20 @@ All prompt guidance is loaded from transparent markdown files that developers ca
 read, understand, and customize.
 
 Example usage:
+    from system_prompt_composer import compose, PromptRequest, SessionState
+
+    request = PromptRequest(
+        user_prompt="Look at config.json and fix any issues",
+        mcp_config={"mcpServers": {"desktop-commander": {"command": "npx"}}},
+        session_state=SessionState(tool_call_count=0),
+    )
+    response = compose(request)
+    system_prompt = response.system_prompt
+
+    # Or reuse the servers configured in Claude Desktop or Cursor
+    request = PromptRequest(user_prompt="...", mcp_config=load_mcp_config("claude_desktop"))
+
+The JSON string functions remain available:
     import json
     from system_prompt_composer import compose_system_prompt
     
@@ -39,14 +53,67 @@ Example usage:
         json.dumps(request), 
         "/path/to/custom/prompts"
     )
+
+    # Add your own section; callables receive a dict with tools, user_prompt
+    # and session_state
+    register_module(
+        "release_notes",
+        generate_content=lambda ctx: "\nRELEASE NOTES:\nMention user-facing changes.",
+        applies_to=lambda ctx: "release" in ctx["user_prompt"],
+    )
+
+    # Frame every prompt and observe compositions; prologue and epilogue
+    # receive the PromptRequest, on_compose the request and PromptResponse
+    register_hook(
+        "tenant",
+        prologue=lambda request: "You are assisting Acme Corp staff.",
+        on_compose=lambda request, response: log(response.metadata),
+    )
+
+    # Scope composition to a directory, e.g. a test fixture; compositions inside
+    # use a composer of their own rather than the process-wide caches
+    with prompts_dir(tmp_path):
+        response = compose(request)
+
+    # Failures raise a PromptComposerError subclass per kind, carrying `code`
+    # and `details`; all derive from RuntimeError
+    try:
+        compose(request, prompts_dir="/missing")
+    except ConfigError as error:
+        print(error.details["path"])
 """
 
+import contextlib
+import contextvars
+import json
 import os
 from ._system_prompt_composer import (
+    compose as _compose,
+    explain as _explain,
+    PromptComposer as _PromptComposer,
+    PromptRequest,
+    PromptResponse,
+    SessionState,
+    McpConfig,
+    ModuleDecision,
+    PromptComposerError,
+    ConfigError,
+    ModuleLoadingError,
+    DiscoveryError,
+    InvalidRequestError,
+    load_mcp_config,
     compose_system_prompt_with_prompts_dir as _compose_system_prompt_with_prompts_dir,
     compose_system_prompt_cached_with_prompts_dir as _compose_system_prompt_cached_with_prompts_dir,
     refresh_server_tools,
-    get_status
+    cache_stats,
+    clear_cache,
+    register_module,
+    unregister_module,
+    register_hook,
+    unregister_hook,
+    get_status,
+    get_request_schema,
+    get_response_schema
 )
 
 __version__ = "1.0.5"
@@ -54,6 +121,125 @@ __version__ = "1.0.5"
 # Get the path to the built-in prompts directory
 _BUILTIN_PROMPTS_DIR = os.path.join(os.path.dirname(__file__), 'prompts')
 
+# Composer of the innermost `prompts_dir` block, if any
+_scoped_composer = contextvars.ContextVar("scoped_composer", default=None)
+
+@contextlib.contextmanager
+def prompts_dir(path):
+    """
+    Scope composition to a prompts directory for the duration of a `with` block.
+    
+    Inside the block, the module-level functions compose with a composer of their own
+    for `path`, so they neither read the built-in library nor share the process-wide
+    prompt file and tool discovery caches. Blocks nest, and are local to the thread or
+    async task that entered them.
+    
+    Args:
+        path (str | os.PathLike): The prompts directory
+        
+    Yields:
+        PromptComposer: The composer used inside the block
+    """
+    composer = PromptComposer(os.fspath(path))
+    token = _scoped_composer.set(composer)
+    try:
+        yield composer
+    finally:
+        _scoped_composer.reset(token)
+
+def _compose_scoped(request_json):
+    return _scoped_composer.get().compose(json.loads(request_json)).to_json()
+
+def compose(request, prompts_dir=None):
+    """
+    Compose a system prompt from a typed request.
+    
+    Args:
+        request (PromptRequest | dict): The request, or a dict shaped like its JSON form
+        prompts_dir (str, optional): Custom prompts directory; defaults to the composer of
+            the enclosing `prompts_dir` block, or else the built-in library
+        
+    Returns:
+        PromptResponse: The composed system prompt and how it was composed
+    """
+    scoped = _scoped_composer.get()
+    if prompts_dir is None and scoped is not None:
+        return scoped.compose(request)
+    return _compose(request, prompts_dir or _BUILTIN_PROMPTS_DIR)
+
+def explain(request, prompts_dir=None):
+    """
+    Decide which modules a request would get without composing it.
+    
+    Args:
+        request (PromptRequest | dict): The request, or a dict shaped like its JSON form
+        prompts_dir (str, optional): Custom prompts directory; defaults to the composer of
+            the enclosing `prompts_dir` block, or else the built-in library
+        
+    Returns:
+        list[ModuleDecision]: Each module considered, whether it was selected and why
+    """
+    scoped = _scoped_composer.get()
+    if prompts_dir is None and scoped is not None:
+        return scoped.explain(request)
+    return _explain(request, prompts_dir or _BUILTIN_PROMPTS_DIR)
+
+class PromptComposer(_PromptComposer):
+    """
+    A composer with its own prompt file cache, tool discovery cache and session state.
+    
+    Use one per tenant when serving several; the module-level functions share a single
+    process-wide cache.
+    
+    Args:
+        prompts_dir (str, optional): Custom prompts directory; defaults to the directory of
+            the enclosing `prompts_dir` block, or else the built-in library
+    """
+
+    def __new__(cls, prompts_dir=None):
+        scoped = _scoped_composer.get()
+        if prompts_dir is None and scoped is not None:
+            prompts_dir = scoped.prompts_dir
+        return super().__new__(cls, prompts_dir or _BUILTIN_PROMPTS_DIR)
+
+def _library(prompts_dir):
+    scoped = _scoped_composer.get()
+    if prompts_dir is None and scoped is not None:
+        return scoped
+    return PromptComposer(prompts_dir)
+
+def list_domains(prompts_dir=None):
+    """Names of the domain prompt files in the prompts directory."""
+    return _library(prompts_dir).list_domains()
+
+def list_behaviors(prompts_dir=None):
+    """Names of the behavior prompt files in the prompts directory."""
+    return _library(prompts_dir).list_behaviors()
+
+def list_tools(prompts_dir=None):
+    """MCP servers with tool instructions in the prompts directory."""
+    return _library(prompts_dir).list_tools()
+
+def has_tool_prompt(server, prompts_dir=None):
+    """Whether the prompts directory has tool instructions for an MCP server."""
+    return _library(prompts_dir).has_tool_prompt(server)
+
+def prompt_content(category, name, prompts_dir=None):
+    """
+    Content of a prompt file as composition reads it, for tools built over the library.
+    
+    Args:
+        category (str): "domains", "behaviors" or "tools"
+        name (str): The file name without `.md`, as listed by `list_domains`,
+            `list_behaviors` or `list_tools`
+        prompts_dir (str, optional): Custom prompts directory; defaults to the directory of
+            the enclosing `prompts_dir` block, or else the built-in library
+        
+    Returns:
+        str: The file's content without its frontmatter
+    """
+    return _library(prompts_dir).prompt_content(category, name)
+
 def compose_system_prompt(request_json):
     """
     Compose a system prompt using the built-in prompt library.
@@ -64,6 +250,8 @@ def compose_system_prompt(request_json):
     Returns:
         str: JSON string containing the composed system prompt
     """
+    if _scoped_composer.get() is not None:
+        return _compose_scoped(request_json)
     return _compose_system_prompt_with_prompts_dir(request_json, _BUILTIN_PROMPTS_DIR)
 
 def compose_system_prompt_with_prompts_dir(request_json, prompts_dir):
@@ -89,6 +277,8 @@ def compose_system_prompt_cached(request_json):
     Returns:
         str: JSON string containing the composed system prompt
     """
+    if _scoped_composer.get() is not None:
+        return _compose_scoped(request_json)
     return _compose_system_prompt_cached_with_prompts_dir(request_json, _BUILTIN_PROMPTS_DIR)
 
 def compose_system_prompt_cached_with_prompts_dir(request_json, prompts_dir):
@@ -105,10 +295,38 @@ def compose_system_prompt_cached_with_prompts_dir(request_json, prompts_dir):
     return _compose_system_prompt_cached_with_prompts_dir(request_json, prompts_dir)
 
 __all__ = [
+    "compose",
+    "PromptComposer",
+    "PromptRequest",
+    "PromptResponse",
+    "SessionState",
+    "McpConfig",
+    "ModuleDecision",
+    "PromptComposerError",
+    "ConfigError",
+    "ModuleLoadingError",
+    "DiscoveryError",
+    "InvalidRequestError",
+    "explain",
+    "prompts_dir",
+    "load_mcp_config",
+    "list_domains",
+    "list_behaviors",
+    "list_tools",
+    "has_tool_prompt",
+    "prompt_content",
     "compose_system_prompt", 
     "compose_system_prompt_with_prompts_dir",
     "compose_system_prompt_cached", 
     "compose_system_prompt_cached_with_prompts_dir",
     "refresh_server_tools",
-    "get_status"
+    "cache_stats",
+    "clear_cache",
+    "register_module",
+    "unregister_module",
+    "register_hook",
+    "unregister_hook",
+    "get_status",
+    "get_request_schema",
+    "get_response_schema"
 ]
@@ -0,0 +1,188 @@
+"""Type stubs for the native extension.
+
+Class attributes mirror the fields of the Rust types; `test_python_stubs_cover_rust_fields`
+in core/lib.rs fails when a field is missing here.
+"""
+
+import os
+from typing import Any, Callable, Dict, List, Literal, Optional, Union
+
+TaskComplexity = Literal["Trivial", "Simple", "Moderate", "Complex", "Epic"]
+RequestedComplexity = Literal["Auto", "Trivial", "Simple", "Moderate", "Complex", "Epic"]
+
+class PromptComposerError(RuntimeError):
+    code: Literal[
+        "McpConnectionFailed", "ToolDiscoveryFailed", "ModuleLoadingFailed",
+        "SerializationError", "HttpError", "ConfigError", "InvalidRequest", "Cancelled",
+    ]
+    details: Dict[str, Any]
+
+class ConfigError(PromptComposerError): ...
+class ModuleLoadingError(PromptComposerError): ...
+class DiscoveryError(PromptComposerError): ...
+class InvalidRequestError(PromptComposerError): ...
+
+class SessionState:
+    session_id: Optional[str]
+    tool_call_count: Optional[int]
+    original_task: Optional[str]
+    has_plan: Optional[bool]
+    last_action: Optional[str]
+    current_step: Optional[str]
+    recent_errors: Optional[List[Dict[str, Any]]]
+    files_modified: Optional[List[str]]
+    last_tool_results: Optional[List[Dict[str, Any]]]
+    consecutive_failures: Optional[int]
+    is_long_running: Optional[bool]
+    memories: Optional[List[str]]
+    agent_role: Optional[Literal["Orchestrator", "Worker"]]
+    phase: Optional[Literal["Exploration", "Planning", "Execution", "Review"]]
+    recent_messages: Optional[List[str]]
+
+    def __init__(self, **fields: Any) -> None: ...
+    @staticmethod
+    def from_dict(data: Dict[str, Any]) -> "SessionState": ...
+    @staticmethod
+    def from_json(json: str) -> "SessionState": ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def to_json(self) -> str: ...
+
+class McpConfig:
+    mcp_servers: Dict[str, Dict[str, Any]]
+
+    def __init__(self, **fields: Any) -> None: ...
+    @staticmethod
+    def from_dict(data: Dict[str, Any]) -> "McpConfig": ...
+    @staticmethod
+    def from_json(json: str) -> "McpConfig": ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def to_json(self) -> str: ...
+
+class PromptRequest:
+    user_prompt: str
+    mcp_config: McpConfig
+    session_state: Optional[SessionState]
+    domain_hints: Optional[List[str]]
+    behavior_hints: Optional[List[str]]
+    task_complexity: Optional[RequestedComplexity]
+    response_format: Optional[Dict[str, Any]]
+    policy: Optional[Dict[str, Any]]
+    selection_seed: Optional[int]
+    module_versions: Optional[Dict[str, str]]
+    variant_assignments: Optional[Dict[str, str]]
+    workspace: Optional[Dict[str, str]]
+    conversation: Optional[List[Dict[str, str]]]
+    max_tokens: Optional[int]
+    locale: Optional[str]
+    force_refresh: Optional[bool]
+    trace_id: Optional[str]
+    profile: Optional[bool]
+
+    def __init__(self, **fields: Any) -> None: ...
+    @staticmethod
+    def from_dict(data: Dict[str, Any]) -> "PromptRequest": ...
+    @staticmethod
+    def from_json(json: str) -> "PromptRequest": ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def to_json(self) -> str: ...
+
+class PromptResponse:
+    system_prompt: str
+    applied_modules: List[str]
+    recognized_tools: List[str]
+    complexity_assessment: TaskComplexity
+    complexity_evidence: Optional[Dict[str, Any]]
+    selection_seed: int
+    selection_trace: List["ModuleDecision"]
+    content_versions: Dict[str, str]
+    warnings: List[Dict[str, Any]]
+    metadata: Dict[str, Any]
+    profile: Optional[Dict[str, Any]]
+
+    def __init__(self, **fields: Any) -> None: ...
+    @staticmethod
+    def from_dict(data: Dict[str, Any]) -> "PromptResponse": ...
+    @staticmethod
+    def from_json(json: str) -> "PromptResponse": ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def to_json(self) -> str: ...
+
+class ModuleDecision:
+    module: str
+    source: Literal["Hint", "Detected", "Policy", "Requirement", "Request"]
+    selected: bool
+    score: Optional[float]
+    reason: str
+
+    def __init__(self, **fields: Any) -> None: ...
+    @staticmethod
+    def from_dict(data: Dict[str, Any]) -> "ModuleDecision": ...
+    @staticmethod
+    def from_json(json: str) -> "ModuleDecision": ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def to_json(self) -> str: ...
+
+class PromptComposer:
+    prompts_dir: str
+
+    def __init__(self, prompts_dir: Optional[str] = None) -> None: ...
+    def compose(self, request: Union[PromptRequest, Dict[str, Any]]) -> PromptResponse: ...
+    def explain(self, request: Union[PromptRequest, Dict[str, Any]]) -> List[ModuleDecision]: ...
+    def refresh_server(self, server_name: str, mcp_config: Union[McpConfig, Dict[str, Any]]) -> List[Dict[str, Any]]: ...
+    def list_domains(self) -> List[str]: ...
+    def list_behaviors(self) -> List[str]: ...
+    def list_tools(self) -> List[str]: ...
+    def has_tool_prompt(self, server: str) -> bool: ...
+    def prompt_content(self, category: Literal["domains", "behaviors", "tools"], name: str) -> str: ...
+    def clear_cache(self) -> None: ...
+    def cache_stats(self) -> Dict[str, Dict[str, Any]]: ...
+    def end_session(self, session_id: str) -> bool: ...
+    def register_module(
+        self,
+        name: str,
+        generate_content: Callable[[Dict[str, Any]], str],
+        applies_to: Optional[Callable[[Dict[str, Any]], bool]] = None,
+    ) -> None: ...
+    def unregister_module(self, name: str) -> bool: ...
+    def register_hook(
+        self,
+        name: str,
+        prologue: Optional[Callable[[PromptRequest], Optional[str]]] = None,
+        epilogue: Optional[Callable[[PromptRequest], Optional[str]]] = None,
+        on_compose: Optional[Callable[[PromptRequest, PromptResponse], None]] = None,
+    ) -> None: ...
+    def unregister_hook(self, name: str) -> bool: ...
+    def hooks(self) -> List[str]: ...
+    def export_config(self) -> str: ...
+    @staticmethod
+    def import_config(config: str) -> "PromptComposer": ...
+
+def compose(request: Union[PromptRequest, Dict[str, Any]], prompts_dir: Optional[str] = None) -> PromptResponse: ...
+def explain(request: Union[PromptRequest, Dict[str, Any]], prompts_dir: Optional[str] = None) -> List[ModuleDecision]: ...
+def load_mcp_config(source: Union[Literal["claude_desktop", "claude", "cursor", "vscode"], str, os.PathLike]) -> McpConfig: ...
+def compose_system_prompt(request_json: str) -> str: ...
+def compose_system_prompt_with_prompts_dir(request_json: str, prompts_dir: str) -> str: ...
+def compose_system_prompt_cached(request_json: str) -> str: ...
+def compose_system_prompt_cached_with_prompts_dir(request_json: str, prompts_dir: str) -> str: ...
+def refresh_server_tools(server_name: str, mcp_config_json: str) -> str: ...
+def cache_stats() -> Dict[str, Dict[str, Any]]: ...
+def clear_cache() -> None: ...
+def register_module(
+    name: str,
+    generate_content: Callable[[Dict[str, Any]], str],
+    applies_to: Optional[Callable[[Dict[str, Any]], bool]] = None,
+) -> None: ...
+def unregister_module(name: str) -> bool: ...
+def register_hook(
+    name: str,
+    prologue: Optional[Callable[[PromptRequest], Optional[str]]] = None,
+    epilogue: Optional[Callable[[PromptRequest], Optional[str]]] = None,
+    on_compose: Optional[Callable[[PromptRequest, PromptResponse], None]] = None,
+) -> None: ...
+def unregister_hook(name: str) -> bool: ...
+def get_status() -> str: ...
+def get_request_schema() -> str: ...
+def get_response_schema() -> str: ...
+def test_tools_feature() -> str: ...
+
+__version__: str
@@ -0,0 +1,180 @@
+"""Check the Python bindings' composer instances, registered callables and errors.
+
+    python -m unittest discover python/tests
+"""
+
+import os
+import subprocess
+import sys
+import tempfile
+import textwrap
+import unittest
+
+from system_prompt_composer import (
+    ConfigError,
+    DiscoveryError,
+    InvalidRequestError,
+    ModuleLoadingError,
+    PromptComposer,
+    PromptComposerError,
+    compose,
+    compose_system_prompt,
+    prompts_dir,
+)
+
+ROOT = os.path.join(os.path.dirname(__file__), "..", "..")
+PROMPTS_DIR = os.path.join(ROOT, "prompts")
+
+REQUEST = {"user_prompt": "Write the release notes"}
+
+
+class IsolationTest(unittest.TestCase):
+    def test_modules_and_hooks_apply_to_their_composer_only(self):
+        tenant, other = PromptComposer(PROMPTS_DIR), PromptComposer(PROMPTS_DIR)
+        tenant.register_module("release_notes", lambda ctx: "RELEASE NOTES:")
+        tenant.register_hook("tenant", prologue=lambda request: "You are assisting Acme Corp staff.")
+
+        self.assertIn("release_notes", tenant.compose(REQUEST).applied_modules)
+        self.assertEqual(tenant.hooks(), ["tenant"])
+        response = other.compose(REQUEST)
+        self.assertNotIn("release_notes", response.applied_modules)
+        self.assertNotIn("Acme Corp", response.system_prompt)
+        self.assertEqual(other.hooks(), [])
+
+    def test_caches_are_per_composer(self):
+        tenant, other = PromptComposer(PROMPTS_DIR), PromptComposer(PROMPTS_DIR)
+        tenant.compose({"user_prompt": "Plan and implement the migration, then run the tests"})
+
+        self.assertGreater(tenant.cache_stats()["prompts"]["entries"], 0)
+        self.assertEqual(other.cache_stats()["prompts"]["entries"], 0)
+        tenant.clear_cache()
+        self.assertEqual(tenant.cache_stats()["prompts"]["entries"], 0)
+
+class ConcurrencyTest(unittest.TestCase):
+    def test_cache_stats_while_a_python_module_composes(self):
+        # Run in a child process, since a deadlock would hang the test run
+        script = textwrap.dedent("""
+            import sys, threading, time
+            from system_prompt_composer import PromptComposer
+
+            composer = PromptComposer(sys.argv[1])
+            generating = threading.Event()
+
+            def generate_content(ctx):
+                generating.set()
+                time.sleep(0.2)
+                return "RELEASE NOTES:"
+
+            composer.register_module("release_notes", generate_content)
+            composing = threading.Thread(target=composer.compose, args=({"user_prompt": "Hi"},))
+            composing.start()
+            generating.wait()
+            composer.cache_stats()
+            composing.join()
+        """)
+        subprocess.run([sys.executable, "-c", script, PROMPTS_DIR], check=True, timeout=30)
+
+
+class PythonModuleTest(unittest.TestCase):
+    def setUp(self):
+        self.composer = PromptComposer(PROMPTS_DIR)
+
+    def test_generate_content_is_composed(self):
+        self.composer.register_module(
+            "release_notes",
+            generate_content=lambda ctx: "RELEASE NOTES:\nMention user-facing changes.",
+            applies_to=lambda ctx: "release" in ctx["user_prompt"],
+        )
+        response = self.composer.compose(REQUEST)
+        self.assertIn("release_notes", response.applied_modules)
+        self.assertIn("RELEASE NOTES:\nMention user-facing changes.", response.system_prompt)
+
+    def test_generate_content_that_raises_fails_composition(self):
+        def generate_content(ctx):
+            raise KeyError("changelog")
+
+        self.composer.register_module("release_notes", generate_content)
+        with self.assertRaises(ModuleLoadingError) as raised:
+            self.composer.compose(REQUEST)
+        self.assertIn("Python module release_notes failed", str(raised.exception))
+        self.assertIn("changelog", str(raised.exception))
+        self.assertEqual(raised.exception.details["module"], "release_notes")
+
+    def test_generate_content_returning_a_non_string_fails_composition(self):
+        self.composer.register_module("release_notes", lambda ctx: 42)
+        with self.assertRaises(ModuleLoadingError) as raised:
+            self.composer.compose(REQUEST)
+        self.assertIn("Python module release_notes failed", str(raised.exception))
+
+    def test_applies_to_that_raises_leaves_the_module_out(self):
+        def applies_to(ctx):
+            raise ValueError("no release")
+
+        self.composer.register_module("release_notes", lambda ctx: "RELEASE NOTES:", applies_to)
+        response = self.composer.compose(REQUEST)
+        self.assertNotIn("release_notes", response.applied_modules)
+
+
+class ErrorTest(unittest.TestCase):
+    def assert_raises_kind(self, exception, code, call):
+        with self.assertRaises(exception) as raised:
+            call()
+        self.assertEqual(raised.exception.code, code)
+        self.assertEqual(raised.exception.details["code"], code)
+        return raised.exception
+
+    def test_config_errors_raise_config_error(self):
+        error = self.assert_raises_kind(ConfigError, "ConfigError", lambda: PromptComposer("/missing").compose(REQUEST))
+        self.assertEqual(error.details["path"], "/missing")
+        error = self.assert_raises_kind(
+            ConfigError, "ConfigError", lambda: PromptComposer(PROMPTS_DIR).refresh_server("files", {"mcpServers": {}})
+        )
+        self.assertEqual(error.details["server"], "files")
+
+    def test_invalid_requests_raise_invalid_request_error(self):
+        self.assert_raises_kind(InvalidRequestError, "InvalidRequest", lambda: compose_system_prompt("not json"))
+        error = self.assert_raises_kind(
+            InvalidRequestError, "InvalidRequest", lambda: compose({"user_prompt": "Hi", "max_tokens": 0})
+        )
+        self.assertEqual(error.details["field"], "max_tokens")
+
+    def test_module_failures_raise_module_loading_error(self):
+        composer = PromptComposer(PROMPTS_DIR)
+        composer.register_module("release_notes", lambda ctx: None)
+        self.assert_raises_kind(ModuleLoadingError, "ModuleLoadingFailed", lambda: composer.compose(REQUEST))
+
+    def test_every_kind_derives_from_prompt_composer_error(self):
+        self.assertTrue(issubclass(PromptComposerError, RuntimeError))
+        for kind in (ConfigError, ModuleLoadingError, DiscoveryError, InvalidRequestError):
+            with self.subTest(kind=kind.__name__):
+                self.assertTrue(issubclass(kind, PromptComposerError))
+
+
+class PromptsDirTest(unittest.TestCase):
+    def setUp(self):
+        self.builtin = PromptComposer().prompts_dir
+        self.fixture = tempfile.mkdtemp()
+
+    def tearDown(self):
+        os.rmdir(self.fixture)
+
+    def test_nested_blocks_unwind_to_the_enclosing_directory(self):
+        with prompts_dir(PROMPTS_DIR) as outer:
+            with prompts_dir(self.fixture) as inner:
+                self.assertEqual(PromptComposer().prompts_dir, self.fixture)
+                self.assertEqual(inner.prompts_dir, self.fixture)
+            self.assertEqual(PromptComposer().prompts_dir, PROMPTS_DIR)
+            self.assertEqual(outer.prompts_dir, PROMPTS_DIR)
+        self.assertEqual(PromptComposer().prompts_dir, self.builtin)
+
+    def test_directory_is_restored_when_the_block_raises(self):
+        with prompts_dir(PROMPTS_DIR):
+            with self.assertRaises(ValueError):
+                with prompts_dir(self.fixture):
+                    raise ValueError("composition test failed")
+            self.assertEqual(PromptComposer().prompts_dir, PROMPTS_DIR)
+        self.assertEqual(PromptComposer().prompts_dir, self.builtin)
+
+
+if __name__ == "__main__":
+    unittest.main()
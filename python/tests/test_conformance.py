@@ -0,0 +1,38 @@
+"""Check the Python bindings against the conformance vectors the Rust and Node suites
+also run, so every surface composes the same prompt for the same request.
+
+    python -m unittest discover python/tests
+"""
+
+import json
+import os
+import unittest
+
+from system_prompt_composer import PromptComposer, compose
+
+ROOT = os.path.join(os.path.dirname(__file__), "..", "..")
+
+with open(os.path.join(ROOT, "conformance", "vectors.json")) as f:
+    VECTORS = json.load(f)
+
+PROMPTS_DIR = os.path.join(ROOT, VECTORS["prompts_dir"])
+
+
+class ConformanceTest(unittest.TestCase):
+    def check(self, composed):
+        for vector in VECTORS["vectors"]:
+            with self.subTest(vector=vector["name"]):
+                response = composed(vector["request"]).to_dict()
+                for field, expected in vector["expected"].items():
+                    self.assertEqual(response[field], expected, field)
+
+    def test_compose(self):
+        self.check(lambda request: compose(request, PROMPTS_DIR))
+
+    def test_composer_instance(self):
+        composer = PromptComposer(PROMPTS_DIR)
+        self.check(composer.compose)
+
+
+if __name__ == "__main__":
+    unittest.main()
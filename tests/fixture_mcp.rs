@@ -0,0 +1,44 @@
+//! Discovery that lists servers, run against the `prompt-composer-fixture-mcp` binary
+#![cfg(feature = "testing")]
+
+use prompt_composer::discovery::ToolDiscovery;
+use prompt_composer::engine::Engine;
+use prompt_composer::mcp_fixture::FixtureMcpServer;
+use prompt_composer::types::{McpConfig, PromptRequest};
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn fixture_config(server: &FixtureMcpServer) -> McpConfig {
+    let command = env!("CARGO_BIN_EXE_prompt-composer-fixture-mcp");
+    McpConfig { mcp_servers: HashMap::from([("files".to_string(), server.config(command))]) }
+}
+
+#[test]
+fn test_discovery_lists_the_tools_of_the_fixture_binary() {
+    let server = FixtureMcpServer::new("files")
+        .with_tool("read_file", "Read a file")
+        .with_tool("grep", "Search files by pattern");
+    let config = fixture_config(&server);
+
+    let listing = ToolDiscovery::embedded().with_server_listing(Duration::from_secs(10));
+    let names = |tools: Vec<prompt_composer::types::Tool>| tools.into_iter().map(|tool| tool.name).collect::<Vec<_>>();
+    assert_eq!(names(listing.discover_tools_immediate(&config).unwrap()), ["files.read_file", "files.grep"]);
+    assert_eq!(names(listing.refresh_server_immediate("files", &config).unwrap()), ["files.read_file", "files.grep"]);
+
+    // Without listing, the server's name matches the filesystem patterns
+    let inferred = names(ToolDiscovery::embedded().discover_tools_immediate(&config).unwrap());
+    assert!(!inferred.contains(&"files.grep".to_string()), "{:?}", inferred);
+}
+
+#[test]
+fn test_engine_composes_with_the_listed_tools() {
+    let server = FixtureMcpServer::new("files").with_tool("grep", "Search files by pattern");
+    let request = PromptRequest::builder()
+        .user_prompt("Find where the config is parsed")
+        .mcp_config(fixture_config(&server))
+        .build()
+        .unwrap();
+    let engine = Engine::with_prompts_dir("prompts").with_server_listing(Duration::from_secs(10));
+    let response = tokio::runtime::Runtime::new().unwrap().block_on(engine.compose(request)).unwrap();
+    assert!(response.recognized_tools.contains(&"files.grep".to_string()), "{:?}", response.recognized_tools);
+}